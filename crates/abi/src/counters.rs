@@ -0,0 +1,163 @@
+//! Benchmark counters
+//!
+//! Named access to the counters exposed through `MIRALIS_READ_COUNTERS_FID` by the counter
+//! benchmark module. The ordering of the counters is defined in [miralis_core::benchmark].
+
+use miralis_core::benchmark::{COUNTER_NAMES, NB_COUNTERS, counters};
+
+/// The benchmark counters of a hart.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counters {
+    /// All traps into Miralis.
+    pub total_exits: usize,
+    /// Traps from the firmware.
+    pub firmware_exits: usize,
+    /// World switches from the payload to the firmware, each followed by a switch back.
+    pub world_switches: usize,
+    /// Cycles spent in world switches, in both directions.
+    pub world_switch_cycles: usize,
+    /// Payload traps forwarded to the firmware.
+    pub not_offloaded: usize,
+    /// Time reads emulated on behalf of the payload.
+    pub read_time: usize,
+    /// SBI timer requests handled on behalf of the payload.
+    pub set_timer: usize,
+    /// Misaligned loads and stores emulated on behalf of the payload.
+    pub misaligned_op: usize,
+    /// SBI IPI requests handled on behalf of the payload.
+    pub ipi: usize,
+    /// SBI remote fence requests handled on behalf of the payload.
+    pub remote_fence: usize,
+    /// Payload page faults.
+    pub page_faults: usize,
+    /// Instructions retired by the firmware and payload, including the context switch code of
+    /// Miralis.
+    pub guest_instructions: usize,
+    /// Average number of guest instructions retired between two traps into Miralis.
+    pub instructions_per_exit: usize,
+}
+
+impl Counters {
+    /// Builds the counters from their raw values, ordered as in [miralis_core::benchmark].
+    pub const fn from_raw(raw: &[usize; NB_COUNTERS]) -> Self {
+        Counters {
+            total_exits: raw[counters::TOTAL_EXITS],
+            firmware_exits: raw[counters::FIRMWARE_TRAP],
+            world_switches: raw[counters::WORLD_SWITCH],
+            world_switch_cycles: raw[counters::WORLD_SWITCH_CYCLES],
+            not_offloaded: raw[counters::NOT_OFFLOADED],
+            read_time: raw[counters::READ_TIME],
+            set_timer: raw[counters::SET_TIMER],
+            misaligned_op: raw[counters::MISALIGNED_OP],
+            ipi: raw[counters::IPI],
+            remote_fence: raw[counters::REMOTE_FENCE],
+            page_faults: raw[counters::PAGE_FAULT],
+            guest_instructions: raw[counters::GUEST_INSTRUCTIONS],
+            instructions_per_exit: raw[counters::INSTRUCTIONS_PER_EXIT],
+        }
+    }
+
+    /// Returns the raw values of the counters, ordered as in [miralis_core::benchmark].
+    pub const fn to_raw(&self) -> [usize; NB_COUNTERS] {
+        let mut raw = [0; NB_COUNTERS];
+        raw[counters::TOTAL_EXITS] = self.total_exits;
+        raw[counters::FIRMWARE_TRAP] = self.firmware_exits;
+        raw[counters::WORLD_SWITCH] = self.world_switches;
+        raw[counters::WORLD_SWITCH_CYCLES] = self.world_switch_cycles;
+        raw[counters::NOT_OFFLOADED] = self.not_offloaded;
+        raw[counters::READ_TIME] = self.read_time;
+        raw[counters::SET_TIMER] = self.set_timer;
+        raw[counters::MISALIGNED_OP] = self.misaligned_op;
+        raw[counters::IPI] = self.ipi;
+        raw[counters::REMOTE_FENCE] = self.remote_fence;
+        raw[counters::PAGE_FAULT] = self.page_faults;
+        raw[counters::GUEST_INSTRUCTIONS] = self.guest_instructions;
+        raw[counters::INSTRUCTIONS_PER_EXIT] = self.instructions_per_exit;
+        raw
+    }
+
+    /// Returns an iterator over the name and value of each counter.
+    ///
+    /// The names are the ones used by the runner when collecting benchmark results.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, usize)> {
+        COUNTER_NAMES.into_iter().zip(self.to_raw())
+    }
+}
+
+/// Ask Miralis for the benchmark counters of the given hart.
+///
+/// The counters are read one at a time, and each read is itself a trap into Miralis. Counters
+/// are therefore not a consistent snapshot, and `total_exits` accounts for the previous reads.
+pub fn read_counters(hart_id: usize) -> Counters {
+    let mut raw = [0; NB_COUNTERS];
+    for (category, value) in raw.iter_mut().enumerate() {
+        *value = unsafe { read_counter(hart_id, category) };
+    }
+    Counters::from_raw(&raw)
+}
+
+/// # Safety
+/// This function will always panic if not executed on a riscv64 architecture
+#[inline]
+#[cfg(not(target_arch = "riscv64"))]
+unsafe fn read_counter(_hart_id: usize, _category: usize) -> usize {
+    panic!("Tried to read Miralis counters on non RISC-V archiecture");
+}
+
+/// Read a single counter.
+///
+/// Unlike most of the Miralis ABI the value is returned in a0.
+#[inline]
+#[cfg(target_arch = "riscv64")]
+unsafe fn read_counter(hart_id: usize, category: usize) -> usize {
+    let value: usize;
+
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inout("a0") hart_id => value,
+            inout("a1") category => _,
+            in("a6") miralis_core::abi::MIRALIS_READ_COUNTERS_FID,
+            in("a7") miralis_core::abi::MIRALIS_EID,
+        );
+    }
+
+    value
+}
+
+// ————————————————————————————————— Tests —————————————————————————————————— //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_counters() {
+        let raw: [usize; NB_COUNTERS] = core::array::from_fn(|idx| idx * 10);
+        let values = Counters::from_raw(&raw);
+
+        assert_eq!(values.total_exits, raw[counters::TOTAL_EXITS]);
+        assert_eq!(values.firmware_exits, raw[counters::FIRMWARE_TRAP]);
+        assert_eq!(values.world_switches, raw[counters::WORLD_SWITCH]);
+        assert_eq!(values.guest_instructions, raw[counters::GUEST_INSTRUCTIONS]);
+        assert_eq!(values.to_raw(), raw);
+    }
+
+    #[test]
+    fn iter_counters() {
+        let values = Counters {
+            world_switches: 20,
+            firmware_exits: 4,
+            ..Default::default()
+        };
+
+        assert_eq!(values.iter().count(), NB_COUNTERS);
+        for (name, value) in values.iter() {
+            match name {
+                "world-switch" => assert_eq!(value, 20),
+                "firmware-trap" => assert_eq!(value, 4),
+                _ => assert_eq!(value, 0),
+            }
+        }
+    }
+}
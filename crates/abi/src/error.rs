@@ -0,0 +1,90 @@
+//! Miralis ABI errors
+//!
+//! Miralis reports errors using the negative error codes of the SBI specification. This module
+//! maps the codes used by Miralis to named variants.
+
+use core::fmt;
+
+use miralis_core::sbi_codes;
+
+/// An error returned by a Miralis ecall.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MiralisError {
+    /// The requested function or extension is not supported.
+    NotSupported,
+    /// One of the parameters is invalid.
+    InvalidParam,
+    /// The request was denied, for instance because the caller lacks the required privileges.
+    Denied,
+    /// An error code without a dedicated variant.
+    Other(usize),
+}
+
+impl MiralisError {
+    /// Returns the error corresponding to a raw SBI error code.
+    pub const fn from_code(code: usize) -> Self {
+        match code {
+            sbi_codes::SBI_ERR_NOT_SUPPORTED => MiralisError::NotSupported,
+            sbi_codes::SBI_ERR_INVALID_PARAM => MiralisError::InvalidParam,
+            sbi_codes::SBI_ERR_DENIED => MiralisError::Denied,
+            code => MiralisError::Other(code),
+        }
+    }
+
+    /// Returns the raw SBI error code.
+    pub const fn code(self) -> usize {
+        match self {
+            MiralisError::NotSupported => sbi_codes::SBI_ERR_NOT_SUPPORTED,
+            MiralisError::InvalidParam => sbi_codes::SBI_ERR_INVALID_PARAM,
+            MiralisError::Denied => sbi_codes::SBI_ERR_DENIED,
+            MiralisError::Other(code) => code,
+        }
+    }
+}
+
+impl fmt::Display for MiralisError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MiralisError::NotSupported => write!(f, "not supported"),
+            MiralisError::InvalidParam => write!(f, "invalid parameter"),
+            MiralisError::Denied => write!(f, "denied"),
+            MiralisError::Other(code) => write!(f, "error code {}", *code as isize),
+        }
+    }
+}
+
+/// Converts the raw result of an ecall, such as returned by [crate::ecall3], into a structured
+/// result.
+pub fn to_miralis_result(result: Result<usize, usize>) -> Result<usize, MiralisError> {
+    result.map_err(MiralisError::from_code)
+}
+
+// ————————————————————————————————— Tests —————————————————————————————————— //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_error_codes() {
+        let known = [
+            (sbi_codes::SBI_ERR_NOT_SUPPORTED, MiralisError::NotSupported),
+            (sbi_codes::SBI_ERR_INVALID_PARAM, MiralisError::InvalidParam),
+            (sbi_codes::SBI_ERR_DENIED, MiralisError::Denied),
+        ];
+
+        for (code, error) in known {
+            assert_eq!(MiralisError::from_code(code), error);
+            assert_eq!(error.code(), code);
+            assert_eq!(to_miralis_result(Err(code)), Err(error));
+        }
+    }
+
+    #[test]
+    fn other_error_codes() {
+        let code = sbi_codes::SBI_ERR_ALREADY_STARTED;
+        assert_eq!(MiralisError::from_code(code), MiralisError::Other(code));
+        assert_eq!(MiralisError::Other(code).code(), code);
+        assert_eq!(to_miralis_result(Ok(42)), Ok(42));
+    }
+}
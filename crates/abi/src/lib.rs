@@ -11,14 +11,19 @@ use core::fmt::{self, Write};
 use core::hint;
 
 use log::Level;
+use miralis_config::LOG_MAX_LENGTH;
 pub use miralis_config::helper::is_enabled;
 pub use miralis_config::{TARGET_FIRMWARE_STACK_SIZE, TARGET_PAYLOAD_STACK_SIZE};
 use miralis_core::abi;
 
-use crate::logger::StackBuffer;
+use crate::logger::ChunkedBuffer;
 
+pub mod counters;
+pub mod error;
 pub mod logger;
 
+pub use counters::{Counters, read_counters};
+pub use error::{MiralisError, to_miralis_result};
 pub use log;
 
 // ———————————————————————————— Client Functions ———————————————————————————— //
@@ -43,6 +48,101 @@ pub fn failure() -> ! {
     }
 }
 
+/// Ask Miralis to grant an all-access PMP region for the next payload execution.
+///
+/// This saves test firmware from configuring the PMP by hand before jumping into an OS. The region
+/// has lower priority than the virtual PMP entries and is revoked as soon as the payload traps
+/// back into the firmware.
+pub fn identity_map() {
+    unsafe {
+        miralis_ecall(abi::MIRALIS_IDENTITY_MAP_FID).expect("Failed to request an identity map")
+    };
+}
+
+/// Ask Miralis whether it handles the SBI extension `eid` itself.
+///
+/// Firmware forwarding SBI calls can use this to avoid handling extensions virtualized by Miralis.
+pub fn probes_sbi(eid: usize) -> bool {
+    let virtualized = unsafe {
+        ecall3(abi::MIRALIS_EID, abi::MIRALIS_PROBE_SBI_FID, eid, 0, 0)
+            .expect("Failed to probe SBI extension")
+    };
+    virtualized != 0
+}
+
+/// Ask Miralis to enable or disable single-stepping of the payload.
+pub fn single_step(enabled: bool) {
+    unsafe {
+        ecall3(
+            abi::MIRALIS_EID,
+            abi::MIRALIS_ENABLE_SINGLESTEP_FID,
+            enabled as usize,
+            0,
+            0,
+        )
+        .expect("Failed to configure single-stepping")
+    };
+}
+
+/// Ask Miralis to zero the memory region of `size` bytes starting at `base`.
+///
+/// The memory is zeroed by Miralis itself, which gives the firmware a guarantee that the region
+/// is cleared (e.g. when tearing down an enclave). The installed policies can deny the request.
+pub fn zero_region(base: usize, size: usize) -> Result<(), MiralisError> {
+    to_miralis_result(unsafe {
+        ecall3(
+            abi::MIRALIS_EID,
+            abi::MIRALIS_ZERO_REGION_FID,
+            base,
+            size,
+            0,
+        )
+    })
+    .map(|_| ())
+}
+
+/// Release the `count` highest-numbered virtual PMP entries back to Miralis.
+///
+/// This is a tuning knob for firmware using fewer PMP entries than exposed by Miralis, the
+/// released entries can not be reclaimed afterward.
+pub fn release_pmp(count: usize) -> Result<(), MiralisError> {
+    to_miralis_result(unsafe {
+        ecall3(abi::MIRALIS_EID, abi::MIRALIS_RELEASE_PMP_FID, count, 0, 0)
+    })
+    .map(|_| ())
+}
+
+/// Ask Miralis to stop counting exits of the current hart in the benchmarks.
+///
+/// Together with [resume_benchmark], this brackets the region of interest of a benchmark.
+pub fn pause_benchmark() {
+    unsafe {
+        miralis_ecall(abi::MIRALIS_BENCHMARK_PAUSE_FID).expect("Failed to pause the benchmark")
+    };
+}
+
+/// Ask Miralis to count exits of the current hart in the benchmarks again.
+pub fn resume_benchmark() {
+    unsafe {
+        miralis_ecall(abi::MIRALIS_BENCHMARK_RESUME_FID).expect("Failed to resume the benchmark")
+    };
+}
+
+/// Read a field of the audit log entry `index`, 0 being the most recent world switch.
+///
+/// Requires the audit policy, see [abi::audit] for the available fields.
+pub fn read_audit_log(index: usize, field: usize) -> Result<usize, MiralisError> {
+    to_miralis_result(unsafe {
+        ecall3(
+            abi::MIRALIS_EID,
+            abi::MIRALIS_READ_AUDIT_LOG_FID,
+            index,
+            field,
+            0,
+        )
+    })
+}
+
 /// Ask Miralis to log a string with the provided log level.
 pub fn miralis_log(level: Level, message: &str) {
     // Prepare ecall arguments
@@ -61,10 +161,14 @@ pub fn miralis_log(level: Level, message: &str) {
 }
 
 /// Ask Miralis to log a formatted string with the provided log level.
+///
+/// The message is formatted into a stack-allocated buffer of `LOG_MAX_LENGTH` bytes, messages
+/// longer than that are logged across multiple calls.
 pub fn miralis_log_fmt(level: Level, args: fmt::Arguments) {
-    let mut buff: StackBuffer<300> = StackBuffer::new();
-    buff.write_fmt(args).unwrap();
-    miralis_log(level, buff.as_str());
+    let mut buff: ChunkedBuffer<LOG_MAX_LENGTH, _> =
+        ChunkedBuffer::new(|chunk| miralis_log(level, chunk));
+    buff.write_fmt(args).ok();
+    buff.flush();
 }
 
 // —————————————————————————————— Binary Setup —————————————————————————————— //
@@ -216,6 +320,6 @@ pub unsafe fn ecall3(
 }
 
 #[inline]
-unsafe fn miralis_ecall(fid: usize) -> Result<usize, usize> {
-    unsafe { ecall3(abi::MIRALIS_EID, fid, 0, 0, 0) }
+unsafe fn miralis_ecall(fid: usize) -> Result<usize, MiralisError> {
+    to_miralis_result(unsafe { ecall3(abi::MIRALIS_EID, fid, 0, 0, 0) })
 }
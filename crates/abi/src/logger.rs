@@ -2,12 +2,11 @@
 //!
 //! This is a logger implementation that uses the Miralis SBI to log messages.
 
-use core::fmt::Write;
 use core::sync::atomic::{AtomicBool, Ordering};
 
 use log::{LevelFilter, Metadata, Record};
 
-use crate::miralis_log;
+use crate::miralis_log_fmt;
 
 // ————————————————————————————————— Logger ————————————————————————————————— //
 
@@ -22,13 +21,7 @@ impl log::Log for Logger {
 
     fn log(&self, record: &Record) {
         if self.enabled(record.metadata()) {
-            // Write data into a stack-allocated buffer
-            // For now we limit ourselves to 300 characters, that should be enough for printing the
-            // panic error while not consuming too much stack space.
-            let mut buff: StackBuffer<300> = StackBuffer::new();
-            write!(&mut buff, "{}", record.args()).ok();
-
-            miralis_log(record.level(), buff.as_str());
+            miralis_log_fmt(record.level(), *record.args());
         }
     }
 
@@ -73,6 +66,18 @@ impl<const N: usize> StackBuffer<N> {
         // NOTE: we only ever put valid strings in this buffer, so this will never panic
         core::str::from_utf8(&self.buff[..self.cursor]).unwrap()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.cursor == 0
+    }
+
+    pub fn remaining(&self) -> usize {
+        N - self.cursor
+    }
+
+    pub fn clear(&mut self) {
+        self.cursor = 0;
+    }
 }
 
 impl<const N: usize> core::fmt::Write for StackBuffer<N> {
@@ -93,3 +98,92 @@ impl<const N: usize> core::fmt::Write for StackBuffer<N> {
         Ok(())
     }
 }
+
+// ————————————————————————————— Chunked Buffer ————————————————————————————— //
+
+/// A stack buffer that hands its content over to `emit` each time it fills up.
+///
+/// This is used to log messages longer than the buffer as a sequence of chunks, instead of
+/// truncating them. Chunks are always split on character boundaries so that each of them is a
+/// valid string.
+pub(crate) struct ChunkedBuffer<const N: usize, F: FnMut(&str)> {
+    buff: StackBuffer<N>,
+    emit: F,
+}
+
+impl<const N: usize, F: FnMut(&str)> ChunkedBuffer<N, F> {
+    pub fn new(emit: F) -> Self {
+        // A chunk must be able to hold any UTF-8 character
+        const { assert!(N >= 4, "The log buffer must hold at least 4 bytes") };
+
+        ChunkedBuffer {
+            buff: StackBuffer::new(),
+            emit,
+        }
+    }
+
+    /// Emit the content of the buffer, if any.
+    pub fn flush(&mut self) {
+        if !self.buff.is_empty() {
+            (self.emit)(self.buff.as_str());
+            self.buff.clear();
+        }
+    }
+}
+
+impl<const N: usize, F: FnMut(&str)> core::fmt::Write for ChunkedBuffer<N, F> {
+    fn write_str(&mut self, mut s: &str) -> core::fmt::Result {
+        while s.len() > self.buff.remaining() {
+            // Fill the buffer as much as possible without splitting a character
+            let mut split = self.buff.remaining();
+            while !s.is_char_boundary(split) {
+                split -= 1;
+            }
+
+            self.buff.write_str(&s[..split])?;
+            self.flush();
+            s = &s[split..];
+        }
+
+        self.buff.write_str(s)
+    }
+}
+
+// ————————————————————————————————— Tests —————————————————————————————————— //
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::fmt::Write;
+    use std::string::String;
+    use std::vec::Vec;
+
+    use super::ChunkedBuffer;
+
+    #[test]
+    fn long_messages_are_chunked() {
+        let mut chunks: Vec<String> = Vec::new();
+        let message = "The quick brown fox jumps over the lazy dog, ünïcödé included.";
+
+        let mut buff: ChunkedBuffer<8, _> = ChunkedBuffer::new(|s: &str| chunks.push(s.into()));
+        write!(&mut buff, "{} {}", message, 42).unwrap();
+        buff.flush();
+
+        assert!(chunks.len() > 1, "The message should span multiple chunks");
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 8));
+        assert_eq!(chunks.concat(), std::format!("{} 42", message));
+    }
+
+    #[test]
+    fn short_messages_are_not_chunked() {
+        let mut chunks: Vec<String> = Vec::new();
+
+        let mut buff: ChunkedBuffer<300, _> = ChunkedBuffer::new(|s: &str| chunks.push(s.into()));
+        let name = "world";
+        write!(&mut buff, "Hello, {}!", name).unwrap();
+        buff.flush();
+
+        assert_eq!(chunks, ["Hello, world!"]);
+    }
+}
@@ -93,6 +93,46 @@ pub const fn parse_str_list<const LEN: usize>(env_var: Option<&str>) -> [&str; L
     res
 }
 
+/// Split a string of comma (",") separated `key=value` pairs into a list of integer pairs.
+///
+/// Both keys and values are expected to be decimal integers.
+pub const fn parse_usize_pair_list<const LEN: usize>(
+    env_var: Option<&str>,
+) -> [(usize, usize); LEN] {
+    let list: [&str; LEN] = parse_str_list(env_var);
+    let mut res: [(usize, usize); LEN] = [(0, 0); LEN];
+    let mut i = 0;
+
+    while i < LEN {
+        // We look for the "=" delimiter
+        let bytes = list[i].as_bytes();
+        let mut idx = 0;
+        while idx < bytes.len() && bytes[idx] != b'=' {
+            idx += 1;
+        }
+        if idx == bytes.len() {
+            panic!("Missing '=' in key-value pair from configuration");
+        }
+
+        // Then we split around the delimiter
+        let (key, value) = bytes.split_at(idx);
+        let value = value.split_at(1).1;
+        let (Ok(key), Ok(value)) = (core::str::from_utf8(key), core::str::from_utf8(value)) else {
+            panic!("Invalid key-value pair in configuration");
+        };
+        let (Ok(key), Ok(value)) = (
+            usize::from_str_radix(key, 10),
+            usize::from_str_radix(value, 10),
+        ) else {
+            panic!("Failed to parse integer in key-value pair from configuration");
+        };
+        res[i] = (key, value);
+        i += 1;
+    }
+
+    res
+}
+
 /// Returns the len of a list of comma (",") separated values.
 pub const fn str_list_len(env_var: Option<&str>) -> usize {
     // First we unwrap the option
@@ -19,6 +19,23 @@ pub const LOG_LEVEL_ENV: &str = "MIRALIS_LOG_LEVEL";
 pub const LOG_COLOR: bool = is_enabled!("MIRALIS_LOG_COLOR");
 pub const LOG_COLOR_ENV: &str = "MIRALIS_LOG_COLOR";
 
+/// If consecutive identical log messages are coalesced.
+pub const LOG_DEDUPLICATE: bool = is_enabled_default_false!("MIRALIS_LOG_DEDUPLICATE");
+pub const LOG_DEDUPLICATE_ENV: &str = "MIRALIS_LOG_DEDUPLICATE";
+
+/// The maximum length of a log message emitted at once by firmware and payloads, longer messages
+/// are split.
+pub const LOG_MAX_LENGTH: usize = parse_usize_or(option_env!("MIRALIS_LOG_MAX_LENGTH"), 300);
+pub const LOG_MAX_LENGTH_ENV: &str = "MIRALIS_LOG_MAX_LENGTH";
+
+/// Where the log records are written: "uart" (the default), "shared-page" or "both".
+pub const LOG_BACKEND: Option<&'static str> = option_env!("MIRALIS_LOG_BACKEND");
+pub const LOG_BACKEND_ENV: &str = "MIRALIS_LOG_BACKEND";
+
+/// Address of the page holding the log ring buffer, when logging to a shared page.
+pub const LOG_SHARED_PAGE: Option<usize> = parse_usize(option_env!("MIRALIS_LOG_SHARED_PAGE"));
+pub const LOG_SHARED_PAGE_ENV: &str = "MIRALIS_LOG_SHARED_PAGE";
+
 /// Log error
 pub const LOG_ERROR: &[&str; str_list_len(option_env!("MIRALIS_LOG_ERROR"))] =
     &parse_str_list(option_env!("MIRALIS_LOG_ERROR"));
@@ -51,16 +68,104 @@ pub const MAX_FIRMWARE_EXIT: Option<usize> =
     parse_usize(option_env!("MIRALIS_DEBUG_MAX_FIRMWARE_EXITS"));
 pub const MAX_FIRMWARE_EXIT_ENV: &str = "MIRALIS_DEBUG_MAX_FIRMWARE_EXITS";
 
+/// Interval in milliseconds between two samples of the exit rate, which is logged at info
+/// level. The exit rate is not sampled if None.
+pub const DEBUG_EXIT_RATE_INTERVAL: Option<usize> =
+    parse_usize(option_env!("MIRALIS_DEBUG_EXIT_RATE_INTERVAL"));
+pub const DEBUG_EXIT_RATE_INTERVAL_ENV: &str = "MIRALIS_DEBUG_EXIT_RATE_INTERVAL";
+
+/// Time in milliseconds the firmware or payload can trap from the same few addresses before
+/// Miralis gives up and exits. No timeout if None.
+pub const PROGRESS_TIMEOUT: Option<usize> = parse_usize(option_env!("MIRALIS_PROGRESS_TIMEOUT"));
+pub const PROGRESS_TIMEOUT_ENV: &str = "MIRALIS_PROGRESS_TIMEOUT";
+
+/// Size in bytes of the canary at the bottom of each hart stack, which is checked for corruption
+/// when reporting the stack usage. The canary is not checked if None.
+pub const DEBUG_STACK_CANARY_SIZE: Option<usize> =
+    parse_usize(option_env!("MIRALIS_DEBUG_STACK_CANARY_SIZE"));
+pub const DEBUG_STACK_CANARY_SIZE_ENV: &str = "MIRALIS_DEBUG_STACK_CANARY_SIZE";
+
 /// Number of iteration for our benchmarks
 pub const BENCHMARK_NB_ITER: Option<usize> = parse_usize(option_env!("MIRALIS_BENCHMARK_NB_ITER"));
 pub const BENCHMARK_NB_ITER_ENV: &str = "MIRALIS_BENCHMARK_NB_ITER";
 
+/// Physical address of a page where benchmarks write their counters, instead of the console.
+pub const BENCHMARK_SHARED_PAGE: Option<usize> =
+    parse_usize(option_env!("MIRALIS_BENCHMARK_SHARED_PAGE"));
+pub const BENCHMARK_SHARED_PAGE_ENV: &str = "MIRALIS_BENCHMARK_SHARED_PAGE";
+
+/// Log the cycles spent in each scope of the trap handler as folded stacks on shutdown, one line
+/// per path of scopes.
+pub const BENCHMARK_FOLDED_STACKS: bool =
+    is_enabled_default_false!("MIRALIS_BENCHMARK_FOLDED_STACKS");
+pub const BENCHMARK_FOLDED_STACKS_ENV: &str = "MIRALIS_BENCHMARK_FOLDED_STACKS";
+
+/// Record the most recent traps, which are displayed in the crash report.
+pub const DEBUG_TRAP_HISTORY: bool = is_enabled_default_false!("MIRALIS_DEBUG_TRAP_HISTORY");
+pub const DEBUG_TRAP_HISTORY_ENV: &str = "MIRALIS_DEBUG_TRAP_HISTORY";
+
+/// Dump the initial firmware state before entering the firmware for the first time.
+pub const BREAK_ON_ENTRY: bool = is_enabled_default_false!("MIRALIS_BREAK_ON_ENTRY");
+pub const BREAK_ON_ENTRY_ENV: &str = "MIRALIS_BREAK_ON_ENTRY";
+
+/// Catch writes to the code of Miralis, including from Miralis itself.
+pub const DEBUG_PROTECT_READ_ONLY: bool =
+    is_enabled_default_false!("MIRALIS_DEBUG_PROTECT_READ_ONLY");
+pub const DEBUG_PROTECT_READ_ONLY_ENV: &str = "MIRALIS_DEBUG_PROTECT_READ_ONLY";
+
+/// Log every CSR write with the old and new values of the CSR.
+pub const TRACE_CSR: bool = is_enabled_default_false!("MIRALIS_TRACE_CSR");
+pub const TRACE_CSR_ENV: &str = "MIRALIS_TRACE_CSR";
+
+/// Log the disassembly of every instruction emulated by Miralis, with its PC and source operands.
+pub const TRACE_INSTRUCTIONS: bool = is_enabled_default_false!("MIRALIS_TRACE_INSTRUCTIONS");
+pub const TRACE_INSTRUCTIONS_ENV: &str = "MIRALIS_TRACE_INSTRUCTIONS";
+
 // —————————————————————————————————— vCPU —————————————————————————————————— //
 
 /// Maximum number of PMP exposed by the vCPU, no limit if None.
 pub const VCPU_MAX_PMP: Option<usize> = parse_usize(option_env!("MIRALIS_VCPU_MAX_PMP"));
 pub const VCPU_MAX_PMP_ENV: &str = "MIRALIS_VCPU_MAX_PMP";
 
+/// Number of implemented HPM counters (`mhpmcounter3` and up), at most 29.
+pub const VCPU_NB_HPM_COUNTERS: usize =
+    parse_usize_or(option_env!("MIRALIS_VCPU_NB_HPM_COUNTERS"), 29);
+pub const VCPU_NB_HPM_COUNTERS_ENV: &str = "MIRALIS_VCPU_NB_HPM_COUNTERS";
+
+/// Number of writable event selector bits in `mhpmeventN`, at most 56.
+pub const VCPU_HPM_EVENT_BITS: usize =
+    parse_usize_or(option_env!("MIRALIS_VCPU_HPM_EVENT_BITS"), 56);
+pub const VCPU_HPM_EVENT_BITS_ENV: &str = "MIRALIS_VCPU_HPM_EVENT_BITS";
+
+/// Emulate the atomic compare-and-swap instructions (Zacas) on cores that lack them.
+pub const VCPU_EMULATE_ZACAS: bool = is_enabled_default_false!("MIRALIS_VCPU_EMULATE_ZACAS");
+pub const VCPU_EMULATE_ZACAS_ENV: &str = "MIRALIS_VCPU_EMULATE_ZACAS";
+
+/// Emulate the fine-grained address-translation cache invalidation instructions (Svinval) on
+/// cores that lack them.
+pub const VCPU_EMULATE_SVINVAL: bool = is_enabled_default_false!("MIRALIS_VCPU_EMULATE_SVINVAL");
+pub const VCPU_EMULATE_SVINVAL_ENV: &str = "MIRALIS_VCPU_EMULATE_SVINVAL";
+
+/// Emulate the wait-on-reservation-set instructions (Zawrs) on cores that lack them.
+pub const VCPU_EMULATE_ZAWRS: bool = is_enabled_default_false!("MIRALIS_VCPU_EMULATE_ZAWRS");
+pub const VCPU_EMULATE_ZAWRS_ENV: &str = "MIRALIS_VCPU_EMULATE_ZAWRS";
+
+/// Emulate the conditional-zero instructions (Zicond) on cores that lack them.
+pub const VCPU_EMULATE_ZICOND: bool = is_enabled_default_false!("MIRALIS_VCPU_EMULATE_ZICOND");
+pub const VCPU_EMULATE_ZICOND_ENV: &str = "MIRALIS_VCPU_EMULATE_ZICOND";
+
+/// Serve the payload reads of `cycle`, `time` and `instret` from the virtual counters instead of
+/// exposing the hardware ones.
+pub const VCPU_VIRTUALIZE_ZICNTR: bool =
+    is_enabled_default_false!("MIRALIS_VCPU_VIRTUALIZE_ZICNTR");
+pub const VCPU_VIRTUALIZE_ZICNTR_ENV: &str = "MIRALIS_VCPU_VIRTUALIZE_ZICNTR";
+
+/// Maximum number of consecutive times the vCPU is resumed on spurious interrupts, before
+/// handling them as regular traps. Disabled (0) by default.
+pub const VCPU_MAX_TRANSIENT_RETRIES: usize =
+    parse_usize_or(option_env!("MIRALIS_VCPU_MAX_TRANSIENT_RETRIES"), 0);
+pub const VCPU_MAX_TRANSIENT_RETRIES_ENV: &str = "MIRALIS_VCPU_MAX_TRANSIENT_RETRIES";
+
 // ———————————————————————————————— Platform ———————————————————————————————— //
 
 /// The target platform
@@ -80,6 +185,17 @@ pub const PLATFORM_BOOT_HART_ID: usize =
     parse_usize_or(option_env!("MIRALIS_PLATFORM_BOOT_HART_ID"), 0);
 pub const PLATFORM_BOOT_HART_ID_ENV: &str = "MIRALIS_PLATFORM_BOOT_HART_ID";
 
+/// Exit with an error, rather than logging a warning, if the first hart to boot is not the boot
+/// hart
+pub const PLATFORM_STRICT_BOOT_HART: bool =
+    is_enabled_default_false!("MIRALIS_PLATFORM_STRICT_BOOT_HART");
+pub const PLATFORM_STRICT_BOOT_HART_ENV: &str = "MIRALIS_PLATFORM_STRICT_BOOT_HART";
+
+/// The virtual devices removed from the platform, by name.
+pub const DISABLED_DEVICES: &[&str; str_list_len(option_env!("MIRALIS_DISABLED_DEVICES"))] =
+    &parse_str_list(option_env!("MIRALIS_DISABLED_DEVICES"));
+pub const DISABLED_DEVICES_ENV: &str = "MIRALIS_DISABLED_DEVICES";
+
 // ————————————————————————————————— Target ————————————————————————————————— //
 
 /// Start address of Miralis
@@ -92,6 +208,11 @@ pub const TARGET_FIRMWARE_ADDRESS: usize =
     parse_usize_or(option_env!("MIRALIS_TARGET_FIRMWARE_ADDRESS"), 0x80200000);
 pub const TARGET_FIRMWARE_ADDRESS_ENV: &str = "MIRALIS_TARGET_FIRMWARE_ADDRESS";
 
+/// Required alignment of the firmware start address
+pub const TARGET_FIRMWARE_ALIGNMENT: usize =
+    parse_usize_or(option_env!("MIRALIS_TARGET_FIRMWARE_ALIGNMENT"), 0x1000);
+pub const TARGET_FIRMWARE_ALIGNMENT_ENV: &str = "MIRALIS_TARGET_FIRMWARE_ALIGNMENT";
+
 /// Start address of the payload
 pub const TARGET_PAYLOAD_ADDRESS: usize =
     parse_usize_or(option_env!("MIRALIS_TARGET_PAYLOAD_ADDRESS"), 0x80400000);
@@ -107,6 +228,14 @@ pub const TARGET_FIRMWARE_STACK_SIZE: usize =
     parse_usize_or(option_env!("MIRALIS_TARGET_FIRMWARE_STACK_SIZE"), 0x8000);
 pub const TARGET_FIRMWARE_STACK_SIZE_ENV: &str = "MIRALIS_TARGET_FIRMWARE_STACK_SIZE";
 
+/// Initial value of firmware registers, as (register index, value) pairs.
+///
+/// Those values are set on top of the default boot arguments (hart ID and device tree).
+pub const TARGET_FIRMWARE_ARGS: &[(usize, usize);
+     str_list_len(option_env!("MIRALIS_TARGET_FIRMWARE_ARGS"))] =
+    &parse_usize_pair_list(option_env!("MIRALIS_TARGET_FIRMWARE_ARGS"));
+pub const TARGET_FIRMWARE_ARGS_ENV: &str = "MIRALIS_TARGET_FIRMWARE_ARGS";
+
 /// The stack size for each payload thread (one per hart)
 pub const TARGET_PAYLOAD_STACK_SIZE: usize =
     parse_usize_or(option_env!("MIRALIS_TARGET_PAYLOAD_STACK_SIZE"), 0x8000);
@@ -118,3 +247,8 @@ pub const TARGET_PAYLOAD_STACK_SIZE_ENV: &str = "MIRALIS_TARGET_PAYLOAD_STACK_SI
 pub const MODULES: &[&str; str_list_len(option_env!("MIRALIS_MODULES"))] =
     &parse_str_list(option_env!("MIRALIS_MODULES"));
 pub const MODULES_ENV: &str = "MIRALIS_MODULES";
+
+/// Whether the protect payload policy validates the `satp` writes of the payload.
+pub const PROTECT_PAYLOAD_TRAP_SATP: bool =
+    is_enabled_default_false!("MIRALIS_PROTECT_PAYLOAD_TRAP_SATP");
+pub const PROTECT_PAYLOAD_TRAP_SATP_ENV: &str = "MIRALIS_PROTECT_PAYLOAD_TRAP_SATP";
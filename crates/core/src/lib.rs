@@ -24,6 +24,53 @@ pub mod abi {
     pub const MIRALIS_LOG_FID: usize = 2;
     /// Returns the performance counters managed by Miralis.
     pub const MIRALIS_READ_COUNTERS_FID: usize = 4;
+    /// Grant the payload an all-access PMP region until the next switch back to the firmware.
+    ///
+    /// Intended for test firmware, only the firmware can issue this call.
+    pub const MIRALIS_IDENTITY_MAP_FID: usize = 5;
+    /// Returns 1 if the SBI extension ID passed in a0 is handled by Miralis, 0 if it is left to
+    /// the firmware.
+    pub const MIRALIS_PROBE_SBI_FID: usize = 6;
+    /// Enable (a0 = 1) or disable (a0 = 0) single-stepping of the payload.
+    ///
+    /// While enabled, Miralis traps after each payload instruction and calls the `on_step` hook
+    /// of the policy modules.
+    pub const MIRALIS_ENABLE_SINGLESTEP_FID: usize = 7;
+    /// Zero the memory region starting at a0 and of a1 bytes.
+    ///
+    /// Only the firmware can issue this call. The region must not overlap Miralis nor the virtual
+    /// devices, and the policy modules can deny zeroing some regions.
+    pub const MIRALIS_ZERO_REGION_FID: usize = 8;
+    /// Returns the field a1 (see [audit]) of the audit log entry a0, 0 being the most recent
+    /// world switch.
+    ///
+    /// Only available when the audit policy is installed.
+    pub const MIRALIS_READ_AUDIT_LOG_FID: usize = 9;
+    /// Release the a0 highest-numbered virtual PMP entries back to Miralis.
+    ///
+    /// Only the firmware can issue this call. The released entries read as zero and ignore
+    /// writes from then on. The call fails if a0 exceeds the number of virtual PMP entries, or if
+    /// one of the released entries is locked.
+    pub const MIRALIS_RELEASE_PMP_FID: usize = 10;
+    /// Pause the benchmark counters of the calling hart, until `MIRALIS_BENCHMARK_RESUME_FID`.
+    ///
+    /// This lets benchmark firmware exclude its setup and teardown from the measurements. The
+    /// pause call itself is not counted, while the resume call is.
+    pub const MIRALIS_BENCHMARK_PAUSE_FID: usize = 11;
+    /// Resume the benchmark counters of the calling hart, see `MIRALIS_BENCHMARK_PAUSE_FID`.
+    pub const MIRALIS_BENCHMARK_RESUME_FID: usize = 12;
+
+    /// Fields of the audit log entries.
+    pub mod audit {
+        /// Value of `mtime` at the time of the world switch.
+        pub const TIME: usize = 0;
+        /// Cause of the trap that led to the world switch.
+        pub const CAUSE: usize = 1;
+        /// PC of the payload when switching.
+        pub const PC: usize = 2;
+        /// 0 for a switch from the firmware to the payload, 1 for the other direction.
+        pub const DIRECTION: usize = 3;
+    }
 
     /// Log level constants, with the same semantic as the `log` crate.
     pub mod log {
@@ -43,11 +90,40 @@ pub mod abi {
 pub mod sbi_codes {
 
     // SBI return codes used in Miralis
+    pub const SBI_ERR_NOT_SUPPORTED: usize = (-2_i64) as usize;
+    pub const SBI_ERR_INVALID_PARAM: usize = (-3_i64) as usize;
     pub const SBI_ERR_DENIED: usize = (-4_i64) as usize;
+    pub const SBI_ERR_ALREADY_AVAILABLE: usize = (-6_i64) as usize;
+    pub const SBI_ERR_ALREADY_STARTED: usize = (-7_i64) as usize;
 
     pub const SBI_SUCCESS: usize = 0x0;
 
     // SBI EIDs and FIDs
+    /// The base extension is designed to be as small as possible. As such, it only contains
+    /// functionality for probing which SBI extensions are available and for querying the version
+    /// of the SBI.
+    pub const SBI_BASE_EID: usize = 0x10;
+    /// Returns the current SBI specification version.
+    pub const GET_SPEC_VERSION_FID: usize = 0x0;
+    /// Returns the current SBI implementation ID, which is different for every SBI implementation.
+    pub const GET_IMPL_ID_FID: usize = 0x1;
+    /// Returns the current SBI implementation version.
+    pub const GET_IMPL_VERSION_FID: usize = 0x2;
+    /// Returns 0 if the given SBI extension ID is not available, or a non-zero value otherwise.
+    pub const PROBE_EXTENSION_FID: usize = 0x3;
+    /// Return a value that is legal for the mvendorid CSR.
+    pub const GET_MVENDORID_FID: usize = 0x4;
+    /// Return a value that is legal for the marchid CSR.
+    pub const GET_MARCHID_FID: usize = 0x5;
+    /// Return a value that is legal for the mimpid CSR.
+    pub const GET_MIMPID_FID: usize = 0x6;
+
+    /// The SBI specification version implemented by Miralis (v2.0), the major version is encoded
+    /// in bits [30:24] and the minor version in bits [23:0].
+    pub const SBI_SPEC_VERSION: usize = 2 << 24;
+    /// The SBI implementation ID returned by Miralis (ASCII "MRLS").
+    pub const MIRALIS_IMPL_ID: usize = 0x4d524c53;
+
     /// The debug console extension defines a generic mechanism for boot-time early prints.
     pub const SBI_DEBUG_CONSOLE_EXTENSION_EID: usize = 0x4442434E;
 
@@ -77,6 +153,37 @@ pub mod sbi_codes {
     /// virtual addresses between start and size.
     pub const REMOTE_FENCE_VMA_FID: usize = 0x1;
 
+    /// The Hart State Management (HSM) extension introduces a set of hart states and a set of
+    /// functions which allow the supervisor-mode software to request a hart state change.
+    pub const HSM_EXTENSION_EID: usize = 0x48534D;
+    /// Request the SBI implementation to start executing the target hart in supervisor-mode at
+    /// address specified by start_addr, with a1 set to opaque.
+    pub const HART_START_FID: usize = 0x0;
+    /// Request the SBI implementation to stop executing the calling hart in supervisor-mode and
+    /// return its ownership to the SBI implementation.
+    pub const HART_STOP_FID: usize = 0x1;
+    /// Get the current status (or HSM state id) of the given hart.
+    pub const HART_GET_STATUS_FID: usize = 0x2;
+    /// Request the SBI implementation to put the calling hart in a platform specific suspend (or
+    /// low power) state specified by the suspend_type parameter.
+    pub const HART_SUSPEND_FID: usize = 0x3;
+
+    /// HSM hart states, as returned by `hart_get_status`.
+    pub mod hsm {
+        pub const STARTED: usize = 0;
+        pub const STOPPED: usize = 1;
+        pub const START_PENDING: usize = 2;
+        pub const STOP_PENDING: usize = 3;
+        pub const SUSPENDED: usize = 4;
+        pub const SUSPEND_PENDING: usize = 5;
+        pub const RESUME_PENDING: usize = 6;
+
+        /// Default retentive suspend, the hart resumes after the `hart_suspend` call.
+        pub const SUSPEND_DEFAULT_RETENTIVE: usize = 0x0000_0000;
+        /// Default non-retentive suspend, the hart resumes at the provided address.
+        pub const SUSPEND_DEFAULT_NON_RETENTIVE: usize = 0x8000_0000;
+    }
+
     pub fn is_timer_request(fid: usize, eid: usize) -> bool {
         fid == SBI_TIMER_FID && eid == SBI_TIMER_EID
     }
@@ -93,3 +200,170 @@ pub mod sbi_codes {
         fid == REMOTE_FENCE_VMA_FID && eid == RFENCE_EXTENSION_EID
     }
 }
+
+// ——————————————————————————— Benchmark Definitions ———————————————————————————— //
+
+/// Binary layout of the benchmark counters, when written to a shared memory page.
+///
+/// The page is an array of little-endian `u64` words. It starts with a header of [HEADER_LEN]
+/// words, followed by `nb_rows * nb_columns` counter values stored in row-major order.
+///
+/// [HEADER_LEN]: benchmark::HEADER_LEN
+pub mod benchmark {
+    /// Magic value identifying a benchmark page.
+    pub const MAGIC: u64 = u64::from_le_bytes(*b"MRLSBNCH");
+    /// Version of the layout, to be bumped on any change.
+    pub const VERSION: u64 = 5;
+
+    /// Index of the magic value in the header.
+    pub const MAGIC_IDX: usize = 0;
+    /// Index of the layout version in the header.
+    pub const VERSION_IDX: usize = 1;
+    /// Index of the number of rows in the header.
+    pub const NB_ROWS_IDX: usize = 2;
+    /// Index of the number of columns in the header.
+    pub const NB_COLUMNS_IDX: usize = 3;
+    /// Number of words in the header.
+    pub const HEADER_LEN: usize = 4;
+
+    /// Size of the shared page, in bytes.
+    pub const PAGE_SIZE: usize = 0x1000;
+
+    /// Number of counters, i.e. of columns.
+    pub const NB_COUNTERS: usize = 13;
+
+    /// Name of the counters stored in each column.
+    pub const COUNTER_NAMES: [&str; NB_COUNTERS] = [
+        "no-offload",
+        "read-time",
+        "set-timer",
+        "misaligned-op",
+        "ipi",
+        "remote-fence",
+        "firmware-trap",
+        "page-fault",
+        "world-switch",
+        "world-switch-cycles",
+        "total-exits",
+        "guest-instructions",
+        "instructions-per-exit",
+    ];
+
+    /// Index of each counter, both as a column of the shared page and as the category passed to
+    /// `MIRALIS_READ_COUNTERS_FID`.
+    pub mod counters {
+        /// Payload traps forwarded to the firmware.
+        pub const NOT_OFFLOADED: usize = 0;
+        /// Time reads emulated on behalf of the payload.
+        pub const READ_TIME: usize = 1;
+        /// SBI timer requests handled on behalf of the payload.
+        pub const SET_TIMER: usize = 2;
+        /// Misaligned loads and stores emulated on behalf of the payload.
+        pub const MISALIGNED_OP: usize = 3;
+        /// SBI IPI requests handled on behalf of the payload.
+        pub const IPI: usize = 4;
+        /// SBI remote fence requests handled on behalf of the payload.
+        pub const REMOTE_FENCE: usize = 5;
+        /// Firmware traps.
+        pub const FIRMWARE_TRAP: usize = 6;
+        /// Payload page faults.
+        pub const PAGE_FAULT: usize = 7;
+        /// World switches from the payload to the firmware, each followed by a switch back. Same
+        /// as [NOT_OFFLOADED].
+        pub const WORLD_SWITCH: usize = 8;
+        /// Cycles spent in world switches, in both directions.
+        pub const WORLD_SWITCH_CYCLES: usize = 9;
+        /// All traps into Miralis.
+        pub const TOTAL_EXITS: usize = 10;
+        /// Instructions retired by the firmware and payload, including the context switch code of
+        /// Miralis.
+        pub const GUEST_INSTRUCTIONS: usize = 11;
+        /// Average number of guest instructions retired between two traps into Miralis.
+        pub const INSTRUCTIONS_PER_EXIT: usize = 12;
+    }
+}
+
+// —————————————————————————— Log Ring Definitions —————————————————————————— //
+
+/// Binary layout of the log ring buffer, when log records are written to a shared memory page.
+///
+/// The page starts with a header of [HEADER_LEN] little-endian `u64` words, followed by the ring
+/// buffer itself. Each record starts with a [RECORD_HEADER_LEN] bytes header holding the length
+/// of the message as a little-endian `u16` and the log level as a byte (see [abi::log]), followed
+/// by the message. Records are padded to [RECORD_ALIGN] bytes and never wrap around the end of the
+/// buffer: when a record does not fit, the remaining space is filled by a record of level
+/// [PADDING_LEVEL] and the record is written at the start of the buffer.
+///
+/// The header holds the total number of bytes written, from which readers derive the position of
+/// the writer. A reader falling behind by more than the buffer size lost records, and can resume
+/// from the start of the buffer.
+///
+/// [HEADER_LEN]: log_ring::HEADER_LEN
+/// [RECORD_HEADER_LEN]: log_ring::RECORD_HEADER_LEN
+/// [RECORD_ALIGN]: log_ring::RECORD_ALIGN
+/// [PADDING_LEVEL]: log_ring::PADDING_LEVEL
+pub mod log_ring {
+    /// Magic value identifying a log page.
+    pub const MAGIC: u64 = u64::from_le_bytes(*b"MRLSLOGS");
+    /// Version of the layout, to be bumped on any change.
+    pub const VERSION: u64 = 1;
+
+    /// Index of the magic value in the header.
+    pub const MAGIC_IDX: usize = 0;
+    /// Index of the layout version in the header.
+    pub const VERSION_IDX: usize = 1;
+    /// Index of the size of the ring buffer, in bytes, in the header.
+    pub const CAPACITY_IDX: usize = 2;
+    /// Index of the total number of bytes written to the ring buffer in the header.
+    pub const WRITTEN_IDX: usize = 3;
+    /// Number of words in the header.
+    pub const HEADER_LEN: usize = 4;
+
+    /// Size of the shared page, in bytes.
+    pub const PAGE_SIZE: usize = 0x1000;
+
+    /// Size of the header of each record, in bytes.
+    pub const RECORD_HEADER_LEN: usize = 4;
+    /// Alignment of the records, in bytes.
+    pub const RECORD_ALIGN: usize = 4;
+    /// Level of the records filling the end of the buffer, which must be skipped by readers.
+    pub const PADDING_LEVEL: u8 = 0;
+}
+
+// ————————————————————————————— PMP Definitions ———————————————————————————— //
+
+/// Number of PMP entries reserved by Miralis, shared with the runner to check that a
+/// configuration fits in the PMP entries of its platform before building it.
+///
+/// See `arch::pmp::pmplayout` in Miralis for the order of the entries.
+pub mod pmp {
+    /// Entries catching writes to the code of Miralis, if enabled.
+    pub const READ_ONLY: usize = 2;
+    /// Entry protecting Miralis.
+    pub const MIRALIS: usize = 1;
+    /// Entry protecting the log page, if logs are written to a shared page.
+    pub const LOG_PAGE: usize = 1;
+    /// Entry used to emulate the MPRV bit.
+    pub const MPRV_EMULATION: usize = 1;
+    /// Inactive entry, used to emulate TOR correctly in the firmware.
+    pub const INACTIVE_ENTRY: usize = 1;
+    /// Last entry, granting access to the whole memory.
+    pub const LAST_ENTRY: usize = 1;
+
+    /// Number of entries used by each module (see `Module::NUMBER_PMPS`), the other modules do
+    /// not use any.
+    pub mod modules {
+        /// Each enclave uses 2 entries because of TOR addressing.
+        pub const KEYSTONE: usize = 2;
+        pub const PROTECT_PAYLOAD: usize = 2;
+    }
+
+    /// Number of protected regions in the memory map of each platform (see
+    /// `Platform::NB_PROTECTED_REGIONS`), each of which is protected with one entry.
+    pub mod protected_regions {
+        pub const VIRT: usize = 0;
+        pub const VISIONFIVE2: usize = 0;
+        pub const PREMIERP550: usize = 0;
+        pub const MIRALIS: usize = 0;
+    }
+}
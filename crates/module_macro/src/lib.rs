@@ -52,17 +52,20 @@ pub fn build_modules(tokens: proc_macro::TokenStream) -> proc_macro::TokenStream
     });
 
     // Build the list of path
-    let paths = modules.iter().map(|mod_name| {
-        let Some(path) = select_macro.get(mod_name) else {
-            return syn::Error::new(
-                Span::call_site(),
-                format!("Could not find path for module '{}'", mod_name),
-            )
-            .into_compile_error();
-        };
+    let paths: Vec<_> = modules
+        .iter()
+        .map(|mod_name| {
+            let Some(path) = select_macro.get(mod_name) else {
+                return syn::Error::new(
+                    Span::call_site(),
+                    format!("Could not find path for module '{}'", mod_name),
+                )
+                .into_compile_error();
+            };
 
-        quote!(#path)
-    });
+            quote!(#path)
+        })
+        .collect();
 
     // Emit the actual code
     quote!(
@@ -74,6 +77,11 @@ pub fn build_modules(tokens: proc_macro::TokenStream) -> proc_macro::TokenStream
         impl #new_mod_name {
             /// The sum of PMP entries used by all modules selected at compile time.
             const TOTAL_PMPS: usize = #(#paths::NUMBER_PMPS +)* 0;
+
+            /// The name and number of PMP entries of each module selected at compile time, in
+            /// order.
+            const MODULE_PMPS: &'static [(&'static str, usize)] =
+                &[#((#paths::NAME, #paths::NUMBER_PMPS)),*];
         }
     )
     .into()
@@ -25,6 +25,18 @@ pub fn set_mtimecmp_deadline(delta: usize, hart: usize) {
     }
 }
 
+/// Write the mtimecmp deadline of the given hart
+pub fn write_mtimecmp(hart: usize, deadline: usize) {
+    let mtimecmp_ptr = (CLINT_BASE + MTIMECMP_OFFSET + 8 * hart) as *mut usize;
+    unsafe { mtimecmp_ptr.write_volatile(deadline) }
+}
+
+/// Read the mtimecmp deadline of the given hart
+pub fn read_mtimecmp(hart: usize) -> usize {
+    let mtimecmp_ptr = (CLINT_BASE + MTIMECMP_OFFSET + 8 * hart) as *const usize;
+    unsafe { mtimecmp_ptr.read_volatile() }
+}
+
 /// Send an MSI to the given hart.
 pub fn send_msi(hart: usize) {
     let msip_ptr = (CLINT_BASE + 4 * hart) as *mut u32;
@@ -0,0 +1,117 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+
+use miralis_abi::{identity_map, read_counters, setup_binary, success};
+
+setup_binary!(main);
+
+/// Number of round trips between the firmware and the OS.
+const NB_ROUND_TRIPS: usize = 10;
+
+/// Number of iterations of the OS loop, each iteration retires two instructions.
+const NB_LOOP_ITERATIONS: usize = 1000;
+
+/// An upper bound on the instructions retired by the guest between two exits outside of the OS
+/// loop, including the context switch code of Miralis.
+const MAX_INSTRUCTIONS_PER_EXIT: usize = 1000;
+
+/// This benchmark verifies that the average number of guest instructions between exits is
+/// plausible.
+///
+/// Specifically, the test checks:
+/// 1. The instructions retired by the OS between two traps are accounted for.
+/// 2. The average reported by Miralis is consistent with the other counters.
+fn main() -> ! {
+    let before = read_counters(0);
+    for _ in 0..NB_ROUND_TRIPS {
+        round_trip();
+    }
+    let after = read_counters(0);
+
+    let instructions = after.guest_instructions - before.guest_instructions;
+    let exits = after.total_exits - before.total_exits;
+    let loop_instructions = NB_ROUND_TRIPS * 2 * NB_LOOP_ITERATIONS;
+    log::info!(
+        "{} guest instructions over {} exits, {} instructions per exit",
+        instructions,
+        exits,
+        instructions / exits
+    );
+
+    assert!(
+        instructions >= loop_instructions,
+        "The OS loop must be counted as guest instructions"
+    );
+    assert!(
+        instructions <= loop_instructions + exits * MAX_INSTRUCTIONS_PER_EXIT,
+        "Miralis must not be counted as guest instructions"
+    );
+
+    // The counters are read one at a time, so the average is computed over slightly more exits
+    let average = after.guest_instructions / after.total_exits;
+    assert!(
+        after.instructions_per_exit >= average / 2 && after.instructions_per_exit <= average * 2,
+        "Inconsistent average number of instructions per exit"
+    );
+
+    success();
+}
+
+/// Jump into the OS and come back once it completed its loop.
+fn round_trip() {
+    // The identity map is revoked each time the OS traps back
+    identity_map();
+
+    let os: usize = _raw_os as usize;
+    let trap: usize = _raw_trap_handler as usize;
+    let mpp = 0b1 << 11; // MPP = S-mode
+
+    unsafe {
+        asm!(
+            "auipc t4, 0",
+            "addi t4, t4, 24",
+            "csrw mtvec, {mtvec}", // Write mtvec with trap handler
+            "csrw mstatus, {mpp}", // Write MPP of mstatus to S-mode
+            "csrw mepc, {os}",     // Write MEPC
+            "mret",                // Jump to OS
+            os = in(reg) os,
+            mtvec = in(reg) trap,
+            mpp = in(reg) mpp,
+            out("t4") _,
+            inout("a0") NB_LOOP_ITERATIONS => _,
+        );
+    }
+}
+
+// —————————————————————————————— Trap Handler —————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_trap_handler
+_raw_trap_handler:
+    jr t4
+"#,
+);
+
+// ———————————————————————————————— Guest OS ———————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_os
+_raw_os:
+    addi a0, a0, -1
+    bnez a0, _raw_os
+    ecall
+"#,
+);
+
+unsafe extern "C" {
+    fn _raw_trap_handler();
+    fn _raw_os();
+}
@@ -0,0 +1,98 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+
+use miralis_abi::{
+    identity_map, pause_benchmark, read_counters, resume_benchmark, setup_binary, success,
+};
+
+setup_binary!(main);
+
+/// Number of round trips between the firmware and the OS during the setup.
+const NB_SETUP_ROUND_TRIPS: usize = 10;
+
+/// This test verifies that the benchmark counters can be paused around a region.
+///
+/// Specifically, the test checks:
+/// 1. The world switches performed while the benchmark is paused are not counted.
+/// 2. Once resumed, the world switches are counted again.
+fn main() -> ! {
+    let before = read_counters(0).world_switches;
+
+    // Setup, excluded from the measurements
+    pause_benchmark();
+    for _ in 0..NB_SETUP_ROUND_TRIPS {
+        round_trip();
+    }
+    resume_benchmark();
+    assert_eq!(
+        read_counters(0).world_switches,
+        before,
+        "World switches must not be counted while paused"
+    );
+
+    // Region of interest
+    round_trip();
+    assert_eq!(
+        read_counters(0).world_switches,
+        before + 2,
+        "World switches must be counted once resumed"
+    );
+
+    success();
+}
+
+/// Jump into the OS and come back, causing two world switches.
+fn round_trip() {
+    // The identity map is revoked each time the OS traps back
+    identity_map();
+
+    let os: usize = _raw_os as usize;
+    let trap: usize = _raw_trap_handler as usize;
+    let mpp = 0b1 << 11; // MPP = S-mode
+
+    unsafe {
+        asm!(
+            "auipc t4, 0",
+            "addi t4, t4, 24",
+            "csrw mtvec, {mtvec}", // Write mtvec with trap handler
+            "csrw mstatus, {mpp}", // Write MPP of mstatus to S-mode
+            "csrw mepc, {os}",     // Write MEPC
+            "mret",                // Jump to OS
+            os = in(reg) os,
+            mtvec = in(reg) trap,
+            mpp = in(reg) mpp,
+            out("t4") _,
+        );
+    }
+}
+
+// —————————————————————————————— Trap Handler —————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_trap_handler
+_raw_trap_handler:
+    jr t4
+"#,
+);
+
+// ———————————————————————————————— Guest OS ———————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_os
+_raw_os:
+    ecall
+"#,
+);
+
+unsafe extern "C" {
+    fn _raw_trap_handler();
+    fn _raw_os();
+}
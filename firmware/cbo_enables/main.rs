@@ -0,0 +1,123 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+
+use miralis_abi::{setup_binary, success};
+
+setup_binary!(main);
+
+/// The CBIE field of `menvcfg` and `senvcfg`.
+const CBIE_OFFSET: usize = 4;
+const CBIE_FILTER: usize = 0b11 << CBIE_OFFSET;
+const CBIE_ILLEGAL: usize = 0b00 << CBIE_OFFSET;
+const CBIE_INVAL: usize = 0b11 << CBIE_OFFSET;
+
+/// The MPP field of `mstatus`.
+const MPP_FILTER: usize = 0b11 << 11;
+
+const ILLEGAL_INSTR: usize = 2;
+const ECALL_FROM_U_MODE: usize = 8;
+
+/// A cache block to invalidate.
+#[repr(align(64))]
+struct CacheBlock([u8; 64]);
+static CACHE_BLOCK: CacheBlock = CacheBlock([0; 64]);
+
+/// This test verifies that `senvcfg.CBIE` controls `cbo.inval` in U-mode.
+///
+/// Specifically, the test checks:
+/// 1. A U-mode `cbo.inval` raises an illegal instruction when `senvcfg.CBIE` is disabled, even if
+///    `menvcfg.CBIE` is enabled.
+/// 2. A U-mode `cbo.inval` executes when both `menvcfg.CBIE` and `senvcfg.CBIE` are enabled.
+fn main() -> ! {
+    // Enable cbo.inval for S-mode, if Zicbom is supported
+    let menvcfg: usize;
+    unsafe {
+        asm!(
+            "csrs 0x30A, {cbie}",
+            "csrr {menvcfg}, 0x30A",
+            cbie = in(reg) CBIE_INVAL,
+            menvcfg = out(reg) menvcfg,
+        );
+    }
+    if menvcfg & CBIE_FILTER != CBIE_INVAL {
+        log::info!("Zicbom is not supported, skipping test");
+        success();
+    }
+
+    assert_eq!(
+        cbo_inval_from_u_mode(CBIE_ILLEGAL),
+        ILLEGAL_INSTR,
+        "cbo.inval must trap when disabled in senvcfg"
+    );
+    assert_eq!(
+        cbo_inval_from_u_mode(CBIE_INVAL),
+        ECALL_FROM_U_MODE,
+        "cbo.inval must execute when enabled in senvcfg"
+    );
+
+    success();
+}
+
+/// Execute `cbo.inval` from U-mode with the given `senvcfg` and returns the cause of the trap back
+/// to the firmware.
+///
+/// The U-mode code issues an ecall right after `cbo.inval`, the cause is therefore an ecall from
+/// U-mode if `cbo.inval` executed successfully.
+fn cbo_inval_from_u_mode(senvcfg: usize) -> usize {
+    let mcause: usize;
+    unsafe {
+        asm!(
+            "li t4, 0xfffffffff",
+            "csrw pmpcfg0, 0xf",   // XRW TOR
+            "csrw pmpaddr0, t4",   // All memory
+            "csrw 0x10A, {senvcfg}",
+            "la t4, 1f",           // The trap handler returns to the end of this snippet
+            "csrw mtvec, {mtvec}",
+            "csrc mstatus, {mpp}", // Set MPP to U-mode
+            "csrw mepc, {user}",
+            "mret",
+            "1:",
+            "csrr {mcause}, mcause",
+            senvcfg = in(reg) senvcfg,
+            mtvec = in(reg) _raw_trap_handler as usize,
+            mpp = in(reg) MPP_FILTER,
+            user = in(reg) _raw_user_code as usize,
+            mcause = out(reg) mcause,
+            in("a0") &raw const CACHE_BLOCK as usize,
+            out("t4") _,
+        );
+    }
+    mcause
+}
+
+// —————————————————————————————— Trap Handler —————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_trap_handler
+_raw_trap_handler:
+    jr t4
+"#,
+);
+
+// ——————————————————————————————— User Code ———————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_user_code
+_raw_user_code:
+    .word 0x0005200f // cbo.inval (a0)
+    ecall
+"#,
+);
+
+unsafe extern "C" {
+    fn _raw_trap_handler();
+    fn _raw_user_code();
+}
@@ -0,0 +1,98 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+
+use miralis_abi::{setup_binary, success};
+use test_helpers::clint;
+
+setup_binary!(main);
+
+/// The hart expected to receive the timer interrupt.
+const TIMER_HART: usize = 1;
+
+/// This test verifies that each hart has its own virtual `mtimecmp` register.
+///
+/// Specifically, the test checks:
+/// 1. Each hart can program its own `mtimecmp` without affecting the other hart's one.
+/// 2. Only the hart with a passed deadline receives the Machine Timer Interrupt (MTI).
+fn main() -> ! {
+    let hart_id: usize;
+    unsafe {
+        asm!(
+            "csrr {0}, mhartid",
+            "csrw mtvec, {handler}",
+            out(reg) hart_id,
+            handler = in(reg) _raw_interrupt_trap_handler as usize,
+        );
+    }
+
+    assert!(hart_id < 2, "Expected only 2 harts for this test");
+
+    // Program a deadline that never fires on hart 0, and a short one on hart 1
+    let deadline = match hart_id {
+        TIMER_HART => clint::read_mtime() + 10000,
+        _ => usize::MAX,
+    };
+    clint::write_mtimecmp(hart_id, deadline);
+    assert_eq!(
+        clint::read_mtimecmp(hart_id),
+        deadline,
+        "mtimecmp does not hold the programmed deadline"
+    );
+
+    unsafe {
+        asm!(
+            "csrs mie, {mtie}",             // Enable machine timer interrupt (MTIE)
+            "csrs mstatus, {mstatus_mie}",  // Enable interrupts (MIE)
+            mtie = in(reg) 0x80,
+            mstatus_mie = in(reg) 0x8,
+        );
+    }
+
+    // Wait for the timer interrupt (or for the other hart to exit)
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+// —————————————————————————————— Trap Handler —————————————————————————————— //
+
+/// This function should be called from the raw trap handler
+extern "C" fn trap_handler() {
+    let mcause: usize;
+    let hart_id: usize;
+    unsafe {
+        asm!(
+            "csrr {0}, mcause",
+            "csrr {1}, mhartid",
+            out(reg) mcause,
+            out(reg) hart_id,
+        );
+    }
+
+    assert_eq!(
+        mcause, 0x8000000000000007,
+        "Expected a machine timer interrupt"
+    );
+    assert_eq!(
+        hart_id, TIMER_HART,
+        "Timer interrupt received on the wrong hart"
+    );
+    success();
+}
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_interrupt_trap_handler
+_raw_interrupt_trap_handler:
+    j {trap_handler} // Jump immediately into the Rust trap handler
+"#,
+    trap_handler = sym trap_handler,
+);
+
+unsafe extern "C" {
+    fn _raw_interrupt_trap_handler();
+}
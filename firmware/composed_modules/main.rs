@@ -0,0 +1,97 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+
+use miralis_abi::{identity_map, read_audit_log, read_counters, setup_binary, success};
+use miralis_core::abi::audit;
+
+setup_binary!(main);
+
+/// Number of round trips between the firmware and the OS.
+const NB_ROUND_TRIPS: usize = 10;
+
+/// Cause of the ecall from the OS.
+const ECALL_FROM_S_MODE: usize = 9;
+
+fn main() -> ! {
+    for _ in 0..NB_ROUND_TRIPS {
+        round_trip();
+    }
+
+    // The `exit_counter` module observed the world switches...
+    let counters = read_counters(0);
+    assert!(
+        counters.world_switches >= 2 * NB_ROUND_TRIPS,
+        "Expected at least {} world switches, got {}",
+        2 * NB_ROUND_TRIPS,
+        counters.world_switches
+    );
+
+    // ...and so did the `audit` module, the most recent switch being the ecall of the OS
+    for index in 0..2 * NB_ROUND_TRIPS {
+        let direction = read_audit_log(index, audit::DIRECTION).expect("Missing audit entry");
+        assert_eq!(direction, (index + 1) % 2, "Invalid switch direction");
+    }
+    assert_eq!(
+        read_audit_log(0, audit::CAUSE).expect("Missing audit entry"),
+        ECALL_FROM_S_MODE
+    );
+
+    log::info!("Both modules received the world switches");
+    success();
+}
+
+/// Jump into the OS and come back, causing two world switches.
+fn round_trip() {
+    // The identity map is revoked each time the OS traps back
+    identity_map();
+
+    let os: usize = _raw_os as usize;
+    let trap: usize = _raw_trap_handler as usize;
+    let mpp = 0b1 << 11; // MPP = S-mode
+
+    unsafe {
+        asm!(
+            "auipc t4, 0",
+            "addi t4, t4, 24",
+            "csrw mtvec, {mtvec}", // Write mtvec with trap handler
+            "csrw mstatus, {mpp}", // Write MPP of mstatus to S-mode
+            "csrw mepc, {os}",     // Write MEPC
+            "mret",                // Jump to OS
+            os = in(reg) os,
+            mtvec = in(reg) trap,
+            mpp = in(reg) mpp,
+            out("t4") _,
+        );
+    }
+}
+
+// —————————————————————————————— Trap Handler —————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_trap_handler
+_raw_trap_handler:
+    jr t4
+"#,
+);
+
+// ———————————————————————————————— Guest OS ———————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_os
+_raw_os:
+    ecall
+"#,
+);
+
+unsafe extern "C" {
+    fn _raw_trap_handler();
+    fn _raw_os();
+}
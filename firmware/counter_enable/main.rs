@@ -0,0 +1,93 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+
+use miralis_abi::{failure, setup_binary};
+
+setup_binary!(main);
+
+/// Illegal instruction and U-mode ecall exceptions.
+const MEDELEG: usize = (1 << 2) | (1 << 8);
+
+/// This test verifies that U-mode reads of `time` disabled by `scounteren` are delivered to S-mode.
+///
+/// Specifically, the test checks:
+/// 1. A U-mode read of `time` with `scounteren.TM` cleared raises an illegal instruction.
+/// 2. The trap is delegated to the S-mode handler, with `sepc` pointing to the faulting read.
+fn main() -> ! {
+    // Jump into the OS, which exits through the Miralis ABI
+    let os: usize = _raw_os as usize;
+    let mpp: usize = 0b1 << 11; // MPP = S-mode
+    unsafe {
+        asm!(
+            "li t4, 0xfffffffff",
+            "csrw pmpcfg0, 0xf",          // XRW TOR
+            "csrw pmpaddr0, t4",          // All memory
+            "csrw mcounteren, 0",         // S-mode time reads are emulated by the offload policy
+            "csrw medeleg, {medeleg}",    // Delegate illegal instructions and U-mode ecalls
+            "csrw mstatus, {mpp}",        // Write MPP of mstatus to S-mode
+            "csrw mepc, {os}",            // Write MEPC
+            "mret",                       // Jump to OS
+            medeleg = in(reg) MEDELEG,
+            mpp = in(reg) mpp,
+            os = in(reg) os,
+            out("t4") _,
+        );
+    }
+    failure()
+}
+
+// ———————————————————————————————— Guest OS ———————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_os
+_raw_os:
+    // Install the trap handler and disable time reads from U-mode
+    la t0, _s_trap_handler
+    csrw stvec, t0
+    csrw scounteren, zero
+
+    // Jump to U-mode
+    li t0, 1 << 8
+    csrc sstatus, t0   // SPP = U-mode
+    la t0, _user
+    csrw sepc, t0
+    sret
+
+.align 4
+_s_trap_handler:
+    // The trap must be an illegal instruction on the time read
+    csrr t0, scause
+    li t1, 2
+    bne t0, t1, 1f
+    csrr t0, sepc
+    la t1, _time_read
+    bne t0, t1, 1f
+
+    li a6, 1           // Miralis ABI FID: success
+    li a7, 0x08475bcd  // Miralis ABI EID
+    ecall
+1:
+    li a6, 0           // Miralis ABI FID: failure
+    li a7, 0x08475bcd  // Miralis ABI EID
+    ecall
+
+// ——————————————————————————————— User Space ——————————————————————————————— //
+
+.align 4
+_user:
+_time_read:
+    csrr t0, time
+
+    // The read should have trapped, report the failure to the OS
+    ecall
+"#,
+);
+
+unsafe extern "C" {
+    fn _raw_os();
+}
@@ -0,0 +1,104 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+
+use miralis_abi::{failure, setup_binary, success};
+
+setup_binary!(main);
+
+/// The local counter overflow interrupt (LCOFI) bit in `mie` and `mip`.
+const LCOFI: usize = 1 << 13;
+
+/// The overflow bit in `mhpmevent`.
+const MHPMEVENT_OF: usize = 1 << 63;
+
+/// The virtual event counting the traps emulated by Miralis.
+const FIRMWARE_TRAP_EVENT: usize = 1;
+
+#[allow(unreachable_code)]
+fn main() -> ! {
+    // LCOFIE is writable only if the Sscofpmf extension is implemented
+    let mie: usize;
+    unsafe {
+        asm!(
+            "csrs mie, {lcofi}",
+            "csrr {mie}, mie",
+            "csrc mie, {lcofi}",
+            lcofi = in(reg) LCOFI,
+            mie = out(reg) mie,
+        );
+    }
+    if mie & LCOFI == 0 {
+        log::info!("Sscofpmf is not supported, skipping test");
+        success();
+    }
+
+    // Configure mhpmcounter3 to overflow on the next trap, then enable the interrupt
+    unsafe {
+        asm!(
+            "csrw mtvec, {handler}",
+            "csrw mhpmcounter3, {counter}",
+            "csrw mhpmevent3, {event}",
+            "csrs mie, {lcofi}",
+            "csrs mstatus, {mstatus_mie}",
+            handler = in(reg) _raw_interrupt_trap_handler as usize,
+            counter = in(reg) usize::MAX,
+            event = in(reg) FIRMWARE_TRAP_EVENT,
+            lcofi = in(reg) LCOFI,
+            mstatus_mie = in(reg) 0x8,
+        );
+    }
+
+    // Any emulated instruction should now trigger the overflow
+    loop {
+        unsafe { asm!("csrr {0}, mscratch", out(reg) _) };
+    }
+
+    // The trap handler should exit, if we reach that point the handler did not do its job
+    failure();
+}
+
+/// This function should be called from the raw trap handler
+extern "C" fn trap_handler() {
+    let mcause: usize;
+    let mip: usize;
+    let mhpmevent: usize;
+    unsafe {
+        asm!(
+            "csrr {mcause}, mcause",
+            "csrr {mip}, mip",
+            "csrr {mhpmevent}, mhpmevent3",
+            mcause = out(reg) mcause,
+            mip = out(reg) mip,
+            mhpmevent = out(reg) mhpmevent,
+        );
+    }
+
+    assert_eq!(
+        mcause, 0x800000000000000d,
+        "Expected a local counter overflow interrupt"
+    );
+    assert!(mip & LCOFI != 0, "LCOFIP flag is not set");
+    assert!(mhpmevent & MHPMEVENT_OF != 0, "Overflow bit is not set");
+
+    log::debug!("Done!");
+    success();
+}
+
+// —————————————————————————————— Trap Handler —————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_interrupt_trap_handler
+_raw_interrupt_trap_handler:
+    j {trap_handler} // Jump immediately into the Rust trap handler
+"#,
+    trap_handler = sym trap_handler,
+);
+
+unsafe extern "C" {
+    fn _raw_interrupt_trap_handler();
+}
@@ -1,7 +1,7 @@
 #![no_std]
 #![no_main]
 
-use core::arch::asm;
+use core::arch::{asm, global_asm};
 
 use miralis_abi::{setup_binary, success};
 
@@ -27,6 +27,12 @@ fn main() -> ! {
     test_mconfigptr();
     log::debug!("Testing menvcfg registers");
     test_menvcfg();
+    log::debug!("Testing debug context registers");
+    test_debug_context();
+    log::debug!("Testing mtvec legalization");
+    test_mtvec();
+    log::debug!("Testing read-only CSRs");
+    test_read_only_csr();
     log::debug!("Testing performance counters");
     test_perf_counters();
     log::debug!("Done!");
@@ -367,14 +373,156 @@ fn test_menvcfg() {
     }
     assert_eq!(res, target_val);
 
+    // Only the RLB bit sticks, the other bits are reserved
     unsafe {
         asm!(
-            "li {0}, 0x42",
+            "li {0}, 0x44",
             "csrw mseccfg, {0}",
             "csrr {1}, mseccfg",
+            "csrw mseccfg, zero",
             out(reg) _,
             out(reg) res,
         );
     }
+    assert_eq!(res, 0x4);
+}
+
+// ————————————————————————— Debug Context registers ———————————————————————— //
+
+fn test_debug_context() {
+    let mut res: usize;
+    unsafe {
+        asm!(
+            "csrw mcontext, {val}",
+            "csrr {res}, mcontext",
+            val = in(reg) 0x42,
+            res = out(reg) res,
+        );
+    }
     assert_eq!(res, 0x42);
+
+    // Only the low bits of mcontext are implemented
+    unsafe {
+        asm!(
+            "csrw mcontext, {val}",
+            "csrr {res}, mcontext",
+            val = in(reg) usize::MAX,
+            res = out(reg) res,
+        );
+    }
+    assert!(
+        res < (1 << 14),
+        "mcontext upper bits must be read-only zero"
+    );
+
+    unsafe {
+        asm!(
+            "csrw scontext, {val}",
+            "csrr {res}, scontext",
+            val = in(reg) 0x1234,
+            res = out(reg) res,
+        );
+    }
+    assert_eq!(res, 0x1234);
+}
+
+// ————————————————————————————————— Mtvec —————————————————————————————————— //
+
+fn test_mtvec() {
+    let mut res: usize;
+
+    // In vectored mode the base is kept 4-byte aligned, the low bits encode the mode
+    unsafe {
+        asm!(
+            "csrr {prev}, mtvec",
+            "csrw mtvec, {val}",
+            "csrr {res}, mtvec",
+            "csrw mtvec, {prev}",
+            val = in(reg) 0x80001235usize,
+            prev = out(reg) _,
+            res = out(reg) res,
+        );
+    }
+    assert_eq!(res, 0x80001235, "Invalid vectored mtvec");
+    assert_eq!(res & !0b11, 0x80001234, "mtvec base must be 4-byte aligned");
+
+    // Writing a reserved mode preserves the previous mode
+    unsafe {
+        asm!(
+            "csrr {prev}, mtvec",
+            "csrw mtvec, {vectored}",
+            "csrw mtvec, {reserved}",
+            "csrr {res}, mtvec",
+            "csrw mtvec, {prev}",
+            vectored = in(reg) 0x80001235usize,
+            reserved = in(reg) 0x80002003usize,
+            prev = out(reg) _,
+            res = out(reg) res,
+        );
+    }
+    assert_eq!(res, 0x80002001, "Reserved mtvec mode must be ignored");
+}
+
+// ———————————————————————————— Read-Only CSRs ————————————————————————————— //
+
+/// Test that `csrrs` with x0 as source is a pure read, while it is an illegal write otherwise.
+fn test_read_only_csr() {
+    let res: usize;
+    let trapped: usize;
+    unsafe {
+        asm!(
+            "csrr {prev}, mtvec",
+            "csrw mtvec, {handler}",
+            "li t5, 0",
+            "csrrs {res}, mvendorid, x0",
+            "csrw mtvec, {prev}",
+            handler = in(reg) _raw_illegal_instr_trap_handler as usize,
+            prev = out(reg) _,
+            res = out(reg) res,
+            out("t5") trapped,
+            out("t6") _,
+        );
+    }
+    assert_eq!(res, 0, "Invalid mvendorid");
+    assert_eq!(trapped, 0, "Reading a read-only CSR must not trap");
+
+    let trapped: usize;
+    let mcause: usize;
+    unsafe {
+        asm!(
+            "csrr {prev}, mtvec",
+            "csrw mtvec, {handler}",
+            "li t5, 0",
+            "csrrs x0, mvendorid, {mask}",
+            "csrr {mcause}, mcause",
+            "csrw mtvec, {prev}",
+            handler = in(reg) _raw_illegal_instr_trap_handler as usize,
+            mask = in(reg) 0x1,
+            prev = out(reg) _,
+            mcause = out(reg) mcause,
+            out("t5") trapped,
+            out("t6") _,
+        );
+    }
+    assert_eq!(trapped, 1, "Writing a read-only CSR must trap");
+    assert_eq!(mcause, 2, "Expected an illegal instruction");
+}
+
+// Skip the faulting instruction and set t5 to signal that a trap occurred.
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_illegal_instr_trap_handler
+_raw_illegal_instr_trap_handler:
+    csrr t6, mepc  // Read EPC
+    addi t6, t6, 4 // Increment return pointer
+    csrw mepc, t6  // Write it back
+    li t5, 1       // Signal the trap
+    mret
+"#,
+);
+
+unsafe extern "C" {
+    fn _raw_illegal_instr_trap_handler();
 }
@@ -1,8 +1,7 @@
 use core::arch::asm;
 
 pub fn test_perf_counters() {
-    // For now we expose 0 performance counters to the firmware, but we might expose more in the
-    // future.
+    // The HPM counters are virtualized by Miralis, they only count events emulated by Miralis.
     test_simple_regs();
     test_some_counters_events();
 }
@@ -73,7 +72,7 @@ fn test_some_counters_events() {
         );
     }
 
-    assert_eq!(res, 0);
+    assert_eq!(res, 0x42);
 
     // Test mhpmcounter5
     unsafe {
@@ -86,7 +85,7 @@ fn test_some_counters_events() {
         );
     }
 
-    assert_eq!(res, 0);
+    assert_eq!(res, 0x42);
 
     // Test mhpmcounter7
     unsafe {
@@ -99,7 +98,7 @@ fn test_some_counters_events() {
         );
     }
 
-    assert_eq!(res, 0);
+    assert_eq!(res, 0x42);
 
     // Test mhpmevent3
     unsafe {
@@ -112,7 +111,7 @@ fn test_some_counters_events() {
         );
     }
 
-    assert_eq!(res, 0);
+    assert_eq!(res, 0x42);
 
     // Test mhpmevent5
     unsafe {
@@ -125,7 +124,7 @@ fn test_some_counters_events() {
         );
     }
 
-    assert_eq!(res, 0);
+    assert_eq!(res, 0x42);
 
     // Test mhpmevent7
     unsafe {
@@ -138,5 +137,5 @@ fn test_some_counters_events() {
         );
     }
 
-    assert_eq!(res, 0);
+    assert_eq!(res, 0x42);
 }
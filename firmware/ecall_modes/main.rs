@@ -0,0 +1,152 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+
+use miralis_abi::{identity_map, setup_binary, success};
+use miralis_core::{abi, sbi_codes};
+
+setup_binary!(main);
+
+/// An extension ID implemented neither by Miralis nor by this firmware.
+const UNKNOWN_EID: usize = 0x1234;
+
+/// The SPP field of `sstatus`.
+const SPP_FILTER: usize = 1 << 8;
+
+const ECALL_FROM_U_MODE: usize = 8;
+const ECALL_FROM_S_MODE: usize = 9;
+const ECALL_FROM_M_MODE: usize = 11;
+
+/// This test verifies that ecalls are dispatched according to the mode they come from.
+///
+/// Specifically, the test checks:
+/// 1. Ecalls from the firmware not targeting Miralis trap to the firmware as ecalls from M-mode.
+/// 2. Ecalls from S-mode to the SBI base extension are served by Miralis and return right after
+///    the ecall, other ecalls are forwarded to the firmware.
+/// 3. Ecalls from U-mode are forwarded to the firmware, even when targeting the Miralis ABI.
+///
+/// In all cases `mepc` points to the ecall, it is the job of the firmware to skip it.
+fn main() -> ! {
+    let trap: usize = _raw_trap_handler as usize;
+    let os: usize = _raw_os as usize;
+    let os_sbi: usize = _raw_os_sbi as usize;
+
+    // 1. Ecall from M-mode
+    let mcause: usize;
+    let mepc: usize;
+    let ecall: usize;
+    unsafe {
+        asm!(
+            "csrw mtvec, {mtvec}", // Write mtvec with trap handler
+            "auipc t4, 0",
+            "addi t4, t4, 12",
+            "ecall",               // Trap to our own handler
+            "csrr {mcause}, mcause",
+            "csrr {mepc}, mepc",
+            mtvec = in(reg) trap,
+            mcause = out(reg) mcause,
+            mepc = out(reg) mepc,
+            out("t4") ecall,
+            in("a7") UNKNOWN_EID,
+        );
+    }
+    assert_eq!(mcause, ECALL_FROM_M_MODE, "Invalid mcause for M-mode ecall");
+    assert_eq!(mepc, ecall - 4, "mepc must point to the M-mode ecall");
+
+    // 2. Ecalls from S-mode, the first one is served by Miralis
+    let (mcause, mepc, ret) = run_os(os_sbi, SPP_FILTER, sbi_codes::SBI_BASE_EID, 0);
+    assert_eq!(ret, sbi_codes::SBI_SUCCESS, "The SBI base call failed");
+    assert_eq!(mcause, ECALL_FROM_S_MODE, "Invalid mcause for S-mode ecall");
+    assert_eq!(
+        mepc,
+        os_sbi + 8,
+        "mepc must point to the second S-mode ecall"
+    );
+
+    // 3. Ecall from U-mode, Miralis must not terminate the execution with a failure
+    let (mcause, mepc, _) = run_os(os, 0, abi::MIRALIS_EID, abi::MIRALIS_FAILURE_FID);
+    assert_eq!(mcause, ECALL_FROM_U_MODE, "Invalid mcause for U-mode ecall");
+    assert_eq!(mepc, os, "mepc must point to the U-mode ecall");
+
+    success();
+}
+
+/// Jumps into the OS at `entry` in the mode selected by `spp`, with `a7` and `a6` set to the
+/// provided EID and FID.
+///
+/// Returns `mcause`, `mepc` and `a0` once the OS traps back.
+fn run_os(entry: usize, spp: usize, eid: usize, fid: usize) -> (usize, usize, usize) {
+    let trap: usize = _raw_trap_handler as usize;
+    let mcause: usize;
+    let mepc: usize;
+    let ret: usize;
+
+    // The OS is allowed to access all memory until it traps back
+    identity_map();
+
+    unsafe {
+        asm!(
+            "csrw mtvec, {mtvec}",   // Write mtvec with trap handler
+            "csrc sstatus, {spp_filter}",
+            "csrs sstatus, {spp}",   // Select S or U-mode
+            "csrw sepc, {entry}",
+            "auipc t4, 0",
+            "addi t4, t4, 12",
+            "sret",                  // Jump to OS
+            "csrr {mcause}, mcause",
+            "csrr {mepc}, mepc",
+            mtvec = in(reg) trap,
+            spp_filter = in(reg) SPP_FILTER,
+            spp = in(reg) spp,
+            entry = in(reg) entry,
+            mcause = out(reg) mcause,
+            mepc = out(reg) mepc,
+            out("t4") _,
+            in("t3") UNKNOWN_EID,
+            in("a7") eid,
+            in("a6") fid,
+            lateout("a0") ret,
+            lateout("a1") _,
+        );
+    }
+
+    (mcause, mepc, ret)
+}
+
+// —————————————————————————————— Trap Handler —————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_trap_handler
+_raw_trap_handler:
+    jr t4
+"#,
+);
+
+// ———————————————————————————————— Guest OS ———————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_os
+_raw_os:
+    ecall
+
+.align 4
+.global _raw_os_sbi
+_raw_os_sbi:
+    ecall
+    mv a7, t3
+    ecall
+"#,
+);
+
+unsafe extern "C" {
+    fn _raw_trap_handler();
+    fn _raw_os();
+    fn _raw_os_sbi();
+}
@@ -0,0 +1,96 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+
+use miralis_abi::{setup_binary, success};
+
+setup_binary!(main);
+
+/// The UBE field of mstatus and sstatus.
+const UBE_FILTER: usize = 1 << 6;
+/// The SBE field of mstatus.
+const SBE_FILTER: usize = 1 << 36;
+/// The MBE field of mstatus.
+const MBE_FILTER: usize = 1 << 37;
+
+/// This test verifies that big-endian modes are reported as unsupported.
+///
+/// Specifically, the test checks:
+/// 1. Setting MBE, SBE and UBE in `mstatus` from the firmware leaves them to zero.
+/// 2. Setting UBE through `sstatus` from the firmware leaves it to zero.
+/// 3. Setting UBE through `sstatus` from the payload leaves it to zero.
+fn main() -> ! {
+    let mstatus: usize;
+    let sstatus: usize;
+    unsafe {
+        asm!(
+            "csrs mstatus, {be}",
+            "csrr {mstatus}, mstatus",
+            "csrs sstatus, {ube}",
+            "csrr {sstatus}, sstatus",
+            be = in(reg) MBE_FILTER | SBE_FILTER | UBE_FILTER,
+            ube = in(reg) UBE_FILTER,
+            mstatus = out(reg) mstatus,
+            sstatus = out(reg) sstatus,
+        );
+    }
+    assert_eq!(
+        mstatus & MBE_FILTER,
+        0,
+        "mstatus.MBE must be read-only zero"
+    );
+    assert_eq!(
+        mstatus & SBE_FILTER,
+        0,
+        "mstatus.SBE must be read-only zero"
+    );
+    assert_eq!(
+        mstatus & UBE_FILTER,
+        0,
+        "mstatus.UBE must be read-only zero"
+    );
+    assert_eq!(
+        sstatus & UBE_FILTER,
+        0,
+        "sstatus.UBE must be read-only zero"
+    );
+
+    // Jump into the OS
+    let mpp: usize = 0b1 << 11; // MPP = S-mode
+    unsafe {
+        asm!(
+            "li t4, 0xfffffffff",
+            "csrw pmpcfg0, 0xf",   // XRW TOR
+            "csrw pmpaddr0, t4",   // All memory
+            "csrw mstatus, {mpp}", // Write MPP of mstatus to S-mode
+            "csrw mepc, {os}",     // Write MEPC
+            "mret",                // Jump to OS
+            mpp = in(reg) mpp,
+            os = in(reg) os_main as usize,
+            options(noreturn),
+        );
+    }
+}
+
+// ———————————————————————————————— Guest OS ———————————————————————————————— //
+
+extern "C" fn os_main() -> ! {
+    let sstatus: usize;
+    unsafe {
+        asm!(
+            "csrs sstatus, {ube}",
+            "csrr {sstatus}, sstatus",
+            ube = in(reg) UBE_FILTER,
+            sstatus = out(reg) sstatus,
+        );
+    }
+    assert_eq!(
+        sstatus & UBE_FILTER,
+        0,
+        "sstatus.UBE must be read-only zero"
+    );
+
+    log::info!("Big-endian modes are not supported");
+    success();
+}
@@ -0,0 +1,40 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+
+use miralis_abi::{setup_binary, success};
+
+setup_binary!(main);
+
+fn main() -> ! {
+    let mut value: usize = 0;
+
+    // Issue each class of fence around memory accesses, the firmware must keep making progress
+    unsafe {
+        asm!(
+            "sd {tmp}, 0({addr})",
+            "fence rw, rw",
+            "ld {tmp}, 0({addr})",
+            "fence r, rw",
+            "fence r, r",
+            "sd {tmp}, 0({addr})",
+            "fence rw, w",
+            "fence w, w",
+            "fence w, rw",
+            "fence rw, r",
+            "fence r, w",
+            "fence w, r",
+            "fence.tso",
+            "fence iorw, iorw",
+            "fence io, io",
+            "addi {tmp}, {tmp}, 1",
+            "sd {tmp}, 0({addr})",
+            addr = in(reg) &mut value as *mut usize,
+            tmp = inout(reg) 0usize => _,
+        );
+    }
+
+    assert_eq!(value, 1, "Memory accesses were not preserved across fences");
+    success();
+}
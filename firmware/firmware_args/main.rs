@@ -0,0 +1,32 @@
+#![no_std]
+#![no_main]
+
+use core::arch::global_asm;
+
+use miralis_abi::firmware_panic;
+
+// This test verifies that the registers configured with `target.firmware.args` hold the expected
+// values when the firmware starts. The check is done before any code could clobber them, the
+// expected values must match the `qemu-virt-firmware-args` configuration.
+global_asm!(
+    r#"
+.text
+.align 4
+.global _start
+_start:
+    li t0, 0x42
+    bne a2, t0, 1f
+    li t0, 1234
+    bne a3, t0, 1f
+
+    li a6, 1           // Miralis ABI FID: success
+    li a7, 0x08475bcd  // Miralis ABI EID
+    ecall
+1:
+    li a6, 0           // Miralis ABI FID: failure
+    li a7, 0x08475bcd  // Miralis ABI EID
+    ecall
+"#,
+);
+
+firmware_panic!();
@@ -0,0 +1,127 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+
+use miralis_abi::{setup_binary, success};
+
+setup_binary!(main);
+
+/// The F extension bit in `misa`.
+const MISA_F: usize = 1 << 5;
+
+/// The FS field of `mstatus`.
+const FS_FILTER: usize = 0b11 << 13;
+
+/// The illegal instruction exception code.
+const ILLEGAL_INSTR: usize = 0x2;
+
+/// This test verifies that accessing an FP CSR while `mstatus.FS` is Off raises an illegal
+/// instruction exception.
+///
+/// Specifically, the test checks:
+/// 1. A read of `fcsr` from the firmware with FS Off traps to the firmware trap handler.
+/// 2. A read of `fcsr` from the payload (S-mode) after clearing `sstatus.FS` is reported to the
+///    firmware as an illegal instruction originating from S-mode.
+fn main() -> ! {
+    let misa: usize;
+    unsafe { asm!("csrr {0}, misa", out(reg) misa) };
+    if misa & MISA_F == 0 {
+        log::info!("F extension is not supported, skipping test");
+        success();
+    }
+
+    let handler = _raw_trap_handler as usize;
+    let sentinel: usize = 0xdeadbeef;
+    let mut value: usize = sentinel;
+    let mcause: usize;
+    let mepc: usize;
+    let csr_addr: usize;
+
+    // Read from the firmware, the trap handler resumes execution after the faulting instruction
+    unsafe {
+        asm!(
+            "csrw mtvec, {mtvec}",
+            "csrc mstatus, {fs}",
+            "auipc t4, 0",
+            "addi t4, t4, 12",
+            "csrr {value}, 0x003", // Should trap
+            "addi {csr_addr}, t4, -4",
+            "csrr {mcause}, mcause",
+            "csrr {mepc}, mepc",
+            mtvec = in(reg) handler,
+            fs = in(reg) FS_FILTER,
+            value = inout(reg) value,
+            csr_addr = out(reg) csr_addr,
+            mcause = out(reg) mcause,
+            mepc = out(reg) mepc,
+            out("t4") _,
+        );
+    }
+
+    assert_eq!(value, sentinel, "The fcsr read should not complete");
+    assert_eq!(mcause, ILLEGAL_INSTR, "Expected an illegal instruction");
+    assert_eq!(mepc, csr_addr, "mepc should point to the fcsr read");
+
+    // Read from the payload, the trap handler resumes execution after the mret
+    let os: usize = _raw_os as usize;
+    let mpp: usize = 0b1 << 11; // MPP = S-mode
+    let mcause: usize;
+    let mstatus: usize;
+    unsafe {
+        asm!(
+            "li t4, 0xfffffffff",
+            "csrw pmpcfg0, 0xf",   // XRW TOR
+            "csrw pmpaddr0, t4",   // All memory
+            "auipc t4, 0",
+            "addi t4, t4, 20",
+            "csrw mstatus, {mpp}", // Write MPP of mstatus to S-mode
+            "csrw mepc, {os}",     // Write MEPC
+            "mret",                // Jump to OS
+            "csrr {mcause}, mcause",
+            "csrr {mstatus}, mstatus",
+            mpp = in(reg) mpp,
+            os = in(reg) os,
+            mcause = out(reg) mcause,
+            mstatus = out(reg) mstatus,
+            out("t4") _,
+        );
+    }
+
+    assert_eq!(mcause, ILLEGAL_INSTR, "Expected an illegal instruction");
+    assert_eq!((mstatus >> 11) & 0b11, 0b01, "Expected a trap from S-mode");
+
+    success();
+}
+
+// —————————————————————————————— Trap Handler —————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_trap_handler
+_raw_trap_handler:
+    jr t4
+"#,
+);
+
+// ———————————————————————————————— Guest OS ———————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_os
+_raw_os:
+    li t0, 0x6000
+    csrc sstatus, t0 // Turn the FP state Off
+    csrr t0, 0x003   // Read fcsr, should trap
+    j _raw_os
+"#,
+);
+
+unsafe extern "C" {
+    fn _raw_trap_handler();
+    fn _raw_os();
+}
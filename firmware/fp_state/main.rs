@@ -0,0 +1,90 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+
+use miralis_abi::{setup_binary, success};
+
+setup_binary!(main);
+
+/// The F extension bit in `misa`.
+const MISA_F: usize = 1 << 5;
+
+/// The FS field of `mstatus`.
+const FS_OFFSET: usize = 13;
+const FS_FILTER: usize = 0b11 << FS_OFFSET;
+const FS_INITIAL: usize = 0b01 << FS_OFFSET;
+
+/// The SD bit of `mstatus`.
+const SD_FILTER: usize = 1 << 63;
+
+/// This test verifies that writing an FP CSR transitions `mstatus.FS` to Dirty.
+///
+/// Specifically, the test checks:
+/// 1. Writing `fcsr` when FS is Initial marks the FP state as Dirty (and sets SD).
+/// 2. The Dirty state and the value of `fcsr` are preserved across a trap and an `mret`.
+fn main() -> ! {
+    let misa: usize;
+    unsafe { asm!("csrr {0}, misa", out(reg) misa) };
+    if misa & MISA_F == 0 {
+        log::info!("F extension is not supported, skipping test");
+        success();
+    }
+
+    // Start from the Initial state, then write fcsr (CSR 0x003)
+    let mstatus: usize;
+    unsafe {
+        asm!(
+            "csrc mstatus, {fs}",
+            "csrs mstatus, {fs_initial}",
+            "csrw 0x003, {fcsr}",
+            "csrr {mstatus}, mstatus",
+            fs = in(reg) FS_FILTER,
+            fs_initial = in(reg) FS_INITIAL,
+            fcsr = in(reg) 0b101_00001,
+            mstatus = out(reg) mstatus,
+        );
+    }
+    assert_eq!(mstatus & FS_FILTER, FS_FILTER, "FS must be Dirty");
+    assert_ne!(mstatus & SD_FILTER, 0, "SD must be set when FS is Dirty");
+
+    // Take a trap and return with mret, the FP state must be preserved
+    let mstatus: usize;
+    let fcsr: usize;
+    unsafe {
+        asm!(
+            "csrw mtvec, {handler}",
+            "ebreak",
+            "csrr {mstatus}, mstatus",
+            "csrr {fcsr}, 0x003",
+            handler = in(reg) _raw_breakpoint_trap_handler as usize,
+            mstatus = out(reg) mstatus,
+            fcsr = out(reg) fcsr,
+            out("t6") _,
+        );
+    }
+    assert_eq!(mstatus & FS_FILTER, FS_FILTER, "FS must still be Dirty");
+    assert_ne!(mstatus & SD_FILTER, 0, "SD must still be set");
+    assert_eq!(fcsr, 0b101_00001, "fcsr must be preserved");
+
+    success();
+}
+
+// —————————————————————————————— Trap Handler —————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_breakpoint_trap_handler
+_raw_breakpoint_trap_handler:
+    csrr t6, mepc  // Read EPC
+    addi t6, t6, 4 // Increment return pointer
+    csrw mepc, t6  // Write it back
+    mret
+"#,
+);
+
+unsafe extern "C" {
+    fn _raw_breakpoint_trap_handler();
+}
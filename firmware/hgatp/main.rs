@@ -0,0 +1,61 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+
+use miralis_abi::{setup_binary, success};
+
+setup_binary!(main);
+
+const SV39X4: usize = 8 << 60;
+const SV57X4: usize = 10 << 60;
+
+fn main() -> ! {
+    log::info!("Hello from hgatp firmware!");
+
+    let misa: usize;
+    unsafe {
+        asm!("csrr {}, misa", out(reg) misa);
+    }
+
+    if (misa & (1 << 7)) == 0 {
+        log::info!("H extension is not available, skipping the hgatp test");
+        success();
+    }
+
+    // Bare mode round-trips the VMID and PPN
+    let bare = (0x12 << 44) | 0x80200;
+    assert_eq!(write_read_hgatp(bare), bare, "Invalid Bare hgatp");
+
+    // Sv39x4 round-trips, with the two lowest bits of the PPN hardwired to zero
+    let sv39x4 = SV39X4 | (0x34 << 44) | 0x80403;
+    assert_eq!(
+        write_read_hgatp(sv39x4),
+        sv39x4 & !0b11,
+        "Invalid Sv39x4 hgatp"
+    );
+
+    // Unsupported modes are ignored and preserve the previous value
+    assert_eq!(
+        write_read_hgatp(SV57X4 | 0x80800),
+        sv39x4 & !0b11,
+        "Unsupported hgatp mode must be ignored"
+    );
+
+    // Restore Bare mode before exiting
+    write_read_hgatp(0);
+    success();
+}
+
+fn write_read_hgatp(value: usize) -> usize {
+    let hgatp: usize;
+    unsafe {
+        asm!(
+            "csrw hgatp, {value}",
+            "csrr {hgatp}, hgatp",
+            value = in(reg) value,
+            hgatp = out(reg) hgatp,
+        );
+    }
+    hgatp
+}
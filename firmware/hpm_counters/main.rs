@@ -0,0 +1,57 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+
+use miralis_abi::{setup_binary, success};
+
+setup_binary!(main);
+
+/// The number of writable event selector bits, must match the test configuration.
+const EVENT_BITS: usize = 16;
+
+/// The event selector bits of `mhpmevent`.
+const EVENT_FILTER: usize = (1 << 56) - 1;
+
+fn main() -> ! {
+    // mhpmcounter3 is implemented and behaves as a regular counter
+    let counter: usize;
+    let event: usize;
+    unsafe {
+        asm!(
+            "csrw mhpmcounter3, {value}",
+            "csrr {counter}, mhpmcounter3",
+            "csrw mhpmevent3, {value}",
+            "csrr {event}, mhpmevent3",
+            value = in(reg) usize::MAX,
+            counter = out(reg) counter,
+            event = out(reg) event,
+        );
+    }
+    assert_eq!(counter, usize::MAX, "mhpmcounter3 should be writable");
+    assert_eq!(
+        event & EVENT_FILTER,
+        (1 << EVENT_BITS) - 1,
+        "Reserved event selector bits should be read-only zero"
+    );
+
+    // mhpmcounter7 is not implemented and must read zero
+    let counter: usize;
+    let event: usize;
+    unsafe {
+        asm!(
+            "csrw mhpmcounter7, {value}",
+            "csrr {counter}, mhpmcounter7",
+            "csrw mhpmevent7, {value}",
+            "csrr {event}, mhpmevent7",
+            value = in(reg) usize::MAX,
+            counter = out(reg) counter,
+            event = out(reg) event,
+        );
+    }
+    assert_eq!(counter, 0, "Unimplemented mhpmcounter7 should read zero");
+    assert_eq!(event, 0, "Unimplemented mhpmevent7 should read zero");
+
+    log::debug!("Done!");
+    success();
+}
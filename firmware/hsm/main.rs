@@ -0,0 +1,158 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+use core::hint;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use miralis_abi::{ecall3, setup_binary, success};
+use miralis_core::sbi_codes;
+use miralis_core::sbi_codes::hsm;
+
+setup_binary!(main);
+
+/// The opaque value passed to the secondary hart when starting it.
+const OPAQUE: usize = 0x42;
+
+/// Set by the secondary hart once started, holds the opaque value it received.
+static SECONDARY_OPAQUE: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of times the secondary hart has been started.
+static SECONDARY_STARTS: AtomicUsize = AtomicUsize::new(0);
+
+/// This test verifies that the payload can stop and start harts using the SBI HSM extension.
+///
+/// Specifically, the test checks:
+/// 1. Secondary harts are stopped at boot and can be started by the boot hart.
+/// 2. Starting a hart resumes it at the requested address, with the hart ID and opaque value in
+///    a0 and a1.
+/// 3. A hart stopping itself is reported as stopped, and can be started again.
+fn main() -> ! {
+    let hart_id: usize;
+    unsafe { asm!("csrr {0}, mhartid", out(reg) hart_id) };
+    assert!(hart_id < 2, "Expected only 2 harts for this test");
+
+    // Jump into the OS on both harts
+    let mpp: usize = 0b1 << 11; // MPP = S-mode
+    unsafe {
+        asm!(
+            "li t4, 0xfffffffff",
+            "csrw pmpcfg0, 0xf",   // XRW TOR
+            "csrw pmpaddr0, t4",   // All memory
+            "csrw mstatus, {mpp}", // Write MPP of mstatus to S-mode
+            "csrw mepc, {os}",     // Write MEPC
+            "mret",                // Jump to OS
+            mpp = in(reg) mpp,
+            os = in(reg) os_main as usize,
+            in("a0") hart_id,
+            options(noreturn),
+        );
+    }
+}
+
+// ———————————————————————————————— Guest OS ———————————————————————————————— //
+
+extern "C" fn os_main(hart_id: usize) -> ! {
+    match hart_id {
+        0 => {
+            // The secondary hart is stopped until we start it
+            assert_eq!(hart_get_status(1), Ok(hsm::STOPPED));
+            start_secondary(1);
+
+            // Wait for the secondary hart to stop itself, then start it again
+            while hart_get_status(1) != Ok(hsm::STOPPED) {
+                hint::spin_loop();
+            }
+            start_secondary(2);
+            assert_eq!(hart_get_status(1), Ok(hsm::STARTED));
+            assert_eq!(
+                hart_start(1, secondary_main as usize, OPAQUE),
+                Err(sbi_codes::SBI_ERR_ALREADY_AVAILABLE),
+                "Hart 1 should already be started"
+            );
+
+            assert_eq!(
+                hart_get_status(2),
+                Err(sbi_codes::SBI_ERR_INVALID_PARAM),
+                "Hart 2 does not exist"
+            );
+
+            log::info!("Hart 1 was started and restarted through HSM");
+            success();
+        }
+        _ => panic!("Hart 1 should be stopped until started by hart 0"),
+    }
+}
+
+/// Start hart 1 and wait until it reaches its entry point for the `nth` time.
+fn start_secondary(nth: usize) {
+    SECONDARY_OPAQUE.store(0, Ordering::SeqCst);
+    assert_eq!(
+        hart_start(1, secondary_main as usize, OPAQUE),
+        Ok(0),
+        "Failed to start hart 1"
+    );
+
+    while SECONDARY_STARTS.load(Ordering::SeqCst) != nth {
+        hint::spin_loop();
+    }
+    assert_eq!(SECONDARY_OPAQUE.load(Ordering::SeqCst), OPAQUE);
+}
+
+/// The entry point of the secondary hart once started.
+///
+/// Miralis keeps the other registers untouched when starting a hart, so we can keep using the
+/// stack of the hart.
+extern "C" fn secondary_main(hart_id: usize, opaque: usize) -> ! {
+    assert_eq!(hart_id, 1, "Invalid hart ID");
+    SECONDARY_OPAQUE.store(opaque, Ordering::SeqCst);
+    let starts = SECONDARY_STARTS.fetch_add(1, Ordering::SeqCst) + 1;
+
+    // Stop after the first start, so that hart 0 can start us again
+    if starts == 1 {
+        hart_stop();
+        panic!("hart_stop should not return");
+    }
+    loop {
+        hint::spin_loop();
+    }
+}
+
+// ——————————————————————————————— HSM Calls ———————————————————————————————— //
+
+fn hart_start(hart_id: usize, start_addr: usize, opaque: usize) -> Result<usize, usize> {
+    unsafe {
+        ecall3(
+            sbi_codes::HSM_EXTENSION_EID,
+            sbi_codes::HART_START_FID,
+            hart_id,
+            start_addr,
+            opaque,
+        )
+    }
+}
+
+fn hart_stop() {
+    unsafe {
+        ecall3(
+            sbi_codes::HSM_EXTENSION_EID,
+            sbi_codes::HART_STOP_FID,
+            0,
+            0,
+            0,
+        )
+        .ok()
+    };
+}
+
+fn hart_get_status(hart_id: usize) -> Result<usize, usize> {
+    unsafe {
+        ecall3(
+            sbi_codes::HSM_EXTENSION_EID,
+            sbi_codes::HART_GET_STATUS_FID,
+            hart_id,
+            0,
+            0,
+        )
+    }
+}
@@ -0,0 +1,67 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+use core::ptr;
+
+use miralis_abi::{setup_binary, success};
+
+setup_binary!(main);
+
+/// The SPVP field of `hstatus`.
+const SPVP_FILTER: usize = 1 << 8;
+
+/// The value initially stored in guest memory.
+const INITIAL_VALUE: usize = 0xdead_beef_cafe_f00d;
+/// The value stored through `hsv.d`.
+const NEW_VALUE: usize = 0x1234_5678_9abc_def0;
+
+/// A double word in guest memory, guest-physical addresses are identity-mapped.
+static mut GUEST_VALUE: usize = INITIAL_VALUE;
+
+/// This test verifies that the hypervisor virtual-machine loads and stores are emulated for the
+/// firmware.
+///
+/// Specifically, the test checks:
+/// 1. `hlv.d` reads a known guest address, with bare guest translation.
+/// 2. `hsv.d` writes to that guest address.
+fn main() -> ! {
+    let misa: usize;
+    unsafe {
+        asm!("csrr {}, misa", out(reg) misa);
+    }
+    if misa & (1 << 7) == 0 {
+        log::info!("H extension is not available, skipping test");
+        success();
+    }
+
+    let addr = &raw mut GUEST_VALUE as usize;
+    let value: usize;
+    unsafe {
+        asm!(
+            "li t4, 0xfffffffff",
+            "csrw pmpcfg0, 0xf",       // XRW TOR
+            "csrw pmpaddr0, t4",       // All memory
+            "csrw hgatp, zero",        // Bare guest-physical translation
+            "csrw vsatp, zero",        // Bare guest-virtual translation
+            "csrs hstatus, {spvp}",    // Access guest memory with VS-mode privileges
+            "hlv.d {value}, ({addr})",
+            "hsv.d {new_value}, ({addr})",
+            spvp = in(reg) SPVP_FILTER,
+            addr = in(reg) addr,
+            new_value = in(reg) NEW_VALUE,
+            value = out(reg) value,
+            out("t4") _,
+        );
+    }
+
+    assert_eq!(value, INITIAL_VALUE, "hlv.d must read the guest memory");
+    assert_eq!(
+        unsafe { ptr::read_volatile(&raw const GUEST_VALUE) },
+        NEW_VALUE,
+        "hsv.d must write the guest memory"
+    );
+
+    log::info!("Hypervisor loads and stores are emulated");
+    success();
+}
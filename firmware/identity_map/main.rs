@@ -0,0 +1,81 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+
+use miralis_abi::{identity_map, setup_binary, success};
+
+setup_binary!(main);
+
+fn main() -> ! {
+    // Ask Miralis for access to all memory instead of configuring the PMP by hand, then jump into
+    // the OS and check that it can execute and trap back to the firmware.
+    identity_map();
+
+    let t6: usize;
+    let mcause: usize;
+    let mepc: usize;
+
+    let os: usize = _raw_os as usize;
+    let trap: usize = _raw_trap_handler as usize;
+    let mpp = 0b1 << 11; // MPP = S-mode
+
+    unsafe {
+        asm!(
+            "auipc t4, 0",
+            "addi t4, t4, 24",
+            "csrw mtvec, {mtvec}", // Write mtvec with trap handler
+            "csrw mstatus, {mpp}", // Write MPP of mstatus to S-mode
+            "csrw mepc, {os}",     // Write MEPC
+
+            "mret",                // Jump to OS
+
+            "csrr {mcause}, mcause",
+            "csrr {mepc}, mepc",
+
+            os = in(reg) os,
+            mtvec = in(reg) trap,
+            mpp = in(reg) mpp,
+            mcause = out(reg) mcause,
+            mepc = out(reg) mepc,
+            out("t4") _,
+            out("t6") t6,
+        );
+    }
+
+    assert_eq!(t6, 0x42, "OS did not execute");
+    assert_eq!(mcause, 9, "Expected an ecall from S-mode");
+    assert_eq!(mepc, os + 4, "mepc must point to the OS ecall");
+
+    success();
+}
+
+// —————————————————————————————— Trap Handler —————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_trap_handler
+_raw_trap_handler:
+    jr t4
+"#,
+);
+
+// ———————————————————————————————— Guest OS ———————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_os
+_raw_os:
+    li t6, 0x42        // Store a value into t6 to prove that the OS did run
+    ecall
+"#,
+);
+
+unsafe extern "C" {
+    fn _raw_trap_handler();
+    fn _raw_os();
+}
@@ -15,6 +15,8 @@ fn main() -> ! {
     test_sie();
     log::debug!("Testing sie by mie register");
     test_sie_by_mie();
+    log::debug!("Testing sie with a narrower mideleg");
+    test_sie_by_mideleg();
     log::debug!("Testing CLINT");
     test_timer_interrupts();
 }
@@ -82,6 +84,48 @@ fn test_sie_by_mie() {
     assert_eq!(res, masked_value);
 }
 
+// Test sie: only the delegated interrupts are visible
+fn test_sie_by_mideleg() {
+    const LCOFI: usize = 1 << 13;
+
+    // Delegate the local counter overflow interrupt, if supported (Sscofpmf)
+    let mideleg: usize;
+    unsafe {
+        asm!(
+            "csrs mideleg, {lcofi}",
+            "csrr {mideleg}, mideleg",
+            lcofi = in(reg) LCOFI,
+            mideleg = out(reg) mideleg,
+        );
+    }
+    if mideleg & LCOFI == 0 {
+        log::info!("Sscofpmf is not supported, skipping sie test with a narrower mideleg");
+        return;
+    }
+
+    let wide_sie: usize;
+    let narrow_sie: usize;
+    unsafe {
+        asm!(
+            "csrw mie, {value}",
+            "csrr {wide_sie}, sie",
+            "csrc mideleg, {lcofi}",
+            "csrr {narrow_sie}, sie",
+            "csrw mie, zero",
+            value = in(reg) 0x222 | LCOFI,
+            lcofi = in(reg) LCOFI,
+            wide_sie = out(reg) wide_sie,
+            narrow_sie = out(reg) narrow_sie,
+        );
+    }
+
+    assert_eq!(wide_sie, 0x222 | LCOFI, "sie must show delegated LCOFIE");
+    assert_eq!(
+        narrow_sie, 0x222,
+        "sie must not show LCOFIE once it is no longer delegated"
+    );
+}
+
 // ———————————————————————————— Timer Interrupt ————————————————————————————— //
 
 #[allow(unreachable_code)]
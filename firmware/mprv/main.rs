@@ -0,0 +1,107 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+use core::ptr;
+
+use miralis_abi::{setup_binary, success};
+
+setup_binary!(main);
+
+/// A page protected by the PMP, with no permissions for U and S-mode.
+#[repr(C, align(4096))]
+struct Page([usize; 512]);
+
+static mut SECRET: Page = Page([0x42; 512]);
+static mut PUBLIC: usize = 0x43;
+
+/// Load access fault exception code.
+const LOAD_ACCESS_FAULT: usize = 5;
+
+/// This test checks the privileges used for memory accesses when mstatus.MPRV is set.
+///
+/// Specifically, the test checks:
+/// 1. Loads with MPRV = 1 and MPP = U use the PMP access rights of U-mode.
+/// 2. Instruction fetches are not affected by MPRV, the PMP denies execution in U-mode but the
+///    code keeps running in M-mode.
+fn main() -> ! {
+    let secret = ptr::addr_of!(SECRET) as usize;
+    let public = ptr::addr_of!(PUBLIC) as usize;
+
+    // PMP 0 denies all accesses to the secret page, PMP 1 grants RW (but not X) to all memory.
+    // The entries are not locked, they only apply to S and U-mode.
+    let pmpaddr0 = (secret >> 2) | ((size_of::<Page>() >> 3) - 1);
+    let pmpaddr1 = usize::MAX >> 10;
+    let pmpcfg0: usize = 0x18 | (0x1b << 8); // NAPOT, NAPOT + RW
+    unsafe {
+        asm!(
+            "csrw pmpaddr0, {pmpaddr0}",
+            "csrw pmpaddr1, {pmpaddr1}",
+            "csrw pmpcfg0, {pmpcfg0}",
+            pmpaddr0 = in(reg) pmpaddr0,
+            pmpaddr1 = in(reg) pmpaddr1,
+            pmpcfg0 = in(reg) pmpcfg0,
+        );
+    }
+
+    // Without MPRV, M-mode is not subject to the PMP
+    assert_eq!(
+        unsafe { ptr::read_volatile(secret as *const usize) },
+        0x42,
+        "Failed to read the secret from M-mode"
+    );
+
+    let public_value: usize;
+    let mcause: usize;
+    unsafe {
+        asm!(
+            "csrw mtvec, {handler}",
+            "csrc mstatus, {mpp}",  // MPP = U-mode
+            "csrs mstatus, {mprv}", // MPRV = 1
+            ".option push",
+            ".option norvc",
+            "ld {public_value}, 0({public})", // Allowed by PMP 1
+            "ld {secret_value}, 0({secret})", // Denied by PMP 0
+            ".option pop",
+            "csrc mstatus, {mprv}", // MPRV = 0
+            handler = in(reg) _raw_trap_handler as usize,
+            mpp = in(reg) 0b11usize << 11,
+            mprv = in(reg) 1usize << 17,
+            public = in(reg) public,
+            secret = in(reg) secret,
+            public_value = out(reg) public_value,
+            secret_value = out(reg) _,
+            inout("t6") 0usize => mcause,
+            out("t5") _,
+        );
+    }
+
+    assert_eq!(public_value, 0x43, "Failed to read with MPRV = 1");
+    assert_eq!(
+        mcause, LOAD_ACCESS_FAULT,
+        "Reading the secret with MPRV = 1 and MPP = U did not fault"
+    );
+
+    success();
+}
+
+// —————————————————————————————— Trap Handler —————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_trap_handler
+_raw_trap_handler:
+    // Skip the faulting instruction and record the cause
+    csrr t6, mcause
+    csrr t5, mepc
+    addi t5, t5, 4
+    csrw mepc, t5
+    mret
+"#,
+);
+
+unsafe extern "C" {
+    fn _raw_trap_handler();
+}
@@ -14,21 +14,28 @@ fn main() -> ! {
         let handler = _raw_breakpoint_trap_handler as usize;
         // Let's rise an exception breakpoint directly
         asm!(
-            "csrw mtvec, {0}",   // Write mtvec
-            "ebreak",            // Cause an exception, we should return right away!
-            "csrr {1}, mstatus", // Read mstatus
+            "csrw mtvec, {0}",    // Write mtvec
+            "ebreak",             // Cause an exception, we should return right away!
+            "csrr {1}, mstatus",  // Read mstatus
+            "csrci mstatus, 0x8", // Disable interrupts again
             in(reg) handler,
             out(reg) mstatus,
-            out("t6") t6,        // The handler writes a secret value in t6
+            out("t6") t6,         // The handler writes a secret value in t6
         );
     }
 
     let mpp = (mstatus >> 11) & 0b11;
     let mpie = (mstatus >> 7) & 0b1;
+    let mie = (mstatus >> 3) & 0b1;
     let mprv = (mstatus >> 17) & 0b1;
 
     assert_eq!(mpp, 0, "Invalid MPP: {}, expected 0", mpp);
     assert_eq!(mpie, 1, "Invalid MPIE: {}, expected 1", mpie);
+    assert_eq!(
+        mie, 1,
+        "Invalid MIE: {}, expected the MPIE set by the handler",
+        mie
+    );
     assert_eq!(mprv, 0, "Invalid MPRV: {}, expected 0", mprv);
     assert_eq!(
         t6, 0x42,
@@ -46,10 +53,12 @@ global_asm!(
 .align 4
 .global _raw_breakpoint_trap_handler
 _raw_breakpoint_trap_handler:
-    csrr t6, mepc  // Read EPC
-    addi t6, t6, 4 // Increment return pointer
-    csrw mepc, t6  // Write it back
-    li t6, 0x42    // And store a secret value in t6 before returning
+    csrr t6, mepc    // Read EPC
+    addi t6, t6, 4   // Increment return pointer
+    csrw mepc, t6    // Write it back
+    li t6, 0x80      // Set MPIE while MIE is cleared,
+    csrs mstatus, t6 // mret must restore MIE from it
+    li t6, 0x42      // And store a secret value in t6 before returning
     mret
 "#,
 );
@@ -0,0 +1,85 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+
+use miralis_abi::{setup_binary, success};
+
+setup_binary!(main);
+
+/// mseccfg.RLB, the Rule Locking Bypass bit.
+const RLB: usize = 1 << 2;
+
+/// A locked TOR entry with read permission.
+const LOCKED_TOR_R: usize = 0b10001001;
+/// A locked TOR entry with read and write permissions.
+const LOCKED_TOR_RW: usize = 0b10001011;
+
+/// The top of the range covered by the locked entry, far below the firmware.
+const TOP_OF_RANGE: usize = 0x400 >> 2;
+
+fn main() -> ! {
+    // RLB can be set while no entry is locked
+    write_mseccfg(RLB);
+    assert_eq!(read_mseccfg(), RLB, "Could not set mseccfg.RLB");
+
+    // With RLB set, locked entries can still be modified
+    write_pmp0(0, LOCKED_TOR_R);
+    write_pmp0(TOP_OF_RANGE, LOCKED_TOR_RW);
+    assert_eq!(
+        read_pmpcfg0(),
+        LOCKED_TOR_RW,
+        "Could not modify a locked pmpcfg with RLB"
+    );
+    assert_eq!(
+        read_pmpaddr0(),
+        TOP_OF_RANGE,
+        "Could not modify a locked pmpaddr with RLB"
+    );
+
+    // Once RLB is cleared the entry can no longer be modified
+    write_mseccfg(0);
+    assert_eq!(read_mseccfg(), 0, "Could not clear mseccfg.RLB");
+    write_pmp0(0, 0);
+    assert_eq!(read_pmpcfg0(), LOCKED_TOR_RW, "Locked pmpcfg was modified");
+    assert_eq!(read_pmpaddr0(), TOP_OF_RANGE, "Locked pmpaddr was modified");
+
+    // And RLB can not be set again while an entry is locked
+    write_mseccfg(RLB);
+    assert_eq!(read_mseccfg(), 0, "mseccfg.RLB set with a locked entry");
+
+    success();
+}
+
+fn write_mseccfg(value: usize) {
+    unsafe { asm!("csrw mseccfg, {0}", in(reg) value) };
+}
+
+fn read_mseccfg() -> usize {
+    let value: usize;
+    unsafe { asm!("csrr {0}, mseccfg", out(reg) value) };
+    value
+}
+
+fn write_pmp0(addr: usize, cfg: usize) {
+    unsafe {
+        asm!(
+            "csrw pmpaddr0, {addr}",
+            "csrw pmpcfg0, {cfg}",
+            addr = in(reg) addr,
+            cfg = in(reg) cfg,
+        );
+    }
+}
+
+fn read_pmpcfg0() -> usize {
+    let value: usize;
+    unsafe { asm!("csrr {0}, pmpcfg0", out(reg) value) };
+    value
+}
+
+fn read_pmpaddr0() -> usize {
+    let value: usize;
+    unsafe { asm!("csrr {0}, pmpaddr0", out(reg) value) };
+    value
+}
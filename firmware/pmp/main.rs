@@ -34,6 +34,21 @@ fn main() -> ! {
     }
     assert_eq!(res, secret_cfg, "Could set invalid bits in pmpcfg0");
 
+    // Test reserved R = 0 and W = 1 combination, which clears R, W, and X
+    unsafe {
+        asm!(
+            "csrw pmpcfg0, {0}",
+            "csrr {1}, pmpcfg0",
+            "csrw pmpcfg0, zero",
+            in(reg) 0x0b_06_0a,
+            out(reg) res,
+        );
+    }
+    assert_eq!(
+        res, 0x0b_00_08,
+        "Could write reserved permissions in pmpcfg0"
+    );
+
     // Test out of range write to config (with 8 PMP)
     unsafe {
         asm!(
@@ -0,0 +1,28 @@
+#![no_std]
+#![no_main]
+
+use miralis_abi::{probes_sbi, setup_binary, success};
+use miralis_core::{abi, sbi_codes};
+
+setup_binary!(main);
+
+fn main() -> ! {
+    // The offload policy handles the timer extension on behalf of the firmware
+    assert!(
+        probes_sbi(sbi_codes::SBI_TIMER_EID),
+        "The timer extension should be virtualized"
+    );
+    assert!(
+        probes_sbi(abi::MIRALIS_EID),
+        "The Miralis ABI is always virtualized"
+    );
+
+    // The debug console is only handled by the protect payload policy
+    assert!(
+        !probes_sbi(sbi_codes::SBI_DEBUG_CONSOLE_EXTENSION_EID),
+        "The debug console should be left to the firmware"
+    );
+
+    log::info!("SBI probes are correct");
+    success();
+}
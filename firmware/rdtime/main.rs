@@ -0,0 +1,89 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+
+use miralis_abi::{setup_binary, success};
+
+setup_binary!(main);
+
+/// The TM bit of `mcounteren` and `scounteren`.
+const TM: usize = 1 << 1;
+
+/// This test verifies that the payload can read `time` on cores that do not implement it.
+///
+/// The test is meant to run on a core lacking the `time` CSR (such as the SiFive U54), where
+/// `rdtime` traps even when enabled by `mcounteren`. Specifically, the test checks:
+/// 1. The firmware can read the time.
+/// 2. The payload can read the time when enabled by `mcounteren`, and it does not go backward.
+fn main() -> ! {
+    let firmware_time: usize;
+    unsafe { asm!("rdtime {0}", out(reg) firmware_time) };
+    assert_ne!(firmware_time, 0, "The firmware time must not be zero");
+
+    let os: usize = _raw_os as usize;
+    let trap: usize = _raw_trap_handler as usize;
+    let mpp: usize = 0b1 << 11; // MPP = S-mode
+
+    // Jump into the payload, it reads the time twice and traps back with an ecall
+    let first: usize;
+    let second: usize;
+    unsafe {
+        asm!(
+            "li t4, 0xfffffffff",
+            "csrw pmpcfg0, 0xf",   // XRW TOR
+            "csrw pmpaddr0, t4",   // All memory
+            "csrw mcounteren, {tm}", // Enable time reads from S-mode
+            "auipc t4, 0",
+            "addi t4, t4, 24",
+            "csrw mtvec, {mtvec}", // Write mtvec with trap handler
+            "csrw mstatus, {mpp}", // Write MPP = S-mode
+            "csrw mepc, {os}",     // Write MEPC
+            "mret",                // Jump to OS
+            os = in(reg) os,
+            mtvec = in(reg) trap,
+            mpp = in(reg) mpp,
+            tm = in(reg) TM,
+            out("t4") _,
+            out("a0") first,
+            out("a1") second,
+            out("a7") _,
+        );
+    }
+    assert!(first >= firmware_time, "The payload time went backward");
+    assert!(second >= first, "The payload time went backward");
+
+    success();
+}
+
+// —————————————————————————————— Trap Handler —————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_trap_handler
+_raw_trap_handler:
+    jr t4
+"#,
+);
+
+// ———————————————————————————————— Guest OS ———————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_os
+_raw_os:
+    rdtime a0
+    rdtime a1
+    li a7, 0           // Any non-Miralis EID, the ecall is forwarded to the firmware
+    ecall
+"#,
+);
+
+unsafe extern "C" {
+    fn _raw_trap_handler();
+    fn _raw_os();
+}
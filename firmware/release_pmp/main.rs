@@ -0,0 +1,68 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+
+use miralis_abi::{release_pmp, setup_binary, success};
+
+setup_binary!(main);
+
+/// An arbitrary pmpaddr value, readable back with any PMP grain.
+const PMPADDR: usize = 0x8000_0000 >> 2;
+
+/// The test runs with 8 virtual PMP entries (see `max_pmp`), and releases the last 4.
+fn main() -> ! {
+    write_pmpaddr4(PMPADDR);
+    assert_eq!(read_pmpaddr4(), PMPADDR, "pmpaddr4 is not implemented");
+
+    release_pmp(4).expect("Failed to release PMP entries");
+
+    // The released entries read as zero, even after a write
+    assert_eq!(read_pmpaddr4(), 0, "Released pmpaddr4 was not cleared");
+    write_pmpaddr4(PMPADDR);
+    assert_eq!(read_pmpaddr4(), 0, "Released pmpaddr4 is still writable");
+    write_pmpaddr7(PMPADDR);
+    assert_eq!(read_pmpaddr7(), 0, "Released pmpaddr7 is still writable");
+
+    // The remaining entries are still available
+    write_pmpaddr3(PMPADDR);
+    assert_eq!(read_pmpaddr3(), PMPADDR, "pmpaddr3 was released");
+
+    // Only the remaining entries can be released
+    assert!(
+        release_pmp(5).is_err(),
+        "Released more entries than available"
+    );
+
+    success();
+}
+
+fn write_pmpaddr3(value: usize) {
+    unsafe { asm!("csrw pmpaddr3, {0}", in(reg) value) };
+}
+
+fn read_pmpaddr3() -> usize {
+    let value: usize;
+    unsafe { asm!("csrr {0}, pmpaddr3", out(reg) value) };
+    value
+}
+
+fn write_pmpaddr4(value: usize) {
+    unsafe { asm!("csrw pmpaddr4, {0}", in(reg) value) };
+}
+
+fn read_pmpaddr4() -> usize {
+    let value: usize;
+    unsafe { asm!("csrr {0}, pmpaddr4", out(reg) value) };
+    value
+}
+
+fn write_pmpaddr7(value: usize) {
+    unsafe { asm!("csrw pmpaddr7, {0}", in(reg) value) };
+}
+
+fn read_pmpaddr7() -> usize {
+    let value: usize;
+    unsafe { asm!("csrr {0}, pmpaddr7", out(reg) value) };
+    value
+}
@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+
+use miralis_abi::{failure, setup_binary};
+
+setup_binary!(main);
+
+/// This test verifies that the payload `satp` writes are validated by the modules.
+///
+/// The payload writes `satp` twice, reads it back and issues an `sfence.vma`, all of which trap
+/// into Miralis while the `satp_test` policy is enabled. The policy approves and counts the
+/// writes, and logs the total on shutdown, which must be two.
+fn main() -> ! {
+    let os: usize = _raw_os as usize;
+    let mpp: usize = 0b1 << 11; // MPP = S-mode
+    unsafe {
+        asm!(
+            "li t4, 0xfffffffff",
+            "csrw pmpcfg0, 0xf",   // XRW TOR
+            "csrw pmpaddr0, t4",   // All memory
+            "csrw mstatus, {mpp}", // Write MPP of mstatus to S-mode
+            "csrw mepc, {os}",     // Write MEPC
+            "mret",                // Jump to OS
+            mpp = in(reg) mpp,
+            os = in(reg) os,
+            out("t4") _,
+        );
+    }
+    failure()
+}
+
+// ———————————————————————————————— Guest OS ———————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_os
+_raw_os:
+    li a7, 0x08475bcd  // Miralis ABI EID
+    li a6, 0           // Miralis ABI FID: failure
+
+    li t0, -1
+    csrw satp, zero    // First validated write
+    csrr t0, satp
+    bnez t0, 1f        // The write must have taken effect
+    sfence.vma
+    csrwi satp, 0      // Second validated write
+
+    li a6, 1           // Miralis ABI FID: success
+1:
+    ecall
+"#,
+);
+
+unsafe extern "C" {
+    fn _raw_os();
+}
@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+
+use miralis_abi::{ecall3, setup_binary, success};
+use miralis_core::{abi, sbi_codes};
+
+setup_binary!(main);
+
+/// This test checks the SBI base extension implemented by Miralis.
+///
+/// Specifically, the test checks:
+/// 1. The spec version and implementation ID are the ones of Miralis.
+/// 2. Probing reports the base extension and the extensions virtualized by Miralis, but not the
+///    ones left to the firmware.
+/// 3. The machine identity matches the virtual `mvendorid`, `marchid` and `mimpid` CSRs.
+fn main() -> ! {
+    assert_eq!(
+        sbi_base(sbi_codes::GET_SPEC_VERSION_FID, 0),
+        Ok(sbi_codes::SBI_SPEC_VERSION),
+        "Invalid SBI spec version"
+    );
+    assert_eq!(
+        sbi_base(sbi_codes::GET_IMPL_ID_FID, 0),
+        Ok(sbi_codes::MIRALIS_IMPL_ID),
+        "Invalid SBI implementation ID"
+    );
+
+    // The offload policy handles the timer extension, the debug console is left to the firmware
+    for (eid, available) in [
+        (sbi_codes::SBI_BASE_EID, true),
+        (abi::MIRALIS_EID, true),
+        (sbi_codes::SBI_TIMER_EID, true),
+        (sbi_codes::SBI_DEBUG_CONSOLE_EXTENSION_EID, false),
+    ] {
+        let probe = sbi_base(sbi_codes::PROBE_EXTENSION_FID, eid).expect("Failed to probe");
+        assert_eq!(probe != 0, available, "Invalid probe for EID 0x{:x}", eid);
+    }
+
+    let mvendorid: usize;
+    let marchid: usize;
+    let mimpid: usize;
+    unsafe {
+        core::arch::asm!(
+            "csrr {mvendorid}, mvendorid",
+            "csrr {marchid}, marchid",
+            "csrr {mimpid}, mimpid",
+            mvendorid = out(reg) mvendorid,
+            marchid = out(reg) marchid,
+            mimpid = out(reg) mimpid,
+        );
+    }
+    assert_eq!(sbi_base(sbi_codes::GET_MVENDORID_FID, 0), Ok(mvendorid));
+    assert_eq!(sbi_base(sbi_codes::GET_MARCHID_FID, 0), Ok(marchid));
+    assert_eq!(sbi_base(sbi_codes::GET_MIMPID_FID, 0), Ok(mimpid));
+
+    // Unknown functions are not supported
+    assert_eq!(
+        sbi_base(0x42, 0),
+        Err(sbi_codes::SBI_ERR_NOT_SUPPORTED),
+        "Unknown base FID must not be supported"
+    );
+
+    log::info!("SBI base extension is correct");
+    success();
+}
+
+fn sbi_base(fid: usize, arg: usize) -> Result<usize, usize> {
+    unsafe { ecall3(sbi_codes::SBI_BASE_EID, fid, arg, 0, 0) }
+}
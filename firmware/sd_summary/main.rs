@@ -0,0 +1,124 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+
+use miralis_abi::{setup_binary, success};
+
+setup_binary!(main);
+
+/// The F extension bit in `misa`.
+const MISA_F: usize = 1 << 5;
+
+/// The FS field of `mstatus`.
+const FS_OFFSET: usize = 13;
+const FS_FILTER: usize = 0b11 << FS_OFFSET;
+const FS_INITIAL: usize = 0b01 << FS_OFFSET;
+const FS_CLEAN: usize = 0b10 << FS_OFFSET;
+
+/// The SD bit of `mstatus`.
+const SD_FILTER: usize = 1 << 63;
+
+/// This test verifies that `mstatus.SD` summarizes the FP state dirtied by the payload.
+///
+/// Specifically, the test checks:
+/// 1. The payload writing `fcsr` with FS Initial makes `mstatus.FS` Dirty and sets SD.
+/// 2. Cleaning the FP state from the firmware clears SD, in both `mstatus` and `sstatus`.
+fn main() -> ! {
+    let misa: usize;
+    unsafe { asm!("csrr {0}, misa", out(reg) misa) };
+    if misa & MISA_F == 0 {
+        log::info!("F extension is not supported, skipping test");
+        success();
+    }
+
+    let os: usize = _raw_os as usize;
+    let trap: usize = _raw_trap_handler as usize;
+    let mpp: usize = 0b1 << 11; // MPP = S-mode
+
+    // Jump into the payload with a clean FP state, it dirties it and traps back with an ecall
+    let mstatus: usize;
+    unsafe {
+        asm!(
+            "li t4, 0xfffffffff",
+            "csrw pmpcfg0, 0xf",   // XRW TOR
+            "csrw pmpaddr0, t4",   // All memory
+            "auipc t4, 0",
+            "addi t4, t4, 24",
+            "csrw mtvec, {mtvec}", // Write mtvec with trap handler
+            "csrw mstatus, {mstatus}", // Write MPP = S-mode and FS = Initial
+            "csrw mepc, {os}",     // Write MEPC
+            "mret",                // Jump to OS
+            "csrr {mstatus}, mstatus",
+            os = in(reg) os,
+            mtvec = in(reg) trap,
+            mstatus = inout(reg) mpp | FS_INITIAL => mstatus,
+            out("t0") _,
+            out("t4") _,
+            out("a7") _,
+        );
+    }
+    assert_eq!(mstatus & FS_FILTER, FS_FILTER, "FS must be Dirty");
+    assert_ne!(mstatus & SD_FILTER, 0, "SD must be set when FS is Dirty");
+
+    // Clean the FP state, SD must be cleared
+    let mstatus: usize;
+    let sstatus: usize;
+    unsafe {
+        asm!(
+            "csrc mstatus, {fs}",
+            "csrs mstatus, {fs_clean}",
+            "csrr {mstatus}, mstatus",
+            "csrr {sstatus}, sstatus",
+            fs = in(reg) FS_FILTER,
+            fs_clean = in(reg) FS_CLEAN,
+            mstatus = out(reg) mstatus,
+            sstatus = out(reg) sstatus,
+        );
+    }
+    assert_eq!(mstatus & FS_FILTER, FS_CLEAN, "FS must be Clean");
+    assert_eq!(
+        mstatus & SD_FILTER,
+        0,
+        "SD must be cleared when FS is Clean"
+    );
+    assert_eq!(
+        sstatus & SD_FILTER,
+        0,
+        "sstatus.SD must be cleared when FS is Clean"
+    );
+
+    success();
+}
+
+// —————————————————————————————— Trap Handler —————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_trap_handler
+_raw_trap_handler:
+    jr t4
+"#,
+);
+
+// ———————————————————————————————— Guest OS ———————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_os
+_raw_os:
+    li t0, 0xa1
+    csrw 0x003, t0     // Write fcsr to dirty the FP state
+    li a7, 0           // Any non-Miralis EID, the ecall is forwarded to the firmware
+    ecall
+"#,
+);
+
+unsafe extern "C" {
+    fn _raw_trap_handler();
+    fn _raw_os();
+}
@@ -0,0 +1,87 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+
+use miralis_abi::{identity_map, setup_binary, success};
+
+setup_binary!(main);
+
+/// The C extension bit in `misa`.
+const MISA_C: usize = 1 << 2;
+
+/// The SPP field of `sstatus`.
+const SPP_FILTER: usize = 1 << 8;
+
+/// This test verifies that writes to `sepc` are legalized.
+///
+/// Specifically, the test checks:
+/// 1. Writing an odd address to `sepc` clears bit 0 (and bit 1 without the C extension).
+/// 2. `sret` returns to the legalized address.
+fn main() -> ! {
+    let misa: usize;
+    unsafe { asm!("csrr {0}, misa", out(reg) misa) };
+    let mask = if misa & MISA_C != 0 { !0b1 } else { !0b11 };
+
+    let os: usize = _raw_os as usize;
+    let trap: usize = _raw_trap_handler as usize;
+    let sepc: usize;
+    let mepc: usize;
+
+    // The OS is allowed to access all memory until it traps back
+    identity_map();
+
+    // Return to the OS with an odd sepc, the OS immediately traps back with an ecall
+    unsafe {
+        asm!(
+            "csrw mtvec, {mtvec}", // Write mtvec with trap handler
+            "csrs sstatus, {spp}", // Write SPP of sstatus to S-mode
+            "csrw sepc, {odd_os}", // Write an odd SEPC
+            "csrr {sepc}, sepc",
+            "auipc t4, 0",
+            "addi t4, t4, 12",
+            "sret",                // Jump to OS
+            "csrr {mepc}, mepc",
+            mtvec = in(reg) trap,
+            spp = in(reg) SPP_FILTER,
+            odd_os = in(reg) os | 0b1,
+            sepc = out(reg) sepc,
+            mepc = out(reg) mepc,
+            out("t4") _,
+        );
+    }
+
+    assert_eq!(sepc, os & mask, "Invalid sepc legalization");
+    assert_eq!(mepc, os, "sret must return to the legalized sepc");
+
+    success();
+}
+
+// —————————————————————————————— Trap Handler —————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_trap_handler
+_raw_trap_handler:
+    jr t4
+"#,
+);
+
+// ———————————————————————————————— Guest OS ———————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_os
+_raw_os:
+    ecall
+"#,
+);
+
+unsafe extern "C" {
+    fn _raw_trap_handler();
+    fn _raw_os();
+}
@@ -0,0 +1,61 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+
+use miralis_abi::{failure, setup_binary};
+
+setup_binary!(main);
+
+/// This test verifies that Miralis can single-step the payload.
+///
+/// The payload enables single-stepping, executes five instructions and disables it again. The
+/// `single_step_test` policy counts the steps and logs the total on shutdown, which must be five.
+fn main() -> ! {
+    let os: usize = _raw_os as usize;
+    let mpp: usize = 0b1 << 11; // MPP = S-mode
+    unsafe {
+        asm!(
+            "li t4, 0xfffffffff",
+            "csrw pmpcfg0, 0xf",   // XRW TOR
+            "csrw pmpaddr0, t4",   // All memory
+            "csrw mstatus, {mpp}", // Write MPP of mstatus to S-mode
+            "csrw mepc, {os}",     // Write MEPC
+            "mret",                // Jump to OS
+            mpp = in(reg) mpp,
+            os = in(reg) os,
+            out("t4") _,
+        );
+    }
+    failure()
+}
+
+// ———————————————————————————————— Guest OS ———————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_os
+_raw_os:
+    li a0, 1           // Enable single-stepping
+    li a6, 7           // Miralis ABI FID: enable single-step
+    li a7, 0x08475bcd  // Miralis ABI EID
+    ecall
+
+    // Five stepped instructions
+    nop
+    nop
+    nop
+    nop
+    li a0, 0           // Disable single-stepping
+    ecall
+
+    li a6, 1           // Miralis ABI FID: success
+    ecall
+"#,
+);
+
+unsafe extern "C" {
+    fn _raw_os();
+}
@@ -0,0 +1,102 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+
+use miralis_abi::{failure, setup_binary};
+
+setup_binary!(main);
+
+/// The UXL field of sstatus and mstatus.
+const UXL_FILTER: usize = 0b11 << 32;
+/// UXL value for a 32 bits U-mode, which is not supported.
+const UXL_32: usize = 0b01 << 32;
+/// UXL value for a 64 bits U-mode.
+const UXL_64: usize = 0b10 << 32;
+
+/// This test verifies that writes to `sstatus` can not change the U-mode XLEN.
+///
+/// Specifically, the test checks:
+/// 1. A write to `sstatus` with garbage UXL bits from the firmware keeps UXL to RV64.
+/// 2. The same write from the payload keeps UXL to RV64, and the payload keeps executing with
+///    64 bits registers.
+fn main() -> ! {
+    let sstatus: usize;
+    let mstatus: usize;
+    unsafe {
+        asm!(
+            "csrs sstatus, {uxl}",
+            "csrr {sstatus}, sstatus",
+            "csrr {mstatus}, mstatus",
+            uxl = in(reg) UXL_FILTER,
+            sstatus = out(reg) sstatus,
+            mstatus = out(reg) mstatus,
+        );
+    }
+    assert_eq!(sstatus & UXL_FILTER, UXL_64, "sstatus.UXL must stay RV64");
+    assert_eq!(mstatus & UXL_FILTER, UXL_64, "mstatus.UXL must stay RV64");
+
+    // Jump into the OS, which exits through the Miralis ABI
+    let os: usize = _raw_os as usize;
+    let mpp: usize = 0b1 << 11; // MPP = S-mode
+    unsafe {
+        asm!(
+            "li t4, 0xfffffffff",
+            "csrw pmpcfg0, 0xf",   // XRW TOR
+            "csrw pmpaddr0, t4",   // All memory
+            "csrw mstatus, {mpp}", // Write MPP of mstatus to S-mode
+            "csrw mepc, {os}",     // Write MEPC
+            "mret",                // Jump to OS
+            mpp = in(reg) mpp,
+            os = in(reg) os,
+            out("t4") _,
+        );
+    }
+    failure()
+}
+
+// ———————————————————————————————— Guest OS ———————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_os
+_raw_os:
+    // Try to switch U-mode to RV32 with garbage UXL bits
+    csrr t0, sstatus
+    li t1, {uxl_filter}
+    not t1, t1
+    and t0, t0, t1
+    li t1, {uxl_32}
+    or t0, t0, t1
+    csrw sstatus, t0
+
+    // UXL must still be RV64
+    csrr t0, sstatus
+    li t1, {uxl_filter}
+    and t0, t0, t1
+    li t1, {uxl_64}
+    bne t0, t1, 1f
+
+    // And registers must still be 64 bits wide
+    li t0, 1
+    slli t0, t0, 32
+    beqz t0, 1f
+
+    li a6, 1           // Miralis ABI FID: success
+    li a7, 0x08475bcd  // Miralis ABI EID
+    ecall
+1:
+    li a6, 0           // Miralis ABI FID: failure
+    li a7, 0x08475bcd  // Miralis ABI EID
+    ecall
+"#,
+    uxl_filter = const UXL_FILTER,
+    uxl_32 = const UXL_32,
+    uxl_64 = const UXL_64,
+);
+
+unsafe extern "C" {
+    fn _raw_os();
+}
@@ -0,0 +1,76 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+
+use miralis_abi::{setup_binary, success};
+
+setup_binary!(main);
+
+/// The STCE field of `menvcfg`.
+const STCE_FILTER: usize = 1 << 63;
+/// The STIP field of `mip`.
+const STIP_FILTER: usize = 1 << 5;
+
+/// This test verifies that `mip.STIP` is driven by `stimecmp` when Sstc is enabled.
+///
+/// Specifically, the test checks:
+/// 1. With `stimecmp` in the future, STIP is clear and setting it in `mip` has no effect.
+/// 2. With `stimecmp` in the past, STIP is set and clearing it in `mip` has no effect.
+fn main() -> ! {
+    // Enable Sstc, if supported
+    let menvcfg: usize;
+    unsafe {
+        asm!(
+            "csrs 0x30A, {stce}",
+            "csrr {menvcfg}, 0x30A",
+            stce = in(reg) STCE_FILTER,
+            menvcfg = out(reg) menvcfg,
+        );
+    }
+    if menvcfg & STCE_FILTER == 0 {
+        log::info!("Sstc is not supported, skipping test");
+        success();
+    }
+
+    assert_eq!(
+        write_stip_with_deadline(usize::MAX, true),
+        0,
+        "STIP must not be pending before the deadline"
+    );
+    assert_eq!(
+        write_stip_with_deadline(0, false),
+        STIP_FILTER,
+        "STIP must be pending after the deadline"
+    );
+
+    success();
+}
+
+/// Program `stimecmp` with the given deadline, then set or clear STIP through `mip` and return
+/// the resulting STIP bit.
+fn write_stip_with_deadline(deadline: usize, set_stip: bool) -> usize {
+    let mip: usize;
+    unsafe {
+        if set_stip {
+            asm!(
+                "csrw 0x14D, {deadline}", // stimecmp
+                "csrs mip, {stip}",
+                "csrr {mip}, mip",
+                deadline = in(reg) deadline,
+                stip = in(reg) STIP_FILTER,
+                mip = out(reg) mip,
+            );
+        } else {
+            asm!(
+                "csrw 0x14D, {deadline}", // stimecmp
+                "csrc mip, {stip}",
+                "csrr {mip}, mip",
+                deadline = in(reg) deadline,
+                stip = in(reg) STIP_FILTER,
+                mip = out(reg) mip,
+            );
+        }
+    }
+    mip & STIP_FILTER
+}
@@ -0,0 +1,88 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+
+use miralis_abi::{failure, setup_binary};
+
+setup_binary!(main);
+
+/// Illegal instruction exceptions.
+const MEDELEG: usize = 1 << 2;
+
+/// This test verifies that the Svinval instructions are emulated when the core lacks them.
+///
+/// Specifically, the test checks:
+/// 1. The firmware can issue the Svinval sequence and makes forward progress.
+/// 2. An S-mode OS can issue the Svinval sequence and makes forward progress, even though it
+///    delegates illegal instructions to itself.
+fn main() -> ! {
+    // The Svinval sequence from the firmware, encoded by hand as not all assemblers know about
+    // the extension.
+    unsafe {
+        asm!(
+            ".4byte 0x18000073", // sfence.w.inval
+            ".4byte 0x16000073", // sinval.vma zero, zero
+            ".4byte 0x16b50073", // sinval.vma a0, a1
+            ".4byte 0x18100073", // sfence.inval.ir
+            in("a0") 0x8020_0000usize,
+            in("a1") 0usize,
+        );
+    }
+
+    // Jump into the OS, which exits through the Miralis ABI
+    let os: usize = _raw_os as usize;
+    let mpp: usize = 0b1 << 11; // MPP = S-mode
+    unsafe {
+        asm!(
+            "li t4, 0xfffffffff",
+            "csrw pmpcfg0, 0xf",          // XRW TOR
+            "csrw pmpaddr0, t4",          // All memory
+            "csrw medeleg, {medeleg}",    // Delegate illegal instructions
+            "csrw mstatus, {mpp}",        // Write MPP of mstatus to S-mode
+            "csrw mepc, {os}",            // Write MEPC
+            "mret",                       // Jump to OS
+            medeleg = in(reg) MEDELEG,
+            mpp = in(reg) mpp,
+            os = in(reg) os,
+            out("t4") _,
+        );
+    }
+    failure()
+}
+
+// ———————————————————————————————— Guest OS ———————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_os
+_raw_os:
+    // Any illegal instruction is a failure
+    la t0, _s_trap_handler
+    csrw stvec, t0
+
+    // The Svinval sequence
+    li a0, 0x80200000
+    li a1, 0
+    .4byte 0x18000073  // sfence.w.inval
+    .4byte 0x16000073  // sinval.vma zero, zero
+    .4byte 0x16b50073  // sinval.vma a0, a1
+    .4byte 0x18100073  // sfence.inval.ir
+
+    li a6, 1           // Miralis ABI FID: success
+    li a7, 0x08475bcd  // Miralis ABI EID
+    ecall
+
+.align 4
+_s_trap_handler:
+    li a6, 0           // Miralis ABI FID: failure
+    li a7, 0x08475bcd  // Miralis ABI EID
+    ecall
+"#,
+);
+
+unsafe extern "C" {
+    fn _raw_os();
+}
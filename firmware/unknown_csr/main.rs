@@ -0,0 +1,114 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+
+use miralis_abi::{setup_binary, success};
+
+setup_binary!(main);
+
+/// A CSR address that is not allocated by the specification.
+const UNALLOCATED_CSR: usize = 0x1ff;
+
+/// The illegal instruction exception code.
+const ILLEGAL_INSTR: usize = 0x2;
+
+/// This test verifies that reading an unallocated CSR raises an illegal instruction exception.
+///
+/// Specifically, the test checks:
+/// 1. A read from the firmware is emulated by Miralis as a trap to the firmware trap handler.
+/// 2. A read from the payload (S-mode) is reported to the firmware as an illegal instruction
+///    originating from S-mode.
+fn main() -> ! {
+    let handler = _raw_trap_handler as usize;
+    let sentinel: usize = 0xdeadbeef;
+    let mut value: usize = sentinel;
+    let mcause: usize;
+    let mepc: usize;
+    let csr_addr: usize;
+
+    // Read from the firmware, the trap handler resumes execution after the faulting instruction
+    unsafe {
+        asm!(
+            "csrw mtvec, {mtvec}",
+            "auipc t4, 0",
+            "addi t4, t4, 12",
+            "csrr {value}, {csr}", // Should trap
+            "addi {csr_addr}, t4, -4",
+            "csrr {mcause}, mcause",
+            "csrr {mepc}, mepc",
+            mtvec = in(reg) handler,
+            csr = const UNALLOCATED_CSR,
+            value = inout(reg) value,
+            csr_addr = out(reg) csr_addr,
+            mcause = out(reg) mcause,
+            mepc = out(reg) mepc,
+            out("t4") _,
+        );
+    }
+
+    assert_eq!(value, sentinel, "The CSR read should not complete");
+    assert_eq!(mcause, ILLEGAL_INSTR, "Expected an illegal instruction");
+    assert_eq!(mepc, csr_addr, "mepc should point to the CSR read");
+
+    // Read from the payload, the trap handler resumes execution after the mret
+    let os: usize = _raw_os as usize;
+    let mpp: usize = 0b1 << 11; // MPP = S-mode
+    let mcause: usize;
+    let mstatus: usize;
+    unsafe {
+        asm!(
+            "li t4, 0xfffffffff",
+            "csrw pmpcfg0, 0xf",   // XRW TOR
+            "csrw pmpaddr0, t4",   // All memory
+            "auipc t4, 0",
+            "addi t4, t4, 20",
+            "csrw mstatus, {mpp}", // Write MPP of mstatus to S-mode
+            "csrw mepc, {os}",     // Write MEPC
+            "mret",                // Jump to OS
+            "csrr {mcause}, mcause",
+            "csrr {mstatus}, mstatus",
+            mpp = in(reg) mpp,
+            os = in(reg) os,
+            mcause = out(reg) mcause,
+            mstatus = out(reg) mstatus,
+            out("t4") _,
+        );
+    }
+
+    assert_eq!(mcause, ILLEGAL_INSTR, "Expected an illegal instruction");
+    assert_eq!((mstatus >> 11) & 0b11, 0b01, "Expected a trap from S-mode");
+
+    success();
+}
+
+// —————————————————————————————— Trap Handler —————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_trap_handler
+_raw_trap_handler:
+    jr t4
+"#,
+);
+
+// ———————————————————————————————— Guest OS ———————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_os
+_raw_os:
+    csrr t0, {csr} // Should trap
+    j _raw_os
+"#,
+    csr = const UNALLOCATED_CSR,
+);
+
+unsafe extern "C" {
+    fn _raw_trap_handler();
+    fn _raw_os();
+}
@@ -0,0 +1,119 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+
+use miralis_abi::{setup_binary, success};
+
+setup_binary!(main);
+
+/// The V extension bit in `misa`.
+const MISA_V: usize = 1 << 21;
+
+/// The VS field of `mstatus`.
+const VS_OFFSET: usize = 9;
+const VS_FILTER: usize = 0b11 << VS_OFFSET;
+const VS_INITIAL: usize = 0b01 << VS_OFFSET;
+const VS_CLEAN: usize = 0b10 << VS_OFFSET;
+
+/// The SD bit of `mstatus`.
+const SD_FILTER: usize = 1 << 63;
+
+/// This test verifies that `mstatus.VS` tracks the vector state dirtied by the firmware and the
+/// payload.
+///
+/// Specifically, the test checks:
+/// 1. The firmware writing `vcsr` with VS Initial makes `mstatus.VS` Dirty and sets SD.
+/// 2. The payload executing a vector instruction with VS Clean makes `mstatus.VS` Dirty and sets
+///    SD.
+fn main() -> ! {
+    let misa: usize;
+    unsafe { asm!("csrr {0}, misa", out(reg) misa) };
+    if misa & MISA_V == 0 {
+        log::info!("V extension is not supported, skipping test");
+        success();
+    }
+
+    // Start from the Initial state, then write vcsr (CSR 0x00F)
+    let mstatus: usize;
+    unsafe {
+        asm!(
+            "csrc mstatus, {vs}",
+            "csrs mstatus, {vs_initial}",
+            "csrw 0x00F, {vcsr}",
+            "csrr {mstatus}, mstatus",
+            vs = in(reg) VS_FILTER,
+            vs_initial = in(reg) VS_INITIAL,
+            vcsr = in(reg) 0b101usize,
+            mstatus = out(reg) mstatus,
+        );
+    }
+    assert_eq!(mstatus & VS_FILTER, VS_FILTER, "VS must be Dirty");
+    assert_ne!(mstatus & SD_FILTER, 0, "SD must be set when VS is Dirty");
+
+    let os: usize = _raw_os as usize;
+    let trap: usize = _raw_trap_handler as usize;
+    let mpp: usize = 0b1 << 11; // MPP = S-mode
+
+    // Jump into the payload with a clean vector state, it dirties it and traps back with an ecall
+    let mstatus: usize;
+    unsafe {
+        asm!(
+            "li t4, 0xfffffffff",
+            "csrw pmpcfg0, 0xf",   // XRW TOR
+            "csrw pmpaddr0, t4",   // All memory
+            "auipc t4, 0",
+            "addi t4, t4, 24",
+            "csrw mtvec, {mtvec}", // Write mtvec with trap handler
+            "csrw mstatus, {mstatus}", // Write MPP = S-mode and VS = Clean
+            "csrw mepc, {os}",     // Write MEPC
+            "mret",                // Jump to OS
+            "csrr {mstatus}, mstatus",
+            os = in(reg) os,
+            mtvec = in(reg) trap,
+            mstatus = inout(reg) mpp | VS_CLEAN => mstatus,
+            out("t0") _,
+            out("t4") _,
+            out("a7") _,
+        );
+    }
+    assert_eq!(mstatus & VS_FILTER, VS_FILTER, "VS must be Dirty");
+    assert_ne!(mstatus & SD_FILTER, 0, "SD must be set when VS is Dirty");
+
+    success();
+}
+
+// —————————————————————————————— Trap Handler —————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_trap_handler
+_raw_trap_handler:
+    jr t4
+"#,
+);
+
+// ———————————————————————————————— Guest OS ———————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_os
+_raw_os:
+.option push
+.option arch, +v
+    vsetivli t0, 4, e32, m1, ta, ma // Configure the vector unit
+    vmv.v.i v1, 0                   // Write a vector register to dirty the vector state
+.option pop
+    li a7, 0                        // Any non-Miralis EID, the ecall is forwarded to the firmware
+    ecall
+"#,
+);
+
+unsafe extern "C" {
+    fn _raw_trap_handler();
+    fn _raw_os();
+}
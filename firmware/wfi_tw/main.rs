@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+
+use miralis_abi::{identity_map, setup_binary, success};
+
+setup_binary!(main);
+
+/// mstatus.TW, wfi is illegal in lower privilege modes when set.
+const TW: usize = 1 << 21;
+
+/// Illegal instruction exception code.
+const ILLEGAL_INSTRUCTION: usize = 2;
+
+fn main() -> ! {
+    identity_map();
+
+    let os: usize = _raw_os as usize;
+    let trap: usize = _raw_trap_handler as usize;
+    let mstatus = (0b1 << 11) | TW; // MPP = S-mode, TW = 1
+
+    let mcause: usize;
+    let mepc: usize;
+
+    // The OS executes a wfi, which must trap to the firmware with TW = 1 instead of waiting
+    unsafe {
+        asm!(
+            "auipc t4, 0",
+            "addi t4, t4, 24",
+            "csrw mtvec, {mtvec}",      // Write mtvec with trap handler
+            "csrw mstatus, {mstatus}",  // Write MPP and TW of mstatus
+            "csrw mepc, {os}",          // Write MEPC
+            "mret",                     // Jump to OS
+            "csrr {mcause}, mcause",
+            "csrr {mepc}, mepc",
+            os = in(reg) os,
+            mtvec = in(reg) trap,
+            mstatus = in(reg) mstatus,
+            mcause = out(reg) mcause,
+            mepc = out(reg) mepc,
+            out("t4") _,
+        );
+    }
+
+    assert_eq!(
+        mcause, ILLEGAL_INSTRUCTION,
+        "wfi must raise an illegal instruction with TW = 1"
+    );
+    assert_eq!(mepc, os, "The trap must be raised by the wfi of the OS");
+
+    success();
+}
+
+// —————————————————————————————— Trap Handler —————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_trap_handler
+_raw_trap_handler:
+    jr t4
+"#,
+);
+
+// ———————————————————————————————— Guest OS ———————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_os
+_raw_os:
+    wfi
+    ecall
+"#,
+);
+
+unsafe extern "C" {
+    fn _raw_trap_handler();
+    fn _raw_os();
+}
@@ -0,0 +1,97 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+
+use miralis_abi::{identity_map, read_counters, setup_binary, success};
+
+setup_binary!(main);
+
+/// Number of round trips between the firmware and the OS.
+const NB_ROUND_TRIPS: usize = 100;
+
+/// An upper bound on the cost of a round trip, anything above is not sane.
+const MAX_CYCLES_PER_ROUND_TRIP: usize = 2_000_000;
+
+fn main() -> ! {
+    for _ in 0..NB_ROUND_TRIPS {
+        round_trip();
+    }
+
+    // Read the counters of hart 0 from the `exit_counter` module
+    let counters = read_counters(0);
+    // Only the switches from the OS to the firmware are counted, the cycles include both
+    // directions and therefore measure round trips.
+    let nb_switches = counters.world_switches;
+    let cycles = counters.world_switch_cycles;
+    assert!(
+        nb_switches >= NB_ROUND_TRIPS,
+        "Expected at least {} world switches, got {}",
+        NB_ROUND_TRIPS,
+        nb_switches
+    );
+
+    let latency = cycles / nb_switches;
+    log::info!("world-switch: {}", nb_switches);
+    log::info!("world-switch-latency: {}", latency);
+    assert!(
+        latency < MAX_CYCLES_PER_ROUND_TRIP,
+        "World switches are unexpectedly slow"
+    );
+
+    success();
+}
+
+/// Jump into the OS and come back, causing two world switches.
+fn round_trip() {
+    // The identity map is revoked each time the OS traps back
+    identity_map();
+
+    let os: usize = _raw_os as usize;
+    let trap: usize = _raw_trap_handler as usize;
+    let mpp = 0b1 << 11; // MPP = S-mode
+
+    unsafe {
+        asm!(
+            "auipc t4, 0",
+            "addi t4, t4, 24",
+            "csrw mtvec, {mtvec}", // Write mtvec with trap handler
+            "csrw mstatus, {mpp}", // Write MPP of mstatus to S-mode
+            "csrw mepc, {os}",     // Write MEPC
+            "mret",                // Jump to OS
+            os = in(reg) os,
+            mtvec = in(reg) trap,
+            mpp = in(reg) mpp,
+            out("t4") _,
+        );
+    }
+}
+
+// —————————————————————————————— Trap Handler —————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_trap_handler
+_raw_trap_handler:
+    jr t4
+"#,
+);
+
+// ———————————————————————————————— Guest OS ———————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_os
+_raw_os:
+    ecall
+"#,
+);
+
+unsafe extern "C" {
+    fn _raw_trap_handler();
+    fn _raw_os();
+}
@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+use core::ptr;
+
+use miralis_abi::{identity_map, setup_binary, success};
+
+setup_binary!(main);
+
+/// The memory targeted by the compare-and-swap instructions.
+static mut VALUE: u32 = 42;
+
+fn main() -> ! {
+    identity_map();
+
+    let os: usize = _raw_os as usize;
+    let trap: usize = _raw_trap_handler as usize;
+    let mpp = 0b1 << 11; // MPP = S-mode
+
+    let success_rd: usize;
+    let failure_rd: usize;
+
+    // The OS performs two compare-and-swap on VALUE:
+    // - amocas.w a1, a2, (a0) with a1 = 42 and a2 = 43, which succeeds
+    // - amocas.w a3, a2, (a0) with a3 = 7, which fails as VALUE is 43 by now
+    unsafe {
+        asm!(
+            "auipc t4, 0",
+            "addi t4, t4, 24",
+            "csrw mtvec, {mtvec}", // Write mtvec with trap handler
+            "csrw mstatus, {mpp}", // Write MPP of mstatus to S-mode
+            "csrw mepc, {os}",     // Write MEPC
+            "mret",                // Jump to OS
+            os = in(reg) os,
+            mtvec = in(reg) trap,
+            mpp = in(reg) mpp,
+            out("t4") _,
+            in("a0") &raw mut VALUE,
+            inout("a1") 42 => success_rd,
+            in("a2") 43,
+            inout("a3") 7 => failure_rd,
+        );
+    }
+
+    let value = unsafe { ptr::read_volatile(&raw const VALUE) };
+    assert_eq!(success_rd, 42, "A successful CAS returns the old value");
+    assert_eq!(failure_rd, 43, "A failing CAS returns the current value");
+    assert_eq!(value, 43, "Only the successful CAS must update memory");
+
+    success();
+}
+
+// —————————————————————————————— Trap Handler —————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_trap_handler
+_raw_trap_handler:
+    jr t4
+"#,
+);
+
+// ———————————————————————————————— Guest OS ———————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_os
+_raw_os:
+    .word 0x28c525af   // amocas.w a1, a2, (a0)
+    .word 0x28c526af   // amocas.w a3, a2, (a0)
+    ecall
+"#,
+);
+
+unsafe extern "C" {
+    fn _raw_trap_handler();
+    fn _raw_os();
+}
@@ -0,0 +1,92 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+use core::ptr;
+
+use miralis_abi::{identity_map, setup_binary, success};
+
+setup_binary!(main);
+
+/// The memory holding the reservation of the OS.
+static mut VALUE: u32 = 42;
+
+fn main() -> ! {
+    identity_map();
+
+    // The firmware waits without a reservation, which must return right away
+    unsafe {
+        asm!(
+            ".word 0x00d00073", // wrs.nto
+            ".word 0x01d00073", // wrs.sto
+        );
+    }
+
+    let os: usize = _raw_os as usize;
+    let trap: usize = _raw_trap_handler as usize;
+    let mpp = 0b1 << 11; // MPP = S-mode
+
+    let loaded: usize;
+
+    // The OS reserves VALUE with an LR, invalidates the reservation with a store of 43, and
+    // then waits on the reservation set. The waits must return for the OS to reach its ecall.
+    unsafe {
+        asm!(
+            "auipc t4, 0",
+            "addi t4, t4, 24",
+            "csrw mtvec, {mtvec}", // Write mtvec with trap handler
+            "csrw mstatus, {mpp}", // Write MPP of mstatus to S-mode
+            "csrw mepc, {os}",     // Write MEPC
+            "mret",                // Jump to OS
+            os = in(reg) os,
+            mtvec = in(reg) trap,
+            mpp = in(reg) mpp,
+            out("t4") _,
+            in("a0") &raw mut VALUE,
+            out("a1") loaded,
+            in("a2") 43,
+        );
+    }
+
+    let value = unsafe { ptr::read_volatile(&raw const VALUE) };
+    assert_eq!(loaded, 42, "The LR must return the initial value");
+    assert_eq!(
+        value, 43,
+        "The store must invalidate the reservation and update memory"
+    );
+
+    success();
+}
+
+// —————————————————————————————— Trap Handler —————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_trap_handler
+_raw_trap_handler:
+    jr t4
+"#,
+);
+
+// ———————————————————————————————— Guest OS ———————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_os
+_raw_os:
+    lr.w a1, (a0)
+    sw a2, 0(a0)
+    .word 0x00d00073   // wrs.nto
+    .word 0x01d00073   // wrs.sto
+    ecall
+"#,
+);
+
+unsafe extern "C" {
+    fn _raw_trap_handler();
+    fn _raw_os();
+}
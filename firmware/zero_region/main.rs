@@ -0,0 +1,32 @@
+#![no_std]
+#![no_main]
+
+use core::ptr;
+
+use miralis_abi::{setup_binary, success, zero_region};
+
+setup_binary!(main);
+
+/// The memory zeroed by Miralis, the first and last bytes are outside of the zeroed region.
+static mut REGION: [u8; 256] = [0; 256];
+
+fn main() -> ! {
+    let region = &raw mut REGION;
+    let base = region as usize;
+
+    // Fill the region with a recognizable pattern
+    for i in 0..256 {
+        unsafe { ptr::write_volatile((base as *mut u8).add(i), 0xaa) };
+    }
+
+    zero_region(base + 1, 254).expect("Failed to zero the region");
+
+    for i in 0..256 {
+        let byte = unsafe { ptr::read_volatile((base as *const u8).add(i)) };
+        let expected = if i == 0 || i == 255 { 0xaa } else { 0 };
+        assert_eq!(byte, expected, "Unexpected value at offset {i}");
+    }
+
+    log::info!("The region has been zeroed");
+    success();
+}
@@ -0,0 +1,73 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+
+use miralis_abi::{setup_binary, success};
+
+setup_binary!(main);
+
+/// The value of the virtual cycle counter.
+const CYCLE: usize = 0x1234_5678;
+/// The value of the virtual instret counter.
+const INSTRET: usize = 0x42;
+/// Enable the cycle, time and instret counters.
+const COUNTEREN: usize = 0b111;
+
+/// This test verifies that the payload reads of the basic counters are served from the virtual
+/// counters when they are virtualized by Miralis.
+///
+/// Specifically, the test checks:
+/// 1. `rdcycle` and `rdinstret` return the values of the virtual `mcycle` and `minstret`.
+/// 2. `rdtime` keeps returning the platform time.
+fn main() -> ! {
+    let mpp: usize = 0b1 << 11; // MPP = S-mode
+    unsafe {
+        asm!(
+            "csrw mcycle, {cycle}",
+            "csrw minstret, {instret}",
+            "csrw mcounteren, {counteren}",
+            "li t4, 0xfffffffff",
+            "csrw pmpcfg0, 0xf",   // XRW TOR
+            "csrw pmpaddr0, t4",   // All memory
+            "csrw mstatus, {mpp}", // Write MPP of mstatus to S-mode
+            "csrw mepc, {os}",     // Write MEPC
+            "mret",                // Jump to OS
+            cycle = in(reg) CYCLE,
+            instret = in(reg) INSTRET,
+            counteren = in(reg) COUNTEREN,
+            mpp = in(reg) mpp,
+            os = in(reg) os_main as usize,
+            options(noreturn),
+        );
+    }
+}
+
+// ———————————————————————————————— Guest OS ———————————————————————————————— //
+
+extern "C" fn os_main() -> ! {
+    let cycle: usize;
+    let instret: usize;
+    let time_before: usize;
+    let time_after: usize;
+    unsafe {
+        asm!(
+            "rdtime {time_before}",
+            "rdcycle {cycle}",
+            "rdinstret {instret}",
+            "rdtime {time_after}",
+            time_before = out(reg) time_before,
+            cycle = out(reg) cycle,
+            instret = out(reg) instret,
+            time_after = out(reg) time_after,
+        );
+    }
+
+    assert_eq!(cycle, CYCLE, "cycle must track the virtual mcycle");
+    assert_eq!(instret, INSTRET, "instret must track the virtual minstret");
+    assert!(time_before != 0, "time must be read from the platform");
+    assert!(time_after >= time_before, "time must not go backward");
+
+    log::info!("Virtual basic counters are served to the payload");
+    success();
+}
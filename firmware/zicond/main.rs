@@ -0,0 +1,104 @@
+#![no_std]
+#![no_main]
+
+use core::arch::{asm, global_asm};
+
+use miralis_abi::{identity_map, setup_binary, success};
+
+setup_binary!(main);
+
+/// A marker for registers that must be overwritten by the conditional-zero instructions.
+const UNWRITTEN: usize = 0xdead;
+
+fn main() -> ! {
+    identity_map();
+
+    let os: usize = _raw_os as usize;
+    let trap: usize = _raw_trap_handler as usize;
+    let mpp = 0b1 << 11; // MPP = S-mode
+    let medeleg = 0b1 << 2; // Illegal instructions are delegated to the OS
+
+    let eqz_zero: usize;
+    let eqz_non_zero: usize;
+    let nez_zero: usize;
+    let nez_non_zero: usize;
+
+    // The OS executes both conditional-zero instructions on a0 = 42, with a zero (a1) and a
+    // non-zero (a2) condition. Illegal instructions are delegated, Miralis must still trap and
+    // emulate the conditional-zero instructions.
+    unsafe {
+        asm!(
+            "auipc t4, 0",
+            "addi t4, t4, 28",
+            "csrw mtvec, {mtvec}",     // Write mtvec with trap handler
+            "csrw medeleg, {medeleg}", // Delegate illegal instructions
+            "csrw mstatus, {mpp}",     // Write MPP of mstatus to S-mode
+            "csrw mepc, {os}",         // Write MEPC
+            "mret",                    // Jump to OS
+            os = in(reg) os,
+            mtvec = in(reg) trap,
+            mpp = in(reg) mpp,
+            medeleg = in(reg) medeleg,
+            out("t4") _,
+            in("a0") 42,
+            in("a1") 0,
+            in("a2") 7,
+            inout("a3") UNWRITTEN => eqz_zero,
+            inout("a4") UNWRITTEN => eqz_non_zero,
+            inout("a5") UNWRITTEN => nez_zero,
+            inout("a6") UNWRITTEN => nez_non_zero,
+        );
+    }
+
+    assert_eq!(
+        eqz_zero, 0,
+        "czero.eqz with a zero condition must return zero"
+    );
+    assert_eq!(
+        eqz_non_zero, 42,
+        "czero.eqz with a non-zero condition must return rs1"
+    );
+    assert_eq!(
+        nez_zero, 42,
+        "czero.nez with a zero condition must return rs1"
+    );
+    assert_eq!(
+        nez_non_zero, 0,
+        "czero.nez with a non-zero condition must return zero"
+    );
+
+    success();
+}
+
+// —————————————————————————————— Trap Handler —————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_trap_handler
+_raw_trap_handler:
+    jr t4
+"#,
+);
+
+// ———————————————————————————————— Guest OS ———————————————————————————————— //
+
+global_asm!(
+    r#"
+.text
+.align 4
+.global _raw_os
+_raw_os:
+    .word 0x0eb556b3   // czero.eqz a3, a0, a1
+    .word 0x0ec55733   // czero.eqz a4, a0, a2
+    .word 0x0eb577b3   // czero.nez a5, a0, a1
+    .word 0x0ec57833   // czero.nez a6, a0, a2
+    ecall
+"#,
+);
+
+unsafe extern "C" {
+    fn _raw_trap_handler();
+    fn _raw_os();
+}
@@ -10,6 +10,7 @@ SECTIONS
     *(.text)
     *(.text.*)
   }
+  _text_stop = .;
 
   /* Output the rodata */
   .rodata : ALIGN(0x8) {
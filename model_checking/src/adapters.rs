@@ -61,7 +61,7 @@ pub fn miralis_to_rv_core(ctx: &VirtContext) -> Core {
     core.menvcfg = raw::MEnvcfg {
         bits: bv(ctx.csr.menvcfg as u64),
     };
-    // sail_ctx.mseccfg = BitField::new(ctx.csr.mseccfg as u64);
+    // The Sail model does not implement Smepmp, mseccfg is left out of the conversion
     core.mcause = raw::Mcause {
         bits: bv(ctx.csr.mcause as u64),
     };
@@ -91,7 +91,7 @@ pub fn miralis_to_rv_core(ctx: &VirtContext) -> Core {
     };
     core.stval = bv(ctx.csr.stval as u64);
     core.satp = bv(ctx.csr.satp as u64);
-    // sail_ctx.scontext = BitField::new(ctx.csr.scontext as u64);
+    // Sail does not model the debug context CSRs (scontext and mcontext)
     core.medeleg = raw::Medeleg {
         bits: bv(ctx.csr.medeleg as u64),
     };
@@ -204,7 +204,7 @@ pub fn rv_core_to_miralis(mut sail_ctx: Core, mctx: &MiralisContext) -> VirtCont
     ctx.csr.mcountinhibit = sail_ctx.mcountinhibit.bits.bits() as u32;
     ctx.csr.mcounteren = sail_ctx.mcounteren.bits.bits() as u32;
     ctx.csr.menvcfg = sail_ctx.menvcfg.bits.bits() as usize;
-    // ctx.csr.mseccfg= sail_ctx.mseccfg.bits.bits() as usize;
+    // The Sail model does not implement Smepmp, mseccfg is left out of the conversion
     ctx.csr.mcause = sail_ctx.mcause.bits.bits() as usize;
     ctx.csr.mepc = sail_ctx.mepc.bits() as usize;
     ctx.csr.mtval = sail_ctx.mtval.bits() as usize;
@@ -217,7 +217,7 @@ pub fn rv_core_to_miralis(mut sail_ctx: Core, mctx: &MiralisContext) -> VirtCont
     ctx.csr.scause = sail_ctx.scause.bits.bits() as usize;
     ctx.csr.stval = sail_ctx.stval.bits() as usize;
     ctx.csr.satp = sail_ctx.satp.bits() as usize;
-    // ctx.csr.scontext= sail_ctx.scontext.bits.bits() as usize;
+    // Sail does not model the debug context CSRs (scontext and mcontext)
     ctx.csr.medeleg = sail_ctx.medeleg.bits.bits() as usize;
     ctx.csr.mideleg = sail_ctx.mideleg.bits.bits() as usize;
     ctx.csr.pmpcfg = pmpcfg_sail_to_miralis(sail_ctx.pmpcfg_n);
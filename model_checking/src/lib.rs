@@ -1,11 +1,15 @@
+use miralis::arch::menvcfg::CboInval;
 use miralis::arch::metal::SOFT_CORE;
-use miralis::arch::pmp::pmplayout;
-use miralis::arch::{MCause, Register, csr, mie, mstatus, write_pmp};
-use miralis::decoder::IllegalInst;
+use miralis::arch::pmp::{PmpGroup, pmpcfg, pmplayout};
+use miralis::arch::{
+    AccessKind, Csr, MCause, Mode, Register, csr, debug_context, hgatp, menvcfg, mie, misa,
+    mstatus, parse_mpp_return_mode, write_pmp,
+};
+use miralis::decoder::{IllegalInst, ZicondInstr, ZicondOp};
 use miralis::host::MiralisContext;
 use miralis::platform::{Plat, Platform};
-use miralis::virt::VirtContext;
 use miralis::virt::traits::{HwRegisterContextSetter, RegisterContextGetter};
+use miralis::virt::{ExecutionMode, VirtContext, is_illegal_csr_access};
 use softcore_rv64::prelude::{BitVector, bv};
 use softcore_rv64::raw;
 use softcore_rv64::raw::{AccessType, Minterrupts, Pmpcfg_ent, Privilege, regidx};
@@ -22,7 +26,7 @@ mod adapters;
 mod model;
 
 #[cfg_attr(kani, kani::proof)]
-#[cfg_attr(test, test)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
 pub fn mret() {
     let (mut ctx, mut mctx, mut core) = symbolic::new_symbolic_contexts();
 
@@ -37,7 +41,147 @@ pub fn mret() {
 }
 
 #[cfg_attr(kani, kani::proof)]
-#[cfg_attr(test, test)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
+pub fn mret_mprv() {
+    let (mut ctx, mut mctx, _) = symbolic::new_symbolic_contexts();
+
+    // MRET must clear MPRV when returning to a mode less privileged than M
+    ctx.csr.mstatus |= mstatus::MPRV_FILTER;
+    let mut core = miralis_to_rv_core(&ctx);
+    let returns_to_m_mode = parse_mpp_return_mode(ctx.csr.mstatus) == Mode::M;
+
+    ctx.emulate_mret(&mut mctx);
+    model::execute_MRET(&mut core);
+
+    assert_eq!(
+        ctx.csr.mstatus & mstatus::MPRV_FILTER != 0,
+        returns_to_m_mode,
+        "mret must clear MPRV if and only if it returns to a lower privilege mode"
+    );
+    assert_eq!(
+        ctx,
+        adapters::rv_core_to_miralis(core, &mctx),
+        "mret instruction emulation is not correct"
+    );
+}
+
+#[cfg_attr(kani, kani::proof)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
+pub fn mret_interrupt_enable() {
+    let (mut ctx, mut mctx, _) = symbolic::new_symbolic_contexts();
+
+    // MPIE differs from MIE before returning, so that MIE must change
+    let mpie = any!(bool);
+    ctx.csr.mstatus &= !(mstatus::MIE_FILTER | mstatus::MPIE_FILTER);
+    ctx.csr.mstatus |= if mpie {
+        mstatus::MPIE_FILTER
+    } else {
+        mstatus::MIE_FILTER
+    };
+    let mut core = miralis_to_rv_core(&ctx);
+    let return_mode = parse_mpp_return_mode(ctx.csr.mstatus);
+    let mprv = ctx.csr.mstatus & mstatus::MPRV_FILTER != 0;
+    let lowest_mode = if ctx.csr.misa & misa::U != 0 {
+        Mode::U
+    } else {
+        Mode::M
+    };
+
+    ctx.emulate_mret(&mut mctx);
+    model::execute_MRET(&mut core);
+
+    let mstatus = ctx.csr.mstatus;
+    assert_eq!(
+        mstatus & mstatus::MIE_FILTER != 0,
+        mpie,
+        "MIE must be set to MPIE"
+    );
+    assert_ne!(mstatus & mstatus::MPIE_FILTER, 0, "MPIE must be set");
+    assert_eq!(
+        parse_mpp_return_mode(mstatus),
+        lowest_mode,
+        "MPP must be set to the lowest supported mode"
+    );
+    assert_eq!(
+        mstatus & mstatus::MPRV_FILTER != 0,
+        mprv && return_mode == Mode::M,
+        "MPRV must be cleared when returning to a mode less privileged than M"
+    );
+    assert_eq!(
+        ctx,
+        adapters::rv_core_to_miralis(core, &mctx),
+        "mret instruction emulation is not correct"
+    );
+}
+
+#[cfg_attr(kani, kani::proof)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
+pub fn mret_pc_alignment() {
+    let (mut ctx, mut mctx, _) = symbolic::new_symbolic_contexts();
+
+    // A 2-bytes aligned mepc, which is misaligned without the C extension
+    let has_c = any!(bool);
+    if has_c {
+        ctx.csr.misa |= misa::C;
+    } else {
+        ctx.csr.misa &= !misa::C;
+    }
+    ctx.csr.mepc = any!(usize) & !0b1;
+    let mepc = ctx.csr.mepc;
+    let mut core = miralis_to_rv_core(&ctx);
+
+    ctx.emulate_mret(&mut mctx);
+    model::execute_MRET(&mut core);
+
+    let expected_pc = if has_c { mepc } else { mepc & !0b11 };
+    assert_eq!(ctx.pc, expected_pc, "mret must align the return address");
+    assert_eq!(
+        ctx,
+        adapters::rv_core_to_miralis(core, &mctx),
+        "mret alignment of the return address is not correct"
+    );
+}
+
+#[cfg_attr(kani, kani::proof)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
+pub fn mret_mpv() {
+    let (mut ctx, mut mctx, _) = symbolic::new_symbolic_contexts();
+
+    // The Sail model does not implement the H extension, so we check the virtualization mode
+    // against the specification directly and the rest of the state against the emulation without
+    // the H extension.
+    ctx.csr.mstatus &= !mstatus::MPV_FILTER;
+    let mut reference = ctx.clone();
+    ctx.extensions.has_h_extension = true;
+    ctx.csr.mstatus |= mstatus::MPV_FILTER;
+    let returns_to_m_mode = parse_mpp_return_mode(ctx.csr.mstatus) == Mode::M;
+
+    ctx.emulate_mret(&mut mctx);
+    reference.emulate_mret(&mut mctx);
+
+    assert_eq!(
+        ctx.is_virtualized(),
+        !returns_to_m_mode,
+        "mret must restore the virtualization mode from MPV"
+    );
+    if returns_to_m_mode {
+        assert_eq!(
+            ctx.csr.mstatus & mstatus::MPV_FILTER,
+            0,
+            "mret to M-mode must clear MPV"
+        );
+    }
+
+    ctx.extensions.has_h_extension = false;
+    ctx.csr.mstatus &= !mstatus::MPV_FILTER;
+    assert_eq!(
+        ctx, reference,
+        "mret with MPV set must not modify the rest of the state"
+    );
+}
+
+#[cfg_attr(kani, kani::proof)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
 pub fn sret() {
     let (mut ctx, mut mctx, mut core) = symbolic::new_symbolic_contexts();
 
@@ -52,7 +196,85 @@ pub fn sret() {
 }
 
 #[cfg_attr(kani, kani::proof)]
-#[cfg_attr(test, test)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
+pub fn sret_spp() {
+    let (mut ctx, mut mctx, _) = symbolic::new_symbolic_contexts();
+
+    // Baseline: no interrupt is pending for M-mode
+    ctx.csr.mip &= ctx.csr.mideleg;
+
+    // SPP selects the return privilege: 1 for S-mode, 0 for U-mode
+    let spp = any!(bool);
+    if spp {
+        ctx.csr.mstatus |= mstatus::SPP_FILTER;
+    } else {
+        ctx.csr.mstatus &= !mstatus::SPP_FILTER;
+    }
+    let expected_mode = if spp { Mode::S } else { Mode::U };
+    let mut core = miralis_to_rv_core(&ctx);
+    assert_eq!(
+        raw::_get_Sstatus_SPP(raw::lower_mstatus(core.mstatus)),
+        bv(spp as u64),
+        "SPP is not the same in the reference"
+    );
+
+    ctx.emulate_sret(&mut mctx);
+    model::execute_SRET(&mut core);
+
+    assert_eq!(
+        ctx.mode, expected_mode,
+        "sret must return to the mode in SPP"
+    );
+    assert_eq!(
+        ctx.csr.mstatus & mstatus::SPP_FILTER,
+        0,
+        "sret must set SPP to U-mode"
+    );
+    assert_eq!(
+        raw::_get_Sstatus_SPP(raw::lower_mstatus(core.mstatus)),
+        bv(0),
+        "sret must set SPP to U-mode in the reference"
+    );
+    assert_eq!(
+        ctx,
+        adapters::rv_core_to_miralis(core, &mctx),
+        "sret instruction emulation is not correct"
+    );
+}
+
+#[cfg_attr(kani, kani::proof)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
+pub fn sret_pc_alignment() {
+    let (mut ctx, mut mctx, _) = symbolic::new_symbolic_contexts();
+
+    // Baseline: no interrupt is pending for M-mode
+    ctx.csr.mip &= ctx.csr.mideleg;
+
+    // A 2-bytes aligned sepc, which is misaligned without the C extension
+    let has_c = any!(bool);
+    if has_c {
+        ctx.csr.misa |= misa::C;
+    } else {
+        ctx.csr.misa &= !misa::C;
+    }
+    ctx.csr.sepc = any!(usize) & !0b1;
+    let sepc = ctx.csr.sepc;
+    let mut core = miralis_to_rv_core(&ctx);
+
+    ctx.emulate_sret(&mut mctx);
+    model::execute_SRET(&mut core);
+
+    let expected_pc = if has_c { sepc } else { sepc & !0b11 };
+    assert_eq!(ctx.pc, expected_pc, "sret must align the return address");
+    assert_eq!(
+        ctx,
+        adapters::rv_core_to_miralis(core, &mctx),
+        "sret alignment of the return address is not correct"
+    );
+}
+
+#[cfg_attr(kani, kani::proof)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
 pub fn wfi() {
     let (mut ctx, mut mctx, mut core) = symbolic::new_symbolic_contexts();
 
@@ -77,10 +299,6 @@ fn generate_csr_register() -> u64 {
     if csr == 0b000100000010 || csr == 0b000100000011 {
         csr = 0x0;
     }
-    // Some CSRs are not supported by the spec
-    if csr == csr::SCONTEXT as u64 {
-        csr = 0;
-    }
     // And some others return non-deterministic values
     if csr == csr::CYCLE as u64
         || csr == csr::INSTRET as u64
@@ -99,8 +317,28 @@ fn generate_csr_register() -> u64 {
     csr
 }
 
+/// Returns the implemented bits of the debug context CSRs (Sdtrig), which are not modeled by Sail.
+fn debug_context_filter(csr: Csr, mctx: &MiralisContext) -> Option<usize> {
+    match csr {
+        Csr::Mcontext => Some(debug_context::mcontext_filter(
+            mctx.hw.extensions.has_h_extension,
+        )),
+        Csr::Scontext => Some(debug_context::SCONTEXT_FILTER),
+        _ => None,
+    }
+}
+
+/// Returns the virtual register backing a debug context CSR.
+fn debug_context_csr(ctx: &mut VirtContext, csr: Csr) -> &mut usize {
+    match csr {
+        Csr::Mcontext => &mut ctx.csr.mcontext,
+        Csr::Scontext => &mut ctx.csr.scontext,
+        _ => unreachable!("Not a debug context CSR: {:?}", csr),
+    }
+}
+
 #[cfg_attr(kani, kani::proof)]
-#[cfg_attr(test, test)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
 pub fn fences() {
     {
         let (mut ctx, mut mctx, mut core) = symbolic::new_symbolic_contexts();
@@ -161,14 +399,37 @@ pub fn fences() {
 }
 
 #[cfg_attr(kani, kani::proof)]
-#[cfg_attr(test, test)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
 pub fn read_csr() {
-    let (ctx, mctx, mut core) = symbolic::new_symbolic_contexts();
+    let (mut ctx, mctx, mut core) = symbolic::new_symbolic_contexts();
 
     let csr_register = generate_csr_register();
+    let decoded_csr = mctx.decode_csr(csr_register as usize);
+
+    // Sail does not model the debug context CSRs, we check them against their specification
+    if let Some(filter) = debug_context_filter(decoded_csr, &mctx) {
+        let value = any!(usize) & filter;
+        *debug_context_csr(&mut ctx, decoded_csr) = value;
+        assert_eq!(ctx.get(decoded_csr), value, "Invalid debug context read");
+        return;
+    }
+
+    // Unallocated CSRs: reads must raise an illegal instruction exception
+    if decoded_csr.is_unknown() {
+        // csrrs x5, csr, x0
+        let csrr = ((csr_register as usize) << 20) | (0b010 << 12) | (5 << 7) | 0b1110011;
+        assert!(
+            is_illegal_csr_access(&mctx.decode_illegal_instruction(csrr)),
+            "Reading an unallocated CSR must trap"
+        );
+        assert!(
+            core.get_csr(csr_register).is_none(),
+            "Miralis traps on a CSR implemented by the reference core"
+        );
+        return;
+    }
 
     // Read value from Miralis
-    let decoded_csr = mctx.decode_csr(csr_register as usize);
     let miralis_value = ctx.get(decoded_csr);
 
     // Read value from Sail
@@ -181,14 +442,18 @@ pub fn read_csr() {
             );
         }
         None => {
-            // For all invalid CSRs Miralis returns 0
-            assert_eq!(miralis_value, 0, "Invalid register should return 0");
+            // The CSR is allocated but not modelled by the reference core (e.g. PMP and HPM
+            // counters beyond the ones it implements), Miralis exposes them as hardwired zero.
+            assert_eq!(
+                miralis_value, 0,
+                "Allocated but unimplemented CSR should return 0"
+            );
         }
     }
 }
 
 #[cfg_attr(kani, kani::proof)]
-#[cfg_attr(test, test)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
 pub fn write_csr() {
     let (mut ctx, mut mctx, mut core) = symbolic::new_symbolic_contexts();
     let mut csr_register = generate_csr_register();
@@ -207,6 +472,21 @@ pub fn write_csr() {
     let decoded_csr = mctx.decode_csr(csr_register as usize);
     ctx.set_csr(decoded_csr, value_to_write, &mut mctx);
 
+    // Sail does not model the debug context CSRs, we check them against their specification
+    if let Some(filter) = debug_context_filter(decoded_csr, &mctx) {
+        let csr = debug_context_csr(&mut ctx, decoded_csr);
+        assert_eq!(*csr, value_to_write & filter, "Invalid debug context write");
+
+        // The rest of the context must be left untouched
+        *csr = 0;
+        assert_eq!(
+            rv_core_to_miralis(core, &mctx).csr,
+            ctx.csr,
+            "CSR write does not match the specification"
+        );
+        return;
+    }
+
     assert_eq!(
         core.cur_privilege,
         Privilege::Machine,
@@ -229,7 +509,147 @@ pub fn write_csr() {
 }
 
 #[cfg_attr(kani, kani::proof)]
-#[cfg_attr(test, test)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
+pub fn write_sip() {
+    let (mut ctx, mut mctx, _) = symbolic::new_symbolic_contexts();
+
+    // Writes to sip depend on the delegated interrupts, so we vary mideleg
+    ctx.csr.mideleg = (any!(usize) & mie::ALL_INT) | mie::MIDELEG_READ_ONLY_ONE;
+    let mut core = miralis_to_rv_core(&ctx);
+
+    // Write sip in both Miralis and Sail
+    let value_to_write = any!(usize);
+    ctx.set_csr(Csr::Sip, value_to_write, &mut mctx);
+    core.set_csr(0x144, value_to_write as u64);
+
+    assert_eq!(
+        rv_core_to_miralis(core, &mctx).csr,
+        ctx.csr,
+        "sip write does not match the specification"
+    );
+}
+
+#[cfg_attr(kani, kani::proof)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
+pub fn write_sstatus() {
+    let (mut ctx, mut mctx, mut core) = symbolic::new_symbolic_contexts();
+    let uxl = ctx.csr.mstatus & mstatus::UXL_FILTER;
+
+    // Write sstatus in both Miralis and Sail, with arbitrary UXL bits
+    let value_to_write = any!(usize);
+    ctx.set_csr(Csr::Sstatus, value_to_write, &mut mctx);
+    core.set_csr(csr::SSTATUS as u64, value_to_write as u64);
+
+    assert_eq!(
+        ctx.csr.mstatus & mstatus::UXL_FILTER,
+        uxl,
+        "sstatus write must not change UXL"
+    );
+    assert_eq!(
+        rv_core_to_miralis(core, &mctx).csr,
+        ctx.csr,
+        "sstatus write does not match the specification"
+    );
+}
+
+#[cfg_attr(kani, kani::proof)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
+pub fn write_mtvec() {
+    let (mut ctx, mut mctx, _) = symbolic::new_symbolic_contexts();
+
+    // Start from a vectored mtvec, then write an arbitrary base with any mode
+    ctx.csr.mtvec = (any!(usize) & !0b11) | 0b01;
+    let mut core = miralis_to_rv_core(&ctx);
+    let value_to_write = any!(usize);
+
+    ctx.set_csr(Csr::Mtvec, value_to_write, &mut mctx);
+    core.set_csr(0x305, value_to_write as u64);
+
+    // The base is always kept, and reserved modes preserve the vectored mode
+    let expected_mode = match value_to_write & 0b11 {
+        0b00 => 0b00,
+        _ => 0b01,
+    };
+    assert_eq!(
+        ctx.csr.mtvec & !0b11,
+        value_to_write & !0b11,
+        "Invalid base"
+    );
+    assert_eq!(ctx.csr.mtvec & 0b11, expected_mode, "Invalid mode");
+    assert_eq!(
+        rv_core_to_miralis(core, &mctx).csr,
+        ctx.csr,
+        "mtvec write does not match the specification"
+    );
+}
+
+#[cfg_attr(kani, kani::proof)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
+pub fn write_xepc() {
+    let (mut ctx, mut mctx, _) = symbolic::new_symbolic_contexts();
+    let mut core = miralis_to_rv_core(&ctx);
+
+    // Write an arbitrary, possibly odd, value to mepc or sepc
+    let (csr, csr_register) = if any!(bool) {
+        (Csr::Mepc, csr::MEPC)
+    } else {
+        (Csr::Sepc, csr::SEPC)
+    };
+    let value_to_write = any!(usize);
+
+    ctx.set_csr(csr, value_to_write, &mut mctx);
+    core.set_csr(csr_register as u64, value_to_write as u64);
+
+    assert_eq!(
+        ctx.get(csr) & 0b1,
+        0,
+        "Bit 0 of xepc must always be cleared"
+    );
+    assert_eq!(
+        rv_core_to_miralis(core, &mctx).csr,
+        ctx.csr,
+        "xepc write does not match the specification"
+    );
+}
+
+#[cfg_attr(kani, kani::proof)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
+pub fn hgatp_read_write() {
+    let (mut ctx, mut mctx, _) = symbolic::new_symbolic_contexts();
+
+    // Sail does not model the H extension, we check hgatp against its specification
+    ctx.extensions.has_h_extension = true;
+    mctx.hw.extensions.has_h_extension = true;
+
+    // Start from a legal hgatp, either Bare or Sv39x4
+    let mode = if any!(bool) { 0b1000 } else { 0b0000 };
+    ctx.csr.hgatp = (mode << hgatp::MODE_OFFSET)
+        | (any!(usize) & (hgatp::VMID_FILTER | hgatp::PPN_FILTER) & !0b11);
+    let mut expected = ctx.clone();
+
+    let value_to_write = any!(usize);
+    ctx.set_csr(Csr::Hgatp, value_to_write, &mut mctx);
+
+    // Only Bare and Sv39x4 are supported, other modes preserve the previous value
+    let legal = value_to_write & (hgatp::MODE_FILTER | hgatp::VMID_FILTER | hgatp::PPN_FILTER);
+    match (value_to_write & hgatp::MODE_FILTER) >> hgatp::MODE_OFFSET {
+        0b0000 => expected.csr.hgatp = legal,
+        0b1000 => expected.csr.hgatp = legal & !0b11,
+        _ => {}
+    }
+    assert_eq!(
+        ctx.get(Csr::Hgatp),
+        expected.csr.hgatp,
+        "Invalid hgatp read"
+    );
+    assert_eq!(
+        ctx, expected,
+        "hgatp write does not match the specification"
+    );
+}
+
+#[cfg_attr(kani, kani::proof)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
 pub fn interrupt_virtualization() {
     let (mut ctx, mctx, mut core) = symbolic::new_symbolic_contexts();
 
@@ -239,7 +659,7 @@ pub fn interrupt_virtualization() {
 
     // Check the virtualization
     core.dispatch_interrupt();
-    ctx.check_and_inject_interrupts();
+    ctx.check_and_inject_interrupts(ExecutionMode::Firmware);
 
     // Verify the results
     assert_eq!(
@@ -250,7 +670,202 @@ pub fn interrupt_virtualization() {
 }
 
 #[cfg_attr(kani, kani::proof)]
-#[cfg_attr(test, test)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
+pub fn interrupt_mie_gating() {
+    let (mut ctx, mctx, _) = symbolic::new_symbolic_contexts();
+
+    // The firmware runs in M-mode, possibly waking up from a WFI, with a symbolic mstatus.MIE
+    ctx.mode = Mode::M;
+    ctx.csr.mideleg = 0;
+    ctx.is_wfi = any!(bool);
+    let enabled = any!(bool);
+    if enabled {
+        ctx.csr.mstatus |= mstatus::MIE_FILTER;
+    } else {
+        ctx.csr.mstatus &= !mstatus::MIE_FILTER;
+    }
+    let mut core = miralis_to_rv_core(&ctx);
+    assert_eq!(
+        raw::_get_Mstatus_MIE(core.mstatus),
+        bv(enabled as u64),
+        "MIE is not the same in the reference"
+    );
+    let mut prev = ctx.clone();
+
+    core.dispatch_interrupt();
+    ctx.check_and_inject_interrupts(ExecutionMode::Firmware);
+
+    if !enabled {
+        // The interrupt must stay pending, only the WFI state is cleared
+        prev.is_wfi = false;
+        assert_eq!(ctx, prev, "No interrupt should be delivered when MIE is 0");
+    }
+    assert_eq!(
+        ctx,
+        rv_core_to_miralis(core, &mctx),
+        "Interrupt gating by mstatus.MIE doesn't work properly"
+    );
+}
+
+#[cfg_attr(kani, kani::proof)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
+pub fn delegated_interrupt_sie_gating() {
+    let (mut ctx, mctx, _) = symbolic::new_symbolic_contexts();
+
+    // The vCPU runs in S-mode with a pending delegated interrupt, but sstatus.SIE is 0
+    let (interrupt, int_id) = match any!(u8) % 3 {
+        0 => (mie::SSIE_FILTER, mie::SSIE_OFFSET),
+        1 => (mie::STIE_FILTER, mie::STIE_OFFSET),
+        _ => (mie::SEIE_FILTER, mie::SEIE_OFFSET),
+    };
+    ctx.mode = Mode::S;
+    ctx.is_wfi = false;
+    ctx.csr.mideleg = mie::MIDELEG_READ_ONLY_ONE;
+    ctx.csr.mie = interrupt; // No M-mode interrupts
+    ctx.csr.mip |= interrupt;
+    ctx.csr.mstatus &= !mstatus::SIE_FILTER;
+    ctx.csr.stvec &= !0b10; // 10 is  an illegal trap vector
+    let mut core = miralis_to_rv_core(&ctx);
+    assert_eq!(
+        raw::_get_Mstatus_SIE(core.mstatus),
+        bv(0),
+        "SIE is not the same in the reference"
+    );
+    let prev = ctx.clone();
+
+    core.dispatch_interrupt();
+    ctx.check_and_inject_interrupts(ExecutionMode::Firmware);
+    assert_eq!(ctx, prev, "No interrupt should be delivered when SIE is 0");
+    assert_eq!(
+        ctx,
+        rv_core_to_miralis(core, &mctx),
+        "Interrupt gating by sstatus.SIE doesn't work properly"
+    );
+
+    // Once sstatus.SIE is set the interrupt is delivered to S-mode
+    ctx.csr.mstatus |= mstatus::SIE_FILTER;
+    let mut core = miralis_to_rv_core(&ctx);
+    core.dispatch_interrupt();
+    ctx.check_and_inject_interrupts(ExecutionMode::Firmware);
+    assert_eq!(
+        ctx.mode,
+        Mode::S,
+        "Delegated interrupts are taken in S-mode"
+    );
+    assert_eq!(ctx.csr.scause, int_id | (1 << 63), "Wrong scause");
+    assert_eq!(
+        ctx,
+        rv_core_to_miralis(core, &mctx),
+        "Delegated interrupt injection doesn't work properly"
+    );
+}
+
+#[cfg_attr(kani, kani::proof)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
+pub fn vs_interrupt_virtualization() {
+    let (mut ctx, _, _) = symbolic::new_symbolic_contexts();
+
+    // The Sail model does not implement the H extension, so we check the injection against the
+    // specification directly.
+    ctx.extensions.has_h_extension = true;
+    ctx.mode = if any!(bool) { Mode::S } else { Mode::U };
+    ctx.csr.mstatus |= mstatus::MPV_FILTER;
+    ctx.csr.mie = 0; // No M-mode interrupts
+    ctx.csr.hvip = any!(usize) & mie::VS_INT;
+    ctx.csr.hideleg = any!(usize) & mie::VS_INT;
+    ctx.csr.vsie = any!(usize) & mie::SIE_FILTER;
+    ctx.csr.vsstatus = any!();
+    ctx.csr.vstvec = any!(usize) & !0b10; // 10 is  an illegal trap vector
+    ctx.is_wfi = false;
+    let prev = ctx.clone();
+
+    ctx.check_and_inject_interrupts(ExecutionMode::Firmware);
+
+    // VS-level interrupts are seen as S-level interrupts from VS-mode
+    let enabled = prev.mode == Mode::U || prev.csr.vsstatus & mstatus::SIE_FILTER != 0;
+    let pending = ((prev.csr.hvip & prev.csr.hideleg) >> 1) & prev.csr.vsie;
+    let expected = [mie::SEIE_OFFSET, mie::SSIE_OFFSET, mie::STIE_OFFSET]
+        .into_iter()
+        .find(|int| enabled && pending & (1 << int) != 0);
+
+    let Some(int) = expected else {
+        assert_eq!(
+            ctx, prev,
+            "No virtual supervisor interrupt should be injected"
+        );
+        return;
+    };
+
+    let vstvec_base = prev.csr.vstvec & !0b11;
+    let expected_pc = match prev.csr.vstvec & 0b11 {
+        0 => vstvec_base,
+        _ => vstvec_base.wrapping_add(4 * int),
+    };
+    let prev_sie = prev.csr.vsstatus & mstatus::SIE_FILTER != 0;
+    assert_eq!(ctx.mode, Mode::S, "VS interrupts are taken in VS-mode");
+    assert_eq!(ctx.pc, expected_pc, "Wrong VS trap vector");
+    assert_eq!(ctx.csr.vscause, int | (1 << 63), "Wrong vscause");
+    assert_eq!(ctx.csr.vsepc, prev.pc, "Wrong vsepc");
+    assert_eq!(ctx.csr.vstval, 0, "Wrong vstval");
+    assert_eq!(
+        ctx.csr.vsstatus & mstatus::SIE_FILTER,
+        0,
+        "vsstatus.SIE must be cleared"
+    );
+    assert_eq!(
+        ctx.csr.vsstatus & mstatus::SPIE_FILTER != 0,
+        prev_sie,
+        "vsstatus.SPIE must hold the previous vsstatus.SIE"
+    );
+    assert_eq!(
+        ctx.csr.vsstatus & mstatus::SPP_FILTER != 0,
+        prev.mode == Mode::S,
+        "vsstatus.SPP must hold the previous mode"
+    );
+
+    // The M-mode state must not be modified
+    assert_eq!(ctx.csr.mstatus, prev.csr.mstatus, "mstatus must not change");
+    assert_eq!(ctx.csr.mepc, prev.csr.mepc, "mepc must not change");
+    assert_eq!(ctx.csr.mcause, prev.csr.mcause, "mcause must not change");
+}
+
+#[cfg_attr(kani, kani::proof)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
+pub fn access_mode_mprv() {
+    let (mut ctx, _, _) = symbolic::new_symbolic_contexts();
+
+    ctx.mode = match any!(u8) % 3 {
+        0 => Mode::U,
+        1 => Mode::S,
+        _ => Mode::M,
+    };
+    // MPRV is cleared when leaving M-mode
+    if ctx.mode != Mode::M {
+        ctx.csr.mstatus &= !mstatus::MPRV_FILTER;
+    }
+    let (access_type, kind) = match any!(u8) % 4 {
+        0 => (AccessType::Read(()), AccessKind::Data),
+        1 => (AccessType::Write(()), AccessKind::Data),
+        2 => (AccessType::ReadWrite(((), ())), AccessKind::Data),
+        _ => (AccessType::InstructionFetch(()), AccessKind::Fetch),
+    };
+
+    let core = miralis_to_rv_core(&ctx);
+    let expected = match raw::effectivePrivilege(access_type, core.mstatus, core.cur_privilege) {
+        Privilege::Machine => Mode::M,
+        Privilege::Supervisor => Mode::S,
+        Privilege::User => Mode::U,
+    };
+
+    assert_eq!(
+        ctx.access_mode(kind),
+        expected,
+        "wrong privilege mode for the memory access"
+    );
+}
+
+#[cfg_attr(kani, kani::proof)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
 pub fn exception_virtualization() {
     let (mut ctx, mctx, mut core) = symbolic::new_symbolic_contexts();
 
@@ -286,6 +901,112 @@ pub fn exception_virtualization() {
     );
 }
 
+#[cfg_attr(kani, kani::proof)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
+pub fn exception_delegation() {
+    let (mut ctx, _, _) = symbolic::new_symbolic_contexts();
+
+    ctx.mode = match any!(u8) % 3 {
+        0 => Mode::U,
+        1 => Mode::S,
+        _ => Mode::M,
+    };
+    let trap_cause = generate_exception_cause();
+
+    let mut core = miralis_to_rv_core(&ctx);
+    let privilege = core.cur_privilege;
+    let sail_target = raw::exception_delegatee(&mut core, exception_type(trap_cause), privilege);
+    let expected = match sail_target {
+        Privilege::Machine => Mode::M,
+        Privilege::Supervisor => Mode::S,
+        Privilege::User => Mode::U,
+    };
+
+    assert_eq!(
+        ctx.get_exception_target_mode(MCause::new(trap_cause)),
+        expected,
+        "Exception routed to the wrong mode"
+    );
+}
+
+#[cfg_attr(kani, kani::proof)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
+pub fn cache_block_enables() {
+    let (mut ctx, _, _) = symbolic::new_symbolic_contexts();
+
+    // The reserved CBIE encoding is never stored, as it is legalized on writes
+    let legal_cbie = |envcfg: usize| {
+        if menvcfg::cbie(envcfg) == menvcfg::CBIE_RESERVED {
+            envcfg & !menvcfg::CBIE_FILTER
+        } else {
+            envcfg
+        }
+    };
+    ctx.csr.menvcfg = legal_cbie(ctx.csr.menvcfg);
+    ctx.csr.senvcfg = legal_cbie(ctx.csr.senvcfg);
+    ctx.mode = match any!(u8) % 3 {
+        0 => Mode::U,
+        1 => Mode::S,
+        _ => Mode::M,
+    };
+    let mode = ctx.mode;
+
+    let mut core = miralis_to_rv_core(&ctx);
+    let privilege = core.cur_privilege;
+
+    let expected_inval = match raw::cbop_priv_check(&mut core, privilege) {
+        raw::checked_cbop::CBOP_ILLEGAL => CboInval::Illegal,
+        raw::checked_cbop::CBOP_INVAL_FLUSH => CboInval::Flush,
+        raw::checked_cbop::CBOP_INVAL_INVAL => CboInval::Inval,
+        raw::checked_cbop::CBOP_ILLEGAL_VIRTUAL => unreachable!("No virtualization"),
+    };
+    assert_eq!(
+        ctx.get_cbo_inval(mode),
+        expected_inval,
+        "Invalid cbo.inval behavior"
+    );
+    assert_eq!(
+        ctx.is_cbo_clean_flush_enabled(mode),
+        raw::cbo_clean_flush_enabled(&mut core, privilege),
+        "Invalid cbo.clean and cbo.flush enable"
+    );
+    let cbze = |envcfg: usize| envcfg & menvcfg::CBZE_FILTER != 0;
+    assert_eq!(
+        ctx.is_cbo_zero_enabled(mode),
+        raw::feature_enabled_for_priv(
+            &mut core,
+            privilege,
+            cbze(ctx.csr.menvcfg),
+            cbze(ctx.csr.senvcfg)
+        ),
+        "Invalid cbo.zero enable"
+    );
+}
+
+/// Returns the Sail exception type corresponding to an exception cause.
+fn exception_type(cause: usize) -> raw::ExceptionType {
+    use raw::ExceptionType::*;
+    match cause {
+        0 => E_Fetch_Addr_Align(()),
+        1 => E_Fetch_Access_Fault(()),
+        2 => E_Illegal_Instr(()),
+        3 => E_Breakpoint(()),
+        4 => E_Load_Addr_Align(()),
+        5 => E_Load_Access_Fault(()),
+        6 => E_SAMO_Addr_Align(()),
+        7 => E_SAMO_Access_Fault(()),
+        8 => E_U_EnvCall(()),
+        9 => E_S_EnvCall(()),
+        10 => E_Reserved_10(()),
+        11 => E_M_EnvCall(()),
+        12 => E_Fetch_Page_Fault(()),
+        13 => E_Load_Page_Fault(()),
+        14 => E_Reserved_14(()),
+        15 => E_SAMO_Page_Fault(()),
+        _ => unreachable!("Not a standard exception cause: {}", cause),
+    }
+}
+
 /// Returns an arbitrary exception cause among the exceptions known to Miralis.
 fn generate_exception_cause() -> usize {
     let code = any!(usize) & 0xF;
@@ -327,7 +1048,7 @@ fn fill_trap_info_structure(ctx: &mut VirtContext, mctx: &MiralisContext, cause:
 /// Checks that Miralis configures PMPs properly configured, meaning that the virtual firmware
 /// executes as it would if it were to execute in M-mode on a reference machine.
 #[cfg_attr(kani, kani::proof)]
-#[cfg_attr(test, test)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
 pub fn pmp_virtualization() {
     let (mut ctx, mut mctx, mut reference_core) = symbolic::new_symbolic_contexts();
 
@@ -353,6 +1074,12 @@ pub fn pmp_virtualization() {
     ctx.csr.mstatus &= !mstatus::MPRV_FILTER;
     reference_core.set_csr(csr::MSTATUS as u64, ctx.csr.mstatus as u64);
 
+    // The firmware configures the first PMP entries, the write must be legalized as on the
+    // reference (e.g. the reserved R = 0 and W = 1 combination).
+    let pmpcfg = any!(usize);
+    ctx.set_csr(Csr::Pmpcfg(0), pmpcfg, &mut mctx);
+    reference_core.set_csr(csr::PMPCFG0 as u64, pmpcfg as u64);
+
     // The reference core is executing in M-mode
     // This corresponds to the scenario where the firmware is running on bare metal
     reference_core.set_mode(Privilege::Machine);
@@ -366,11 +1093,11 @@ pub fn pmp_virtualization() {
         mctx.pmp.load_with_offset(
             &ctx.csr.pmpaddr,
             &ctx.csr.pmpcfg,
+            ctx.csr.mseccfg,
+            Mode::M,
             pmplayout::VIRTUAL_PMP_OFFSET,
             mctx.pmp.nb_virt_pmp,
         );
-        mctx.pmp
-            .set_range_rwx(mctx.pmp.virt_pmp_offset, mctx.pmp.nb_virt_pmp);
         unsafe { write_pmp(&mctx.pmp).flush() };
 
         // And then we can perform a PMP check on the physical core.
@@ -396,6 +1123,96 @@ pub fn pmp_virtualization() {
     }
 }
 
+/// Checks that the firmware can not modify locked PMP entries, nor the address register used as
+/// the lower bound of a locked TOR entry.
+#[cfg_attr(kani, kani::proof)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
+pub fn locked_pmp() {
+    let (mut ctx, mut mctx, _) = symbolic::new_symbolic_contexts();
+
+    // The firmware configures and possibly locks the first PMP entries
+    for idx in 0..8 {
+        ctx.set_csr(Csr::Pmpaddr(idx), any!(usize), &mut mctx);
+    }
+    ctx.set_csr(Csr::Pmpcfg(0), any!(usize), &mut mctx);
+    let pmpcfg = ctx.csr.pmpcfg;
+    let pmpaddr = ctx.csr.pmpaddr;
+
+    // Then it tries to overwrite one of them
+    let csr = if any!(bool) {
+        Csr::Pmpcfg(0)
+    } else {
+        Csr::Pmpaddr(any!(usize) % 8)
+    };
+    ctx.set_csr(csr, any!(usize), &mut mctx);
+
+    for idx in 0..8 {
+        let cfg = (pmpcfg[0] >> (idx * 8)) & 0xff;
+        if cfg & pmpcfg::L as usize == 0 {
+            continue;
+        }
+        assert_eq!(ctx.get_pmpcfg(idx) as usize, cfg, "Locked pmpcfg modified");
+        assert_eq!(
+            ctx.csr.pmpaddr[idx], pmpaddr[idx],
+            "Locked pmpaddr modified"
+        );
+        if idx > 0 && cfg & pmpcfg::A_MASK as usize == pmpcfg::TOR as usize {
+            assert_eq!(
+                ctx.csr.pmpaddr[idx - 1],
+                pmpaddr[idx - 1],
+                "Lower bound of a locked TOR entry modified"
+            );
+        }
+    }
+}
+
+/// Checks that the locked entries protecting the code of Miralis catch M-mode writes, while still
+/// allowing reads and instruction fetches, and do not expose the rest of Miralis to lower modes.
+#[cfg_attr(kani, kani::proof)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
+pub fn read_only_protection() {
+    let (_, _, mut core) = symbolic::new_symbolic_contexts();
+    let start: usize = 0x8000_0000;
+    let end: usize = 0x8001_0000;
+
+    let mut pmp = PmpGroup::new(16);
+    pmp.protect_read_only(0, start, end);
+    // The entry protecting Miralis, which also covers its data after the code
+    pmp.set_napot(2, start, 0x2_0000, pmpcfg::NO_PERMISSIONS);
+    pmp.set_napot(15, 0, usize::MAX, pmpcfg::RWX);
+    for idx in 0..64 {
+        core.pmpcfg_n[idx] = Pmpcfg_ent {
+            bits: bv(pmp.get_pmpcfg(idx) as u64),
+        };
+        core.pmpaddr_n[idx] = bv(pmp.pmpaddr()[idx] as u64);
+    }
+
+    // A deliberate write from M-mode to the read-only memory must fault
+    let addr = start + ((any!(usize) % (end - start)) & !0b111);
+    assert!(pmp.is_read_only(0, addr));
+    core.set_mode(Privilege::Machine);
+    assert!(
+        core.pmp_check(addr as u64, AccessType::Write(())).is_some(),
+        "M-mode writes to read-only memory must be caught"
+    );
+    assert!(core.pmp_check(addr as u64, AccessType::Read(())).is_none());
+    assert!(
+        core.pmp_check(addr as u64, AccessType::InstructionFetch(()))
+            .is_none()
+    );
+
+    // The rest of the memory is not affected
+    assert!(!pmp.is_read_only(0, end));
+    assert!(core.pmp_check(end as u64, AccessType::Write(())).is_none());
+
+    // The data of Miralis remains hidden from lower modes
+    core.set_mode(Privilege::User);
+    assert!(
+        core.pmp_check(end as u64, AccessType::Read(())).is_some(),
+        "Only the code of Miralis must be exposed by the locked entries"
+    );
+}
+
 /// Returns true if the address is within the memory range of Miralis or any of the virtual
 /// devices.
 ///
@@ -430,7 +1247,7 @@ fn addr_is_within_miralis_or_device(addr: u64, width: u64) -> bool {
 }
 
 #[cfg_attr(kani, kani::proof)]
-#[cfg_attr(test, test)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
 pub fn verify_decoder() {
     let (_, mctx, mut core) = symbolic::new_symbolic_contexts();
 
@@ -449,7 +1266,7 @@ pub fn verify_decoder() {
 }
 
 #[cfg_attr(kani, kani::proof)]
-#[cfg_attr(test, test)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
 pub fn verify_compressed_loads() {
     let (_, mctx, mut core) = symbolic::new_symbolic_contexts();
 
@@ -477,7 +1294,7 @@ pub fn verify_compressed_loads() {
 }
 
 #[cfg_attr(kani, kani::proof)]
-#[cfg_attr(test, test)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
 pub fn verify_load() {
     let (_, mctx, mut sail_ctx) = symbolic::new_symbolic_contexts();
 
@@ -503,7 +1320,7 @@ pub fn verify_load() {
 }
 
 #[cfg_attr(kani, kani::proof)]
-#[cfg_attr(test, test)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
 pub fn verify_compressed_stores() {
     let (_, mctx, mut core) = symbolic::new_symbolic_contexts();
 
@@ -535,7 +1352,7 @@ pub fn verify_compressed_stores() {
 }
 
 #[cfg_attr(kani, kani::proof)]
-#[cfg_attr(test, test)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
 pub fn verify_stores() {
     let (_, mctx, mut sail_ctx) = symbolic::new_symbolic_contexts();
 
@@ -560,3 +1377,233 @@ pub fn verify_stores() {
         }
     }
 }
+
+#[cfg_attr(kani, kani::proof)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
+pub fn verify_amocas() {
+    let (_, mctx, mut core) = symbolic::new_symbolic_contexts();
+
+    // Generate an instruction to decode
+    let instr = any!(u32, 0x28c5a52f);
+    let decoded = mctx.decode_amocas(instr as usize);
+
+    // Zacas is not part of the reference model, so no instruction it knows about must be decoded
+    // as a compare-and-swap.
+    if !matches!(
+        raw::encdec_backwards(&mut core, bv(instr as u64)),
+        raw::ast::ILLEGAL(_)
+    ) {
+        assert!(decoded.is_none(), "standard instruction decoded as amocas");
+    }
+
+    // The compare-and-swap instructions share their layout with the other AMOs, we check the
+    // decoded operands against the equivalent AMOSWAP.
+    let Some(decoded) = decoded else {
+        return;
+    };
+    if decoded.len == 16 {
+        // There is no 128 bits AMO in the reference model
+        return;
+    }
+    core.config.extensions.Zaamo.supported = true;
+    let amoswap = (instr & !(0b11111 << 27)) | (0b00001 << 27);
+    match raw::encdec_backwards(&mut core, bv(amoswap as u64)) {
+        raw::ast::AMO((raw::amoop::AMOSWAP, aq, rl, rs2, rs1, width, rd)) => {
+            assert_eq!(decoded.rd, Register::from(rd.bits() as usize), "wrong rd");
+            assert_eq!(
+                decoded.rs1,
+                Register::from(rs1.bits() as usize),
+                "wrong rs1"
+            );
+            assert_eq!(
+                decoded.rs2,
+                Register::from(rs2.bits() as usize),
+                "wrong rs2"
+            );
+            assert_eq!(decoded.aq, aq, "wrong aq bit");
+            assert_eq!(decoded.rl, rl, "wrong rl bit");
+            let len = match width {
+                raw::word_width::WORD => 4,
+                raw::word_width::DOUBLE => 8,
+                _ => unreachable!("amocas only exists for words and double words"),
+            };
+            assert_eq!(decoded.len, len, "wrong width");
+        }
+        other => panic!("amocas decoded for a non-AMO encoding {:?}", other),
+    }
+}
+
+#[cfg_attr(kani, kani::proof)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
+pub fn verify_svinval() {
+    let (_, mctx, mut core) = symbolic::new_symbolic_contexts();
+    core.config.extensions.Svinval.supported = true;
+
+    // Generate an instruction to decode
+    let instr = (any!(u32, 0x16b50073) & !0b1111111) | 0b1110011;
+
+    let ground_truth = match raw::encdec_backwards(&mut core, bv(instr as u64)) {
+        raw::ast::SINVAL_VMA((rs1, rs2)) => Some(IllegalInst::Sinvalvma {
+            rs1: Register::from(rs1.bits() as usize),
+            rs2: Register::from(rs2.bits() as usize),
+        }),
+        raw::ast::SFENCE_W_INVAL(()) => Some(IllegalInst::Sfencewinval),
+        raw::ast::SFENCE_INVAL_IR(()) => Some(IllegalInst::Sfenceinvalir),
+        _ => None,
+    };
+    let decoded = mctx.decode_illegal_instruction(instr as usize);
+    let is_svinval = matches!(
+        decoded,
+        IllegalInst::Sinvalvma { .. } | IllegalInst::Sfencewinval | IllegalInst::Sfenceinvalir
+    );
+
+    match ground_truth {
+        Some(ground_truth) => assert_eq!(ground_truth, decoded, "wrong svinval decoding"),
+        None => assert!(!is_svinval, "non-svinval instruction decoded as svinval"),
+    }
+}
+
+#[cfg_attr(kani, kani::proof)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
+pub fn verify_zicond() {
+    let (_, mctx, mut core) = symbolic::new_symbolic_contexts();
+    core.config.extensions.Zicond.supported = true;
+
+    // Generate an instruction to decode
+    let instr = (any!(u32, 0x0ec5d533) & !0b1111111) | 0b0110011;
+
+    let ground_truth = match raw::encdec_backwards(&mut core, bv(instr as u64)) {
+        raw::ast::ZICOND_RTYPE((rs2, rs1, rd, op)) => Some(ZicondInstr {
+            rd: Register::from(rd.bits() as usize),
+            rs1: Register::from(rs1.bits() as usize),
+            rs2: Register::from(rs2.bits() as usize),
+            op: match op {
+                raw::zicondop::CZERO_EQZ => ZicondOp::CzeroEqz,
+                raw::zicondop::CZERO_NEZ => ZicondOp::CzeroNez,
+            },
+        }),
+        _ => None,
+    };
+
+    assert_eq!(
+        ground_truth,
+        mctx.decode_zicond(instr as usize),
+        "wrong zicond decoding"
+    );
+}
+
+#[cfg_attr(kani, kani::proof)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
+pub fn verify_hlv() {
+    let (_, mctx, mut core) = symbolic::new_symbolic_contexts();
+
+    // Generate a SYSTEM instruction with the funct3 of the hypervisor loads and stores
+    let instr = (any!(u32, 0x6c05c573) & !0x707f) | 0x4073;
+    let decoded = mctx.decode_illegal_instruction(instr as usize);
+
+    // The hypervisor extension is not part of the reference model, instead we check the decoded
+    // operands against the equivalent load with no offset.
+    let funct7 = instr >> 25;
+    let rs2 = (instr >> 20) & 0b11111;
+    let size = (funct7 >> 1) & 0b11;
+    let is_hlv = funct7 & 0b1111001 == 0b0110000 && (rs2 == 0 || (rs2 == 1 && size != 0b11));
+    let IllegalInst::Hlv(decoded) = decoded else {
+        assert!(!is_hlv, "hlv instruction not decoded");
+        return;
+    };
+    assert!(is_hlv, "non-hlv instruction decoded as hlv");
+
+    let load = (instr & (0b11111 << 15)) | (((rs2 << 2) | size) << 12) | (instr & (0b11111 << 7));
+    let load = load | 0b0000011;
+    let reference = ast_to_miralis_load(model::sail_decoder_load::encdec_backwards(
+        &mut core,
+        bv(load as u64),
+    ));
+    assert_eq!(decoded, reference, "wrong hlv decoding");
+}
+
+#[cfg_attr(kani, kani::proof)]
+#[cfg_attr(all(test, not(feature = "rand")), test)]
+pub fn verify_hsv() {
+    let (_, mctx, mut core) = symbolic::new_symbolic_contexts();
+
+    // Generate a SYSTEM instruction with the funct3 of the hypervisor loads and stores
+    let instr = (any!(u32, 0x6ac5c073) & !0x707f) | 0x4073;
+    let decoded = mctx.decode_illegal_instruction(instr as usize);
+
+    // As for hlv, we check the decoded operands against the equivalent store with no offset.
+    let funct7 = instr >> 25;
+    let rd = (instr >> 7) & 0b11111;
+    let size = (funct7 >> 1) & 0b11;
+    let is_hsv = funct7 & 0b1111001 == 0b0110001 && rd == 0;
+    let IllegalInst::Hsv(decoded) = decoded else {
+        assert!(!is_hsv, "hsv instruction not decoded");
+        return;
+    };
+    assert!(is_hsv, "non-hsv instruction decoded as hsv");
+
+    let store = (instr & (0b1111111111 << 15)) | (size << 12) | 0b0100011;
+    let reference = ast_to_miralis_store(model::sail_decoder_store::encdec_backwards(
+        &mut core,
+        bv(store as u64),
+    ));
+    assert_eq!(decoded, reference, "wrong hsv decoding");
+}
+
+// ——————————————————————————— Random Test Mode ———————————————————————————— //
+
+/// Run each proof as a unit test over many random inputs, see [symbolic::random].
+#[cfg(all(test, not(kani), feature = "rand"))]
+mod random_tests {
+    macro_rules! random_tests {
+        ($($proof:ident),* $(,)?) => {
+            $(
+                #[test]
+                fn $proof() {
+                    crate::symbolic::random::run_iterations(super::$proof);
+                }
+            )*
+        };
+    }
+
+    random_tests!(
+        mret,
+        mret_mprv,
+        mret_interrupt_enable,
+        access_mode_mprv,
+        read_only_protection,
+        mret_pc_alignment,
+        mret_mpv,
+        sret,
+        sret_spp,
+        sret_pc_alignment,
+        wfi,
+        fences,
+        read_csr,
+        write_csr,
+        write_sip,
+        write_sstatus,
+        write_mtvec,
+        write_xepc,
+        hgatp_read_write,
+        interrupt_virtualization,
+        interrupt_mie_gating,
+        delegated_interrupt_sie_gating,
+        vs_interrupt_virtualization,
+        exception_virtualization,
+        exception_delegation,
+        cache_block_enables,
+        pmp_virtualization,
+        locked_pmp,
+        verify_decoder,
+        verify_compressed_loads,
+        verify_load,
+        verify_compressed_stores,
+        verify_stores,
+        verify_amocas,
+        verify_svinval,
+        verify_zicond,
+        verify_hlv,
+        verify_hsv,
+    );
+}
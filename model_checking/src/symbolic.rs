@@ -25,7 +25,8 @@ pub const MIRALIS_SIZE: usize = 0x1000;
 ///
 /// This macro either generate a value of a type, or an arbitrary Kani value during model checking.
 /// We use this macro to make our Kani proofs runnable as simple tests, which ensures that we don't
-/// break the Kani verification harnesses.
+/// break the Kani verification harnesses. When the `rand` feature is enabled, values are drawn
+/// from a seeded random generator instead (see [random]).
 macro_rules! any {
     () => {{
         #[cfg(kani)]
@@ -38,7 +39,7 @@ macro_rules! any {
         }
         #[cfg(all(not(kani), feature = "rand"))]
         {
-            rand::random()
+            crate::symbolic::random::random()
         }
     }};
     ($t:ty) => {{
@@ -52,7 +53,7 @@ macro_rules! any {
         }
         #[cfg(all(not(kani), feature = "rand"))]
         {
-            rand::random::<$t>()
+            crate::symbolic::random::random::<$t>()
         }
     }};
     ($t:ty, $value:tt) => {{
@@ -68,6 +69,71 @@ macro_rules! any {
     }};
 }
 
+// ——————————————————————————— Random Test Mode ———————————————————————————— //
+
+/// Random inputs for running the proofs as unit tests.
+///
+/// When the `rand` feature is enabled each proof is run many times as a normal unit test, drawing
+/// its inputs from a seeded random generator. This gives some coverage to developers without Kani
+/// installed. The seed is printed when a test fails, and can be set with the
+/// `MIRALIS_MODEL_CHECKING_SEED` environment variable to reproduce a failure.
+#[cfg(all(not(kani), feature = "rand"))]
+pub mod random {
+    use std::cell::RefCell;
+    use std::env;
+
+    use miralis::arch::metal::SOFT_CORE;
+    use rand::distributions::{Distribution, Standard};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Environment variable used to seed the random generator.
+    pub const SEED_ENV: &str = "MIRALIS_MODEL_CHECKING_SEED";
+
+    /// Environment variable used to select the number of iterations per proof.
+    pub const ITERATIONS_ENV: &str = "MIRALIS_MODEL_CHECKING_ITERATIONS";
+
+    /// The default number of iterations per proof.
+    const DEFAULT_ITERATIONS: usize = 1000;
+
+    thread_local! {
+        /// One generator per thread, as the test harness runs tests in parallel.
+        static RNG: RefCell<StdRng> = RefCell::new(StdRng::seed_from_u64(0));
+    }
+
+    /// Return a random value from the current thread's generator.
+    pub fn random<T>() -> T
+    where
+        Standard: Distribution<T>,
+    {
+        RNG.with(|rng| rng.borrow_mut().r#gen())
+    }
+
+    /// Run a proof many times with random inputs.
+    pub fn run_iterations(proof: fn()) {
+        let seed = match env::var(SEED_ENV) {
+            Ok(seed) => seed.parse().expect("Invalid model checking seed"),
+            Err(_) => rand::random(),
+        };
+        let iterations = match env::var(ITERATIONS_ENV) {
+            Ok(iterations) => iterations.parse().expect("Invalid number of iterations"),
+            Err(_) => DEFAULT_ITERATIONS,
+        };
+
+        // The output is captured by the test harness and displayed only on failure
+        println!("To reproduce, run with {}={}", SEED_ENV, seed);
+        RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+        for _ in 0..iterations {
+            // Proofs might leave the soft core in an arbitrary state (e.g. in U-mode), we reset it
+            // before each iteration.
+            SOFT_CORE.with_borrow_mut(|core| core.reset());
+            proof();
+        }
+    }
+}
+
+// ——————————————————————————— Symbolic Contexts ———————————————————————————— //
+
 /// Return a new context with symbolic values
 pub fn new_ctx(available_extension: ExtensionsCapability) -> VirtContext {
     let mut ctx = VirtContext::new(0, 0, available_extension);
@@ -104,7 +170,7 @@ pub fn new_ctx(available_extension: ExtensionsCapability) -> VirtContext {
     ctx.csr.mcountinhibit = any!();
     ctx.csr.mcounteren = any!();
     ctx.csr.menvcfg = any!(usize) & (menvcfg::FIOM_FILTER | menvcfg::STCE_FILTER);
-    // ctx.csr.mseccfg = any!();
+    // mseccfg is left at zero, the Sail model does not implement Smepmp
     ctx.csr.mcause = any!();
     ctx.csr.mepc = any!(usize) & (!0b11);
     ctx.csr.mtval = any!();
@@ -138,7 +204,7 @@ pub fn new_ctx(available_extension: ExtensionsCapability) -> VirtContext {
     ctx.csr.scause = any!();
     ctx.csr.stval = any!();
     ctx.csr.satp = any!();
-    //ctx.csr.scontext = any!(); // TODO: What should we do with this?
+    // Sail does not model scontext and mcontext, they are checked by the CSR read/write proofs
     ctx.csr.medeleg = any!();
     ctx.csr.mideleg = (any!(usize) & mie::ALL_INT) | mie::MIDELEG_READ_ONLY_ONE;
     ctx.csr.pmpcfg = [any!(); 8];
@@ -147,15 +213,11 @@ pub fn new_ctx(available_extension: ExtensionsCapability) -> VirtContext {
     // ctx.csr.mhpmcounter = [any!(); 29]; todo: What should we do?
     // ctx.csr.mhpmevent = [any!(); 29]; todo: What should we do?
 
-    // Lock mode is not supported at the moment in Miralis
     for i in 0..8 {
         for j in 0..8 {
             let offset = j * 8;
             let mut pmpcfg = ctx.csr.pmpcfg[i];
 
-            // Lock mode not supported
-            pmpcfg &= !(1 << (7 + offset));
-
             // NA4 not supported for PMP grain >= 1
             // If bit 4 is 1, then either NA4 or NAPOT is selected.
             // In that case, we set bit 3, which forces NAPOT.
@@ -164,7 +226,7 @@ pub fn new_ctx(available_extension: ExtensionsCapability) -> VirtContext {
 
             // If R = 0 and W = 1, then we set RWX to 0
             // This is what the Sail spec does too.
-            if pmpcfg & (0b11 << offset) == 0b10 {
+            if pmpcfg & (0b11 << offset) == 0b10 << offset {
                 pmpcfg &= !(0b111 << offset)
             }
 
@@ -12,6 +12,7 @@ use std::{env, fs};
 use serde::Deserialize;
 
 use crate::ArtifactArgs;
+use crate::build::check_disabled_devices;
 use crate::config::{Config, Profiles};
 use crate::path::{
     EXT2_EXTENSION, GZ_COMPRESSION, IMG_EXTENSION, XZ_COMPRESSION, ZST_COMPRESSION,
@@ -202,6 +203,20 @@ pub fn prepare_payload_artifact(name: &str, cfg: &Config) -> Option<PathBuf> {
     }
 }
 
+/// Returns true if the artifact can be used without being downloaded first.
+pub fn is_artifact_available_locally(name: &str) -> bool {
+    match locate_bin_artifact(name) {
+        Some(BinArtifact::Source { .. }) => true,
+        Some(BinArtifact::Downloaded { name, .. }) => {
+            let mut artifact = get_artifacts_path();
+            artifact.push(name);
+            artifact.is_file()
+        }
+        Some(BinArtifact::Binary { path }) => path.is_file(),
+        None => false,
+    }
+}
+
 /// Try to locate the desired binary artifact.
 ///
 /// Artifacts can be either available as sources, as external binaries that can be downloaded, or
@@ -320,6 +335,11 @@ pub fn build_target(target: Target, cfg: &Config) -> PathBuf {
 
     match target {
         Target::Miralis => {
+            if let Err(err) = check_disabled_devices(cfg) {
+                log::error!("Invalid configuration: {}", err);
+                std::process::exit(1);
+            }
+
             // Linker arguments
             let start_address = cfg.target.miralis.start_address.unwrap_or(0x80000000);
             let linker_args = format!(
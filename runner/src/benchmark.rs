@@ -0,0 +1,378 @@
+//! # Benchmark Analysis
+//!
+//! This module compares two sets of benchmark results and reports the change of each counter.
+//!
+//! A result file contains one measure per line, in the form `<counter>: <value>`. Lines that do
+//! not follow that format are ignored, which makes it possible to use the raw output of a run as
+//! a result file. A counter can be measured multiple times, in which case statistics are computed
+//! over all the measures. When a directory is given, all the files it contains are considered.
+//!
+//! A result file can also be a dump of the benchmark shared page, in the binary layout defined by
+//! `miralis_core::benchmark`. In that case each row of the table is a measure of every counter.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
+
+use miralis_core::benchmark as layout;
+
+use crate::BenchmarkDiffArgs;
+
+/// The ANSI escape codes used to color the output.
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+/// Name of the average latency of a world switch round trip (from the payload to the firmware
+/// and back), derived from the world switch counters.
+const WORLD_SWITCH_LATENCY: &str = "world-switch-latency";
+
+/// Counters for which an increase is an improvement, all the other counters measure costs.
+const HIGHER_IS_BETTER: &[&str] = &["instructions-per-exit"];
+
+/// Changes smaller than this threshold, in percent, are not colored.
+const NOISE_THRESHOLD: f64 = 1.0;
+
+// ————————————————————————————— Result Parsing ————————————————————————————— //
+
+/// The measures of each counter, indexed by counter name.
+type Measures = BTreeMap<String, Vec<f64>>;
+
+/// Parse the content of a result file, appending the measures to the given map.
+fn parse_content(content: &str, measures: &mut Measures) {
+    for line in content.lines() {
+        let Some((counter, value)) = line.rsplit_once(':') else {
+            continue;
+        };
+        let Ok(value) = value.trim().parse::<f64>() else {
+            continue;
+        };
+        measures
+            .entry(counter.trim().to_string())
+            .or_default()
+            .push(value);
+    }
+}
+
+/// Parse a dump of the benchmark shared page, appending the measures to the given map.
+fn parse_shared_page(bytes: &[u8], measures: &mut Measures) -> Result<(), String> {
+    let words: Vec<u64> = bytes
+        .chunks_exact(size_of::<u64>())
+        .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+        .collect();
+    if words.len() < layout::HEADER_LEN || words[layout::MAGIC_IDX] != layout::MAGIC {
+        return Err(String::from("Not a benchmark shared page"));
+    }
+    if words[layout::VERSION_IDX] != layout::VERSION {
+        return Err(format!(
+            "Unsupported shared page version {}, expected {}",
+            words[layout::VERSION_IDX],
+            layout::VERSION
+        ));
+    }
+
+    let nb_rows = words[layout::NB_ROWS_IDX] as usize;
+    let nb_columns = words[layout::NB_COLUMNS_IDX] as usize;
+    if nb_columns > layout::COUNTER_NAMES.len() {
+        return Err(format!("Invalid number of columns: {}", nb_columns));
+    }
+    let values = &words[layout::HEADER_LEN..];
+    if nb_rows.saturating_mul(nb_columns) > values.len() {
+        return Err(String::from("Truncated shared page"));
+    }
+
+    let column = |name: &str| {
+        layout::COUNTER_NAMES[..nb_columns]
+            .iter()
+            .position(|column| *column == name)
+    };
+    let world_switch = column("world-switch");
+    let world_switch_cycles = column("world-switch-cycles");
+
+    for row in values.chunks_exact(nb_columns.max(1)).take(nb_rows) {
+        for (name, value) in layout::COUNTER_NAMES.iter().zip(row) {
+            measures
+                .entry(name.to_string())
+                .or_default()
+                .push(*value as f64);
+        }
+
+        // Report the average world switch latency, in cycles
+        if let (Some(count), Some(cycles)) = (world_switch, world_switch_cycles)
+            && row[count] != 0
+        {
+            measures
+                .entry(String::from(WORLD_SWITCH_LATENCY))
+                .or_default()
+                .push(row[cycles] as f64 / row[count] as f64);
+        }
+    }
+
+    Ok(())
+}
+
+/// Read the measures from a result file, or all the files in a result directory.
+fn read_measures(path: &Path) -> Result<Measures, String> {
+    let mut measures = Measures::new();
+    let files = if path.is_dir() {
+        let mut files = fs::read_dir(path)
+            .map_err(|err| format!("Could not read directory {}: {}", path.display(), err))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    for file in files {
+        let content =
+            fs::read(&file).map_err(|err| format!("Could not read {}: {}", file.display(), err))?;
+        if content.starts_with(&layout::MAGIC.to_le_bytes()) {
+            parse_shared_page(&content, &mut measures)
+                .map_err(|err| format!("Invalid shared page {}: {}", file.display(), err))?;
+        } else {
+            let content = String::from_utf8_lossy(&content);
+            parse_content(&content, &mut measures);
+        }
+    }
+
+    Ok(measures)
+}
+
+// ——————————————————————————————— Statistics ——————————————————————————————— //
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Statistics {
+    mean: f64,
+    min: f64,
+    max: f64,
+    std_dev: f64,
+}
+
+/// Compute the statistics over a non-empty list of measures.
+fn compute_statistics(values: &[f64]) -> Statistics {
+    assert!(
+        !values.is_empty(),
+        "Can not compute statistics without values"
+    );
+
+    let count = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / count;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / count;
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+
+    Statistics {
+        mean,
+        min,
+        max,
+        std_dev: variance.sqrt(),
+    }
+}
+
+/// Returns the change from `base` to `new`, in percent.
+///
+/// Returns `None` if the change can not be expressed as a percentage (i.e. `base` is zero).
+fn percentage_change(base: f64, new: f64) -> Option<f64> {
+    if base == 0.0 {
+        None
+    } else {
+        Some((new - base) / base * 100.0)
+    }
+}
+
+// ————————————————————————————————— Diffing ———————————————————————————————— //
+
+/// The comparison of a counter between the base and new result sets.
+#[derive(Debug, PartialEq)]
+struct CounterDiff {
+    name: String,
+    base: Option<Statistics>,
+    new: Option<Statistics>,
+}
+
+impl CounterDiff {
+    fn change(&self) -> Option<f64> {
+        percentage_change(self.base?.mean, self.new?.mean)
+    }
+}
+
+/// Compare the counters of the two result sets.
+fn diff_measures(base: &Measures, new: &Measures) -> Vec<CounterDiff> {
+    let mut names: Vec<&String> = base.keys().chain(new.keys()).collect();
+    names.sort();
+    names.dedup();
+
+    names
+        .into_iter()
+        .map(|name| CounterDiff {
+            name: name.clone(),
+            base: base.get(name).map(|values| compute_statistics(values)),
+            new: new.get(name).map(|values| compute_statistics(values)),
+        })
+        .collect()
+}
+
+/// Format the change of a counter, colored if requested.
+///
+/// Most counters measure costs (e.g. cycles or number of traps), hence an increase is a
+/// regression and is displayed in red, while a decrease is an improvement and is displayed in
+/// green. The colors are swapped for the counters listed in [HIGHER_IS_BETTER].
+fn format_change(name: &str, change: Option<f64>, color: bool) -> String {
+    let Some(change) = change else {
+        return String::from("-");
+    };
+
+    let text = format!("{:+.2}%", change);
+    let regression = (change > 0.0) != HIGHER_IS_BETTER.contains(&name);
+    if !color || change.abs() < NOISE_THRESHOLD {
+        text
+    } else if regression {
+        format!("{}{}{}", RED, text, RESET)
+    } else {
+        format!("{}{}{}", GREEN, text, RESET)
+    }
+}
+
+fn format_mean(stats: Option<Statistics>) -> String {
+    match stats {
+        Some(stats) => format!("{:.2}", stats.mean),
+        None => String::from("-"),
+    }
+}
+
+/// Compare two benchmark result sets and print the change of each counter.
+pub fn diff_benchmarks(args: &BenchmarkDiffArgs) -> ExitCode {
+    let (base, new) = match (read_measures(&args.base), read_measures(&args.new)) {
+        (Ok(base), Ok(new)) => (base, new),
+        (Err(err), _) | (_, Err(err)) => {
+            log::error!("{}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let diffs = diff_measures(&base, &new);
+    if diffs.is_empty() {
+        log::warn!("No measures found");
+        return ExitCode::SUCCESS;
+    }
+
+    let width = diffs.iter().map(|diff| diff.name.len()).max().unwrap_or(0);
+    println!(
+        "{:<width$}  {:>16}  {:>16}  {:>10}",
+        "counter", "base", "new", "change"
+    );
+    for diff in &diffs {
+        println!(
+            "{:<width$}  {:>16}  {:>16}  {:>10}",
+            diff.name,
+            format_mean(diff.base),
+            format_mean(diff.new),
+            format_change(&diff.name, diff.change(), !args.no_color)
+        );
+    }
+
+    ExitCode::SUCCESS
+}
+
+// ————————————————————————————————— Tests —————————————————————————————————— //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let mut measures = Measures::new();
+        parse_content(
+            "Booting Miralis\nworld_switches: 10\nfirmware_traps: 4\nworld_switches: 20\n",
+            &mut measures,
+        );
+
+        assert_eq!(measures.len(), 2);
+        assert_eq!(measures["world_switches"], vec![10.0, 20.0]);
+        assert_eq!(measures["firmware_traps"], vec![4.0]);
+    }
+
+    #[test]
+    fn shared_page() {
+        // A table with two rows, in the layout written by Miralis
+        let mut words = vec![layout::MAGIC, layout::VERSION, 2, 10];
+        words.extend([10, 0, 0, 0, 0, 0, 4, 0, 20, 2000]);
+        words.extend([20, 0, 0, 0, 0, 0, 6, 0, 0, 0]);
+        words.extend([0; 16]); // Rest of the page
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+
+        let mut measures = Measures::new();
+        parse_shared_page(&bytes, &mut measures).unwrap();
+        assert_eq!(measures.len(), 11);
+        assert_eq!(measures["no-offload"], vec![10.0, 20.0]);
+        assert_eq!(measures["firmware-trap"], vec![4.0, 6.0]);
+        assert_eq!(
+            measures[WORLD_SWITCH_LATENCY],
+            vec![100.0],
+            "Rows without world switches have no latency"
+        );
+
+        // Truncated and unknown pages are rejected
+        assert!(parse_shared_page(&bytes[..64], &mut Measures::new()).is_err());
+        assert!(parse_shared_page(&bytes[8..], &mut Measures::new()).is_err());
+    }
+
+    #[test]
+    fn statistics() {
+        let stats = compute_statistics(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        assert_eq!(stats.mean, 5.0);
+        assert_eq!(stats.min, 2.0);
+        assert_eq!(stats.max, 9.0);
+        assert_eq!(stats.std_dev, 2.0);
+    }
+
+    #[test]
+    fn diff() {
+        let mut base = Measures::new();
+        let mut new = Measures::new();
+        parse_content(
+            "cycles: 100\ncycles: 300\ntraps: 50\nremoved: 1\n",
+            &mut base,
+        );
+        parse_content("cycles: 150\ncycles: 150\ntraps: 60\nadded: 1\n", &mut new);
+
+        let diffs = diff_measures(&base, &new);
+        let changes: Vec<(&str, Option<f64>)> = diffs
+            .iter()
+            .map(|diff| (diff.name.as_str(), diff.change()))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("added", None),
+                ("cycles", Some(-25.0)),
+                ("removed", None),
+                ("traps", Some(20.0)),
+            ]
+        );
+
+        // Regressions are red, improvements green, and colors can be disabled
+        assert_eq!(
+            format_change("cycles", Some(20.0), true),
+            "\x1b[31m+20.00%\x1b[0m"
+        );
+        assert_eq!(
+            format_change("cycles", Some(-25.0), true),
+            "\x1b[32m-25.00%\x1b[0m"
+        );
+        assert_eq!(format_change("cycles", Some(20.0), false), "+20.00%");
+        assert_eq!(format_change("cycles", Some(0.5), true), "+0.50%");
+        assert_eq!(format_change("cycles", None, true), "-");
+
+        // Fewer instructions between exits is a regression
+        assert_eq!(
+            format_change("instructions-per-exit", Some(-25.0), true),
+            "\x1b[31m-25.00%\x1b[0m"
+        );
+    }
+}
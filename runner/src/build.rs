@@ -2,12 +2,18 @@
 
 use std::process::ExitCode;
 
+use miralis_core::pmp;
+
 use crate::BuildArgs;
 use crate::artifacts::{Target, build_target, prepare_firmware_artifact};
-use crate::config::read_config;
+use crate::config::{Config, ModuleName, Platforms, read_config};
 
 pub fn build(args: &BuildArgs) -> ExitCode {
     let cfg = read_config(&args.config);
+    if args.check_budget {
+        return check_budget(&cfg);
+    }
+
     if let Some(firmware) = &args.firmware {
         let Some(firmware) = prepare_firmware_artifact(firmware, &cfg) else {
             return ExitCode::FAILURE;
@@ -30,3 +36,250 @@ pub fn build(args: &BuildArgs) -> ExitCode {
 
     ExitCode::SUCCESS
 }
+
+// —————————————————————————————— PMP Budget ———————————————————————————————— //
+
+/// The PMP entries required by a configuration, and the ones available on its platform.
+#[derive(Debug, PartialEq, Eq)]
+struct PmpBudget {
+    /// Number of PMP entries implemented by the platform.
+    available: usize,
+    /// Number of PMP entries reserved by Miralis, including the ones used by modules.
+    required: usize,
+    /// Number of PMP entries used by the modules.
+    modules: usize,
+    /// Maximum number of virtual PMP entries exposed to the firmware, if limited.
+    max_virtual: Option<usize>,
+}
+
+impl PmpBudget {
+    fn fits(&self) -> bool {
+        self.required <= self.available
+    }
+
+    /// Number of virtual PMP entries exposed to the firmware, if the configuration fits.
+    fn virtual_pmps(&self) -> usize {
+        let remaining = self.available.saturating_sub(self.required);
+        self.max_virtual.map_or(remaining, |max| max.min(remaining))
+    }
+}
+
+fn check_budget(cfg: &Config) -> ExitCode {
+    let budget = pmp_budget(cfg);
+    let platform = cfg.platform.name.unwrap_or(Platforms::QemuVirt);
+    if !budget.fits() {
+        log::error!(
+            "Configuration requires {} PMP entries ({} for modules), but {} only has {}",
+            budget.required,
+            budget.modules,
+            platform,
+            budget.available
+        );
+        return ExitCode::FAILURE;
+    }
+
+    log::info!(
+        "Configuration fits in the PMP budget: {} of {} entries reserved ({} for modules), {} virtual PMP entries for the firmware",
+        budget.required,
+        budget.available,
+        budget.modules,
+        budget.virtual_pmps()
+    );
+    if let Some(max_virtual) = budget.max_virtual
+        && max_virtual > budget.virtual_pmps()
+    {
+        log::warn!(
+            "vcpu.max_pmp is {} but only {} entries are left for the firmware",
+            max_virtual,
+            budget.virtual_pmps()
+        );
+    }
+    ExitCode::SUCCESS
+}
+
+/// Computes the PMP budget of a configuration, following the PMP layout of Miralis (see
+/// `arch::pmp::pmplayout`).
+fn pmp_budget(cfg: &Config) -> PmpBudget {
+    let platform = cfg.platform.name.unwrap_or(Platforms::QemuVirt);
+    let disabled = cfg.platform.disabled_devices.as_deref().unwrap_or(&[]);
+    let read_only = if cfg.debug.protect_read_only.unwrap_or(false) {
+        pmp::READ_ONLY
+    } else {
+        0
+    };
+    let devices = platform_devices(platform)
+        .iter()
+        .filter(|device| !disabled.iter().any(|name| name == *device))
+        .count();
+    let log_page = match cfg.log.backend.as_deref() {
+        Some("shared-page" | "both") => pmp::LOG_PAGE,
+        _ => 0,
+    };
+    let modules = cfg.modules.modules.iter().copied().map(module_pmps).sum();
+    let fixed = pmp::MIRALIS + pmp::MPRV_EMULATION + pmp::INACTIVE_ENTRY + pmp::LAST_ENTRY;
+
+    PmpBudget {
+        available: platform_pmps(platform),
+        required: read_only + devices + protected_regions(platform) + log_page + modules + fixed,
+        modules,
+        max_virtual: cfg.vcpu.max_pmp,
+    }
+}
+
+/// Number of PMP entries implemented by the platform.
+fn platform_pmps(platform: Platforms) -> usize {
+    match platform {
+        Platforms::QemuVirt | Platforms::Spike => 16,
+        Platforms::VisionFive2 | Platforms::PremierP550 => 8,
+    }
+}
+
+/// The virtual devices of the platform, each of which is protected with one PMP entry.
+fn platform_devices(platform: Platforms) -> &'static [&'static str] {
+    match platform {
+        Platforms::QemuVirt | Platforms::Spike => &["CLINT", "TEST"],
+        Platforms::VisionFive2 | Platforms::PremierP550 => &["CLINT"],
+    }
+}
+
+/// Virtual devices Miralis can not run without, which can therefore not be disabled.
+const REQUIRED_DEVICES: &[&str] = &["CLINT"];
+
+/// Checks that the disabled devices of a configuration exist on its platform, and are not
+/// required by Miralis.
+pub fn check_disabled_devices(cfg: &Config) -> Result<(), String> {
+    let platform = cfg.platform.name.unwrap_or(Platforms::QemuVirt);
+    let devices = platform_devices(platform);
+    for name in cfg.platform.disabled_devices.as_deref().unwrap_or(&[]) {
+        if !devices.contains(&name.as_str()) {
+            return Err(format!(
+                "Unknown device '{}' in platform.disabled_devices, {} has {:?}",
+                name, platform, devices
+            ));
+        }
+        if REQUIRED_DEVICES.contains(&name.as_str()) {
+            return Err(format!(
+                "Device '{}' is required by Miralis and can not be disabled",
+                name
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Number of protected regions in the memory map of the platform, each of which is protected
+/// with one PMP entry.
+fn protected_regions(platform: Platforms) -> usize {
+    match platform {
+        Platforms::QemuVirt | Platforms::Spike => pmp::protected_regions::VIRT,
+        Platforms::VisionFive2 => pmp::protected_regions::VISIONFIVE2,
+        Platforms::PremierP550 => pmp::protected_regions::PREMIERP550,
+    }
+}
+
+/// Number of PMP entries used by a module (see `Module::NUMBER_PMPS`).
+fn module_pmps(module: ModuleName) -> usize {
+    match module {
+        ModuleName::Keystone => pmp::modules::KEYSTONE,
+        ModuleName::ProtectPayload => pmp::modules::PROTECT_PAYLOAD,
+        ModuleName::Offload
+        | ModuleName::EntropyTest
+        | ModuleName::Hsm
+        | ModuleName::SingleStepTest
+        | ModuleName::ShutdownTest
+        | ModuleName::Audit
+        | ModuleName::SatpTest
+        | ModuleName::BootCounter
+        | ModuleName::ExitCounterPerCause
+        | ModuleName::ExitCounter => 0,
+    }
+}
+
+// ————————————————————————————————— Tests —————————————————————————————————— //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pmp_budget_checks() {
+        let cfg: Config = toml::from_str(
+            r#"
+            [platform]
+            name = "qemu_virt"
+
+            [modules]
+            modules = ["keystone", "protect_payload", "audit"]
+            "#,
+        )
+        .unwrap();
+        let budget = pmp_budget(&cfg);
+        assert_eq!(budget.modules, 4);
+        assert_eq!(budget.required, 10);
+        assert!(budget.fits());
+        assert_eq!(budget.virtual_pmps(), 6);
+
+        // The number of virtual PMP entries can be limited, and logging to a shared page takes
+        // one more entry
+        let cfg: Config = toml::from_str(
+            r#"
+            [platform]
+            name = "qemu_virt"
+
+            [log]
+            backend = "shared-page"
+            shared_page = 0x80600000
+
+            [vcpu]
+            max_pmp = 4
+            "#,
+        )
+        .unwrap();
+        let budget = pmp_budget(&cfg);
+        assert_eq!(budget.required, 7);
+        assert_eq!(budget.virtual_pmps(), 4);
+
+        // The VisionFive 2 has only 8 PMP entries, not enough for both modules
+        let cfg: Config = toml::from_str(
+            r#"
+            [platform]
+            name = "visionfive2"
+
+            [debug]
+            protect_read_only = true
+
+            [modules]
+            modules = ["keystone", "protect_payload"]
+            "#,
+        )
+        .unwrap();
+        let budget = pmp_budget(&cfg);
+        assert_eq!(budget.required, 11);
+        assert!(!budget.fits());
+    }
+
+    #[test]
+    fn disabled_devices() {
+        let with_disabled = |platform: &str, devices: &str| -> Config {
+            toml::from_str(&format!(
+                r#"
+                [platform]
+                name = "{platform}"
+                disabled_devices = {devices}
+                "#
+            ))
+            .unwrap()
+        };
+
+        assert!(check_disabled_devices(&with_disabled("qemu_virt", "[]")).is_ok());
+        assert!(check_disabled_devices(&with_disabled("qemu_virt", r#"["TEST"]"#)).is_ok());
+
+        // Unknown devices, including devices of other platforms, are rejected
+        assert!(check_disabled_devices(&with_disabled("qemu_virt", r#"["UART"]"#)).is_err());
+        assert!(check_disabled_devices(&with_disabled("visionfive2", r#"["TEST"]"#)).is_err());
+
+        // The CLINT is required
+        assert!(check_disabled_devices(&with_disabled("qemu_virt", r#"["CLINT"]"#)).is_err());
+    }
+}
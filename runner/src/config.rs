@@ -4,7 +4,7 @@
 //! appropriate environment variables during Miralis's build.
 
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 use std::{fmt, fs};
 
@@ -41,6 +41,10 @@ pub struct Config {
 pub struct Log {
     pub level: Option<String>,
     pub color: Option<bool>,
+    pub deduplicate: Option<bool>,
+    pub max_length: Option<usize>,
+    pub backend: Option<String>,
+    pub shared_page: Option<usize>,
     pub error: Option<Vec<String>>,
     pub warn: Option<Vec<String>>,
     pub info: Option<Vec<String>>,
@@ -52,7 +56,17 @@ pub struct Log {
 #[serde(deny_unknown_fields)]
 pub struct Debug {
     pub max_firmware_exits: Option<usize>,
+    pub exit_rate_interval: Option<usize>,
+    pub progress_timeout: Option<usize>,
+    pub stack_canary_size: Option<usize>,
     pub nb_iter: Option<usize>,
+    pub benchmark_shared_page: Option<usize>,
+    pub folded_stacks: Option<bool>,
+    pub trap_history: Option<bool>,
+    pub break_on_entry: Option<bool>,
+    pub trace_csr: Option<bool>,
+    pub trace_instructions: Option<bool>,
+    pub protect_read_only: Option<bool>,
 }
 
 #[derive(Deserialize, Debug, Default)]
@@ -60,6 +74,14 @@ pub struct Debug {
 pub struct VCpu {
     pub max_pmp: Option<usize>,
     pub delegate_perf_counters: Option<bool>,
+    pub nb_hpm_counters: Option<usize>,
+    pub hpm_event_bits: Option<usize>,
+    pub emulate_zacas: Option<bool>,
+    pub emulate_svinval: Option<bool>,
+    pub emulate_zawrs: Option<bool>,
+    pub emulate_zicond: Option<bool>,
+    pub virtualize_zicntr: Option<bool>,
+    pub max_transient_retries: Option<usize>,
 }
 
 #[derive(Deserialize, Debug, Default)]
@@ -68,6 +90,8 @@ pub struct Platform {
     pub name: Option<Platforms>,
     pub nb_harts: Option<usize>,
     pub boot_hart_id: Option<usize>,
+    pub strict_boot_hart: Option<bool>,
+    pub disabled_devices: Option<Vec<String>>,
 }
 
 #[derive(Deserialize, Debug, Default)]
@@ -78,6 +102,9 @@ pub struct Qemu {
     pub memory: Option<String>,
     pub disk: Option<String>,
     pub path: Option<String>,
+    /// The QEMU executable, selected from the command line (see `--qemu`).
+    #[serde(skip)]
+    pub binary: Option<PathBuf>,
 }
 
 #[derive(Deserialize, Debug, Clone, Copy)]
@@ -118,12 +145,17 @@ pub struct Target {
     pub profile: Option<Profiles>,
     pub start_address: Option<usize>,
     pub stack_size: Option<usize>,
+    /// Required alignment of the start address, only used for the firmware.
+    pub alignment: Option<usize>,
+    /// Initial register values, as `register=value` pairs, only used for the firmware.
+    pub args: Option<Vec<String>>,
 }
 
 #[derive(Deserialize, Debug, Default)]
 #[serde(deny_unknown_fields)]
 pub struct Modules {
     pub modules: Vec<ModuleName>,
+    pub protect_payload_trap_satp: Option<bool>,
 }
 
 #[derive(Deserialize, Debug, Clone, Copy)]
@@ -134,6 +166,18 @@ pub enum ModuleName {
     ProtectPayload,
     #[serde(rename = "offload")]
     Offload,
+    #[serde(rename = "entropy_test")]
+    EntropyTest,
+    #[serde(rename = "hsm")]
+    Hsm,
+    #[serde(rename = "single_step_test")]
+    SingleStepTest,
+    #[serde(rename = "shutdown_test")]
+    ShutdownTest,
+    #[serde(rename = "audit")]
+    Audit,
+    #[serde(rename = "satp_test")]
+    SatpTest,
     #[serde(rename = "boot_counter")]
     BootCounter,
     #[serde(rename = "exit_counter_per_cause")]
@@ -148,6 +192,12 @@ impl fmt::Display for ModuleName {
             ModuleName::Keystone => write!(f, "keystone"),
             ModuleName::ProtectPayload => write!(f, "protect_payload"),
             ModuleName::Offload => write!(f, "offload"),
+            ModuleName::EntropyTest => write!(f, "entropy_test"),
+            ModuleName::Hsm => write!(f, "hsm"),
+            ModuleName::SingleStepTest => write!(f, "single_step_test"),
+            ModuleName::ShutdownTest => write!(f, "shutdown_test"),
+            ModuleName::Audit => write!(f, "audit"),
+            ModuleName::SatpTest => write!(f, "satp_test"),
             ModuleName::BootCounter => write!(f, "boot_counter"),
             ModuleName::ExitCounterPerCause => write!(f, "exit_counter_per_cause"),
             ModuleName::ExitCounter => write!(f, "exit_counter"),
@@ -215,6 +265,16 @@ impl Log {
         // Decides between colored and gray output
         envs.insert(config::LOG_COLOR_ENV, &self.color);
 
+        // Coalesce consecutive identical messages
+        envs.insert(config::LOG_DEDUPLICATE_ENV, &self.deduplicate);
+
+        // Maximum length of log messages from firmware and payloads
+        envs.insert(config::LOG_MAX_LENGTH_ENV, &self.max_length);
+
+        // Where to write the logs
+        envs.insert(config::LOG_BACKEND_ENV, &self.backend);
+        envs.insert(config::LOG_SHARED_PAGE_ENV, &self.shared_page);
+
         // Modules logged at error level
         envs.insert_array(config::LOG_ERROR_ENV, &self.error);
 
@@ -238,7 +298,23 @@ impl Debug {
     fn build_envs(&self) -> HashMap<String, String> {
         let mut envs = EnvVars::new();
         envs.insert(config::MAX_FIRMWARE_EXIT_ENV, &self.max_firmware_exits);
+        envs.insert(
+            config::DEBUG_EXIT_RATE_INTERVAL_ENV,
+            &self.exit_rate_interval,
+        );
+        envs.insert(config::PROGRESS_TIMEOUT_ENV, &self.progress_timeout);
+        envs.insert(config::DEBUG_STACK_CANARY_SIZE_ENV, &self.stack_canary_size);
         envs.insert(config::BENCHMARK_NB_ITER_ENV, &self.nb_iter);
+        envs.insert(
+            config::BENCHMARK_SHARED_PAGE_ENV,
+            &self.benchmark_shared_page,
+        );
+        envs.insert(config::BENCHMARK_FOLDED_STACKS_ENV, &self.folded_stacks);
+        envs.insert(config::DEBUG_TRAP_HISTORY_ENV, &self.trap_history);
+        envs.insert(config::BREAK_ON_ENTRY_ENV, &self.break_on_entry);
+        envs.insert(config::TRACE_CSR_ENV, &self.trace_csr);
+        envs.insert(config::TRACE_INSTRUCTIONS_ENV, &self.trace_instructions);
+        envs.insert(config::DEBUG_PROTECT_READ_ONLY_ENV, &self.protect_read_only);
         envs.envs
     }
 }
@@ -247,6 +323,17 @@ impl VCpu {
     fn build_envs(&self) -> HashMap<String, String> {
         let mut envs = EnvVars::new();
         envs.insert(config::VCPU_MAX_PMP_ENV, &self.max_pmp);
+        envs.insert(config::VCPU_NB_HPM_COUNTERS_ENV, &self.nb_hpm_counters);
+        envs.insert(config::VCPU_HPM_EVENT_BITS_ENV, &self.hpm_event_bits);
+        envs.insert(config::VCPU_EMULATE_ZACAS_ENV, &self.emulate_zacas);
+        envs.insert(config::VCPU_EMULATE_SVINVAL_ENV, &self.emulate_svinval);
+        envs.insert(config::VCPU_EMULATE_ZAWRS_ENV, &self.emulate_zawrs);
+        envs.insert(config::VCPU_EMULATE_ZICOND_ENV, &self.emulate_zicond);
+        envs.insert(config::VCPU_VIRTUALIZE_ZICNTR_ENV, &self.virtualize_zicntr);
+        envs.insert(
+            config::VCPU_MAX_TRANSIENT_RETRIES_ENV,
+            &self.max_transient_retries,
+        );
         envs.insert(
             config::DELEGATE_PERF_COUNTER_ENV,
             &self.delegate_perf_counters,
@@ -261,6 +348,11 @@ impl Platform {
         envs.insert(config::PLATFORM_NAME_ENV, &self.name);
         envs.insert(config::PLATFORM_NB_HARTS_ENV, &self.nb_harts);
         envs.insert(config::PLATFORM_BOOT_HART_ID_ENV, &self.boot_hart_id);
+        envs.insert(
+            config::PLATFORM_STRICT_BOOT_HART_ENV,
+            &self.strict_boot_hart,
+        );
+        envs.insert_array(config::DISABLED_DEVICES_ENV, &self.disabled_devices);
         envs.envs
     }
 }
@@ -284,10 +376,24 @@ impl Targets {
             config::TARGET_FIRMWARE_ADDRESS_ENV,
             &self.firmware.start_address.or(Some(0x80200000)),
         );
+        envs.insert(
+            config::TARGET_FIRMWARE_ALIGNMENT_ENV,
+            &self.firmware.alignment,
+        );
         envs.insert(
             config::TARGET_FIRMWARE_STACK_SIZE_ENV,
             &self.firmware.stack_size.or(Some(0x8000)),
         );
+        let firmware_args = self.firmware.args.as_ref().filter(|args| !args.is_empty());
+        let firmware_args = firmware_args.map(|args| {
+            args.iter()
+                .map(|arg| match parse_register_arg(arg) {
+                    Ok((register, value)) => format!("{}={}", register, value),
+                    Err(err) => panic!("Invalid firmware argument '{}': {}", arg, err),
+                })
+                .collect()
+        });
+        envs.insert_array(config::TARGET_FIRMWARE_ARGS_ENV, &firmware_args);
 
         // Payload
         if let Some(payload_target) = &self.payload {
@@ -317,10 +423,55 @@ impl Modules {
         if !modules.is_empty() {
             envs.insert(config::MODULES_ENV, &Some(modules));
         }
+        envs.insert(
+            config::PROTECT_PAYLOAD_TRAP_SATP_ENV,
+            &self.protect_payload_trap_satp,
+        );
         envs.envs
     }
 }
 
+/// ABI names of the general purpose registers, indexed by register number.
+const REGISTER_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+/// Parses a `register=value` pair, returning the register index and the value.
+///
+/// Registers can be named either by their ABI name (e.g. `a2`) or their number (e.g. `x12`), and
+/// values can be decimal or hexadecimal with a `0x` prefix.
+pub fn parse_register_arg(arg: &str) -> Result<(usize, usize), String> {
+    let Some((name, value)) = arg.split_once('=') else {
+        return Err(String::from("expected 'register=value'"));
+    };
+    let (name, value) = (name.trim(), value.trim());
+
+    let register = match name {
+        "fp" => 8,
+        _ => match REGISTER_NAMES.iter().position(|reg| *reg == name) {
+            Some(register) => register,
+            None => match name.strip_prefix('x').map(str::parse::<usize>) {
+                Some(Ok(register)) if register < 32 => register,
+                _ => return Err(format!("unknown register '{}'", name)),
+            },
+        },
+    };
+    if register == 0 {
+        return Err(String::from("the zero register can not be set"));
+    }
+
+    let value = match value.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => value.parse::<usize>(),
+    };
+    match value {
+        Ok(value) => Ok((register, value)),
+        Err(err) => Err(format!("invalid value: {}", err)),
+    }
+}
+
 // ————————————————————————————— Config Loader —————————————————————————————— //
 
 pub fn read_config<P: AsRef<Path>>(path: &Option<P>) -> Config {
@@ -390,3 +541,24 @@ fn check_config_file(config: &Path) {
         }
     }
 }
+
+// ————————————————————————————————— Tests —————————————————————————————————— //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_args() {
+        assert_eq!(parse_register_arg("a2=0x42"), Ok((12, 0x42)));
+        assert_eq!(parse_register_arg("x12=66"), Ok((12, 66)));
+        assert_eq!(parse_register_arg("fp=1"), Ok((8, 1)));
+        assert_eq!(parse_register_arg("t6=0"), Ok((31, 0)));
+
+        assert!(parse_register_arg("a2").is_err());
+        assert!(parse_register_arg("a8=1").is_err());
+        assert!(parse_register_arg("x32=1").is_err());
+        assert!(parse_register_arg("zero=1").is_err());
+        assert!(parse_register_arg("a2=0xzz").is_err());
+    }
+}
@@ -9,6 +9,7 @@ use log::LevelFilter;
 use crate::logger::RunnerLogger;
 
 mod artifacts;
+mod benchmark;
 mod build;
 mod config;
 mod gdb;
@@ -47,6 +48,8 @@ enum Subcommands {
     Gdb(GdbArgs),
     /// List the artifacts
     Artifact(ArtifactArgs),
+    /// Compare two sets of benchmark results
+    BenchmarkDiff(BenchmarkDiffArgs),
 }
 
 #[derive(Args)]
@@ -71,6 +74,21 @@ struct RunArgs {
     /// Redirect the output of the run to a file
     #[arg(long)]
     output: Option<String>,
+    /// Run QEMU in deterministic mode, using instruction counting (`-icount`)
+    ///
+    /// The virtual clock and `mcycle` are then derived from the number of executed instructions
+    /// rather than from the host, which makes benchmark numbers stable across runs. Note that
+    /// timing-dependent guest bugs may appear or disappear in this mode.
+    #[arg(long, action)]
+    deterministic: bool,
+    /// Set the initial value of a firmware register, as `register=value` (e.g. `a2=0x42`)
+    ///
+    /// Can be repeated to set multiple registers.
+    #[arg(long = "firmware-arg")]
+    firmware_args: Vec<String>,
+    /// Path to the QEMU executable to use instead of the one in the configuration or `PATH`
+    #[arg(long)]
+    qemu: Option<PathBuf>,
 }
 
 #[derive(Args)]
@@ -81,6 +99,9 @@ struct BuildArgs {
     /// Build a firmware instead of Miralis
     #[arg(short, long)]
     firmware: Option<String>,
+    /// Check that the selected modules fit in the platform's PMP entries, without building
+    #[arg(long, visible_alias = "check-only", action)]
+    check_budget: bool,
 }
 
 #[derive(Args)]
@@ -92,6 +113,15 @@ struct TestArgs {
     /// This flag can also be configured with the environment variable `MIRALIS_RUNNER_STRICT=1`
     #[arg(long, action)]
     strict: bool,
+    /// Path to the QEMU executable to use instead of the one in the configuration or `PATH`
+    #[arg(long)]
+    qemu: Option<PathBuf>,
+    /// Print the name of the tests, one per line, instead of running them
+    #[arg(long, visible_alias = "list-tests", action)]
+    list: bool,
+    /// Re-run a failing test with QEMU execution tracing (`-d in_asm,int`) enabled
+    #[arg(long, action)]
+    trace_on_failure: bool,
 }
 
 #[derive(Args)]
@@ -125,6 +155,17 @@ struct ArtifactArgs {
     markdown: bool,
 }
 
+#[derive(Args)]
+struct BenchmarkDiffArgs {
+    /// Path to the baseline results, either a file or a directory
+    base: PathBuf,
+    /// Path to the new results, either a file or a directory
+    new: PathBuf,
+    /// Disable colors in the output
+    #[arg(long, action)]
+    no_color: bool,
+}
+
 // ————————————————————————— Environment Variables —————————————————————————— //
 
 const RUNNER_STRICT_MODE: &str = "MIRALIS_RUNNER_STRICT";
@@ -163,6 +204,7 @@ fn main() -> ExitCode {
         Subcommands::Gdb(args) => gdb::gdb(&args),
         Subcommands::CheckConfig(args) => config::check_config(&args),
         Subcommands::Artifact(args) => artifacts::list_artifacts(&args),
+        Subcommands::BenchmarkDiff(args) => benchmark::diff_benchmarks(&args),
     }
 }
 
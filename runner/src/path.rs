@@ -111,6 +111,14 @@ pub fn get_artifacts_path() -> PathBuf {
     path
 }
 
+/// Return the path to the folder holding the execution traces of failed tests.
+pub fn get_traces_path() -> PathBuf {
+    let mut path = get_workspace_path();
+    path.push("target");
+    path.push("traces");
+    path
+}
+
 /// Return the target triple definition path for the provided target.
 pub fn get_target_config_path(target: &Target) -> PathBuf {
     let mut path = get_misc_path();
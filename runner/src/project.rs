@@ -32,4 +32,16 @@ pub struct Test {
     pub payload: Option<String>,
     /// An expected string from the output of the test
     pub expect: Option<String>,
+    /// A string that must appear in the output of the test before the expected one
+    pub expect_before: Option<String>,
+    /// A string that must not appear in the output of the test
+    pub forbid: Option<String>,
+    /// Maximum duration of the test, in seconds
+    pub timeout: Option<u64>,
+    /// Skip the test if the firmware image is not present locally, instead of downloading it
+    #[serde(default)]
+    pub optional: bool,
+    /// Run the test with QEMU's deterministic instruction counting mode, see `run --deterministic`
+    #[serde(default)]
+    pub deterministic: bool,
 }
@@ -4,8 +4,9 @@
 //! images.
 
 use core::str;
-use std::fs::File;
-use std::path::PathBuf;
+use std::fs::{self, File};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
 use std::process::{Command, ExitCode};
 use std::str::FromStr;
 
@@ -36,10 +37,19 @@ const FIRMWARE_ADDR: u64 = 0x80200000;
 /// Address at which the payload is loaded in memory.
 const PAYLOAD_ADDR: u64 = 0x80400000;
 
+/// Default alignment required for the firmware address.
+const FIRMWARE_ALIGNMENT: u64 = 0x1000;
+
 // —————————————————————————————————— Run ——————————————————————————————————— //
 
 /// The run command, runs Miralis with the provided arguments.
 pub fn run(args: &RunArgs) -> ExitCode {
+    if let Some(qemu) = &args.qemu
+        && let Err(err) = check_executable(qemu)
+    {
+        log::error!("Invalid QEMU executable '{}': {}", qemu.display(), err);
+        return ExitCode::FAILURE;
+    }
     let cfg = get_config(args);
 
     // Build or retrieve the artifacts to run
@@ -57,7 +67,15 @@ pub fn run(args: &RunArgs) -> ExitCode {
     };
 
     let cmd = match cfg.platform.name.unwrap_or(Platforms::QemuVirt) {
-        Platforms::QemuVirt => get_qemu_cmd(&cfg, miralis, firmware, None, args.debug, args.stop),
+        Platforms::QemuVirt => get_qemu_cmd(
+            &cfg,
+            miralis,
+            firmware,
+            None,
+            args.debug,
+            args.stop,
+            args.deterministic,
+        ),
         Platforms::Spike => get_spike_cmd(&cfg, miralis, firmware),
         Platforms::VisionFive2 | Platforms::PremierP550 => {
             log::error!("We can't run real hardware on simulator.");
@@ -108,6 +126,16 @@ fn get_config(args: &RunArgs) -> Config {
     if let Some(disk) = &args.disk {
         cfg.qemu.disk = Some(disk.to_owned());
     }
+    if let Some(qemu) = &args.qemu {
+        cfg.qemu.binary = Some(qemu.to_owned());
+    }
+    if !args.firmware_args.is_empty() {
+        cfg.target
+            .firmware
+            .args
+            .get_or_insert_default()
+            .extend(args.firmware_args.iter().cloned());
+    }
 
     cfg
 }
@@ -120,12 +148,9 @@ pub fn get_qemu_cmd(
     payload: Option<&String>,
     debug: bool,
     stop: bool,
+    deterministic: bool,
 ) -> Result<Command, ()> {
-    let mut qemu_cmd = if let Some(path) = &cfg.qemu.path {
-        Command::new([path, QEMU].join("/"))
-    } else {
-        Command::new(QEMU)
-    };
+    let mut qemu_cmd = Command::new(qemu_executable(cfg));
 
     qemu_cmd.args(QEMU_ARGS);
     if let Some(machine) = &cfg.qemu.machine {
@@ -142,6 +167,23 @@ pub fn get_qemu_cmd(
         qemu_cmd.arg("2048");
     }
 
+    let firmware_addr = cfg
+        .target
+        .firmware
+        .start_address
+        .map_or(FIRMWARE_ADDR, |addr| addr as u64);
+    let payload_addr = cfg
+        .target
+        .payload
+        .as_ref()
+        .and_then(|payload| payload.start_address)
+        .map_or(PAYLOAD_ADDR, |addr| addr as u64);
+    let alignment = cfg
+        .target
+        .firmware
+        .alignment
+        .map_or(FIRMWARE_ALIGNMENT, |align| align as u64);
+
     qemu_cmd
         .arg("-bios")
         .arg(miralis)
@@ -149,7 +191,7 @@ pub fn get_qemu_cmd(
         .arg(format!(
             "loader,file={},addr=0x{:x},force-raw=on",
             firmware.to_str().unwrap(),
-            FIRMWARE_ADDR
+            firmware_addr
         ));
 
     // If a payload is defined in the config, try to load it at the specified address.
@@ -173,10 +215,25 @@ pub fn get_qemu_cmd(
             }
         };
 
+        // The firmware must not overlap with the payload, otherwise one of the two images would
+        // be silently corrupted by QEMU's loader.
+        let firmware_size = match fs::metadata(&firmware) {
+            Ok(metadata) => metadata.len(),
+            Err(err) => {
+                log::error!("Could not read firmware '{}': {}", firmware.display(), err);
+                return Err(());
+            }
+        };
+        if let Err(err) = check_firmware_fits(firmware_addr, payload_addr, alignment, firmware_size)
+        {
+            log::error!("Invalid firmware '{}': {}", firmware.display(), err);
+            return Err(());
+        }
+
         qemu_cmd.arg("-device").arg(format!(
             "loader,file={},addr=0x{:x},force-raw=on",
             payload.to_str().unwrap(),
-            PAYLOAD_ADDR
+            payload_addr
         ));
     }
 
@@ -211,10 +268,80 @@ pub fn get_qemu_cmd(
     if stop {
         qemu_cmd.arg("-S");
     }
+    if deterministic {
+        // Derive the virtual time from the number of executed instructions, and keep the RTC
+        // on the virtual clock rather than the host clock.
+        qemu_cmd
+            .arg("-icount")
+            .arg("shift=auto")
+            .arg("-rtc")
+            .arg("clock=vm");
+    }
 
     Ok(qemu_cmd)
 }
 
+/// Returns the QEMU executable selected by the configuration.
+///
+/// An executable passed with `--qemu` takes precedence over the `path` of the configuration, and
+/// the executable is looked up in the `PATH` if none of them is provided.
+fn qemu_executable(cfg: &Config) -> PathBuf {
+    if let Some(binary) = &cfg.qemu.binary {
+        binary.clone()
+    } else if let Some(path) = &cfg.qemu.path {
+        Path::new(path).join(QEMU)
+    } else {
+        PathBuf::from(QEMU)
+    }
+}
+
+/// Checks that the path points to an executable file.
+pub fn check_executable(path: &Path) -> Result<(), String> {
+    let metadata = fs::metadata(path).map_err(|err| err.to_string())?;
+    if !metadata.is_file() {
+        return Err(String::from("not a file"));
+    }
+    if metadata.permissions().mode() & 0o111 == 0 {
+        return Err(String::from("not executable"));
+    }
+
+    Ok(())
+}
+
+/// Checks that a firmware image of the given size, loaded at the given address, is properly aligned
+/// and fits before the payload.
+fn check_firmware_fits(
+    firmware_addr: u64,
+    payload_addr: u64,
+    alignment: u64,
+    firmware_size: u64,
+) -> Result<(), String> {
+    if !alignment.is_power_of_two() {
+        return Err(format!("alignment 0x{:x} is not a power of two", alignment));
+    }
+    if !firmware_addr.is_multiple_of(alignment) {
+        return Err(format!(
+            "address 0x{:x} is not aligned to 0x{:x}",
+            firmware_addr, alignment
+        ));
+    }
+    if firmware_addr >= payload_addr {
+        return Err(format!(
+            "address 0x{:x} is past the payload address 0x{:x}",
+            firmware_addr, payload_addr
+        ));
+    }
+    let available = payload_addr - firmware_addr;
+    if firmware_size > available {
+        return Err(format!(
+            "image of 0x{:x} bytes overlaps with the payload at 0x{:x} (only 0x{:x} bytes available)",
+            firmware_size, payload_addr, available
+        ));
+    }
+
+    Ok(())
+}
+
 /// Return the command to run Miralis on Spike.
 pub fn get_spike_cmd(cfg: &Config, miralis: PathBuf, firmware: PathBuf) -> Result<Command, ()> {
     let mut spike_cmd = Command::new(SPIKE);
@@ -236,9 +363,9 @@ fn raw_to_elf(raw_path: &str) -> &str {
     &raw_path[..raw_path.len() - 4]
 }
 
-/// Returns true if QEMU is available.
-pub fn qemu_is_available() -> bool {
-    let mut qemu_cmd = Command::new(QEMU);
+/// Returns true if QEMU is available, using the provided executable if any.
+pub fn qemu_is_available(binary: Option<&Path>) -> bool {
+    let mut qemu_cmd = Command::new(binary.unwrap_or(Path::new(QEMU)));
     qemu_cmd.arg("--version");
     qemu_cmd
         .stdout(std::process::Stdio::null())
@@ -257,3 +384,49 @@ pub fn spike_is_available() -> bool {
 
     spike_cmd.status().is_ok()
 }
+
+// ————————————————————————————————— Tests —————————————————————————————————— //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn firmware_fits() {
+        let size = PAYLOAD_ADDR - FIRMWARE_ADDR;
+        assert!(check_firmware_fits(FIRMWARE_ADDR, PAYLOAD_ADDR, FIRMWARE_ALIGNMENT, size).is_ok());
+
+        // Oversized image, overlapping with the payload
+        assert!(
+            check_firmware_fits(FIRMWARE_ADDR, PAYLOAD_ADDR, FIRMWARE_ALIGNMENT, size + 1).is_err()
+        );
+
+        // Misaligned firmware address
+        assert!(check_firmware_fits(FIRMWARE_ADDR + 8, PAYLOAD_ADDR, 0x1000, 0x100).is_err());
+
+        // Invalid alignment
+        assert!(check_firmware_fits(FIRMWARE_ADDR, PAYLOAD_ADDR, 0x1001, 0x100).is_err());
+
+        // Firmware past the payload
+        assert!(check_firmware_fits(PAYLOAD_ADDR, FIRMWARE_ADDR, FIRMWARE_ALIGNMENT, 0).is_err());
+    }
+
+    #[test]
+    fn executable() {
+        // The test binary itself is executable
+        let test_binary = std::env::current_exe().unwrap();
+        assert!(check_executable(&test_binary).is_ok());
+
+        // Directories and missing files are not
+        assert!(check_executable(test_binary.parent().unwrap()).is_err());
+        assert!(check_executable(Path::new("/this/qemu/does/not/exist")).is_err());
+
+        // Nor are regular files
+        let file = std::env::temp_dir().join(format!("miralis-qemu-{}", std::process::id()));
+        fs::write(&file, "").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o644)).unwrap();
+        let result = check_executable(&file);
+        fs::remove_file(&file).unwrap();
+        assert_eq!(result, Err(String::from("not executable")));
+    }
+}
@@ -3,14 +3,20 @@
 use std::collections::HashMap;
 use std::io::Read;
 use std::path::PathBuf;
-use std::process::{ExitCode, Stdio};
-use std::{env, fs};
+use std::process::{Child, Command, ExitCode, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+use std::{env, fs, thread};
 
-use crate::artifacts::{Target, build_target, prepare_firmware_artifact};
+use crate::artifacts::{
+    Target, build_target, is_artifact_available_locally, prepare_firmware_artifact,
+};
 use crate::config::{Config, Platforms, read_config};
-use crate::path::{get_project_config_path, make_path_relative_to_root};
+use crate::path::{get_project_config_path, get_traces_path, make_path_relative_to_root};
 use crate::project::{ProjectConfig, Test};
-use crate::run::{QEMU, SPIKE, get_qemu_cmd, get_spike_cmd, qemu_is_available, spike_is_available};
+use crate::run::{
+    QEMU, SPIKE, check_executable, get_qemu_cmd, get_spike_cmd, qemu_is_available,
+    spike_is_available,
+};
 use crate::{RUNNER_STRICT_MODE, TestArgs};
 
 #[derive(Debug, PartialEq, Eq)]
@@ -34,8 +40,13 @@ struct SkippedTests {
     qemu: usize,
     /// Skipped because Spike is not available
     spike: usize,
+    /// Skipped because the firmware image of an optional test is not present
+    missing: usize,
 }
 
+/// Interval at which we check if a test with a timeout has completed.
+const TIMEOUT_POLLING_INTERVAL: Duration = Duration::from_millis(100);
+
 /// The test command, run all the tests.
 pub fn run_tests(args: &mut TestArgs) -> ExitCode {
     if env::var(RUNNER_STRICT_MODE).is_ok() && !args.strict {
@@ -43,6 +54,13 @@ pub fn run_tests(args: &mut TestArgs) -> ExitCode {
         args.strict = true;
     }
 
+    if let Some(qemu) = &args.qemu
+        && let Err(err) = check_executable(qemu)
+    {
+        log::error!("Invalid QEMU executable '{}': {}", qemu.display(), err);
+        return ExitCode::FAILURE;
+    }
+
     let mut stats = TestStats::default();
     let path = get_project_config_path();
     let config = match fs::read_to_string(&path) {
@@ -62,6 +80,20 @@ pub fn run_tests(args: &mut TestArgs) -> ExitCode {
         }
     };
 
+    // Only list the tests if requested
+    if args.list {
+        for name in config.test.keys() {
+            if args
+                .pattern
+                .as_ref()
+                .is_none_or(|pattern| name.starts_with(pattern))
+            {
+                println!("{}", name);
+            }
+        }
+        return ExitCode::SUCCESS;
+    }
+
     // Group tests by config files
     let mut test_groups = HashMap::new();
     for (cfg_name, cfg) in &config.config {
@@ -88,13 +120,14 @@ pub fn run_tests(args: &mut TestArgs) -> ExitCode {
     }
 
     // Check which emulators are available
-    let qemu_available = qemu_is_available();
+    let qemu_available = qemu_is_available(args.qemu.as_deref());
     let spike_available = spike_is_available();
 
     // Run tests, grouped by config (to minimize the need to re-compile)
     for (cfg_name, _) in &config.config {
         let test_group = &test_groups[cfg_name];
-        let cfg = read_config(&Some(&test_group.config_path));
+        let mut cfg = read_config(&Some(&test_group.config_path));
+        cfg.qemu.binary = args.qemu.clone();
         for (test_name, test) in &test_group.tests {
             // Filter tests if a pattern is provided
             if let Some(pattern) = &args.pattern
@@ -116,11 +149,26 @@ pub fn run_tests(args: &mut TestArgs) -> ExitCode {
                 _ => (),
             }
 
+            // Skip optional tests if the firmware image is not present
+            if test.optional
+                && let Some(firmware) = test.firmware.as_ref().or(cfg.target.firmware.name.as_ref())
+                && !is_artifact_available_locally(firmware)
+            {
+                log::info!("Skipping {}, '{}' is not present", test_name, firmware);
+                stats.skipped.missing += 1;
+                continue;
+            }
+
             if let Err(cmd) = run_one_test(test, test_name, &cfg) {
                 log::error!("Failed to run test '{}'", test_name);
                 if let Some(cmd) = cmd {
                     log::info!("To reproduce, run:\n{}", cmd);
                 }
+                if args.trace_on_failure
+                    && let Some(trace) = trace_one_test(test, test_name, &cfg)
+                {
+                    log::info!("Execution trace written to '{}'", trace.display());
+                }
                 return ExitCode::FAILURE;
             } else {
                 stats.success += 1;
@@ -147,6 +195,14 @@ pub fn run_tests(args: &mut TestArgs) -> ExitCode {
         );
     }
 
+    if stats.skipped.missing > 0 {
+        log::warn!(
+            "Firmware images are not present, skipped {} optional test{}",
+            stats.skipped.missing,
+            if stats.skipped.missing > 1 { "s" } else { "" }
+        );
+    }
+
     if args.strict {
         // Strict runs are successful only if all tests run successfully. They fail if some tests
         // are skipped.
@@ -166,21 +222,21 @@ pub fn run_one_test(test: &Test, test_name: &str, cfg: &Config) -> Result<(), Op
     log::info!("Running {}", test_name);
 
     // Build or retrieve the artifacts to run
-    let miralis = build_target(Target::Miralis, cfg);
-    let Some(firmware) = test.firmware.as_ref().or(cfg.target.firmware.name.as_ref()) else {
-        log::error!("No firmware specified for test '{}'", test_name);
-        return Err(None);
-    };
-    let Some(firmware) = prepare_firmware_artifact(firmware, cfg) else {
-        log::error!("Failed to prepare firmware artifact '{}'", test_name);
+    let Some((miralis, firmware)) = prepare_test_artifacts(test, test_name, cfg) else {
         return Err(None);
     };
 
     // Prepare the command to run
     let cmd = match cfg.platform.name.unwrap_or(Platforms::QemuVirt) {
-        Platforms::QemuVirt => {
-            get_qemu_cmd(cfg, miralis, firmware, test.payload.as_ref(), false, false)
-        }
+        Platforms::QemuVirt => get_qemu_cmd(
+            cfg,
+            miralis,
+            firmware,
+            test.payload.as_ref(),
+            false,
+            false,
+            test.deterministic,
+        ),
         Platforms::Spike => get_spike_cmd(cfg, miralis, firmware),
         invalid_platform => {
             log::error!("Invalid test platform: '{}'", invalid_platform);
@@ -192,39 +248,73 @@ pub fn run_one_test(test: &Test, test_name: &str, cfg: &Config) -> Result<(), Op
         return Err(None);
     };
 
-    log::debug!(
-        "{} {}",
-        cmd.get_program().to_str().unwrap(),
-        cmd.get_args()
-            .map(|arg| arg.to_str().unwrap())
-            .collect::<Vec<_>>()
-            .join(" ")
-    );
+    log::debug!("{}", format_cmd(&cmd));
 
     // Then execute the test and check for the success criteria
     //
-    // For some tests we require a substring to be present in the output, in those cases we do some
-    // aditionnal work on top of checking the exit status.
+    // For some tests we require a substring to be present (or absent) in the output, or the test
+    // to complete within a timeout. In those cases we do some aditionnal work on top of checking
+    // the exit status.
     let mut succeeded = true;
-    let exit_status = if let Some(expected) = &test.expect {
+    let exit_status = if test.expect.is_some()
+        || test.expect_before.is_some()
+        || test.forbid.is_some()
+        || test.timeout.is_some()
+    {
         // We need to get the output of the child, we create a pipe for that purpose
         cmd.stdout(Stdio::piped());
         let mut child = cmd.spawn().expect("Failed to spawn command");
-        let pipe = child
+        let mut pipe = child
             .stdout
-            .as_mut()
+            .take()
             .expect("Could not read child process output");
-        let mut buff = Vec::new();
-        pipe.read_to_end(&mut buff)
-            .expect("Failed to read output from child process");
-        let exit_status = child.wait().expect("Failed to wait for child process");
+        let reader = thread::spawn(move || {
+            let mut buff = Vec::new();
+            pipe.read_to_end(&mut buff)
+                .expect("Failed to read output from child process");
+            buff
+        });
 
-        // We got the exit status, now also check for the expected pattern
+        let exit_status = match test.timeout {
+            Some(timeout) => wait_with_timeout(&mut child, Duration::from_secs(timeout)),
+            None => Some(child.wait().expect("Failed to wait for child process")),
+        };
+        let buff = reader
+            .join()
+            .expect("Failed to collect the child process output");
+        let Some(exit_status) = exit_status else {
+            log::error!("Test did not complete within {}s", test.timeout.unwrap());
+            return Err(Some(format_cmd(&cmd)));
+        };
+
+        // We got the exit status, now also check for the expected and forbidden patterns
         let buff = String::from_utf8_lossy(&buff);
-        if !buff.contains(expected) {
+        if let Some(expected) = &test.expect
+            && !buff.contains(expected)
+        {
             log::error!("Could not find '{}' in the test output", expected);
             succeeded = false;
         }
+        if let Some(before) = &test.expect_before {
+            let expected = test.expect.as_deref().unwrap_or_default();
+            match (buff.find(before.as_str()), buff.find(expected)) {
+                (Some(before_idx), Some(expected_idx)) if before_idx <= expected_idx => (),
+                _ => {
+                    log::error!(
+                        "Could not find '{}' before '{}' in the test output",
+                        before,
+                        expected
+                    );
+                    succeeded = false;
+                }
+            }
+        }
+        if let Some(forbidden) = &test.forbid
+            && buff.contains(forbidden)
+        {
+            log::error!("Found '{}' in the test output", forbidden);
+            succeeded = false;
+        }
 
         exit_status
     } else {
@@ -233,16 +323,104 @@ pub fn run_one_test(test: &Test, test_name: &str, cfg: &Config) -> Result<(), Op
     };
 
     if !exit_status.success() || !succeeded {
-        let cmd_str = format!(
-            "{} {}",
-            cmd.get_program().to_str().unwrap(),
-            cmd.get_args()
-                .map(|arg| arg.to_str().unwrap())
-                .collect::<Vec<_>>()
-                .join(" ")
-        );
-        Err(Some(cmd_str))
+        Err(Some(format_cmd(&cmd)))
     } else {
         Ok(())
     }
 }
+
+/// Build or retrieve the Miralis and firmware artifacts of a test.
+fn prepare_test_artifacts(
+    test: &Test,
+    test_name: &str,
+    cfg: &Config,
+) -> Option<(PathBuf, PathBuf)> {
+    let miralis = build_target(Target::Miralis, cfg);
+    let Some(firmware) = test.firmware.as_ref().or(cfg.target.firmware.name.as_ref()) else {
+        log::error!("No firmware specified for test '{}'", test_name);
+        return None;
+    };
+    let Some(firmware) = prepare_firmware_artifact(firmware, cfg) else {
+        log::error!("Failed to prepare firmware artifact '{}'", test_name);
+        return None;
+    };
+    Some((miralis, firmware))
+}
+
+/// Re-run a test on QEMU, logging the executed instructions and interrupts to a file.
+///
+/// Returns the path to the trace file, named after the test, or `None` if the trace could not be
+/// collected.
+fn trace_one_test(test: &Test, test_name: &str, cfg: &Config) -> Option<PathBuf> {
+    if !matches!(cfg.platform.name, None | Some(Platforms::QemuVirt)) {
+        log::warn!("Execution traces are only supported on QEMU");
+        return None;
+    }
+
+    let (miralis, firmware) = prepare_test_artifacts(test, test_name, cfg)?;
+    let Ok(mut cmd) = get_qemu_cmd(
+        cfg,
+        miralis,
+        firmware,
+        test.payload.as_ref(),
+        false,
+        false,
+        test.deterministic,
+    ) else {
+        log::error!("Failed to build command");
+        return None;
+    };
+
+    let traces = get_traces_path();
+    if let Err(err) = fs::create_dir_all(&traces) {
+        log::error!("Could not create '{}': {}", traces.display(), err);
+        return None;
+    }
+    let trace = traces.join(format!("{}.log", test_name));
+    cmd.arg("-d").arg("in_asm,int").arg("-D").arg(&trace);
+    cmd.stdout(Stdio::null());
+
+    log::info!("Re-running {} with execution tracing", test_name);
+    log::debug!("{}", format_cmd(&cmd));
+    let mut child = cmd.spawn().expect("Failed to spawn command");
+    match test.timeout {
+        Some(timeout) => {
+            wait_with_timeout(&mut child, Duration::from_secs(timeout));
+        }
+        None => {
+            child.wait().expect("Failed to wait for child process");
+        }
+    }
+
+    Some(trace)
+}
+
+/// Wait for the child process to exit, killing it if it does not exit before the timeout.
+///
+/// Returns `None` if the timeout expired.
+fn wait_with_timeout(child: &mut Child, timeout: Duration) -> Option<ExitStatus> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait().expect("Failed to wait for child process") {
+            return Some(status);
+        }
+        if Instant::now() >= deadline {
+            child.kill().expect("Failed to kill child process");
+            child.wait().expect("Failed to wait for child process");
+            return None;
+        }
+        thread::sleep(TIMEOUT_POLLING_INTERVAL);
+    }
+}
+
+/// Format a command so that it can be copied and run from a shell.
+fn format_cmd(cmd: &Command) -> String {
+    format!(
+        "{} {}",
+        cmd.get_program().to_str().unwrap(),
+        cmd.get_args()
+            .map(|arg| arg.to_str().unwrap())
+            .collect::<Vec<_>>()
+            .join(" ")
+    )
+}
@@ -15,10 +15,10 @@ use softcore_asm_rv64::softcore_init;
 #[cfg(any(test, feature = "userspace"))]
 use softcore_rv64::{Core, config, new_core};
 
-use super::{Csr, ExtensionsCapability, Mode, RegistersCapability, menvcfg};
+use super::{BarrierKind, Csr, ExtensionsCapability, Mode, RegistersCapability, menvcfg};
 use crate::arch::Csr::{Mtinst, Mtval2};
 use crate::arch::hstatus::GVA_FILTER;
-use crate::arch::{HardwareCapability, Width, mie, misa, mstatus, parse_mpp_return_mode};
+use crate::arch::{HardwareCapability, Width, hstatus, mie, misa, mstatus, parse_mpp_return_mode};
 use crate::decoder::{LoadInstr, StoreInstr};
 use crate::platform::{Plat, Platform};
 use crate::virt::VirtContext;
@@ -166,6 +166,29 @@ fn install_handler(handler: usize) {
     assert_eq!(handler, mtvec, "Failed to set trap handler");
 }
 
+/// Reads the Zkr `seed` CSR, returns None if the CSR is not implemented.
+///
+/// The `seed` CSR must be accessed with a read-write instruction, the write value is ignored.
+/// Reading it raises an illegal instruction exception if Zkr is not implemented, which is
+/// caught by the tracing handler.
+pub unsafe fn read_seed() -> Option<usize> {
+    install_handler(_tracing_trap_handler as usize);
+    let seed: usize;
+    let tracer_var: usize;
+    unsafe {
+        soft_asm!(
+            "csrw mscratch, zero",
+            "csrrw {0}, 0x015, zero",
+            "csrr {1}, mscratch",
+            out(reg) seed,
+            out(reg) tracer_var,
+        );
+    }
+    install_handler(_raw_trap_handler as usize);
+
+    if tracer_var == 0 { Some(seed) } else { None }
+}
+
 /// Wait for interrupt
 #[inline]
 pub fn wfi() {
@@ -319,6 +342,7 @@ pub unsafe fn write_csr(csr: Csr, value: usize) -> usize {
             Csr::Cycle => todo!(),
             Csr::Time => todo!(),
             Csr::Instret => todo!(),
+            Csr::Cycleh | Csr::Timeh | Csr::Instreth => todo!(),
             Csr::Mhpmcounter(_) => todo!(),
             Csr::Mcountinhibit => asm_write_csr!("mcountinhibit"),
             Csr::Mhpmevent(_) => todo!(),
@@ -378,6 +402,9 @@ pub unsafe fn write_csr(csr: Csr, value: usize) -> usize {
             Csr::Vstval => asm_write_csr!("vstval"),
             Csr::Vsip => asm_write_csr!("vsip"),
             Csr::Vsatp => asm_write_csr!("vsatp"),
+            Csr::Fflags => asm_write_csr!("fflags"),
+            Csr::Frm => asm_write_csr!("frm"),
+            Csr::Fcsr => asm_write_csr!("fcsr"),
             Csr::Vstart => todo!(),
             Csr::Vxsat => todo!(),
             Csr::Vxrm => todo!(),
@@ -430,6 +457,7 @@ pub fn read_csr(csr: Csr) -> usize {
             value = Plat::get_clint().read_mtime();
         }
         Csr::Instret => todo!(),
+        Csr::Cycleh | Csr::Timeh | Csr::Instreth => todo!(),
         Csr::Mhpmcounter(_) => todo!(),
         Csr::Mcountinhibit => asm_read_csr!("mcountinhibit"),
         Csr::Mhpmevent(_) => todo!(),
@@ -489,6 +517,9 @@ pub fn read_csr(csr: Csr) -> usize {
         Csr::Vstval => asm_read_csr!("vstval"),
         Csr::Vsip => asm_read_csr!("vsip"),
         Csr::Vsatp => asm_read_csr!("vsatp"),
+        Csr::Fflags => asm_read_csr!("fflags"),
+        Csr::Frm => asm_read_csr!("frm"),
+        Csr::Fcsr => asm_read_csr!("fcsr"),
         Csr::Vstart => todo!(),
         Csr::Vxsat => todo!(),
         Csr::Vxrm => todo!(),
@@ -504,6 +535,63 @@ pub fn read_csr(csr: Csr) -> usize {
     value
 }
 
+/// Number of bytes of a CSR access stub in the raw CSR jump tables.
+#[cfg(not(any(test, feature = "userspace")))]
+const RAW_CSR_STUB_SIZE: usize = 8;
+
+/// Read a CSR identified by its raw 12-bit number.
+///
+/// Unlike [read_csr] this works for any CSR, including those without a [Csr] variant. Because
+/// CSR numbers are immediates the access goes through a jump table holding one `csrr` stub per
+/// CSR, which is only linked in when this function is used.
+///
+/// Reading a CSR not implemented by the hardware traps into Miralis.
+pub fn read_csr_raw(csr: u16) -> usize {
+    assert!(csr < 0x1000, "Invalid CSR number: 0x{:x}", csr);
+
+    #[cfg(not(any(test, feature = "userspace")))]
+    unsafe {
+        let stub = _read_csr_table as usize + csr as usize * RAW_CSR_STUB_SIZE;
+        let stub: extern "C" fn() -> usize = core::mem::transmute(stub);
+        stub()
+    }
+
+    #[cfg(any(test, feature = "userspace"))]
+    SOFT_CORE.with_borrow_mut(|core| {
+        core.get_csr(csr as u64)
+            .unwrap_or_else(|| panic!("Illegal read from CSR 0x{:x}", csr)) as usize
+    })
+}
+
+/// Write a CSR identified by its raw 12-bit number and return its previous value.
+///
+/// This is the raw counterpart of [write_csr], see [read_csr_raw].
+///
+/// # Safety
+///
+/// This function writes to the hardware CSR, use with caution as it might change the execution
+/// environment. Writing a CSR not implemented by the hardware traps into Miralis.
+pub unsafe fn write_csr_raw(csr: u16, value: usize) -> usize {
+    assert!(csr < 0x1000, "Invalid CSR number: 0x{:x}", csr);
+
+    #[cfg(not(any(test, feature = "userspace")))]
+    unsafe {
+        let stub = _write_csr_table as usize + csr as usize * RAW_CSR_STUB_SIZE;
+        let stub: extern "C" fn(usize) -> usize = core::mem::transmute(stub);
+        stub(value)
+    }
+
+    #[cfg(any(test, feature = "userspace"))]
+    SOFT_CORE.with_borrow_mut(|core| {
+        let prev = core.get_csr(csr as u64);
+        let new = core.set_csr(csr as u64, value as u64);
+        match (prev, new) {
+            (Some(prev), Some(_)) => prev as usize,
+            _ => panic!("Illegal write to CSR 0x{:x}", csr),
+        }
+    })
+}
+
 /// Detect available hardware capabilities.
 ///
 /// Capabilities are local to a core: two cores (harts in RISC-V parlance) can have different
@@ -579,6 +667,9 @@ pub unsafe fn detect_hardware() -> HardwareCapability {
         }
     }
 
+    // The debug context registers are optional, even with the Sdtrig extension
+    let is_scontext_present: bool = register_present!("scontext");
+
     // Detect available PMP registers:
     // - On RV64 platforms only even-numbered pmpcfg registers are present
     // - The spec mandates that there is either 0, 16 or 64 PMP registers implemented
@@ -639,6 +730,7 @@ pub unsafe fn detect_hardware() -> HardwareCapability {
             menvcfg: is_menvcfg_present,
             henvcfg: is_henvcfg_present,
             senvcfg: is_senvcfg_present,
+            scontext: is_scontext_present,
             nb_pmp,
         },
         extensions: ExtensionsCapability {
@@ -650,11 +742,13 @@ pub unsafe fn detect_hardware() -> HardwareCapability {
             has_zicboz_extension,
             is_sstc_enabled: false, // Since the virtual menvcfg is initialized with 0
             has_v_extension: false,
+            has_f_extension: (misa & misa::F) != 0,
             has_crypto_extension: false,
             has_zicntr: is_mcycle_present,
             has_zfinx: false,
             has_zihpm_extension: true,
             has_tee_extension: true,
+            has_sscofpmf_extension: (available_int & mie::LCOFIE_FILTER) != 0,
         },
     }
 }
@@ -790,6 +884,35 @@ pub fn ifence() {
     unsafe { soft_asm!("fence.i") };
 }
 
+/// Emits a memory fence enforcing the ordering described by `kind`.
+pub fn fence(kind: BarrierKind) {
+    // Softcore does not model memory ordering: it executes a single hart and all its memory
+    // accesses are sequentially consistent, therefore fences are no-ops when testing.
+    macro_rules! asm_fence {
+        ($instr:literal) => {{
+            #[cfg(not(any(test, feature = "userspace")))]
+            unsafe {
+                core::arch::asm!($instr, options(nostack));
+            }
+        }};
+    }
+
+    match kind {
+        BarrierKind::RwRw => asm_fence!("fence rw, rw"),
+        BarrierKind::RRw => asm_fence!("fence r, rw"),
+        BarrierKind::RR => asm_fence!("fence r, r"),
+        BarrierKind::RwW => asm_fence!("fence rw, w"),
+        BarrierKind::WW => asm_fence!("fence w, w"),
+        BarrierKind::WRw => asm_fence!("fence w, rw"),
+        BarrierKind::RwR => asm_fence!("fence rw, r"),
+        BarrierKind::RW => asm_fence!("fence r, w"),
+        BarrierKind::WR => asm_fence!("fence w, r"),
+        BarrierKind::Tso => asm_fence!("fence.tso"),
+        BarrierKind::IoRwIoRw => asm_fence!("fence iorw, iorw"),
+        BarrierKind::None => {}
+    }
+}
+
 /// Change mstatus.MPP and return the previous mstatus.MPP
 pub unsafe fn set_mpp(mode: Mode) -> Mode {
     let value = mode.to_bits() << mstatus::MPP_OFFSET;
@@ -836,6 +959,7 @@ pub unsafe fn clear_csr_bits(csr: Csr, bits_mask: usize) -> usize {
             Csr::Cycle => todo!(),
             Csr::Time => todo!(),
             Csr::Instret => todo!(),
+            Csr::Cycleh | Csr::Timeh | Csr::Instreth => todo!(),
             Csr::Mhpmcounter(_) => todo!(),
             Csr::Mcountinhibit => asm_clear_csr_bits!("mcountinhibit"),
             Csr::Mhpmevent(_) => todo!(),
@@ -895,6 +1019,9 @@ pub unsafe fn clear_csr_bits(csr: Csr, bits_mask: usize) -> usize {
             Csr::Vstval => asm_clear_csr_bits!("vstval"),
             Csr::Vsip => asm_clear_csr_bits!("vsip"),
             Csr::Vsatp => asm_clear_csr_bits!("vsatp"),
+            Csr::Fflags => asm_clear_csr_bits!("fflags"),
+            Csr::Frm => asm_clear_csr_bits!("frm"),
+            Csr::Fcsr => asm_clear_csr_bits!("fcsr"),
             Csr::Vstart => todo!(),
             Csr::Vxsat => todo!(),
             Csr::Vxrm => todo!(),
@@ -950,6 +1077,7 @@ pub unsafe fn set_csr_bits(csr: Csr, bits_mask: usize) -> usize {
             Csr::Cycle => todo!(),
             Csr::Time => todo!(),
             Csr::Instret => todo!(),
+            Csr::Cycleh | Csr::Timeh | Csr::Instreth => todo!(),
             Csr::Mhpmcounter(_) => todo!(),
             Csr::Mcountinhibit => asm_set_csr_bits!("mcountinhibit"),
             Csr::Mhpmevent(_) => todo!(),
@@ -1009,6 +1137,9 @@ pub unsafe fn set_csr_bits(csr: Csr, bits_mask: usize) -> usize {
             Csr::Vstval => asm_set_csr_bits!("vstval"),
             Csr::Vsip => asm_set_csr_bits!("vsip"),
             Csr::Vsatp => asm_set_csr_bits!("vsatp"),
+            Csr::Fflags => asm_set_csr_bits!("fflags"),
+            Csr::Frm => asm_set_csr_bits!("frm"),
+            Csr::Fcsr => asm_set_csr_bits!("fcsr"),
             Csr::Vstart => todo!(),
             Csr::Vxsat => todo!(),
             Csr::Vxrm => todo!(),
@@ -1025,16 +1156,15 @@ pub unsafe fn set_csr_bits(csr: Csr, bits_mask: usize) -> usize {
     prev_value
 }
 
-/// Emulates a load instruction using MPRV = 1.
+/// Emulates a load instruction using MPRV = 1, with the privileges of `mode`.
 ///
 /// # Safety
 ///
 /// This function performs a load using MPRV = 1, whose behavior depends on the privilegd state
 /// and in particular PMP and page tables. The privileged state must be configured properly to
 /// ensure the proper access rights are enforced.
-pub unsafe fn handle_virtual_load(instr: LoadInstr, ctx: &mut VirtContext) {
-    // Set the MPP mode to match the vMPP
-    let prev_mpp = unsafe { set_mpp(parse_mpp_return_mode(ctx.csr.mstatus)) };
+pub unsafe fn handle_virtual_load(instr: LoadInstr, ctx: &mut VirtContext, mode: Mode) {
+    let prev_mpp = unsafe { set_mpp(mode) };
     let prev_satp = unsafe { write_csr(Csr::Satp, ctx.csr.satp) };
 
     // Changes to SATP require an sfence instruction to take effect
@@ -1075,15 +1205,14 @@ pub unsafe fn handle_virtual_load(instr: LoadInstr, ctx: &mut VirtContext) {
     sfencevma(None, None);
 }
 
-/// Emulates a store instruction using MPRV = 1.
+/// Emulates a store instruction using MPRV = 1, with the privileges of `mode`.
 ///
 /// # Safety
 /// This function performs a store using MPRV = 1, whose behavior depends on the privilegd
 /// state and in particular PMP and page tables. The privileged state must be configured
 /// properly to ensure the proper access rights are enforced.
-pub unsafe fn handle_virtual_store(instr: StoreInstr, ctx: &mut VirtContext) {
-    // Set the MPP mode to match the vMPP
-    let prev_mpp = unsafe { set_mpp(parse_mpp_return_mode(ctx.csr.mstatus)) };
+pub unsafe fn handle_virtual_store(instr: StoreInstr, ctx: &mut VirtContext, mode: Mode) {
+    let prev_mpp = unsafe { set_mpp(mode) };
     let prev_satp = unsafe { write_csr(Csr::Satp, ctx.csr.satp) };
 
     // Changes to SATP require an sfence instruction to take effect
@@ -1105,7 +1234,7 @@ pub unsafe fn handle_virtual_store(instr: StoreInstr, ctx: &mut VirtContext) {
     // Silence unused warning caused by the macro
     let _ = value;
 
-    if success {
+    if !success {
         // In that case we need to update the trap info and inject the trap back.
         ctx.emulate_firmware_trap();
     } else {
@@ -1122,6 +1251,121 @@ pub unsafe fn handle_virtual_store(instr: StoreInstr, ctx: &mut VirtContext) {
     sfencevma(None, None);
 }
 
+/// Emulates a hypervisor virtual-machine load (`hlv`) using MPRV = 1 and MPV = 1.
+///
+/// # Safety
+///
+/// This function performs a load with the two-stage translation of the virtual guest, see
+/// [hypervisor_mem_op]. The PMP must be configured to enforce the access rights of the
+/// virtual firmware.
+pub unsafe fn handle_hypervisor_load(instr: LoadInstr, ctx: &mut VirtContext) {
+    let mut value: usize = 0;
+    let addr = ctx.get(instr.rs1);
+
+    let success = unsafe {
+        hypervisor_mem_op(ctx, || match (instr.len, instr.is_unsigned) {
+            (Width::Byte, false) => asm_mprv_mem_op!("lb", addr, value),
+            (Width::Byte2, false) => asm_mprv_mem_op!("lh", addr, value),
+            (Width::Byte4, false) => asm_mprv_mem_op!("lw", addr, value),
+            (Width::Byte8, false) => asm_mprv_mem_op!("ld", addr, value),
+            (Width::Byte, true) => asm_mprv_mem_op!("lbu", addr, value),
+            (Width::Byte2, true) => asm_mprv_mem_op!("lhu", addr, value),
+            (Width::Byte4, true) => asm_mprv_mem_op!("lwu", addr, value),
+            _ => panic!("Unknown hypervisor load instruction"),
+        })
+    };
+
+    if success {
+        ctx.set(instr.rd, value);
+        ctx.pc += 4;
+    } else {
+        ctx.emulate_firmware_trap();
+    }
+}
+
+/// Emulates a hypervisor virtual-machine store (`hsv`) using MPRV = 1 and MPV = 1.
+///
+/// # Safety
+///
+/// This function performs a store with the two-stage translation of the virtual guest, see
+/// [hypervisor_mem_op]. The PMP must be configured to enforce the access rights of the
+/// virtual firmware.
+pub unsafe fn handle_hypervisor_store(instr: StoreInstr, ctx: &mut VirtContext) {
+    let mut value: usize = ctx.get(instr.rs2);
+    let addr = ctx.get(instr.rs1);
+
+    let success = unsafe {
+        hypervisor_mem_op(ctx, || match instr.len {
+            Width::Byte => asm_mprv_mem_op!("sb", addr, value),
+            Width::Byte2 => asm_mprv_mem_op!("sh", addr, value),
+            Width::Byte4 => asm_mprv_mem_op!("sw", addr, value),
+            Width::Byte8 => asm_mprv_mem_op!("sd", addr, value),
+        })
+    };
+
+    if success {
+        ctx.pc += 4;
+    } else {
+        ctx.emulate_firmware_trap();
+    }
+}
+
+/// Performs a memory access as a hypervisor virtual-machine load or store.
+///
+/// The two-stage translation of the virtual guest (`vsatp`, `hgatp` and `vsstatus`) is installed,
+/// and the MPRV accesses are performed with V = 1 and the privilege selected by `hstatus.SPVP`.
+/// If the access traps, the trap information of the context is updated with the guest fault so
+/// that it can be forwarded to the firmware.
+///
+/// # Safety
+///
+/// The access must be performed with [asm_mprv_mem_op].
+unsafe fn hypervisor_mem_op(ctx: &mut VirtContext, access: impl FnOnce() -> bool) -> bool {
+    let mode = if ctx.csr.hstatus & hstatus::SPVP_FILTER != 0 {
+        Mode::S
+    } else {
+        Mode::U
+    };
+
+    let prev_mstatus = read_csr(Csr::Mstatus);
+    let mstatus = (prev_mstatus & !(mstatus::MPP_FILTER | mstatus::MXR_FILTER))
+        | (mode.to_bits() << mstatus::MPP_OFFSET)
+        | mstatus::MPV_FILTER
+        | (ctx.csr.mstatus & mstatus::MXR_FILTER);
+
+    let success = unsafe {
+        let prev_vsatp = write_csr(Csr::Vsatp, ctx.csr.vsatp);
+        let prev_hgatp = write_csr(Csr::Hgatp, ctx.csr.hgatp);
+        let prev_vsstatus = write_csr(Csr::Vsstatus, ctx.csr.vsstatus);
+        write_csr(Csr::Mstatus, mstatus);
+        // Changes to the guest translation require fences to take effect
+        hfencegvma(None, None);
+        hfencevvma(None, None);
+
+        let success = access();
+        if !success {
+            ctx.trap_info.mcause = read_csr(Csr::Mcause);
+            ctx.trap_info.mtval = read_csr(Csr::Mtval);
+            ctx.trap_info.mtval2 = read_csr(Mtval2);
+            ctx.trap_info.mtinst = read_csr(Mtinst);
+            ctx.trap_info.gva = read_csr(Csr::Mstatus) & mstatus::GVA_FILTER != 0;
+        }
+
+        // Restore the original values
+        write_csr(Csr::Mstatus, prev_mstatus);
+        write_csr(Csr::Vsatp, prev_vsatp);
+        write_csr(Csr::Hgatp, prev_hgatp);
+        write_csr(Csr::Vsstatus, prev_vsstatus);
+        success
+    };
+
+    // Ensure memory consistency
+    hfencegvma(None, None);
+    hfencevvma(None, None);
+
+    success
+}
+
 /// Copies dest.len() bytes from src to dest, using the provided mode to read from src.
 ///
 /// This function can be useful to copy bytes from the virtual address space of a lower
@@ -1412,6 +1656,41 @@ naked_soft_asm!(
     "jr ra",                 // Return
 );
 
+// ————————————————————————————— Raw CSR Tables ————————————————————————————— //
+// One stub per CSR number, each stub being an access to the CSR followed by  //
+// a return. The instructions are encoded by hand as the assembler only       //
+// accepts literal CSR numbers, the stubs are never compressed so that they   //
+// all have the same size.                                                    //
+// —————————————————————————————————————————————————————————————————————————— //
+
+/// `csrr a0, <csr>; ret`, with the CSR number stored in the upper 12 bits.
+#[cfg(not(any(test, feature = "userspace")))]
+#[unsafe(naked)]
+extern "C" fn _read_csr_table() {
+    core::arch::naked_asm!(
+        ".set .Lread_csr_idx, 0",
+        ".rept 4096",
+        ".word (.Lread_csr_idx << 20) | 0x2573",
+        ".word 0x00008067",
+        ".set .Lread_csr_idx, .Lread_csr_idx + 1",
+        ".endr",
+    );
+}
+
+/// `csrrw a0, <csr>, a0; ret`, with the CSR number stored in the upper 12 bits.
+#[cfg(not(any(test, feature = "userspace")))]
+#[unsafe(naked)]
+extern "C" fn _write_csr_table() {
+    core::arch::naked_asm!(
+        ".set .Lwrite_csr_idx, 0",
+        ".rept 4096",
+        ".word (.Lwrite_csr_idx << 20) | 0x51573",
+        ".word 0x00008067",
+        ".set .Lwrite_csr_idx, .Lwrite_csr_idx + 1",
+        ".endr",
+    );
+}
+
 // —————————————————————————————— Tracing trap Handler —————————————————————————————— //
 
 naked_soft_asm!(
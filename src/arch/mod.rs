@@ -19,15 +19,16 @@ use core::ptr;
 
 // Re-export bare-metal interaction
 pub use metal::{
-    clear_csr_bits, detect_hardware, handle_virtual_load, handle_virtual_store, hfencegvma,
-    hfencevvma, ifence, init, read_bytes_from_mode, read_csr, run_vcpu, set_csr_bits, set_mpp,
-    sfencevma, store_bytes_from_mode, wfi, write_csr,
+    clear_csr_bits, detect_hardware, fence, handle_hypervisor_load, handle_hypervisor_store,
+    handle_virtual_load, handle_virtual_store, hfencegvma, hfencevvma, ifence, init,
+    read_bytes_from_mode, read_csr, read_csr_raw, read_seed, run_vcpu, set_csr_bits, set_mpp,
+    sfencevma, store_bytes_from_mode, wfi, write_csr, write_csr_raw,
 };
 use pmp::{PmpFlush, PmpGroup};
 pub use registers::{Csr, Register, csr};
 pub use trap::{MCause, TrapInfo};
 
-use crate::arch::mstatus::{MPP_FILTER, MPP_OFFSET, SPP_FILTER, SPP_OFFSET};
+use crate::arch::mstatus::{MPP_FILTER, MPP_OFFSET, MPRV_FILTER, SPP_FILTER, SPP_OFFSET};
 use crate::utils::PhantomNotSendNotSync;
 use crate::virt::{ExecutionMode, VirtContext};
 
@@ -51,6 +52,20 @@ pub struct HardwareCapability {
     _marker: PhantomNotSendNotSync,
 }
 
+impl HardwareCapability {
+    /// Returns the interrupts that can be enabled, raised, or delegated in the virtual `mie`,
+    /// `mip` and `mideleg`.
+    ///
+    /// The local counter overflow interrupt is only available with the Sscofpmf extension.
+    pub fn virtual_interrupts(&self) -> usize {
+        if self.extensions.has_sscofpmf_extension {
+            self.interrupts
+        } else {
+            self.interrupts & !mie::LCOFIE_FILTER
+        }
+    }
+}
+
 /// A struct that contains information about the available registers
 #[derive(Debug, Clone)]
 pub struct RegistersCapability {
@@ -60,6 +75,8 @@ pub struct RegistersCapability {
     pub henvcfg: bool,
     /// Boolean value indicating if Supervisor environment configuration register is present
     pub senvcfg: bool,
+    /// Boolean value indicating if the Supervisor context register (Sdtrig) is present
+    pub scontext: bool,
     /// The number of implemented and non-zero PMP registers
     pub nb_pmp: usize,
 }
@@ -73,6 +90,8 @@ pub struct ExtensionsCapability {
     pub has_s_extension: bool,
     /// Vector extension
     pub has_v_extension: bool,
+    /// Single-precision floating-point extension
+    pub has_f_extension: bool,
     /// Compressed Instructions extension
     pub has_c_extension: bool,
     /// Crypto extension
@@ -93,6 +112,8 @@ pub struct ExtensionsCapability {
     pub has_zicboz_extension: bool,
     /// Has Trusted Execution Environment Task Group
     pub has_tee_extension: bool,
+    /// Has Sscofpmf extension (counter overflow and privilege mode filtering)
+    pub has_sscofpmf_extension: bool,
 }
 
 // ———————————————————————————— Privilege Modes ————————————————————————————— //
@@ -127,6 +148,27 @@ pub fn parse_spp_return_mode(mstatus_reg: usize) -> Mode {
     }
 }
 
+/// The kind of a memory access.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessKind {
+    /// An instruction fetch.
+    Fetch,
+    /// A load or a store.
+    Data,
+}
+
+/// Returns the mode whose access rights apply to a memory access issued from `mode`.
+///
+/// When `mstatus.MPRV` is set, loads and stores use the privileges of `mstatus.MPP`, but
+/// instruction fetches are not affected. MPRV is cleared when returning to a mode less
+/// privileged than M, it can therefore only be set while running in M-mode.
+pub fn effective_access_mode(mode: Mode, mstatus_reg: usize, kind: AccessKind) -> Mode {
+    match kind {
+        AccessKind::Data if mstatus_reg & MPRV_FILTER != 0 => parse_mpp_return_mode(mstatus_reg),
+        _ => mode,
+    }
+}
+
 impl Mode {
     /// Returns the bit pattern corresponding to the given mode.
     pub const fn to_bits(self) -> usize {
@@ -302,6 +344,20 @@ pub mod mstatus {
     /// SD
     pub const SD_OFFSET: usize = 63;
     pub const SD_FILTER: usize = 0b1 << SD_OFFSET;
+
+    /// Returns `mstatus` with SD set if and only if one of FS, VS or XS is Dirty.
+    ///
+    /// This follows the `dirty` computation of `legalize_mstatus` in the Sail model.
+    pub const fn with_sd(mstatus: usize) -> usize {
+        let dirty = mstatus & FS_FILTER == FS_FILTER
+            || mstatus & VS_FILTER == VS_FILTER
+            || mstatus & XS_FILTER == XS_FILTER;
+        if dirty {
+            mstatus | SD_FILTER
+        } else {
+            mstatus & !SD_FILTER
+        }
+    }
 }
 
 // ———————————————————————— Machine Interrupt-Enabled ——————————————————————— //
@@ -313,18 +369,23 @@ pub mod mie {
     pub const SIE_FILTER: usize = SSIE_FILTER | STIE_FILTER | SEIE_FILTER /* | LCOFIE_FILTER */;
 
     /// Constant to filter out writable bits of mie.
-    pub const MIE_WRITE_FILTER: usize = SIE_FILTER | MSIE_FILTER | MTIE_FILTER | MEIE_FILTER;
+    ///
+    /// LCOFIE is only writable if the Sscofpmf extension is implemented, see
+    /// [HardwareCapability::virtual_interrupts](super::HardwareCapability::virtual_interrupts).
+    pub const MIE_WRITE_FILTER: usize =
+        SIE_FILTER | MSIE_FILTER | MTIE_FILTER | MEIE_FILTER | LCOFIE_FILTER;
 
     /// Constant to filter out writable bits of mip.
-    pub const MIP_WRITE_FILTER: usize = SSIE_FILTER | STIE_FILTER | SEIE_FILTER;
+    ///
+    /// LCOFIP is only writable if the Sscofpmf extension is implemented.
+    pub const MIP_WRITE_FILTER: usize = SSIE_FILTER | STIE_FILTER | SEIE_FILTER | LCOFIE_FILTER;
 
     /// The bits in mideleg that must be read-only one.
     ///
     /// Some interrupts are forced to be delegated to S-mode because Miralis doesn't implement
     /// virtualization for them (as that would incur a cost in terms of complexity and
     /// performance).
-    pub const MIDELEG_READ_ONLY_ONE: usize =
-        SSIE_FILTER | STIE_FILTER | SEIE_FILTER | LCOFIE_FILTER;
+    pub const MIDELEG_READ_ONLY_ONE: usize = SSIE_FILTER | STIE_FILTER | SEIE_FILTER;
 
     /// The bits in mideleg that are read-only zero
     ///
@@ -355,6 +416,20 @@ pub mod mie {
     pub const LCOFIE_OFFSET: usize = 13;
     pub const LCOFIE_FILTER: usize = 0b1 << LCOFIE_OFFSET;
 
+    // Virtual supervisor interrupts, only with the H extension
+    /// VSSIE
+    pub const VSSIE_OFFSET: usize = 2;
+    pub const VSSIE_FILTER: usize = 0b1 << VSSIE_OFFSET;
+    /// VSTIE
+    pub const VSTIE_OFFSET: usize = 6;
+    pub const VSTIE_FILTER: usize = 0b1 << VSTIE_OFFSET;
+    /// VSEIE
+    pub const VSEIE_OFFSET: usize = 10;
+    pub const VSEIE_FILTER: usize = 0b1 << VSEIE_OFFSET;
+
+    /// Mask with all virtual supervisor interrupt bits
+    pub const VS_INT: usize = VSSIE_FILTER | VSTIE_FILTER | VSEIE_FILTER;
+
     /// Mask with all valid interrupt bits
     pub const ALL_INT: usize =
         SSIE_FILTER | MSIE_FILTER | STIE_FILTER | MTIE_FILTER | SEIE_FILTER | MEIE_FILTER;
@@ -389,6 +464,26 @@ pub mod mtvec {
     }
 }
 
+pub mod mcountinhibit {
+    /// CY: inhibits mcycle
+    pub const CY_FILTER: usize = 0b1 << 0;
+    /// IR: inhibits minstret
+    pub const IR_FILTER: usize = 0b1 << 2;
+}
+
+/// Fields of the Zkr `seed` CSR.
+pub mod seed {
+    /// OPST: status of the entropy source
+    pub const OPST_OFFSET: usize = 30;
+    pub const OPST_FILTER: usize = 0b11 << OPST_OFFSET;
+    /// ES16: 16 bits of entropy are available
+    pub const OPST_ES16: usize = 0b10;
+    /// DEAD: unrecoverable self-test failure
+    pub const OPST_DEAD: usize = 0b11;
+    /// ENTROPY: the 16 bits of entropy, only valid when OPST is ES16
+    pub const ENTROPY_FILTER: usize = 0xffff;
+}
+
 pub mod menvcfg {
     /// Fence I/O Implies Memoru
     pub const FIOM_OFFSET: usize = 0;
@@ -398,6 +493,12 @@ pub mod menvcfg {
     pub const CBIE_OFFSET: usize = 4;
     pub const CBIE_FILTER: usize = 0b11 << CBIE_OFFSET;
 
+    /// Encodings of the CBIE field.
+    pub const CBIE_ILLEGAL: usize = 0b00;
+    pub const CBIE_FLUSH: usize = 0b01;
+    pub const CBIE_RESERVED: usize = 0b10;
+    pub const CBIE_INVAL: usize = 0b11;
+
     // CBCFE from Zicbom extension
     pub const CBCFE_OFFSET: usize = 6;
     pub const CBCFE_FILTER: usize = 0b1 << CBCFE_OFFSET;
@@ -415,6 +516,40 @@ pub mod menvcfg {
     /// Note that not all bits might be available on a given hart, depending on the implemented
     /// extensions.
     pub const ALL: usize = FIOM_FILTER | CBIE_FILTER | CBCFE_FILTER | CBZE_FILTER | STCE_FILTER;
+
+    /// The behavior of the `cbo.inval` instruction, as configured by the CBIE fields.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum CboInval {
+        /// The instruction raises an illegal instruction exception.
+        Illegal,
+        /// The instruction performs a flush.
+        Flush,
+        /// The instruction performs an invalidation.
+        Inval,
+    }
+
+    /// Returns the CBIE field of an `menvcfg` or `senvcfg` value.
+    pub const fn cbie(envcfg: usize) -> usize {
+        (envcfg & CBIE_FILTER) >> CBIE_OFFSET
+    }
+}
+
+/// Constants for the Machine Security Configuration (mseccfg) CSR, from the Smepmp extension.
+pub mod mseccfg {
+    /// Machine Mode Lockdown
+    pub const MML_OFFSET: usize = 0;
+    pub const MML_FILTER: usize = 0b1 << MML_OFFSET;
+
+    /// Machine Mode Whitelist Policy
+    pub const MMWP_OFFSET: usize = 1;
+    pub const MMWP_FILTER: usize = 0b1 << MMWP_OFFSET;
+
+    /// Rule Locking Bypass
+    pub const RLB_OFFSET: usize = 2;
+    pub const RLB_FILTER: usize = 0b1 << RLB_OFFSET;
+
+    /// All the Smepmp bits of mseccfg.
+    pub const ALL: usize = MML_FILTER | MMWP_FILTER | RLB_FILTER;
 }
 
 // ————————————————————————————— Hypervisor Status ————————————————————————————— //
@@ -457,6 +592,57 @@ pub mod hstatus {
 
 // ———————————————————————— Performance counters ——————————————————————— //
 
+/// Constants for the Machine Hardware Performance Monitoring Event (mhpmevent) CSRs.
+pub mod mhpmevent {
+    /// Event selector
+    pub const EVENT_OFFSET: usize = 0;
+    pub const EVENT_FILTER: usize = (0b1 << 56) - 1;
+
+    /// VU-mode inhibit (Sscofpmf)
+    pub const VUINH_OFFSET: usize = 58;
+    pub const VUINH_FILTER: usize = 0b1 << VUINH_OFFSET;
+
+    /// VS-mode inhibit (Sscofpmf)
+    pub const VSINH_OFFSET: usize = 59;
+    pub const VSINH_FILTER: usize = 0b1 << VSINH_OFFSET;
+
+    /// U-mode inhibit (Sscofpmf)
+    pub const UINH_OFFSET: usize = 60;
+    pub const UINH_FILTER: usize = 0b1 << UINH_OFFSET;
+
+    /// S-mode inhibit (Sscofpmf)
+    pub const SINH_OFFSET: usize = 61;
+    pub const SINH_FILTER: usize = 0b1 << SINH_OFFSET;
+
+    /// M-mode inhibit (Sscofpmf)
+    pub const MINH_OFFSET: usize = 62;
+    pub const MINH_FILTER: usize = 0b1 << MINH_OFFSET;
+
+    /// Overflow (Sscofpmf)
+    pub const OF_OFFSET: usize = 63;
+    pub const OF_FILTER: usize = 0b1 << OF_OFFSET;
+
+    /// All the bits introduced by the Sscofpmf extension.
+    pub const SSCOFPMF_FILTER: usize =
+        OF_FILTER | MINH_FILTER | SINH_FILTER | UINH_FILTER | VSINH_FILTER | VUINH_FILTER;
+
+    /// Virtual event: traps from the virtual M-mode handled by Miralis.
+    ///
+    /// Miralis can not observe micro-architectural events from the firmware, the only events it
+    /// can count are the ones it emulates.
+    pub const FIRMWARE_TRAP_EVENT: usize = 1;
+
+    /// Returns the mask of the writable event selector bits, given the number of implemented
+    /// event bits.
+    pub const fn event_filter(nb_event_bits: usize) -> usize {
+        if nb_event_bits >= 56 {
+            EVENT_FILTER
+        } else {
+            (1 << nb_event_bits) - 1
+        }
+    }
+}
+
 pub mod perf_counters {
     pub const DELEGATE_CYCLE_MASK: usize = 0x1;
     pub const DELEGATE_TIME_MASK: usize = 0x2;
@@ -466,6 +652,171 @@ pub mod perf_counters {
         DELEGATE_INSTRET_MASK | DELEGATE_TIME_MASK | DELEGATE_CYCLE_MASK;
 }
 
+// ———————————————————————————— Debug Context ————————————————————————————— //
+
+/// Constants for the debug context CSRs (mcontext and scontext) of the Sdtrig extension.
+///
+/// The width of the context fields is implementation defined, we implement the widths
+/// recommended by the specification for RV64.
+pub mod debug_context {
+    /// Implemented bits of mcontext.HCONTEXT without the H extension.
+    pub const MCONTEXT_FILTER: usize = (1 << 13) - 1;
+    /// Implemented bits of mcontext.HCONTEXT with the H extension.
+    pub const MCONTEXT_H_FILTER: usize = (1 << 14) - 1;
+    /// Implemented bits of scontext.DATA.
+    pub const SCONTEXT_FILTER: usize = (1 << 34) - 1;
+
+    /// Returns the implemented bits of mcontext.
+    pub const fn mcontext_filter(has_h_extension: bool) -> usize {
+        if has_h_extension {
+            MCONTEXT_H_FILTER
+        } else {
+            MCONTEXT_FILTER
+        }
+    }
+}
+
+/// Constants for the instruction count trigger (icount) of the Sdtrig extension.
+pub mod icount {
+    /// The trigger type, in the top bits of tdata1.
+    pub const TYPE_OFFSET: usize = 60;
+    /// The type of instruction count triggers.
+    pub const TYPE_ICOUNT: usize = 3;
+
+    /// Fire in U-mode.
+    pub const U_FILTER: usize = 0b1 << 6;
+    /// Fire in S-mode.
+    pub const S_FILTER: usize = 0b1 << 7;
+
+    /// The number of instructions to retire before firing.
+    pub const COUNT_OFFSET: usize = 10;
+    pub const COUNT_FILTER: usize = 0x3fff << COUNT_OFFSET;
+    /// Set by the hardware when the trigger fires, if implemented.
+    pub const HIT_FILTER: usize = 0b1 << 24;
+
+    /// An icount trigger that fires after one instruction retired in S or U-mode, raising a
+    /// breakpoint exception (action 0).
+    pub const SINGLE_STEP: usize =
+        (TYPE_ICOUNT << TYPE_OFFSET) | (1 << COUNT_OFFSET) | S_FILTER | U_FILTER;
+
+    /// Returns true if the trigger configured with [SINGLE_STEP] fired.
+    ///
+    /// The count reaches 0 once the instruction retired, unless it is hard-wired to 1 in which case
+    /// only the hit bit tells whether the trigger fired.
+    pub const fn has_fired(tdata1: usize) -> bool {
+        tdata1 & HIT_FILTER != 0 || tdata1 & COUNT_FILTER == 0
+    }
+}
+
+// ————————————————————————— Address Translation ——————————————————————————— //
+
+pub mod satp {
+    /// MODE
+    pub const MODE_OFFSET: usize = 60;
+    pub const MODE_FILTER: usize = 0b1111 << MODE_OFFSET;
+    /// PPN: physical page number of the root page table
+    pub const PPN_FILTER: usize = (1 << 44) - 1;
+
+    /// The address translation modes on RV64.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum SatpMode {
+        /// No translation or protection
+        Bare,
+        /// Page-based 39-bit virtual addressing
+        Sv39,
+        /// Page-based 48-bit virtual addressing
+        Sv48,
+        /// Page-based 57-bit virtual addressing
+        Sv57,
+    }
+
+    /// Returns the translation mode encoded in `satp`, if valid.
+    ///
+    /// This follows the `satpMode_of_bits` function of the Sail model for RV64: Sv32 is only
+    /// valid on RV32 and all the other encodings are reserved.
+    pub const fn mode_of_bits(satp: usize) -> Option<SatpMode> {
+        match (satp & MODE_FILTER) >> MODE_OFFSET {
+            0b0000 => Some(SatpMode::Bare),
+            0b1000 => Some(SatpMode::Sv39),
+            0b1001 => Some(SatpMode::Sv48),
+            0b1010 => Some(SatpMode::Sv57),
+            _ => None,
+        }
+    }
+}
+
+/// Constants for the hgatp CSR (H extension).
+pub mod hgatp {
+    /// MODE
+    pub const MODE_OFFSET: usize = 60;
+    pub const MODE_FILTER: usize = 0b1111 << MODE_OFFSET;
+    /// VMID: virtual machine identifier
+    pub const VMID_OFFSET: usize = 44;
+    pub const VMID_FILTER: usize = 0x3fff << VMID_OFFSET;
+    /// PPN: physical page number of the root page table
+    pub const PPN_FILTER: usize = (1 << 44) - 1;
+
+    /// The G-stage address translation modes on RV64.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum HgatpMode {
+        /// No G-stage translation or protection
+        Bare,
+        /// Page-based 41-bit guest physical addressing
+        Sv39x4,
+        /// Page-based 50-bit guest physical addressing
+        Sv48x4,
+        /// Page-based 59-bit guest physical addressing
+        Sv57x4,
+    }
+
+    /// Returns the G-stage translation mode encoded in `hgatp`, if valid.
+    pub const fn mode_of_bits(hgatp: usize) -> Option<HgatpMode> {
+        match (hgatp & MODE_FILTER) >> MODE_OFFSET {
+            0b0000 => Some(HgatpMode::Bare),
+            0b1000 => Some(HgatpMode::Sv39x4),
+            0b1001 => Some(HgatpMode::Sv48x4),
+            0b1010 => Some(HgatpMode::Sv57x4),
+            _ => None,
+        }
+    }
+}
+
+/// Page Table Entries (PTE) bits from the Svnapot and Svpbmt extensions.
+pub mod pte {
+    /// N: the PTE is part of a naturally aligned power-of-two range (Svnapot)
+    pub const N_OFFSET: usize = 63;
+    pub const N_FILTER: usize = 0b1 << N_OFFSET;
+    /// PBMT: the page-based memory type (Svpbmt)
+    pub const PBMT_OFFSET: usize = 61;
+    pub const PBMT_FILTER: usize = 0b11 << PBMT_OFFSET;
+
+    /// Page-based memory types.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Pbmt {
+        /// None, use the attributes of the underlying physical memory
+        Pma,
+        /// Non-cacheable, idempotent, weakly-ordered main memory
+        Nc,
+        /// Non-cacheable, non-idempotent, strongly-ordered I/O memory
+        Io,
+    }
+
+    /// Whether the PTE describes a NAPOT range.
+    pub const fn is_napot(pte: usize) -> bool {
+        pte & N_FILTER != 0
+    }
+
+    /// Returns the memory type of the PTE, or `None` for the reserved encoding.
+    pub const fn pbmt(pte: usize) -> Option<Pbmt> {
+        match (pte & PBMT_FILTER) >> PBMT_OFFSET {
+            0 => Some(Pbmt::Pma),
+            1 => Some(Pbmt::Nc),
+            2 => Some(Pbmt::Io),
+            _ => None,
+        }
+    }
+}
+
 // ——————————————————————— Width of Access Instructions —————————————————————— //
 
 /// Represents different data widths:
@@ -507,6 +858,70 @@ impl From<usize> for Width {
 /// Number of bits in a risc-v page table entry
 pub const PAGE_SIZE: usize = 4096;
 
+// ——————————————————————————— Memory Barriers ———————————————————————————— //
+
+/// The ordering enforced by a `FENCE` instruction.
+///
+/// The variants follow the `barrier_kind` enum of the Sail reference, with the memory read (R)
+/// and write (W) accesses of the predecessor set on the left and of the successor set on the
+/// right. Fences with device input or output (I/O) bits are conservatively mapped to `IoRwIoRw`,
+/// and fences with an empty predecessor or successor set do not order anything.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BarrierKind {
+    RwRw,
+    RRw,
+    RR,
+    RwW,
+    WW,
+    WRw,
+    RwR,
+    RW,
+    WR,
+    /// `FENCE.TSO`
+    Tso,
+    /// Full fence, including device input and output
+    IoRwIoRw,
+    /// Empty predecessor or successor set
+    None,
+}
+
+impl BarrierKind {
+    /// Memory read bit of a fence predecessor or successor set.
+    pub const R: usize = 0b0010;
+    /// Memory write bit of a fence predecessor or successor set.
+    pub const W: usize = 0b0001;
+    /// Device input and output bits of a fence predecessor or successor set.
+    pub const IO: usize = 0b1100;
+    /// Fence mode of `FENCE.TSO`.
+    pub const FM_TSO: usize = 0b1000;
+
+    /// Builds the barrier kind from the fence mode and the predecessor and successor sets.
+    ///
+    /// Fence modes other than TSO are reserved, and must be treated as normal fences.
+    pub fn from_fence_sets(fm: usize, pred: usize, succ: usize) -> Self {
+        const RW: usize = BarrierKind::R | BarrierKind::W;
+
+        if (pred | succ) & Self::IO != 0 {
+            return BarrierKind::IoRwIoRw;
+        }
+
+        match (pred & RW, succ & RW) {
+            (RW, RW) if fm == Self::FM_TSO => BarrierKind::Tso,
+            (0, _) | (_, 0) => BarrierKind::None,
+            (RW, RW) => BarrierKind::RwRw,
+            (Self::R, RW) => BarrierKind::RRw,
+            (Self::R, Self::R) => BarrierKind::RR,
+            (RW, Self::W) => BarrierKind::RwW,
+            (Self::W, Self::W) => BarrierKind::WW,
+            (Self::W, RW) => BarrierKind::WRw,
+            (RW, Self::R) => BarrierKind::RwR,
+            (Self::R, Self::W) => BarrierKind::RW,
+            (Self::W, Self::R) => BarrierKind::WR,
+            _ => unreachable!(),
+        }
+    }
+}
+
 // —————————————————————————————— Custom CSRs ——————————————————————————————— //
 // CSR IDs are hard-coded in the CSR read/write/clear/set instructions,       //
 // rather than being passed as operands in a register. Because of this each   //
@@ -515,6 +930,8 @@ pub const PAGE_SIZE: usize = 4096;
 // To avoid bloating Miralis we do not include instructions for all possible  //
 // custom CSRs. Instead we expose macros to access arbitrary CSRs which are   //
 // expected to be used by each platform implementation on an as-needed basis. //
+// Code that needs arbitrary CSR numbers at runtime can instead use           //
+// [read_csr_raw] and [write_csr_raw], at the cost of a 64 KiB jump table.    //
 // —————————————————————————————————————————————————————————————————————————— //
 
 /// Write to a custom CSR.
@@ -588,8 +1005,9 @@ pub unsafe fn get_raw_faulting_instr(ctx: &VirtContext) -> usize {
         return ctx.trap_info.mtval;
     }
 
-    // Then as a fallback we read the instruction directly from memory
-    match ctx.mode {
+    // Then as a fallback we read the instruction directly from memory, instruction fetches are
+    // not affected by MPRV.
+    match ctx.access_mode(AccessKind::Fetch) {
         Mode::M => {
             // The virtual firmware runs in U-mode without virtual memory, we can read memory
             // directly
@@ -633,3 +1051,62 @@ pub unsafe fn write_pmp(pmp: &PmpGroup) -> PmpFlush {
 
     PmpFlush()
 }
+
+// ————————————————————————————————— Tests —————————————————————————————————— //
+
+#[cfg(test)]
+mod tests {
+    use super::pte::{self, Pbmt};
+    use super::satp::{self, SatpMode};
+
+    #[test]
+    fn satp_modes() {
+        let satp_with_mode = |mode: usize| (mode << satp::MODE_OFFSET) | 0x8000_1234;
+
+        assert_eq!(satp::mode_of_bits(satp_with_mode(0)), Some(SatpMode::Bare));
+        assert_eq!(satp::mode_of_bits(satp_with_mode(8)), Some(SatpMode::Sv39));
+        assert_eq!(satp::mode_of_bits(satp_with_mode(9)), Some(SatpMode::Sv48));
+        assert_eq!(satp::mode_of_bits(satp_with_mode(10)), Some(SatpMode::Sv57));
+
+        // Sv32 (1) is not valid on RV64, the other encodings are reserved
+        for mode in [1, 2, 3, 4, 5, 6, 7, 11, 12, 13, 14, 15] {
+            assert_eq!(satp::mode_of_bits(satp_with_mode(mode)), None);
+        }
+    }
+
+    #[test]
+    fn pte_bits() {
+        assert!(pte::is_napot(1 << 63));
+        assert!(!pte::is_napot(0xfff));
+        assert_eq!(pte::pbmt(0), Some(Pbmt::Pma));
+        assert_eq!(pte::pbmt(1 << 61), Some(Pbmt::Nc));
+        assert_eq!(pte::pbmt(2 << 61), Some(Pbmt::Io));
+        assert_eq!(pte::pbmt(3 << 61), None);
+    }
+
+    #[test]
+    fn raw_csr_access() {
+        use super::{Csr, read_csr, read_csr_raw, write_csr, write_csr_raw};
+
+        unsafe { write_csr(Csr::Mscratch, 0x1234) };
+        unsafe { write_csr(Csr::Mtvec, 0x8000_0100) };
+        for csr in [
+            Csr::Mhartid,
+            Csr::Misa,
+            Csr::Mstatus,
+            Csr::Mtvec,
+            Csr::Mscratch,
+        ] {
+            assert_eq!(
+                read_csr_raw(csr.idx() as u16),
+                read_csr(csr),
+                "Raw and typed reads of {} differ",
+                csr
+            );
+        }
+
+        let prev = unsafe { write_csr_raw(Csr::Mscratch.idx() as u16, 0xabcd) };
+        assert_eq!(prev, 0x1234);
+        assert_eq!(read_csr(Csr::Mscratch), 0xabcd);
+    }
+}
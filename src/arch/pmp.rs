@@ -6,12 +6,14 @@
 use core::fmt;
 use core::fmt::Formatter;
 
+use crate::arch::Mode;
 use crate::arch::pmp::pmpcfg::{INACTIVE, NAPOT, TOR};
 use crate::arch::pmp::pmplayout::{
-    DEVICES_OFFSET, INACTIVE_ENTRY_OFFSET, MIRALIS_OFFSET, MIRALIS_TOTAL_PMP, MODULE_OFFSET,
-    MODULE_SIZE, MPRV_EMULATION_OFFSET, VIRTUAL_PMP_OFFSET,
+    DEVICES_OFFSET, INACTIVE_ENTRY_OFFSET, INACTIVE_ENTRY_SIZE, MEMORY_MAP_OFFSET, MEMORY_MAP_SIZE,
+    MIRALIS_OFFSET, MIRALIS_TOTAL_PMP, MODULE_OFFSET, MODULE_SIZE, MPRV_EMULATION_OFFSET,
+    VIRTUAL_PMP_OFFSET,
 };
-use crate::platform::{Plat, Platform};
+use crate::platform::{MemoryRegion, Plat, Platform};
 use crate::{arch, config, logger};
 
 // ——————————————————————————— PMP Configuration ———————————————————————————— //
@@ -27,7 +29,8 @@ use crate::{arch, config, logger};
 /// overwrite memory protection.
 ///
 /// The current PMP layout is depicted below. The first block is used for Miralis' internal usage,
-/// including protecting its own memory and hardware emulation. Modules can also claim PMP entries,
+/// including protecting its own memory, the protected regions of the platform memory map, and
+/// hardware emulation. Modules can also claim PMP entries,
 /// which enables the definition of security policies. MPRV emulation is a bit of a special case.
 /// MPRV stands for Memory Privilege, or maybe Modify Privilege, the spec is not clear. In any
 /// case, when the MPRV bit is set to 1 all data memory accesses are performed with the access
@@ -39,8 +42,13 @@ use crate::{arch, config, logger};
 /// previous address is hardwired at 0. We need to emulate this behavior, and therefore keep an
 /// entry to 0 before virtual PMP 1.
 ///
+/// When debugging, two locked entries can be placed before all others to catch writes from
+/// Miralis to its own code (see `MIRALIS_DEBUG_PROTECT_READ_ONLY`).
+///
 /// The virtual PMP entries are controlled by the virtual firmware. Miralis of course has to do
-/// some filtering, for instance it removes the lock bit.
+/// some filtering, for instance it removes the lock bit. The firmware can release its last virtual
+/// PMP entries (see `MIRALIS_RELEASE_PMP_FID`), in which case the virtual PMP entries are moved up
+/// and the released entries are placed before the null entry, where modules can use them.
 ///
 /// Finally, the last entry is used to emulate the default hardware behavior, which is to grant
 /// access to all memory when running the firmware, and deny all access when running the payload.
@@ -68,34 +76,65 @@ use crate::{arch, config, logger};
 ///                     └─ └─────────┘
 /// ```
 pub mod pmplayout {
+    use miralis_core::pmp;
+
     use crate::modules::{MainModule, Module};
     use crate::platform::{Plat, Platform};
+    use crate::{config, logger};
+
+    /// Locked PMP entries catching writes from Miralis to its own code, if enabled.
+    ///
+    /// The first entry holds the start address of the code, and the second one is a TOR entry
+    /// covering it. Locked entries also apply to M-mode, and must therefore come first.
+    ///
+    /// Coming first, the locked entries take precedence over the entry protecting Miralis. They
+    /// are therefore restricted to the code, the read-only data is left out as it can hold secrets
+    /// (such as the attestation key) that the firmware and payload must not read.
+    pub const READ_ONLY_SIZE: usize = if config::DEBUG_PROTECT_READ_ONLY {
+        pmp::READ_ONLY
+    } else {
+        0
+    };
+    pub const READ_ONLY_OFFSET: usize = 0;
 
     /// PMP entry used to protect Miralis.
-    pub const MIRALIS_SIZE: usize = 1;
-    pub const MIRALIS_OFFSET: usize = 0;
+    pub const MIRALIS_SIZE: usize = pmp::MIRALIS;
+    pub const MIRALIS_OFFSET: usize = READ_ONLY_OFFSET + READ_ONLY_SIZE;
 
     /// PMP entries used to protect the devices.
     pub const DEVICES_SIZE: usize = Plat::NB_VIRT_DEVICES;
     pub const DEVICES_OFFSET: usize = MIRALIS_OFFSET + MIRALIS_SIZE;
 
+    /// PMP entries used to protect the reserved regions of the platform memory map.
+    pub const MEMORY_MAP_SIZE: usize = Plat::NB_PROTECTED_REGIONS;
+    pub const MEMORY_MAP_OFFSET: usize = DEVICES_OFFSET + DEVICES_SIZE;
+
+    /// PMP entry making the log page read-only to the firmware and payload, if logs are written
+    /// to a shared page.
+    pub const LOG_PAGE_SIZE: usize = if logger::SHARED_PAGE.is_some() {
+        pmp::LOG_PAGE
+    } else {
+        0
+    };
+    pub const LOG_PAGE_OFFSET: usize = MEMORY_MAP_OFFSET + MEMORY_MAP_SIZE;
+
     /// PMP entries used by the loaded modules.
     pub const MODULE_SIZE: usize = MainModule::NUMBER_PMPS;
-    pub const MODULE_OFFSET: usize = DEVICES_OFFSET + DEVICES_SIZE;
+    pub const MODULE_OFFSET: usize = LOG_PAGE_OFFSET + LOG_PAGE_SIZE;
 
     /// We need to reserve one entry to emulate the behavior of the MPRV bit (memory privilege) in
     /// software.
-    pub const MPRV_EMULATION_SIZE: usize = 1;
+    pub const MPRV_EMULATION_SIZE: usize = pmp::MPRV_EMULATION;
     pub const MPRV_EMULATION_OFFSET: usize = MODULE_OFFSET + MODULE_SIZE;
 
     /// Last PMP entry used in to emulate TOR correctly in the firmware.
-    pub const INACTIVE_ENTRY_SIZE: usize = 1;
+    pub const INACTIVE_ENTRY_SIZE: usize = pmp::INACTIVE_ENTRY;
     pub const INACTIVE_ENTRY_OFFSET: usize = MPRV_EMULATION_OFFSET + MPRV_EMULATION_SIZE;
 
     /// Offset at which the virtual PMPs can start.
     pub const VIRTUAL_PMP_OFFSET: usize = INACTIVE_ENTRY_OFFSET + INACTIVE_ENTRY_SIZE;
     /// At the very end, there is a last PMP entry.
-    pub const MIRALIS_TOTAL_PMP: usize = VIRTUAL_PMP_OFFSET + 1;
+    pub const MIRALIS_TOTAL_PMP: usize = VIRTUAL_PMP_OFFSET + pmp::LAST_ENTRY;
 }
 
 /// PMP Configuration
@@ -175,6 +214,10 @@ pub struct PmpGroup {
     pub nb_virt_pmp: usize,
     /// The offset of the virtual PMP registers, compared to physical PMP.
     pub virt_pmp_offset: usize,
+    /// The offset of the PMP entries released by the firmware, see [PmpGroup::release_virt_pmp].
+    pub free_pmp_offset: usize,
+    /// Number of PMP entries released by the firmware, available to the modules.
+    pub nb_free_pmp: usize,
 }
 
 /// A struct that can be consumed to flush the caches, making the latest PMP configuration
@@ -257,6 +300,8 @@ impl PmpGroup {
             nb_pmp: nb_pmp as u8,
             nb_virt_pmp: 0,
             virt_pmp_offset: 0,
+            free_pmp_offset: 0,
+            nb_free_pmp: 0,
         }
     }
 
@@ -288,6 +333,26 @@ impl PmpGroup {
                 );
             }
 
+            // Protect the reserved regions of the platform memory map
+            let nb_protected = pmp.protect_memory_map(MEMORY_MAP_OFFSET, Plat::get_memory_map());
+            assert_eq!(
+                nb_protected, MEMORY_MAP_SIZE,
+                "Unexpected number of protected memory regions"
+            );
+
+            // DMA buffers are accessed by devices directly, Miralis must not deny them
+            for region in Plat::dma_regions() {
+                if let Some(denied) = pmp.denied_overlap(*region) {
+                    panic!(
+                        "DMA region [0x{:x}, 0x{:x}) overlaps memory denied by Miralis at [0x{:x}, 0x{:x})",
+                        region.start(),
+                        region.end(),
+                        denied.start(),
+                        denied.end()
+                    );
+                }
+            }
+
             // This PMP entry is used by the policy module for its own purpose
             #[allow(clippy::reversed_empty_ranges)]
             for idx in 0..MODULE_SIZE {
@@ -319,6 +384,63 @@ impl PmpGroup {
         pmp
     }
 
+    /// Installs two locked PMP entries, starting at index `offset`, catching writes to the
+    /// read-only memory in `[start, end)`, including from M-mode.
+    ///
+    /// Because locked entries apply to all privilege modes, the firmware and payload can read and
+    /// execute that memory while the protection is enabled. It must only be used for debugging,
+    /// and only over memory that holds no secret.
+    /// The entries can not be modified until the next reset.
+    pub fn protect_read_only(&mut self, offset: usize, start: usize, end: usize) {
+        logger::debug!(
+            "PMP protect read-only memory at [0x{:x}, 0x{:x}]",
+            start,
+            end
+        );
+
+        self.set_inactive(offset, start);
+        self.pmpaddr[offset + 1] = build_tor(end);
+        self.set_pmpcfg(offset + 1, pmpcfg::R | pmpcfg::X | TOR | pmpcfg::L);
+    }
+
+    /// Whether `addr` belongs to the read-only memory protected by [Self::protect_read_only]
+    /// at index `offset`.
+    pub fn is_read_only(&self, offset: usize, addr: usize) -> bool {
+        if self.get_pmpcfg(offset + 1) & pmpcfg::L == 0 {
+            return false;
+        }
+
+        let start = self.pmpaddr[offset] << 2;
+        let end = self.pmpaddr[offset + 1] << 2;
+        (start..end).contains(&addr)
+    }
+
+    /// Installs a PMP entry denying all accesses for each protected region of the memory map,
+    /// starting at index `offset`.
+    ///
+    /// Returns the number of PMP entries used.
+    pub fn protect_memory_map(&mut self, offset: usize, memory_map: &[MemoryRegion]) -> usize {
+        let mut idx = offset;
+        for region in memory_map.iter().filter(|region| region.is_protected()) {
+            logger::debug!(
+                "PMP protect {:?} region at [0x{:x}, 0x{:x}]",
+                region.kind,
+                region.base,
+                region.base + region.size
+            );
+            self.set_napot(idx, region.base, region.size, pmpcfg::NO_PERMISSIONS);
+            idx += 1;
+        }
+        idx - offset
+    }
+
+    /// Returns the first region denied by an entry of the group overlapping `segment`, if any.
+    pub fn denied_overlap(&self, segment: Segment) -> Option<Segment> {
+        self.into_iter()
+            .find(|(region, cfg)| *cfg == pmpcfg::NO_PERMISSIONS && region.overlap(segment))
+            .map(|(region, _)| region)
+    }
+
     /// This function builds a PMP Napot entry, note that the caller must only set the permissions bits and don't have to care about the low level formatting details to build the napot entry.
     pub fn set_napot(&mut self, idx: usize, from: usize, to: usize, permissions: u8) {
         assert!(
@@ -392,14 +514,60 @@ impl PmpGroup {
         cfg as u8
     }
 
+    /// Releases the `count` last virtual PMP entries, and hands them over to the modules.
+    ///
+    /// The virtual PMP entries are moved up by `count`, so that the released entries sit right
+    /// before them (followed by the null entry) and take precedence over the firmware rules. The
+    /// virtual PMP must then be loaded again at the new [PmpGroup::virt_pmp_offset].
+    pub fn release_virt_pmp(&mut self, count: usize) {
+        assert!(
+            count <= self.nb_virt_pmp,
+            "Can not release {} virtual PMP entries out of {}",
+            count,
+            self.nb_virt_pmp
+        );
+
+        // The released entries start at the current null entry
+        if self.nb_free_pmp == 0 {
+            self.free_pmp_offset = self.virt_pmp_offset - INACTIVE_ENTRY_SIZE;
+        }
+        let first_released = self.free_pmp_offset + self.nb_free_pmp;
+        self.nb_free_pmp += count;
+        self.nb_virt_pmp -= count;
+        self.virt_pmp_offset += count;
+
+        // Clear the released entries and install the new null entry
+        for idx in first_released..self.virt_pmp_offset {
+            self.set_inactive(idx, 0);
+        }
+    }
+
+    /// Configures one of the entries released by the firmware, for use by the modules.
+    ///
+    /// The entries follow the MPRV emulation entry, which modules can not rely on as the lower
+    /// bound of a TOR entry.
+    pub fn set_free(&mut self, idx: usize, addr: usize, cfg: u8) {
+        assert!(
+            idx < self.nb_free_pmp,
+            "Invalid free PMP entry {}, only {} are available",
+            idx,
+            self.nb_free_pmp
+        );
+        self.set(self.free_pmp_offset + idx, addr, cfg);
+    }
+
     /// Loads PMP registers into the PMP group at the provided offset.
     ///
     /// This functions is used to import PMP registers, which is useful to load the virtual PMP
-    /// registers into the set of physical PMP.
+    /// registers into the set of physical PMP. The lock bits are removed and the permissions are
+    /// replaced by the access rights the entries grant to `mode` under the provided `mseccfg`,
+    /// see [effective_permissions].
     pub fn load_with_offset(
         &mut self,
         pmpaddr: &[usize; 64],
         pmpcfg: &[usize; 8],
+        mseccfg: usize,
+        mode: Mode,
         offset: usize,
         nb_pmp: usize,
     ) {
@@ -411,18 +579,61 @@ impl PmpGroup {
             let reg_idx = idx / 8;
             let inner_idx = idx % 8;
             let shift = inner_idx * 8; // 8 bits per config
-            let cfg = (pmpcfg[reg_idx] >> shift) & 0x7f; // Remove the lock bit
-            self.set_pmpcfg(idx + offset, cfg as u8);
+            let cfg = ((pmpcfg[reg_idx] >> shift) & 0xff) as u8;
+            let permissions = effective_permissions(cfg, mseccfg, mode);
+            self.set_pmpcfg(idx + offset, (cfg & pmpcfg::A_MASK) | permissions);
         }
     }
+}
 
-    /// Set RWX permission for `nb_pmp` PMP registers starting from `start`.
-    pub fn set_range_rwx(&mut self, start: usize, nb_pmp: usize) {
-        for idx in 0..nb_pmp {
-            let idx = start + idx;
-            let cfg = self.get_pmpcfg(idx);
-            self.set_pmpcfg(idx, cfg | pmpcfg::RWX);
-        }
+// ————————————————————————————————— Smepmp ————————————————————————————————— //
+
+/// Returns the access rights granted by a matching PMP entry to an access from `mode`.
+///
+/// Without `mseccfg.MML` M-mode is only restricted by locked entries, while S and U-mode are
+/// restricted by all entries. When `mseccfg.MML` is set the rules follow the truth table of the
+/// Smepmp extension: locked entries only apply to M-mode, unlocked entries only apply to S and
+/// U-mode, and the reserved `W = 1 & R = 0` encodings describe regions shared by both.
+pub fn effective_permissions(cfg: u8, mseccfg: usize, mode: Mode) -> u8 {
+    use pmpcfg::{L, NO_PERMISSIONS, R, RWX, W, X};
+    const WX: u8 = W | X;
+
+    let locked = cfg & L != 0;
+    let rwx = cfg & RWX;
+    let machine = mode == Mode::M;
+
+    if mseccfg & arch::mseccfg::MML_FILTER == 0 {
+        return match (machine, locked) {
+            (true, false) => RWX,
+            _ => rwx,
+        };
+    }
+
+    match (locked, rwx, machine) {
+        // Shared data regions
+        (false, W, true) => R | W,
+        (false, W, false) => R,
+        (false, WX, _) => R | W,
+        (true, RWX, _) => R,
+        // Shared code regions
+        (true, W, _) => X,
+        (true, WX, true) => R | X,
+        (true, WX, false) => X,
+        // M-mode only and S/U-mode only regions
+        (false, _, true) | (true, _, false) => NO_PERMISSIONS,
+        (false, _, false) | (true, _, true) => rwx,
+    }
+}
+
+/// Returns the access rights of M-mode accesses matching no PMP entry.
+pub fn machine_default_permissions(mseccfg: usize) -> u8 {
+    if mseccfg & arch::mseccfg::MMWP_FILTER != 0 {
+        pmpcfg::NO_PERMISSIONS
+    } else if mseccfg & arch::mseccfg::MML_FILTER != 0 {
+        // Executing code from a region without a matching rule is denied
+        pmpcfg::R | pmpcfg::W
+    } else {
+        pmpcfg::RWX
     }
 }
 
@@ -504,7 +715,7 @@ impl Iterator for PmpIterator<'_> {
             let addr = pmps.pmpaddr[self.idx];
             let prev_addr = self.prev_addr;
             self.idx += 1;
-            self.prev_addr = addr;
+            self.prev_addr = addr << 2;
 
             match cfg & pmpcfg::A_MASK {
                 pmpcfg::NA4 => {
@@ -512,14 +723,16 @@ impl Iterator for PmpIterator<'_> {
                     return Some((Segment::new(addr, 4), cfg & pmpcfg::RWX));
                 }
                 pmpcfg::NAPOT => {
+                    // The size saturates for entries covering the whole address space
                     let trailing_ones = addr.trailing_ones();
-                    let addr_mask = !((1 << trailing_ones) - 1);
+                    let addr_mask = usize::MAX.checked_shl(trailing_ones).unwrap_or(0);
                     let addr = (addr & addr_mask) << 2;
-                    let shift = trailing_ones + 3;
-                    return Some((Segment::new(addr, 1 << shift), cfg & pmpcfg::RWX));
+                    let size = 1_usize.checked_shl(trailing_ones + 3).unwrap_or(usize::MAX);
+                    return Some((Segment::new(addr, size), cfg & pmpcfg::RWX));
                 }
                 pmpcfg::TOR => {
                     // if prev_addr is bigger then that entry does not match anything
+                    let addr = addr << 2;
                     if prev_addr >= addr {
                         continue;
                     }
@@ -617,8 +830,8 @@ mod tests {
         }
 
         // Configure some PMP entries
-        pmps.set(0, 1000, RWX | TOR);
-        pmps.set(1, 1500, R | W | TOR);
+        pmps.set(0, 1000 >> 2, RWX | TOR); // TOR addresses are shifted by 2
+        pmps.set(1, 1500 >> 2, R | W | TOR);
         pmps.set(2, 2000 >> 2, RWX | NA4); // NA4 addresses are shifted by 2
         pmps.set(3, 0x8000 >> 2 | 0b0111, RWX | NAPOT); // NAPOT addresses are shifted by 2
 
@@ -635,4 +848,100 @@ mod tests {
             assert_eq!(actual, expected, "Unexpected PMP region")
         }
     }
+
+    #[test]
+    fn memory_map() {
+        use crate::platform::MemoryKind;
+
+        // A test platform with a rich memory map
+        const MEMORY_MAP: &[MemoryRegion] = &[
+            MemoryRegion {
+                base: 0x100000,
+                size: 0x1000,
+                kind: MemoryKind::Device,
+            },
+            MemoryRegion {
+                base: 0x2000000,
+                size: 0x10000,
+                kind: MemoryKind::Device,
+            },
+            MemoryRegion {
+                base: 0x8000000,
+                size: 0x100000,
+                kind: MemoryKind::Reserved,
+            },
+            MemoryRegion {
+                base: 0x80000000,
+                size: 0x200000,
+                kind: MemoryKind::MiralisSelf,
+            },
+            MemoryRegion {
+                base: 0x80200000,
+                size: 0x40000000,
+                kind: MemoryKind::Ram,
+            },
+            MemoryRegion {
+                base: 0xfff00000,
+                size: 0x100000,
+                kind: MemoryKind::Reserved,
+            },
+        ];
+        assert_eq!(crate::platform::count_protected_regions(MEMORY_MAP), 3);
+
+        let mut pmps = PmpGroup::new(8);
+        assert_eq!(pmps.protect_memory_map(1, MEMORY_MAP), 3);
+
+        // Only the protected regions are denied, in order
+        let expected = [
+            (Segment::new(0x8000000, 0x100000), pmpcfg::NO_PERMISSIONS),
+            (Segment::new(0x80000000, 0x200000), pmpcfg::NO_PERMISSIONS),
+            (Segment::new(0xfff00000, 0x100000), pmpcfg::NO_PERMISSIONS),
+        ];
+        assert_eq!(pmps.get_pmpcfg(0), pmpcfg::INACTIVE);
+        assert_eq!((&pmps).into_iter().count(), expected.len());
+        for (actual, expected) in pmps.into_iter().zip(expected.into_iter()) {
+            assert_eq!(actual, expected, "Unexpected PMP region")
+        }
+    }
+
+    #[test]
+    fn smepmp_permissions() {
+        use pmpcfg::{L, NO_PERMISSIONS, R, RWX, W, X};
+
+        let mml = arch::mseccfg::MML_FILTER;
+
+        // Without MML, M-mode is only restricted by locked entries
+        assert_eq!(effective_permissions(R, 0, Mode::M), RWX);
+        assert_eq!(effective_permissions(L | R, 0, Mode::M), R);
+        assert_eq!(effective_permissions(R, 0, Mode::S), R);
+        assert_eq!(effective_permissions(L | R | X, 0, Mode::U), R | X);
+
+        // With MML, locked entries are M-mode only and unlocked entries are S/U-mode only
+        assert_eq!(effective_permissions(R | X, mml, Mode::M), NO_PERMISSIONS);
+        assert_eq!(effective_permissions(R | X, mml, Mode::S), R | X);
+        assert_eq!(effective_permissions(L | R | X, mml, Mode::M), R | X);
+        assert_eq!(
+            effective_permissions(L | R | X, mml, Mode::U),
+            NO_PERMISSIONS
+        );
+
+        // Shared regions
+        assert_eq!(effective_permissions(W, mml, Mode::M), R | W);
+        assert_eq!(effective_permissions(W, mml, Mode::S), R);
+        assert_eq!(effective_permissions(W | X, mml, Mode::U), R | W);
+        assert_eq!(effective_permissions(L | W, mml, Mode::M), X);
+        assert_eq!(effective_permissions(L | W, mml, Mode::S), X);
+        assert_eq!(effective_permissions(L | W | X, mml, Mode::M), R | X);
+        assert_eq!(effective_permissions(L | W | X, mml, Mode::U), X);
+        assert_eq!(effective_permissions(L | RWX, mml, Mode::M), R);
+        assert_eq!(effective_permissions(L | RWX, mml, Mode::S), R);
+
+        // Default M-mode permissions
+        assert_eq!(machine_default_permissions(0), RWX);
+        assert_eq!(machine_default_permissions(mml), R | W);
+        assert_eq!(
+            machine_default_permissions(arch::mseccfg::MMWP_FILTER),
+            NO_PERMISSIONS
+        );
+    }
 }
@@ -1,5 +1,7 @@
 //! RISC-V Registers
 
+use core::fmt;
+
 /// General purpose registers.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[repr(u8)]
@@ -75,6 +77,16 @@ impl Register {
     pub fn from(value: usize) -> Self {
         Register::try_from(value & 0b11111).unwrap()
     }
+
+    /// Returns the ABI name of the register, as used in assembly.
+    pub const fn abi_name(self) -> &'static str {
+        const ABI_NAMES: [&str; 32] = [
+            "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3",
+            "a4", "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11",
+            "t3", "t4", "t5", "t6",
+        ];
+        ABI_NAMES[self as usize]
+    }
 }
 
 /// A RISC-V Control and Status Register (CSR).
@@ -116,6 +128,12 @@ pub enum Csr {
     Time,
     /// Instret register
     Instret,
+    /// Upper 32 bits of cycle, RV32 only
+    Cycleh,
+    /// Upper 32 bits of time, RV32 only
+    Timeh,
+    /// Upper 32 bits of instret, RV32 only
+    Instreth,
     /// Machine performance-monitoring counter
     Mhpmcounter(usize),
     /// Machine counter-inhibit register
@@ -242,6 +260,15 @@ pub enum Csr {
     /// Virtual Supervisor Address Translation and Protection
     Vsatp,
 
+    /// Floating-point extension
+    ///
+    /// Floating-Point Accrued Exceptions
+    Fflags,
+    /// Floating-Point Dynamic Rounding Mode
+    Frm,
+    /// Floating-Point Control and Status Register
+    Fcsr,
+
     /// Vector extension
     ///
     /// Vector Start Index CSR
@@ -316,6 +343,9 @@ pub mod csr {
     pub const CYCLE: usize = 0xC00;
     pub const TIME: usize = 0xC01;
     pub const INSTRET: usize = 0xC02;
+    pub const CYCLEH: usize = 0xC80;
+    pub const TIMEH: usize = 0xC81;
+    pub const INSTRETH: usize = 0xC82;
     pub const VL: usize = 0xC20;
     pub const VTYPE: usize = 0xC21;
     pub const VLENB: usize = 0xC22;
@@ -365,6 +395,11 @@ pub mod csr {
     pub const HTINST: usize = 0x64A;
     pub const HGATP: usize = 0x680;
 
+    // Floating-point extension CSRs
+    pub const FFLAGS: usize = 0x1;
+    pub const FRM: usize = 0x2;
+    pub const FCSR: usize = 0x3;
+
     // Vector extension CSRs
     pub const VSTART: usize = 0x8;
     pub const VXSAT: usize = 0x9;
@@ -403,6 +438,14 @@ impl Csr {
         self == Csr::Unknown
     }
 
+    /// Returns true if the CSR is read-only.
+    ///
+    /// Per the privileged specification the CSRs with the two top bits of their address set are
+    /// read-only, attempting to write them raises an illegal instruction exception.
+    pub fn is_read_only(self) -> bool {
+        !self.is_unknown() && (self.idx() >> 10) & 0b11 == 0b11
+    }
+
     /// Return the index of the CSR (i.e. its addess in the CSR address space).
     pub fn idx(self) -> usize {
         match self {
@@ -424,6 +467,9 @@ impl Csr {
             Csr::Cycle => csr::CYCLE,
             Csr::Time => csr::TIME,
             Csr::Instret => csr::INSTRET,
+            Csr::Cycleh => csr::CYCLEH,
+            Csr::Timeh => csr::TIMEH,
+            Csr::Instreth => csr::INSTRETH,
             Csr::Mhpmcounter(id) => csr::MHPMCOUNTER3 + id,
             Csr::Mcountinhibit => csr::MCOUNTINHIBIT,
             Csr::Mhpmevent(id) => csr::MHPMEVENT3 + id,
@@ -488,6 +534,11 @@ impl Csr {
             Csr::Vsip => csr::VSIP,
             Csr::Vsatp => csr::VSATP,
 
+            // Floating-point extension CSRs
+            Csr::Fflags => csr::FFLAGS,
+            Csr::Frm => csr::FRM,
+            Csr::Fcsr => csr::FCSR,
+
             // Vector extension CSRs
             Csr::Vstart => csr::VSTART,
             Csr::Vxsat => csr::VXSAT,
@@ -550,3 +601,38 @@ impl TryFrom<usize> for Register {
         }
     }
 }
+
+// ——————————————————————————————— Formatting ——————————————————————————————— //
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.abi_name())
+    }
+}
+
+impl fmt::Display for Csr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Csr::Pmpcfg(idx) => write!(f, "pmpcfg{}", idx),
+            Csr::Pmpaddr(idx) => write!(f, "pmpaddr{}", idx),
+            Csr::Mhpmcounter(idx) => write!(f, "mhpmcounter{}", idx + 3),
+            Csr::Mhpmevent(idx) => write!(f, "mhpmevent{}", idx + 3),
+            Csr::Custom(addr) => write!(f, "0x{:x}", addr),
+            Csr::Unknown => f.write_str("unknown"),
+            // The other variants are named after the CSR
+            _ => fmt::write(&mut Lowercase(f), format_args!("{:?}", self)),
+        }
+    }
+}
+
+/// Writes to the inner formatter in lower case.
+struct Lowercase<'a, 'b>(&'a mut fmt::Formatter<'b>);
+
+impl fmt::Write for Lowercase<'_, '_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            self.0.write_char(c.to_ascii_lowercase())?;
+        }
+        Ok(())
+    }
+}
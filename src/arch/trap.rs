@@ -46,6 +46,7 @@ pub enum MCause {
     VirtualSupervisorExternalInt = INTERRUPT_BIT + 10,
     MachineExternalInt = INTERRUPT_BIT + 11,
     SupervisorGuestExternalInt = INTERRUPT_BIT + 12,
+    LocalCounterOverflowInt = INTERRUPT_BIT + 13,
     UnknownInt,
 }
 
@@ -90,6 +91,7 @@ impl TryFrom<usize> for MCause {
                 8 => Ok(MCause::UserExternalInt),
                 9 => Ok(MCause::SupervisorExternalInt),
                 11 => Ok(MCause::MachineExternalInt),
+                13 => Ok(MCause::LocalCounterOverflowInt),
                 _ => Ok(MCause::UnknownInt),
             }
         } else {
@@ -143,6 +145,20 @@ impl TrapInfo {
     pub fn get_cause(&self) -> MCause {
         MCause::new(self.mcause)
     }
+
+    /// Whether the trap is a spurious machine interrupt, that can safely be ignored.
+    ///
+    /// A machine interrupt is spurious if its pending bit was already cleared when the trap was
+    /// taken, for instance because the device deasserted the interrupt line. Only timer,
+    /// software and external machine interrupts are considered, exceptions are never spurious.
+    pub fn is_spurious_interrupt(&self) -> bool {
+        match self.get_cause() {
+            MCause::MachineSoftInt | MCause::MachineTimerInt | MCause::MachineExternalInt => {
+                self.mip & (1 << MCause::cause_number(self.mcause)) == 0
+            }
+            _ => false,
+        }
+    }
 }
 
 // ———————————————————————————————— Display ————————————————————————————————— //
@@ -166,6 +182,7 @@ impl fmt::Debug for MCause {
                 write!(f, "virtual supervisor external interrupt")
             }
             MCause::SupervisorGuestExternalInt => write!(f, "supervisor guest external interrupt"),
+            MCause::LocalCounterOverflowInt => write!(f, "local counter overflow interrupt"),
             MCause::UnknownInt => write!(f, "unknown interrupt"),
             // Traps
             MCause::InstrAddrMisaligned => write!(f, "instruction address misaligned"),
@@ -0,0 +1,64 @@
+//! Attestation
+//!
+//! Policies can authenticate their measurements (for instance the hash of an enclave or of the
+//! payload) with the device key of the platform (see
+//! [Platform::get_attestation_key](crate::platform::Platform::get_attestation_key)). Provisioning
+//! the key is left to the platforms, which read it from their own secure store.
+//!
+//! Measurements are authenticated with KMAC256, a message authentication code: this is not a
+//! signature, checking a MAC requires the device key and must therefore be done by a verifier
+//! sharing the key with the device.
+
+use tiny_keccak::{Hasher, Kmac};
+
+use crate::platform::{Plat, Platform};
+
+/// The size of a MAC, in bytes.
+pub const MAC_SIZE: usize = 32;
+
+/// Distinguishes attestation MACs from other uses of the device key.
+const CUSTOMIZATION: &[u8] = b"Miralis attestation";
+
+/// Computes the MAC of a measurement split in `parts` with the device key, or returns None if the
+/// platform has no key.
+pub fn mac_measurement(parts: &[&[u8]]) -> Option<[u8; MAC_SIZE]> {
+    Some(mac_with_key(Plat::get_attestation_key()?, parts))
+}
+
+/// Computes the MAC of a measurement split in `parts` with the provided key.
+fn mac_with_key(key: &[u8], parts: &[&[u8]]) -> [u8; MAC_SIZE] {
+    let mut kmac = Kmac::v256(key, CUSTOMIZATION);
+    for part in parts {
+        kmac.update(part);
+    }
+
+    let mut mac = [0; MAC_SIZE];
+    kmac.finalize(&mut mac);
+    mac
+}
+
+// ————————————————————————————————— Tests —————————————————————————————————— //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deterministic_mac() {
+        let mac = mac_measurement(&[&[0x42; 32]]).expect("The test platform has a key");
+        assert_eq!(mac, mac_measurement(&[&[0x42; 32]]).unwrap());
+        assert_eq!(mac, mac_measurement(&[&[0x42; 16], &[0x42; 16]]).unwrap());
+        assert_ne!(mac, mac_measurement(&[&[0x43; 32]]).unwrap());
+        assert_ne!(mac, mac_with_key(&[0; 32], &[&[0x42; 32]]));
+
+        // Known answer with the key of the virt platform, MACs must not change across versions
+        assert_eq!(
+            mac,
+            [
+                0x93, 0xa9, 0x6f, 0xc1, 0x8a, 0xf0, 0xe0, 0x09, 0x83, 0x0f, 0x71, 0xad, 0x00, 0x51,
+                0xa1, 0x47, 0x1d, 0x23, 0x56, 0x26, 0x21, 0xbe, 0x8d, 0x68, 0x2c, 0x91, 0x7d, 0x72,
+                0xf8, 0x0a, 0x1c, 0xa9
+            ]
+        );
+    }
+}
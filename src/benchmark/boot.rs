@@ -4,7 +4,9 @@ use miralis_core::abi;
 
 use crate::arch;
 use crate::arch::{Csr, Register};
-use crate::benchmark::{NUMBER_CATEGORIES, get_exception_category};
+use crate::benchmark::{
+    NUMBER_CATEGORIES, get_exception_category, is_paused, shared_page, write_shared_page,
+};
 use crate::config::MODULES;
 use crate::host::MiralisContext;
 use crate::modules::{Module, ModuleAction};
@@ -51,6 +53,10 @@ impl Module for BootBenchmark {
         previous_mode: ExecutionMode,
         next_mode: ExecutionMode,
     ) {
+        if is_paused(ctx.hart_id) {
+            return;
+        }
+
         if let Some(exception_offset) = get_exception_category(ctx, previous_mode, next_mode) {
             let current_time_bin = arch::read_csr(Csr::Time) / CYCLES_PER_INTERVALL;
 
@@ -84,8 +90,13 @@ impl Module for BootBenchmark {
         self.ecall_from_any_mode(ctx)
     }
 
-    fn on_shutdown(&mut self) {
-        self.display_benchmark(arch::read_csr(Csr::Mhartid));
+    fn on_shutdown(&mut self, ctx: &mut VirtContext, _mctx: &mut MiralisContext) {
+        // Shutdown can happen on any hart, only the boot hart reports and the others must not be
+        // parked as the platform still needs to exit.
+        if ctx.hart_id != 0 {
+            return;
+        }
+        self.display_benchmark(ctx.hart_id);
     }
 }
 
@@ -113,6 +124,14 @@ impl BootBenchmark {
             }
         }
 
+        if let Some(page) = shared_page() {
+            write_shared_page(page, NUMBER_SECONDS, |row, column| {
+                BUCKETS[row * NUMBER_CATEGORIES + column].load(Ordering::SeqCst) as u64
+            })
+            .expect("Failed to write the benchmark shared page");
+            return;
+        }
+
         log::info!("{}", CSV_HEADER);
         for i in 0..NUMBER_SECONDS {
             assert!(i * NUMBER_CATEGORIES + 1 < NUMBER_CATEGORIES * NUMBER_SECONDS);
@@ -3,7 +3,9 @@ use core::sync::atomic::{AtomicU64, Ordering};
 use miralis_core::abi;
 
 use crate::arch::Register;
-use crate::benchmark::{ExceptionCategory, get_exception_category};
+use crate::benchmark::{
+    ExceptionCategory, get_exception_category, is_paused, shared_page, write_shared_page,
+};
 use crate::config::PLATFORM_NB_HARTS;
 use crate::host::MiralisContext;
 use crate::modules::{Module, ModuleAction};
@@ -13,7 +15,7 @@ use crate::virt::{ExecutionMode, VirtContext};
 // We use this structure to avoid false sharing in the benchmark.
 // The typical size of a cache line is 64 bytes
 #[repr(C, align(64))]
-#[derive(Debug, Default)]
+#[derive(Debug)]
 struct PaddedCounter {
     firmware_traps: AtomicU64,
     world_switches: AtomicU64,
@@ -23,7 +25,10 @@ struct PaddedCounter {
     ipi_request: AtomicU64,
     remote_fence_request: AtomicU64,
     page_faults: AtomicU64,
-    _padding: [u8; 64 - 7 * size_of::<AtomicU64>()],
+    world_switch_cycles: AtomicU64,
+    total_exits: AtomicU64,
+    guest_instructions: AtomicU64,
+    _padding: [u8; 2 * 64 - 11 * size_of::<AtomicU64>()],
 }
 
 // NOTE: Clippy is triggering a warning here but it's fine as we use the const only for array
@@ -38,7 +43,10 @@ const ZEROED_COUNTER: PaddedCounter = PaddedCounter {
     ipi_request: const { AtomicU64::new(0) },
     remote_fence_request: const { AtomicU64::new(0) },
     page_faults: const { AtomicU64::new(0) },
-    _padding: [0; 64 - 7 * size_of::<AtomicU64>()],
+    world_switch_cycles: const { AtomicU64::new(0) },
+    total_exits: const { AtomicU64::new(0) },
+    guest_instructions: const { AtomicU64::new(0) },
+    _padding: [0; 2 * 64 - 11 * size_of::<AtomicU64>()],
 };
 
 static COUNTERS: [PaddedCounter; PLATFORM_NB_HARTS] = [ZEROED_COUNTER; PLATFORM_NB_HARTS];
@@ -62,6 +70,17 @@ impl Module for CounterBenchmark {
         previous_mode: ExecutionMode,
         next_mode: ExecutionMode,
     ) {
+        if is_paused(ctx.hart_id) {
+            return;
+        }
+
+        COUNTERS[ctx.hart_id]
+            .total_exits
+            .fetch_add(1, Ordering::Relaxed);
+        COUNTERS[ctx.hart_id]
+            .guest_instructions
+            .fetch_add(ctx.last_run_instret as u64, Ordering::Relaxed);
+
         match get_exception_category(ctx, previous_mode, next_mode) {
             Some(ExceptionCategory::FirmwareTrap) => {
                 COUNTERS[ctx.hart_id]
@@ -107,6 +126,17 @@ impl Module for CounterBenchmark {
         }
     }
 
+    fn world_switch_done(&mut self, ctx: &mut VirtContext, cycles: usize) {
+        if is_paused(ctx.hart_id) {
+            return;
+        }
+
+        // The switches themselves are counted as not offloaded traps, see `decided_next_exec_mode`
+        COUNTERS[ctx.hart_id]
+            .world_switch_cycles
+            .fetch_add(cycles as u64, Ordering::Relaxed);
+    }
+
     fn ecall_from_payload(
         &mut self,
         _mctx: &mut MiralisContext,
@@ -152,7 +182,23 @@ impl CounterBenchmark {
             return;
         }
 
-        let measure = match exception_category {
+        let measure = Self::load(hart_to_read, exception_category);
+
+        // Also dump all the counters to the shared page, if any. This lets the firmware collect
+        // the whole table at once.
+        if let Some(page) = shared_page() {
+            write_shared_page(page, PLATFORM_NB_HARTS, |hart, category| {
+                Self::load(hart, ExceptionCategory::try_from(category).unwrap())
+            })
+            .expect("Failed to write the benchmark shared page");
+        }
+
+        ctx.set(Register::X10, measure as usize);
+    }
+
+    /// Returns the counter of the given category for a hart.
+    fn load(hart_to_read: usize, exception_category: ExceptionCategory) -> u64 {
+        match exception_category {
             ExceptionCategory::NotOffloaded => {
                 COUNTERS[hart_to_read].world_switches.load(Ordering::SeqCst)
             }
@@ -173,8 +219,78 @@ impl CounterBenchmark {
             ExceptionCategory::PageFault => {
                 COUNTERS[hart_to_read].page_faults.load(Ordering::SeqCst)
             }
-        };
+            ExceptionCategory::WorldSwitch => {
+                COUNTERS[hart_to_read].world_switches.load(Ordering::SeqCst)
+            }
+            ExceptionCategory::WorldSwitchCycles => COUNTERS[hart_to_read]
+                .world_switch_cycles
+                .load(Ordering::SeqCst),
+            ExceptionCategory::TotalExits => {
+                COUNTERS[hart_to_read].total_exits.load(Ordering::SeqCst)
+            }
+            ExceptionCategory::GuestInstructions => COUNTERS[hart_to_read]
+                .guest_instructions
+                .load(Ordering::SeqCst),
+            ExceptionCategory::InstructionsPerExit => {
+                // A low value means the guest traps into Miralis too often
+                let exits = Self::load(hart_to_read, ExceptionCategory::TotalExits);
+                let instructions = Self::load(hart_to_read, ExceptionCategory::GuestInstructions);
+                instructions.checked_div(exits).unwrap_or(0)
+            }
+        }
+    }
+}
 
-        ctx.set(Register::X10, measure as usize);
+// ————————————————————————————————— Tests —————————————————————————————————— //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch;
+    use crate::benchmark::set_paused;
+
+    #[test]
+    fn paused_counters() {
+        let hw = unsafe { arch::detect_hardware() };
+        let mut ctx = VirtContext::new(0, hw.available_reg.nb_pmp, hw.extensions);
+        let mut benchmark = CounterBenchmark::init();
+        let exits = || CounterBenchmark::load(0, ExceptionCategory::TotalExits);
+        let switches = || CounterBenchmark::load(0, ExceptionCategory::WorldSwitch);
+        let cycles = || CounterBenchmark::load(0, ExceptionCategory::WorldSwitchCycles);
+        let instructions = || CounterBenchmark::load(0, ExceptionCategory::GuestInstructions);
+        ctx.last_run_instret = 1000;
+
+        // Nothing is recorded while paused
+        set_paused(0, true);
+        let (before_exits, before_switches) = (exits(), switches());
+        let (before_instructions, before_cycles) = (instructions(), cycles());
+        benchmark.decided_next_exec_mode(&mut ctx, ExecutionMode::Payload, ExecutionMode::Firmware);
+        benchmark.world_switch_done(&mut ctx, 100);
+        assert_eq!(exits(), before_exits);
+        assert_eq!(switches(), before_switches);
+        assert_eq!(instructions(), before_instructions);
+        assert_eq!(cycles(), before_cycles);
+
+        // Counting starts again once resumed
+        set_paused(0, false);
+        benchmark.decided_next_exec_mode(&mut ctx, ExecutionMode::Payload, ExecutionMode::Firmware);
+        benchmark.world_switch_done(&mut ctx, 100);
+        assert_eq!(exits(), before_exits + 1);
+        assert_eq!(
+            switches(),
+            before_switches + 1,
+            "Switches to the firmware are counted with the not offloaded traps"
+        );
+        assert_eq!(
+            CounterBenchmark::load(0, ExceptionCategory::NotOffloaded),
+            switches()
+        );
+        assert_eq!(instructions(), before_instructions + 1000);
+        assert_eq!(cycles(), before_cycles + 100);
+        assert_eq!(
+            CounterBenchmark::load(0, ExceptionCategory::InstructionsPerExit),
+            instructions() / exits(),
+            "The average must be derived from the guest instructions and exits"
+        );
     }
 }
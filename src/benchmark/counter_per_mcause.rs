@@ -7,6 +7,7 @@ use miralis_core::abi;
 
 use crate::arch;
 use crate::arch::{Csr, MCause, Register};
+use crate::benchmark::is_paused;
 use crate::config::PLATFORM_NB_HARTS;
 use crate::host::MiralisContext;
 use crate::modules::{Module, ModuleAction};
@@ -97,6 +98,10 @@ impl Module for CounterPerMcauseBenchmark {
         next_mode: ExecutionMode,
     ) {
         let hart_id: usize = hard_id();
+        if is_paused(hart_id) {
+            return;
+        }
+
         let mcause_offset: usize = raw_cause_to_entry(ctx.trap_info.mcause);
 
         if previous_mode == ExecutionMode::Payload && next_mode == ExecutionMode::Firmware {
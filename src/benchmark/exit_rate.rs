@@ -0,0 +1,108 @@
+//! Exit Rate
+//!
+//! Periodically samples the number of exits against `mtime` to report the number of exits per
+//! second while the system is running. This surfaces runaway trap rates in real time, rather than
+//! only once the maximum number of exits is reached.
+
+use core::fmt;
+
+/// Samples the number of exits at a fixed interval of `mtime` ticks.
+#[derive(Clone, Copy, Debug)]
+pub struct ExitRateSampler {
+    /// Number of `mtime` ticks between two samples.
+    interval: usize,
+    /// Frequency of `mtime`, in Hz.
+    frequency: usize,
+    /// The `mtime` value and number of exits at the last sample, if any.
+    last_sample: Option<(usize, usize)>,
+    /// The rate measured over the last complete interval.
+    exits_per_second: Option<usize>,
+}
+
+impl ExitRateSampler {
+    /// Creates a sampler measuring the exit rate every `interval_ms` milliseconds, for an `mtime`
+    /// running at `frequency` Hz.
+    pub const fn new(interval_ms: usize, frequency: usize) -> Self {
+        let interval = interval_ms * (frequency / 1000);
+        ExitRateSampler {
+            interval: if interval > 0 { interval } else { 1 },
+            frequency,
+            last_sample: None,
+            exits_per_second: None,
+        }
+    }
+
+    /// Records the number of exits at time `now`.
+    ///
+    /// Returns the exit rate once per interval, and None otherwise.
+    pub fn sample(&mut self, nb_exits: usize, now: usize) -> Option<ExitRate> {
+        let Some((last_time, last_exits)) = self.last_sample else {
+            self.last_sample = Some((now, nb_exits));
+            return None;
+        };
+
+        let elapsed = now.wrapping_sub(last_time);
+        if elapsed < self.interval {
+            return None;
+        }
+
+        let exits = nb_exits.wrapping_sub(last_exits);
+        let exits_per_second = (exits as u128 * self.frequency as u128 / elapsed as u128) as usize;
+        self.last_sample = Some((now, nb_exits));
+        self.exits_per_second = Some(exits_per_second);
+        Some(ExitRate {
+            exits,
+            exits_per_second,
+        })
+    }
+
+    /// Returns the exit rate measured over the last complete interval, if any.
+    pub fn exits_per_second(&self) -> Option<usize> {
+        self.exits_per_second
+    }
+}
+
+/// The exit rate over one sampling interval.
+#[derive(Clone, Copy, Debug)]
+pub struct ExitRate {
+    exits: usize,
+    exits_per_second: usize,
+}
+
+impl fmt::Display for ExitRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Exit rate: {} exits/s ({} exits)",
+            self.exits_per_second, self.exits
+        )
+    }
+}
+
+// ————————————————————————————————— Tests —————————————————————————————————— //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn periodic_exit_rate() {
+        // Sample every 10ms with a 10 MHz timebase
+        let mut sampler = ExitRateSampler::new(10, 10_000_000);
+        let mut lines = Vec::new();
+
+        // A trap every 100 ticks (10 µs), that is 100_000 exits per second
+        for exit in 0..=3_000 {
+            if let Some(rate) = sampler.sample(exit, exit * 100) {
+                lines.push(format!("{}", rate));
+            }
+        }
+
+        assert_eq!(
+            lines,
+            vec!["Exit rate: 100000 exits/s (1000 exits)"; 3],
+            "Expected one line per 10ms interval"
+        );
+        assert_eq!(sampler.exits_per_second(), Some(100_000));
+    }
+}
@@ -0,0 +1,202 @@
+//! Folded Stacks
+//!
+//! Measures the cycles spent in the nested scopes of the main loop and formats them as folded
+//! stacks: one line per scope with the `;`-separated path of scopes followed by the cycles spent
+//! in that scope, excluding its children (e.g. `HandleTrap;Emulate 1234`). This is the input
+//! format of most flamegraph tools, which sum the lines sharing the same path.
+//!
+//! Printing a line on each trap would be measured by the enclosing scopes, the cycles are
+//! therefore summed per path and the lines are only printed on shutdown.
+
+use core::fmt;
+
+use crate::utils::FixedMap;
+
+/// Maximum nesting depth of the scopes.
+const MAX_DEPTH: usize = 4;
+/// Maximum number of distinct paths of scopes.
+const MAX_PATHS: usize = 16;
+
+/// The scopes of the main loop.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scope {
+    /// Executing the firmware or payload, until the next trap.
+    RunVCPU,
+    /// Handling a trap, from the trap entry to the next vCPU entry.
+    HandleTrap,
+    /// Emulating the trap on behalf of the firmware or payload.
+    Emulate,
+    /// Switching between the firmware and the payload, including the PMP flush.
+    WorldSwitch,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Frame {
+    scope: Scope,
+    start: usize,
+    /// Total cycles spent in the nested scopes.
+    children: usize,
+}
+
+/// A path of nested scopes, from the outermost to the innermost.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Path {
+    scopes: [Scope; MAX_DEPTH],
+    depth: usize,
+}
+
+impl Path {
+    fn scopes(&self) -> &[Scope] {
+        &self.scopes[..self.depth]
+    }
+}
+
+/// A stack of open scopes, and the cycles spent in each path of scopes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FoldedStacks {
+    frames: [Frame; MAX_DEPTH],
+    depth: usize,
+    cycles: FixedMap<Path, usize, MAX_PATHS>,
+}
+
+impl FoldedStacks {
+    pub const fn new() -> Self {
+        FoldedStacks {
+            frames: [Frame {
+                scope: Scope::RunVCPU,
+                start: 0,
+                children: 0,
+            }; MAX_DEPTH],
+            depth: 0,
+            cycles: FixedMap::new(),
+        }
+    }
+
+    /// Opens a new scope nested in the current one, `now` is the current cycle count.
+    pub fn enter(&mut self, scope: Scope, now: usize) {
+        assert!(self.depth < MAX_DEPTH, "Folded stack is too deep");
+        self.frames[self.depth] = Frame {
+            scope,
+            start: now,
+            children: 0,
+        };
+        self.depth += 1;
+    }
+
+    /// Closes the innermost scope, `now` is the current cycle count.
+    ///
+    /// The cycles spent in the scope itself are added to the line of its path.
+    pub fn exit(&mut self, now: usize) {
+        assert!(self.depth > 0, "No scope to exit");
+        self.depth -= 1;
+        let frame = self.frames[self.depth];
+        let total = now.wrapping_sub(frame.start);
+        if self.depth > 0 {
+            self.frames[self.depth - 1].children += total;
+        }
+
+        let mut path = Path {
+            scopes: [Scope::RunVCPU; MAX_DEPTH],
+            depth: self.depth + 1,
+        };
+        for (scope, frame) in path.scopes.iter_mut().zip(&self.frames[..=self.depth]) {
+            *scope = frame.scope;
+        }
+
+        let cycles = total.saturating_sub(frame.children);
+        if let Some(sum) = self.cycles.get_mut(path) {
+            *sum += cycles;
+        } else {
+            self.cycles
+                .insert(path, cycles)
+                .expect("Too many folded stack paths");
+        }
+    }
+
+    /// Returns the folded lines, one per path of scopes.
+    pub fn lines(&self) -> impl Iterator<Item = FoldedLine> + '_ {
+        self.cycles.iter().map(|(path, cycles)| FoldedLine {
+            path,
+            cycles: *cycles,
+        })
+    }
+}
+
+impl Default for FoldedStacks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A single line of folded stacks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FoldedLine {
+    path: Path,
+    cycles: usize,
+}
+
+impl fmt::Display for FoldedLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, scope) in self.path.scopes().iter().enumerate() {
+            if idx > 0 {
+                write!(f, ";")?;
+            }
+            write!(f, "{:?}", scope)?;
+        }
+        write!(f, " {}", self.cycles)
+    }
+}
+
+// ————————————————————————————————— Tests —————————————————————————————————— //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folded_format() {
+        let mut stacks = FoldedStacks::new();
+        let lines = |stacks: &FoldedStacks| -> Vec<String> {
+            stacks.lines().map(|line| line.to_string()).collect()
+        };
+
+        stacks.enter(Scope::RunVCPU, 0);
+        stacks.exit(100);
+        assert_eq!(lines(&stacks), ["RunVCPU 100"]);
+
+        stacks.enter(Scope::HandleTrap, 100);
+        stacks.enter(Scope::Emulate, 110);
+        stacks.exit(150);
+        stacks.enter(Scope::WorldSwitch, 155);
+        stacks.exit(185);
+        stacks.exit(200);
+
+        // Time spent in the nested scopes is excluded
+        assert_eq!(
+            lines(&stacks),
+            [
+                "RunVCPU 100",
+                "HandleTrap;Emulate 40",
+                "HandleTrap;WorldSwitch 30",
+                "HandleTrap 30"
+            ]
+        );
+
+        // Lines sharing the same path are summed
+        stacks.enter(Scope::RunVCPU, 200);
+        stacks.exit(250);
+        stacks.enter(Scope::HandleTrap, 250);
+        stacks.enter(Scope::Emulate, 260);
+        stacks.exit(270);
+        stacks.exit(280);
+        assert_eq!(
+            lines(&stacks),
+            [
+                "RunVCPU 150",
+                "HandleTrap;Emulate 50",
+                "HandleTrap;WorldSwitch 30",
+                "HandleTrap 50"
+            ]
+        );
+    }
+}
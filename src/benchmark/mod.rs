@@ -5,30 +5,53 @@
 pub mod boot;
 pub mod counter;
 pub mod counter_per_mcause;
+pub mod exit_rate;
+pub mod folded;
 
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use miralis_core::benchmark as layout;
+use miralis_core::benchmark::counters;
 use miralis_core::sbi_codes::{
     is_i_fence_request, is_ipi_request, is_timer_request, is_vma_request,
 };
 
-use crate::arch::{MCause, Register};
+use crate::arch::{self, Csr, MCause, Register, mcountinhibit};
 use crate::benchmark::ExceptionCategory::{
-    FirmwareTrap, IPI, MisalignedOp, NotOffloaded, PageFault, ReadTime, RemoteFence, SetTimer,
+    FirmwareTrap, GuestInstructions, IPI, InstructionsPerExit, MisalignedOp, NotOffloaded,
+    PageFault, ReadTime, RemoteFence, SetTimer, TotalExits, WorldSwitch, WorldSwitchCycles,
 };
+use crate::config::{BENCHMARK_SHARED_PAGE, PLATFORM_NB_HARTS};
+use crate::host::MiralisContext;
+use crate::platform::{Plat, Platform};
 use crate::virt::traits::RegisterContextGetter;
 use crate::virt::{ExecutionMode, VirtContext};
 
-const NUMBER_CATEGORIES: usize = 8;
+const NUMBER_CATEGORIES: usize = layout::NB_COUNTERS;
 
+/// The categories of the benchmark counters.
+///
+/// The discriminants follow the layout shared with the firmware, see [miralis_core::benchmark].
 #[derive(Clone, Copy, Debug)]
 pub enum ExceptionCategory {
-    NotOffloaded = 0,
-    ReadTime = 1,
-    SetTimer = 2,
-    MisalignedOp = 3,
-    IPI = 4,
-    RemoteFence = 5,
-    FirmwareTrap = 6,
-    PageFault = 7,
+    NotOffloaded = counters::NOT_OFFLOADED as isize,
+    ReadTime = counters::READ_TIME as isize,
+    SetTimer = counters::SET_TIMER as isize,
+    MisalignedOp = counters::MISALIGNED_OP as isize,
+    IPI = counters::IPI as isize,
+    RemoteFence = counters::REMOTE_FENCE as isize,
+    FirmwareTrap = counters::FIRMWARE_TRAP as isize,
+    PageFault = counters::PAGE_FAULT as isize,
+    /// World switches from the payload to the firmware, counted with [Self::NotOffloaded].
+    WorldSwitch = counters::WORLD_SWITCH as isize,
+    /// Cycles spent in world switches in both directions, including the PMP flush.
+    WorldSwitchCycles = counters::WORLD_SWITCH_CYCLES as isize,
+    /// All traps into Miralis, not an exception category on its own.
+    TotalExits = counters::TOTAL_EXITS as isize,
+    /// Instructions retired by the guest, not an exception category on its own.
+    GuestInstructions = counters::GUEST_INSTRUCTIONS as isize,
+    /// Guest instructions per exit, derived from the other counters when read.
+    InstructionsPerExit = counters::INSTRUCTIONS_PER_EXIT as isize,
 }
 
 impl TryFrom<usize> for ExceptionCategory {
@@ -36,14 +59,19 @@ impl TryFrom<usize> for ExceptionCategory {
 
     fn try_from(exception_category: usize) -> Result<Self, Self::Error> {
         match exception_category {
-            0 => Ok(NotOffloaded),
-            1 => Ok(ReadTime),
-            2 => Ok(SetTimer),
-            3 => Ok(MisalignedOp),
-            4 => Ok(IPI),
-            5 => Ok(RemoteFence),
-            6 => Ok(FirmwareTrap),
-            7 => Ok(PageFault),
+            counters::NOT_OFFLOADED => Ok(NotOffloaded),
+            counters::READ_TIME => Ok(ReadTime),
+            counters::SET_TIMER => Ok(SetTimer),
+            counters::MISALIGNED_OP => Ok(MisalignedOp),
+            counters::IPI => Ok(IPI),
+            counters::REMOTE_FENCE => Ok(RemoteFence),
+            counters::FIRMWARE_TRAP => Ok(FirmwareTrap),
+            counters::PAGE_FAULT => Ok(PageFault),
+            counters::WORLD_SWITCH => Ok(WorldSwitch),
+            counters::WORLD_SWITCH_CYCLES => Ok(WorldSwitchCycles),
+            counters::TOTAL_EXITS => Ok(TotalExits),
+            counters::GUEST_INSTRUCTIONS => Ok(GuestInstructions),
+            counters::INSTRUCTIONS_PER_EXIT => Ok(InstructionsPerExit),
             _ => Err(()),
         }
     }
@@ -93,3 +121,158 @@ pub fn get_exception_category(
         _ => None,
     }
 }
+
+// ———————————————————————————————— Counters ———————————————————————————————— //
+
+/// Ensures the hardware `mcycle` and `minstret` counters used to measure Miralis are running.
+///
+/// The firmware only ever accesses the virtual `mcountinhibit`, which is never written to
+/// hardware, so the physical inhibit bits are under the sole control of Miralis. Clearing them at
+/// boot protects the measurements against counters left inhibited by a previous boot stage.
+///
+/// # Safety
+///
+/// This function must only be called on hardware that implements `mcountinhibit`.
+pub unsafe fn enable_counters() {
+    unsafe {
+        arch::clear_csr_bits(
+            Csr::Mcountinhibit,
+            mcountinhibit::CY_FILTER | mcountinhibit::IR_FILTER,
+        );
+    }
+}
+
+// ————————————————————————————————— Pause —————————————————————————————————— //
+
+/// Whether the benchmarks are paused, for each hart.
+static PAUSED: [AtomicBool; PLATFORM_NB_HARTS] =
+    [const { AtomicBool::new(false) }; PLATFORM_NB_HARTS];
+
+/// Pauses or resumes the benchmark counters of a hart, as requested by the firmware through
+/// `MIRALIS_BENCHMARK_PAUSE_FID` and `MIRALIS_BENCHMARK_RESUME_FID`.
+pub fn set_paused(hart_id: usize, paused: bool) {
+    PAUSED[hart_id].store(paused, Ordering::Relaxed);
+}
+
+/// Returns true if the benchmark counters of the hart must not record anything.
+pub fn is_paused(hart_id: usize) -> bool {
+    PAUSED[hart_id].load(Ordering::Relaxed)
+}
+
+// ——————————————————————————————— Shared Page —————————————————————————————— //
+
+/// Validates the benchmark shared page, if one is configured.
+///
+/// The page must be aligned and in guest RAM, otherwise Miralis stops before the benchmarks
+/// write to it. Must be called at boot, before any call to [shared_page].
+pub fn check_shared_page(mctx: &MiralisContext) {
+    let Some(addr) = BENCHMARK_SHARED_PAGE else {
+        return;
+    };
+
+    if let Err(err) = mctx.check_shared_page(addr, layout::PAGE_SIZE) {
+        log::error!("Invalid benchmark shared page 0x{:x}: {}", addr, err);
+        Plat::exit_failure();
+    }
+}
+
+/// Returns the benchmark shared page, if one is configured.
+///
+/// When a shared page is configured benchmarks write their counters to it rather than printing
+/// them on the console, which avoids perturbing the measurements with the UART overhead.
+pub fn shared_page() -> Option<&'static mut [u64]> {
+    let addr = BENCHMARK_SHARED_PAGE?;
+
+    // SAFETY: the shared page is reserved for the benchmarks by the configuration and has been
+    // validated at boot by [check_shared_page].
+    Some(unsafe {
+        core::slice::from_raw_parts_mut(addr as *mut u64, layout::PAGE_SIZE / size_of::<u64>())
+    })
+}
+
+/// Writes a table of counters to a shared page, following the layout of [miralis_core::benchmark].
+///
+/// The table has one column per exception category, `counter(row, column)` returns the value of
+/// each cell.
+pub fn write_shared_page(
+    page: &mut [u64],
+    nb_rows: usize,
+    counter: impl Fn(usize, usize) -> u64,
+) -> Result<(), &'static str> {
+    let len = layout::HEADER_LEN + nb_rows * NUMBER_CATEGORIES;
+    if len > page.len() {
+        return Err("Benchmark counters do not fit in the shared page");
+    }
+
+    // Write the counters first, and the header last so that a reader never sees a valid header
+    // with partial content.
+    page[layout::MAGIC_IDX] = 0;
+    for row in 0..nb_rows {
+        for column in 0..NUMBER_CATEGORIES {
+            page[layout::HEADER_LEN + row * NUMBER_CATEGORIES + column] =
+                counter(row, column).to_le();
+        }
+    }
+    page[layout::VERSION_IDX] = layout::VERSION.to_le();
+    page[layout::NB_ROWS_IDX] = (nb_rows as u64).to_le();
+    page[layout::NB_COLUMNS_IDX] = (NUMBER_CATEGORIES as u64).to_le();
+    core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+    page[layout::MAGIC_IDX] = layout::MAGIC.to_le();
+
+    Ok(())
+}
+
+// ————————————————————————————————— Tests —————————————————————————————————— //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_page_layout() {
+        let mut page = [0xffff_u64; 32];
+        write_shared_page(&mut page, 2, |row, column| (row * 100 + column) as u64).unwrap();
+
+        assert_eq!(&page[..4], &[layout::MAGIC, layout::VERSION, 2, 13]);
+        assert_eq!(&page[4..17], &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+        assert_eq!(
+            &page[17..30],
+            &[
+                100, 101, 102, 103, 104, 105, 106, 107, 108, 109, 110, 111, 112
+            ]
+        );
+        assert_eq!(page[30], 0xffff, "Must not write past the table");
+        assert_eq!(
+            &page[0].to_le_bytes(),
+            b"MRLSBNCH",
+            "The magic must be readable as bytes"
+        );
+
+        // Tables larger than the page are rejected
+        assert!(write_shared_page(&mut page, 3, |_, _| 0).is_err());
+    }
+
+    /// The firmware inhibiting its counters must not stop the counters used by the benchmarks.
+    #[test]
+    fn guest_inhibited_counters() {
+        use crate::host::MiralisContext;
+        use crate::virt::traits::*;
+
+        let hw = unsafe { arch::detect_hardware() };
+        let mut mctx = MiralisContext::new(hw, 0x10000, 0x2000);
+        let mut ctx = VirtContext::new(0, mctx.hw.available_reg.nb_pmp, mctx.hw.extensions.clone());
+        let inhibit = mcountinhibit::CY_FILTER | mcountinhibit::IR_FILTER;
+
+        // Counters left inhibited at boot are re-enabled
+        unsafe {
+            arch::write_csr(Csr::Mcountinhibit, inhibit);
+            enable_counters();
+        }
+        assert_eq!(arch::read_csr(Csr::Mcountinhibit) & inhibit, 0);
+
+        // The firmware inhibits mcycle and minstret, only the virtual counters are affected
+        ctx.set_csr(Csr::Mcountinhibit, usize::MAX, &mut mctx);
+        assert_eq!(ctx.get(Csr::Mcountinhibit), inhibit);
+        assert_eq!(arch::read_csr(Csr::Mcountinhibit) & inhibit, 0);
+    }
+}
@@ -1,8 +1,14 @@
 //! Debug utils for Miralis
 
+use core::fmt;
+
 use crate::arch;
-use crate::arch::Csr;
-use crate::config::TARGET_STACK_SIZE;
+use crate::arch::{Csr, MCause, Register, TrapInfo};
+use crate::config::{DEBUG_STACK_CANARY_SIZE, TARGET_STACK_SIZE};
+use crate::decoder::IllegalInst;
+use crate::host::MiralisContext;
+use crate::virt::VirtContext;
+use crate::virt::traits::RegisterContextGetter;
 
 // ————————————————————————————— Logging Utils —————————————————————————————— //
 
@@ -90,8 +96,72 @@ unsafe fn get_max_stack_usage(stack_top: usize, stack_bottom: usize) -> usize {
     (len - counter) * PATTERN_SIZE
 }
 
+/// An overwritten stack canary.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StackCorruption {
+    hart_id: usize,
+    /// Address of the lowest overwritten word of the canary.
+    address: usize,
+    /// Number of overwritten words in the canary.
+    nb_words: usize,
+}
+
+impl fmt::Display for StackCorruption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Stack corruption on hart {}: {} canary words overwritten, lowest at 0x{:x}",
+            self.hart_id, self.nb_words, self.address
+        )
+    }
+}
+
+/// Checks the stack canary, that is the `canary_size` bytes at the bottom of the stack.
+///
+/// The canary lies below the high-water mark of a healthy stack, and must still hold the memory
+/// pattern written at boot. Returns the corruption if any word of the canary was overwritten.
+///
+/// # Safety
+///
+/// This function requires the canary to be valid memory, and that it is not mutated for the
+/// whole duration of the function.
+unsafe fn check_stack_canary(
+    stack_bottom: usize,
+    canary_size: usize,
+    hart_id: usize,
+) -> Option<StackCorruption> {
+    const PATTERN_SIZE: usize = core::mem::size_of::<u32>();
+
+    assert!(stack_bottom.is_multiple_of(4));
+
+    let canary_ptr = stack_bottom as *const u32;
+    let len = canary_size / PATTERN_SIZE;
+
+    // Read the canary
+    //
+    // # Safety
+    //
+    // The caller must ensure that the canary is valid and not mutated for the whole duration of
+    // the operation.
+    let canary = unsafe { core::slice::from_raw_parts(canary_ptr, len) };
+    let lowest = canary.iter().position(|data| *data != MEMORY_PATTERN)?;
+    let nb_words = canary[lowest..]
+        .iter()
+        .filter(|data| **data != MEMORY_PATTERN)
+        .count();
+
+    Some(StackCorruption {
+        hart_id,
+        address: stack_bottom + lowest * PATTERN_SIZE,
+        nb_words,
+    })
+}
+
 /// Display debug information related to maximal stack usage
 ///
+/// If `MIRALIS_DEBUG_STACK_CANARY_SIZE` is set, this function also checks the stack canary of the
+/// current hart and logs a warning if it was overwritten.
+///
 /// # Safety
 ///
 /// This function assumes the stack is not shared across cores.
@@ -99,15 +169,15 @@ pub unsafe fn log_stack_usage(stack_start: usize) {
     /// Percent usage threshold for emitting a warning.
     const WARNING_THRESHOLD: usize = 80;
 
+    let hart_id = arch::read_csr(Csr::Mhartid);
+    let stack_bottom = stack_start + hart_id * TARGET_STACK_SIZE;
+
     // Get stack usage
     //
     // # Safety:
     //
     // We rely on the correct computation of the stack size here.
     let max_stack_usage = unsafe {
-        let stack_bottom = stack_start;
-        let hart_id = arch::read_csr(Csr::Mhartid);
-        let stack_bottom = stack_bottom + hart_id * TARGET_STACK_SIZE;
         let stack_top = stack_bottom + TARGET_STACK_SIZE;
         get_max_stack_usage(stack_top, stack_bottom)
     };
@@ -135,4 +205,572 @@ pub unsafe fn log_stack_usage(stack_start: usize) {
             decimal
         );
     }
+
+    if let Some(canary_size) = DEBUG_STACK_CANARY_SIZE {
+        // SAFETY: the canary is part of the stack, as its size is capped by the stack size.
+        let corruption = unsafe {
+            check_stack_canary(stack_bottom, canary_size.min(TARGET_STACK_SIZE), hart_id)
+        };
+        if let Some(corruption) = corruption {
+            log::warn!("{}", corruption);
+        }
+    }
+}
+
+// —————————————————————————————— Trap History —————————————————————————————— //
+
+/// Number of traps kept in the [TrapHistory].
+const TRAP_HISTORY_SIZE: usize = 8;
+
+/// A summary of a trap, as recorded in the [TrapHistory].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TrapRecord {
+    pub mcause: usize,
+    pub mepc: usize,
+    pub mtval: usize,
+}
+
+/// A ring buffer of the most recent traps handled by Miralis.
+///
+/// The history is displayed as part of the [CrashReport], which helps understanding what led to
+/// an unexpected trap. Traps are only recorded when `MIRALIS_DEBUG_TRAP_HISTORY` is enabled.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrapHistory {
+    records: [TrapRecord; TRAP_HISTORY_SIZE],
+    /// Total number of recorded traps.
+    count: usize,
+}
+
+impl TrapHistory {
+    pub const fn new() -> Self {
+        TrapHistory {
+            records: [TrapRecord {
+                mcause: 0,
+                mepc: 0,
+                mtval: 0,
+            }; TRAP_HISTORY_SIZE],
+            count: 0,
+        }
+    }
+
+    /// Record a new trap, overwritting the oldest one if the history is full.
+    pub fn record(&mut self, trap: &TrapInfo) {
+        self.records[self.count % TRAP_HISTORY_SIZE] = TrapRecord {
+            mcause: trap.mcause,
+            mepc: trap.mepc,
+            mtval: trap.mtval,
+        };
+        self.count += 1;
+    }
+
+    /// Returns an iterator over the recorded traps, from the oldest to the most recent.
+    pub fn iter(&self) -> impl Iterator<Item = &TrapRecord> {
+        let len = self.count.min(TRAP_HISTORY_SIZE);
+        let start = self.count - len;
+        (start..self.count).map(|idx| &self.records[idx % TRAP_HISTORY_SIZE])
+    }
+}
+
+impl Default for TrapHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// —————————————————————————————— Crash Report —————————————————————————————— //
+
+/// A structured report of the Miralis and virtual contexts, displayed when Miralis crashes.
+///
+/// The report is made of one `key: value` pair per line, enclosed between a start and an end
+/// marker, so that it can easily be extracted from the console output and parsed.
+pub struct CrashReport<'a> {
+    ctx: &'a VirtContext,
+    mctx: &'a MiralisContext,
+}
+
+impl<'a> CrashReport<'a> {
+    /// Marker emitted before the report.
+    pub const START_MARKER: &'static str = "--- Miralis crash report ---";
+    /// Marker emitted after the report.
+    pub const END_MARKER: &'static str = "--- End of crash report ---";
+
+    pub fn new(ctx: &'a VirtContext, mctx: &'a MiralisContext) -> Self {
+        CrashReport { ctx, mctx }
+    }
+}
+
+impl fmt::Display for CrashReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ctx = self.ctx;
+        let trap = &ctx.trap_info;
+        writeln!(f, "{}", Self::START_MARKER)?;
+
+        // Trap that caused the crash
+        writeln!(f, "hart: {}", ctx.hart_id)?;
+        writeln!(
+            f,
+            "trap.mcause: 0x{:x} ({:?})",
+            trap.mcause,
+            trap.get_cause()
+        )?;
+        writeln!(f, "trap.mepc: 0x{:x}", trap.mepc)?;
+        writeln!(f, "trap.mtval: 0x{:x}", trap.mtval)?;
+        writeln!(f, "trap.mstatus: 0x{:x}", trap.mstatus)?;
+        writeln!(f, "trap.mip: 0x{:x}", trap.mip)?;
+
+        // Virtual context
+        writeln!(f, "vcpu.mode: {:?}", ctx.mode)?;
+        writeln!(f, "vcpu.pc: 0x{:x}", ctx.pc)?;
+        writeln!(f, "vcpu.exits: {}", ctx.nb_exits)?;
+        for idx in 1..32 {
+            writeln!(f, "vcpu.x{}: 0x{:x}", idx, ctx.regs[idx])?;
+        }
+        let csrs = [
+            ("mstatus", ctx.csr.mstatus),
+            ("mie", ctx.csr.mie),
+            ("mip", ctx.csr.mip),
+            ("mtvec", ctx.csr.mtvec),
+            ("mepc", ctx.csr.mepc),
+            ("mcause", ctx.csr.mcause),
+            ("mtval", ctx.csr.mtval),
+            ("medeleg", ctx.csr.medeleg),
+            ("mideleg", ctx.csr.mideleg),
+            ("satp", ctx.csr.satp),
+        ];
+        for (name, value) in csrs {
+            writeln!(f, "vcpu.{}: 0x{:x}", name, value)?;
+        }
+        writeln!(f, "vcpu.satp_mode: {:?}", ctx.current_satp_mode())?;
+
+        // Physical PMP layout
+        let pmp = &self.mctx.pmp;
+        writeln!(f, "pmp.count: {}", pmp.nb_pmp)?;
+        writeln!(f, "pmp.virt_offset: {}", pmp.virt_pmp_offset)?;
+        for idx in 0..(pmp.nb_pmp as usize) {
+            writeln!(
+                f,
+                "pmp.{}: addr=0x{:x} cfg=0x{:02x}",
+                idx,
+                pmp.pmpaddr()[idx],
+                pmp.get_pmpcfg(idx)
+            )?;
+        }
+
+        // Recent traps, from the oldest to the most recent
+        for (idx, record) in ctx.trap_history.iter().enumerate() {
+            writeln!(
+                f,
+                "history.{}: mcause=0x{:x} ({:?}) mepc=0x{:x} mtval=0x{:x}",
+                idx,
+                record.mcause,
+                MCause::new(record.mcause),
+                record.mepc,
+                record.mtval
+            )?;
+        }
+
+        write!(f, "{}", Self::END_MARKER)
+    }
+}
+
+// ——————————————————————————————— Entry Dump ——————————————————————————————— //
+
+/// The register state handed over to the firmware, displayed right before its first instruction.
+///
+/// Enabled with `MIRALIS_BREAK_ON_ENTRY`, this gives a clean inspection point for firmware
+/// bring-up. The format follows the [CrashReport] one.
+pub struct EntryDump<'a> {
+    ctx: &'a VirtContext,
+}
+
+impl<'a> EntryDump<'a> {
+    /// Marker emitted before the dump.
+    pub const START_MARKER: &'static str = "--- Miralis firmware entry ---";
+    /// Marker emitted after the dump.
+    pub const END_MARKER: &'static str = "--- End of firmware entry ---";
+
+    pub fn new(ctx: &'a VirtContext) -> Self {
+        EntryDump { ctx }
+    }
+}
+
+impl fmt::Display for EntryDump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ctx = self.ctx;
+        writeln!(f, "{}", Self::START_MARKER)?;
+        writeln!(f, "hart: {}", ctx.hart_id)?;
+        writeln!(f, "vcpu.mode: {:?}", ctx.mode)?;
+        writeln!(f, "vcpu.pc: 0x{:x}", ctx.pc)?;
+        for idx in 1..32 {
+            writeln!(f, "vcpu.x{}: 0x{:x}", idx, ctx.regs[idx])?;
+        }
+        writeln!(f, "vcpu.misa: 0x{:x}", ctx.csr.misa)?;
+        writeln!(f, "vcpu.mstatus: 0x{:x}", ctx.csr.mstatus)?;
+        write!(f, "{}", Self::END_MARKER)
+    }
+}
+
+// ——————————————————————————————— CSR Writes ——————————————————————————————— //
+
+/// A CSR write from the firmware, with the value of the CSR before and after the write.
+///
+/// Logged for every CSR write when `MIRALIS_TRACE_CSR` is enabled, the new value is the one
+/// after legalization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsrWrite {
+    pub csr: Csr,
+    pub old: usize,
+    pub new: usize,
+}
+
+impl CsrWrite {
+    pub fn new(csr: Csr, old: usize, new: usize) -> Self {
+        CsrWrite { csr, old, new }
+    }
+}
+
+impl fmt::Display for CsrWrite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "csr write: {:?} 0x{:x} -> 0x{:x}",
+            self.csr, self.old, self.new
+        )
+    }
+}
+
+// ——————————————————————————— Instruction Trace ———————————————————————————— //
+
+/// An instruction trapped and emulated by Miralis, with the values of its source registers.
+///
+/// Logged for every emulated instruction when `MIRALIS_TRACE_INSTRUCTIONS` is enabled, which
+/// turns Miralis into a coarse tracer of the privileged instructions of the firmware.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmulatedInstr {
+    pub pc: usize,
+    pub instr: IllegalInst,
+    /// The source registers other than `zero`, with their values before emulation.
+    pub sources: [Option<(Register, usize)>; 2],
+}
+
+impl EmulatedInstr {
+    pub fn new(ctx: &VirtContext, instr: &IllegalInst) -> Self {
+        let source = |reg: Register| (reg != Register::X0).then(|| (reg, ctx.get(reg)));
+        let sources = match instr {
+            IllegalInst::Csrrw { rs1, .. }
+            | IllegalInst::Csrrs { rs1, .. }
+            | IllegalInst::Csrrc { rs1, .. } => [source(*rs1), None],
+            IllegalInst::Sfencevma { rs1, rs2 }
+            | IllegalInst::Hfencevvma { rs1, rs2 }
+            | IllegalInst::Hfencegvma { rs1, rs2 }
+            | IllegalInst::Sinvalvma { rs1, rs2 } => [source(*rs1), source(*rs2)],
+            IllegalInst::Hlv(load) => [source(load.rs1), None],
+            IllegalInst::Hsv(store) => [source(store.rs1), source(store.rs2)],
+            _ => [None, None],
+        };
+
+        EmulatedInstr {
+            pc: ctx.pc,
+            instr: instr.clone(),
+            sources,
+        }
+    }
+}
+
+impl fmt::Display for EmulatedInstr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:x}: {}", self.pc, self.instr)?;
+        let mut sources = self.sources.iter().flatten().peekable();
+        if sources.peek().is_some() {
+            write!(f, "  #")?;
+        }
+        for (reg, value) in sources {
+            write!(f, " {}=0x{:x}", reg, value)?;
+        }
+        Ok(())
+    }
+}
+
+// ——————————————————————————— Progress Watchdog ———————————————————————————— //
+
+/// Number of distinct addresses a guest can trap from without being considered making progress.
+const PROGRESS_WATCHDOG_SIZE: usize = 4;
+
+/// Detects guests that make no progress for a given amount of `mtime`.
+///
+/// A guest is considered stuck while all its traps come from a small set of addresses, such as a
+/// loop spinning on `wfi` or polling a device. Trapping from an address outside of that set
+/// counts as progress and restarts the timeout. This catches livelocks that trap too rarely to
+/// hit the maximum number of exits.
+#[derive(Clone, Copy, Debug)]
+pub struct ProgressWatchdog {
+    /// Number of `mtime` ticks without progress before the watchdog fires.
+    timeout: usize,
+    /// Frequency of `mtime`, in Hz.
+    frequency: usize,
+    /// The `mtime` value at the last progress, if any.
+    last_progress: Option<usize>,
+    /// The addresses trapped from since the last progress.
+    addresses: [usize; PROGRESS_WATCHDOG_SIZE],
+    nb_addresses: usize,
+}
+
+impl ProgressWatchdog {
+    /// Creates a watchdog firing after `timeout_ms` milliseconds without progress, for an `mtime`
+    /// running at `frequency` Hz.
+    pub const fn new(timeout_ms: usize, frequency: usize) -> Self {
+        let timeout = timeout_ms * (frequency / 1000);
+        ProgressWatchdog {
+            timeout: if timeout > 0 { timeout } else { 1 },
+            frequency,
+            last_progress: None,
+            addresses: [0; PROGRESS_WATCHDOG_SIZE],
+            nb_addresses: 0,
+        }
+    }
+
+    /// Records a trap from address `pc` at time `now`.
+    ///
+    /// Returns a diagnostic if the guest made no progress within the timeout, and None otherwise.
+    pub fn sample(&mut self, pc: usize, now: usize) -> Option<NoProgress> {
+        let Some(last_progress) = self.last_progress else {
+            self.restart(pc, now);
+            return None;
+        };
+
+        let addresses = &self.addresses[..self.nb_addresses];
+        if !addresses.contains(&pc) {
+            if self.nb_addresses == PROGRESS_WATCHDOG_SIZE {
+                self.restart(pc, now);
+                return None;
+            }
+            self.addresses[self.nb_addresses] = pc;
+            self.nb_addresses += 1;
+        }
+
+        let elapsed = now.wrapping_sub(last_progress);
+        if elapsed < self.timeout {
+            return None;
+        }
+
+        Some(NoProgress {
+            elapsed_ms: (elapsed as u128 * 1000 / self.frequency as u128) as usize,
+            addresses: self.addresses,
+            nb_addresses: self.nb_addresses,
+        })
+    }
+
+    fn restart(&mut self, pc: usize, now: usize) {
+        self.last_progress = Some(now);
+        self.addresses[0] = pc;
+        self.nb_addresses = 1;
+    }
+}
+
+/// The diagnostic of a guest stuck within a set of addresses.
+#[derive(Clone, Copy, Debug)]
+pub struct NoProgress {
+    elapsed_ms: usize,
+    addresses: [usize; PROGRESS_WATCHDOG_SIZE],
+    nb_addresses: usize,
+}
+
+impl fmt::Display for NoProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "No progress for {} ms, stuck at", self.elapsed_ms)?;
+        for (idx, pc) in self.addresses[..self.nb_addresses].iter().enumerate() {
+            let separator = if idx == 0 { "" } else { "," };
+            write!(f, "{} 0x{:x}", separator, pc)?;
+        }
+        Ok(())
+    }
+}
+
+// ————————————————————————————————— Tests —————————————————————————————————— //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::{MCause, mstatus};
+
+    #[test]
+    fn trap_history() {
+        let mut history = TrapHistory::new();
+        assert_eq!(history.iter().count(), 0);
+
+        let mut trap = TrapInfo::default();
+        for idx in 0..(TRAP_HISTORY_SIZE + 3) {
+            trap.mepc = idx;
+            history.record(&trap);
+        }
+
+        // Only the most recent traps are kept, oldest first
+        let mepcs: Vec<usize> = history.iter().map(|record| record.mepc).collect();
+        let expected: Vec<usize> = (3..(TRAP_HISTORY_SIZE + 3)).collect();
+        assert_eq!(mepcs, expected);
+    }
+
+    #[test]
+    fn stack_canary() {
+        let mut stack = [MEMORY_PATTERN; 64];
+        let bottom = stack.as_ptr() as usize;
+        let check =
+            |stack: &[u32; 64]| unsafe { check_stack_canary(stack.as_ptr() as usize, 64, 1) };
+
+        // A used stack, with the canary intact
+        stack[32..].fill(0);
+        assert_eq!(check(&stack), None);
+
+        // Overwrite the canary, below the high-water mark
+        stack[3] = 0xdead;
+        stack[7] = 0xbeef;
+        let corruption = check(&stack).expect("Corruption not detected");
+        assert_eq!(
+            corruption,
+            StackCorruption {
+                hart_id: 1,
+                address: bottom + 12,
+                nb_words: 2,
+            }
+        );
+        assert_eq!(
+            format!("{}", corruption),
+            format!(
+                "Stack corruption on hart 1: 2 canary words overwritten, lowest at 0x{:x}",
+                bottom + 12
+            )
+        );
+    }
+
+    #[test]
+    fn crash_report() {
+        let hw = unsafe { arch::detect_hardware() };
+        let mctx = MiralisContext::new(hw, 0x10000, 0x2000);
+        let mut ctx = VirtContext::new(0, mctx.hw.available_reg.nb_pmp, mctx.hw.extensions.clone());
+
+        // A trap from the firmware, followed by a trap from Miralis itself
+        ctx.trap_info.mcause = MCause::IllegalInstr as usize;
+        ctx.trap_info.mepc = 0x80000042;
+        ctx.trap_history.record(&ctx.trap_info.clone());
+        ctx.trap_info.mcause = MCause::LoadAccessFault as usize;
+        ctx.trap_info.mepc = 0x80001234;
+        ctx.trap_info.mtval = 0xdeadbeef;
+        ctx.trap_info.mstatus = mstatus::MPP_FILTER;
+        assert!(ctx.trap_info.is_from_mmode());
+
+        let report = format!("{}", CrashReport::new(&ctx, &mctx));
+        assert!(report.starts_with(CrashReport::START_MARKER));
+        assert!(report.ends_with(CrashReport::END_MARKER));
+        for field in [
+            "trap.mcause: 0x5 (",
+            "trap.mepc: 0x80001234",
+            "trap.mtval: 0xdeadbeef",
+            "vcpu.mode: M",
+            "vcpu.mstatus: 0x",
+            "pmp.count: ",
+            "pmp.0: addr=0x",
+            "history.0: mcause=0x2 (",
+        ] {
+            assert!(report.contains(field), "Missing field: {}", field);
+        }
+    }
+
+    #[test]
+    fn csr_write_trace() {
+        let hw = unsafe { arch::detect_hardware() };
+        let mut mctx = MiralisContext::new(hw, 0x10000, 0x2000);
+        let mut ctx = VirtContext::new(0, mctx.hw.available_reg.nb_pmp, mctx.hw.extensions.clone());
+
+        let trace: Vec<String> = [
+            (Csr::Mscratch, 0x42),
+            (Csr::Mscratch, 0x43),
+            (Csr::Mepc, 0x80000001), // The lowest bit is not writable
+            (Csr::Mstatus, 0b10 << mstatus::MPP_OFFSET), // Illegal MPP value
+        ]
+        .into_iter()
+        .map(|(csr, value)| ctx.set_csr_traced(csr, value, &mut mctx).to_string())
+        .collect();
+
+        assert_eq!(
+            trace,
+            [
+                "csr write: Mscratch 0x0 -> 0x42",
+                "csr write: Mscratch 0x42 -> 0x43",
+                "csr write: Mepc 0x0 -> 0x80000000",
+                "csr write: Mstatus 0x0 -> 0xa00000000",
+            ]
+        );
+    }
+
+    #[test]
+    fn instruction_trace() {
+        use crate::virt::traits::RegisterContextSetter;
+
+        let hw = unsafe { arch::detect_hardware() };
+        let mctx = MiralisContext::new(hw, 0x10000, 0x2000);
+        let mut ctx = VirtContext::new(0, mctx.hw.available_reg.nb_pmp, mctx.hw.extensions.clone());
+        ctx.pc = 0x80000100;
+        ctx.set(Register::X5, 0x1888);
+        ctx.set(Register::X11, 0x42);
+
+        let trace: Vec<String> = [
+            0x30029573, // csrrw a0, mstatus, t0
+            0x34059073, // csrrw zero, mscratch, a1
+            0x3a0025f3, // csrrs a1, pmpcfg0, zero
+            0x3045f073, // csrrci zero, mie, 11
+            0x30200073, // mret
+        ]
+        .into_iter()
+        .map(|raw| {
+            let instr = mctx.decode_illegal_instruction(raw);
+            EmulatedInstr::new(&ctx, &instr).to_string()
+        })
+        .collect();
+
+        assert_eq!(
+            trace,
+            [
+                "0x80000100: csrrw a0, mstatus, t0  # t0=0x1888",
+                "0x80000100: csrrw zero, mscratch, a1  # a1=0x42",
+                "0x80000100: csrrs a1, pmpcfg0, zero",
+                "0x80000100: csrrci zero, mie, 11",
+                "0x80000100: mret",
+            ]
+        );
+    }
+
+    #[test]
+    fn progress_watchdog() {
+        // Time out after 10ms with a 10 MHz timebase
+        let mut watchdog = ProgressWatchdog::new(10, 10_000_000);
+
+        // A guest making progress, trapping from a new address every 1ms
+        for idx in 0..100 {
+            assert!(
+                watchdog
+                    .sample(0x80000000 + 4 * idx, idx * 10_000)
+                    .is_none()
+            );
+        }
+
+        // A guest spinning on `wfi`, waking up from a timer interrupt every 1ms
+        let start = 100 * 10_000;
+        let mut diagnostic = None;
+        for idx in 0..20 {
+            let pc = 0x80000400 + 4 * (idx % 2);
+            if let Some(no_progress) = watchdog.sample(pc, start + idx * 10_000) {
+                diagnostic = Some((idx, no_progress.to_string()));
+                break;
+            }
+        }
+
+        assert_eq!(
+            diagnostic,
+            Some((
+                10,
+                String::from("No progress for 10 ms, stuck at 0x80000400, 0x80000404")
+            )),
+            "Expected the watchdog to fire after 10ms of spinning"
+        );
+    }
 }
@@ -1,14 +1,32 @@
 //! RISC-V instruction decoder
-use crate::arch::{Csr, Register, Width, csr};
+use core::fmt;
+
+use crate::arch::{BarrierKind, Csr, Register, Width, csr};
 use crate::host::MiralisContext;
-use crate::logger;
 use crate::platform::{Plat, Platform};
 use crate::utils::bits_to_int;
+use crate::{config, logger};
 
 const ILLEGAL_OPCODE_MASK: usize = 0b1110011;
+const MISC_MEM_OPCODE_MASK: usize = 0b0001111;
 const SFENCE_INSTR_VMA_MASK: usize = 0b0001001 << 25;
 const HFENCE_INSTR_VVMA_MASK: usize = 0b0010001 << 25;
 const HFENCE_INSTR_GVMA_MASK: usize = 0b0110001 << 25;
+/// The funct7 of the Svinval `sinval.vma` instruction
+const SINVAL_INSTR_VMA_MASK: usize = 0b0001011 << 25;
+
+/// Atomic memory operation opcode
+const AMO_OPCODE_MASK: usize = 0b0101111;
+/// The funct5 of the atomic compare-and-swap instructions (Zacas)
+const AMOCAS_FUNCT5: usize = 0b00101;
+
+/// Integer register-register operation opcode
+const OP_OPCODE_MASK: usize = 0b0110011;
+/// The funct7 of the conditional-zero instructions (Zicond)
+const CZERO_FUNCT7: usize = 0b0000111;
+
+/// The funct3 of the hypervisor virtual-machine load and store instructions
+const HLV_HSV_FUNC3: usize = 0b100 << 12;
 
 const RS1_RS1_INSTR_TYPE_MASK: usize = 0b1111111111000000001111111;
 const FUNC3_MASK: usize = 0b111000000000000;
@@ -78,6 +96,25 @@ pub enum IllegalInst {
         rs1: Register,
         rs2: Register,
     },
+    /// Fine-grained address-translation cache invalidation (Svinval)
+    Sinvalvma {
+        rs1: Register,
+        rs2: Register,
+    },
+    /// Orders the preceding stores before the following `sinval.vma` (Svinval)
+    Sfencewinval,
+    /// Orders the preceding `sinval.vma` before the following implicit references (Svinval)
+    Sfenceinvalir,
+    /// Wait on reservation set, without timeout (Zawrs)
+    Wrsnto,
+    /// Wait on reservation set, with a short timeout (Zawrs)
+    Wrssto,
+    /// Hypervisor virtual-machine load (`hlv`)
+    Hlv(LoadInstr),
+    /// Hypervisor virtual-machine store (`hsv`)
+    Hsv(StoreInstr),
+    /// Memory ordering fence
+    Fence(BarrierKind),
     Unknown,
 }
 
@@ -102,6 +139,106 @@ pub struct StoreInstr {
     pub is_compressed: bool,
 }
 
+/// An atomic compare-and-swap instruction (Zacas).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AmoCasInstr {
+    pub rd: Register,
+    pub rs1: Register,
+    pub rs2: Register,
+    /// Size of the memory operand, in bytes (4, 8 or 16).
+    pub len: usize,
+    pub aq: bool,
+    pub rl: bool,
+}
+
+/// The conditional-zero operations (Zicond).
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ZicondOp {
+    /// `czero.eqz`: rd is zero if rs2 is zero, rs1 otherwise.
+    CzeroEqz,
+    /// `czero.nez`: rd is zero if rs2 is not zero, rs1 otherwise.
+    CzeroNez,
+}
+
+/// A conditional-zero instruction (Zicond).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ZicondInstr {
+    pub rd: Register,
+    pub rs1: Register,
+    pub rs2: Register,
+    pub op: ZicondOp,
+}
+
+// —————————————————————————————— Disassembly ——————————————————————————————— //
+
+impl fmt::Display for IllegalInst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IllegalInst::Wfi => write!(f, "wfi"),
+            IllegalInst::Csrrw { csr, rd, rs1 } => write!(f, "csrrw {}, {}, {}", rd, csr, rs1),
+            IllegalInst::Csrrs { csr, rd, rs1 } => write!(f, "csrrs {}, {}, {}", rd, csr, rs1),
+            IllegalInst::Csrrc { csr, rd, rs1 } => write!(f, "csrrc {}, {}, {}", rd, csr, rs1),
+            IllegalInst::Csrrwi { csr, rd, uimm } => {
+                write!(f, "csrrwi {}, {}, {}", rd, csr, uimm)
+            }
+            IllegalInst::Csrrsi { csr, rd, uimm } => {
+                write!(f, "csrrsi {}, {}, {}", rd, csr, uimm)
+            }
+            IllegalInst::Csrrci { csr, rd, uimm } => {
+                write!(f, "csrrci {}, {}, {}", rd, csr, uimm)
+            }
+            IllegalInst::Mret => write!(f, "mret"),
+            IllegalInst::Sret => write!(f, "sret"),
+            IllegalInst::Sfencevma { rs1, rs2 } => write!(f, "sfence.vma {}, {}", rs1, rs2),
+            IllegalInst::Hfencevvma { rs1, rs2 } => write!(f, "hfence.vvma {}, {}", rs1, rs2),
+            IllegalInst::Hfencegvma { rs1, rs2 } => write!(f, "hfence.gvma {}, {}", rs1, rs2),
+            IllegalInst::Sinvalvma { rs1, rs2 } => write!(f, "sinval.vma {}, {}", rs1, rs2),
+            IllegalInst::Sfencewinval => write!(f, "sfence.w.inval"),
+            IllegalInst::Sfenceinvalir => write!(f, "sfence.inval.ir"),
+            IllegalInst::Wrsnto => write!(f, "wrs.nto"),
+            IllegalInst::Wrssto => write!(f, "wrs.sto"),
+            IllegalInst::Hlv(instr) => {
+                let unsigned = if instr.is_unsigned { "u" } else { "" };
+                let suffix = width_suffix(instr.len);
+                write!(
+                    f,
+                    "hlv.{}{} {}, ({})",
+                    suffix, unsigned, instr.rd, instr.rs1
+                )
+            }
+            IllegalInst::Hsv(instr) => {
+                let suffix = width_suffix(instr.len);
+                write!(f, "hsv.{} {}, ({})", suffix, instr.rs2, instr.rs1)
+            }
+            IllegalInst::Fence(kind) => match kind {
+                BarrierKind::RwRw => write!(f, "fence rw, rw"),
+                BarrierKind::RRw => write!(f, "fence r, rw"),
+                BarrierKind::RR => write!(f, "fence r, r"),
+                BarrierKind::RwW => write!(f, "fence rw, w"),
+                BarrierKind::WW => write!(f, "fence w, w"),
+                BarrierKind::WRw => write!(f, "fence w, rw"),
+                BarrierKind::RwR => write!(f, "fence rw, r"),
+                BarrierKind::RW => write!(f, "fence r, w"),
+                BarrierKind::WR => write!(f, "fence w, r"),
+                BarrierKind::Tso => write!(f, "fence.tso"),
+                BarrierKind::IoRwIoRw => write!(f, "fence iorw, iorw"),
+                BarrierKind::None => write!(f, "fence (no-op)"),
+            },
+            IllegalInst::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// Returns the size suffix of a load or store mnemonic.
+fn width_suffix(width: Width) -> &'static str {
+    match width {
+        Width::Byte => "b",
+        Width::Byte2 => "h",
+        Width::Byte4 => "w",
+        Width::Byte8 => "d",
+    }
+}
+
 impl MiralisContext {
     /// Decodes a raw read RISC-V instruction.
     pub fn decode_load(&self, raw: usize) -> LoadInstr {
@@ -127,8 +264,59 @@ impl MiralisContext {
         }
     }
 
+    /// Decodes an atomic compare-and-swap instruction (`amocas.w/d/q`).
+    ///
+    /// Returns `None` if the instruction is not an atomic compare-and-swap.
+    pub fn decode_amocas(&self, raw: usize) -> Option<AmoCasInstr> {
+        if raw & 0b1111111 != AMO_OPCODE_MASK || (raw >> 27) & 0b11111 != AMOCAS_FUNCT5 {
+            return None;
+        }
+
+        let len = match raw & FUNC3_MASK {
+            0x2000 => 4,
+            0x3000 => 8,
+            0x4000 => 16,
+            _ => return None,
+        };
+
+        Some(AmoCasInstr {
+            rd: Register::from((raw >> 7) & 0b11111),
+            rs1: Register::from((raw >> 15) & 0b11111),
+            rs2: Register::from((raw >> 20) & 0b11111),
+            len,
+            aq: (raw >> 26) & 0b1 == 1,
+            rl: (raw >> 25) & 0b1 == 1,
+        })
+    }
+
+    /// Decodes a conditional-zero instruction (`czero.eqz` or `czero.nez`).
+    ///
+    /// Returns `None` if the instruction is not a conditional-zero.
+    pub fn decode_zicond(&self, raw: usize) -> Option<ZicondInstr> {
+        if raw & 0b1111111 != OP_OPCODE_MASK || (raw >> 25) & 0b1111111 != CZERO_FUNCT7 {
+            return None;
+        }
+
+        let op = match raw & FUNC3_MASK {
+            0x5000 => ZicondOp::CzeroEqz,
+            0x7000 => ZicondOp::CzeroNez,
+            _ => return None,
+        };
+
+        Some(ZicondInstr {
+            rd: Register::from((raw >> 7) & 0b11111),
+            rs1: Register::from((raw >> 15) & 0b11111),
+            rs2: Register::from((raw >> 20) & 0b11111),
+            op,
+        })
+    }
+
     /// Decodes a raw illegal instruction
     pub fn decode_illegal_instruction(&self, raw_instr: usize) -> IllegalInst {
+        if raw_instr & 0b1111111 == MISC_MEM_OPCODE_MASK {
+            return self.decode_fence(raw_instr);
+        }
+
         assert_eq!(
             raw_instr & 0b1111111,
             ILLEGAL_OPCODE_MASK,
@@ -140,6 +328,10 @@ impl MiralisContext {
             0b00010000010100000000000001110011 => return IllegalInst::Wfi,
             0b00110000001000000000000001110011 => return IllegalInst::Mret,
             0b00010000001000000000000001110011 => return IllegalInst::Sret,
+            0b00011000000000000000000001110011 => return IllegalInst::Sfencewinval,
+            0b00011000000100000000000001110011 => return IllegalInst::Sfenceinvalir,
+            0b00000000110100000000000001110011 => return IllegalInst::Wrsnto,
+            0b00000001110100000000000001110011 => return IllegalInst::Wrssto,
             _ => {}
         }
 
@@ -151,9 +343,14 @@ impl MiralisContext {
             SFENCE_INSTR_VMA_MASK => return IllegalInst::Sfencevma { rs1, rs2 },
             HFENCE_INSTR_VVMA_MASK => return IllegalInst::Hfencevvma { rs1, rs2 },
             HFENCE_INSTR_GVMA_MASK => return IllegalInst::Hfencegvma { rs1, rs2 },
+            SINVAL_INSTR_VMA_MASK => return IllegalInst::Sinvalvma { rs1, rs2 },
             _ => {}
         }
 
+        if raw_instr & FUNC3_MASK == HLV_HSV_FUNC3 {
+            return self.decode_hypervisor_mem(raw_instr);
+        }
+
         let csr = self.decode_csr((raw_instr >> 20) & 0b111111111111);
         let rd = Register::from((raw_instr >> 7) & 0b11111);
 
@@ -168,6 +365,68 @@ impl MiralisContext {
         }
     }
 
+    /// Decodes a hypervisor virtual-machine load (`hlv`) or store (`hsv`).
+    ///
+    /// Those instructions have no offset, the address is the value of rs1. The width is encoded
+    /// in bits 26:25, bit 25 distinguishes stores. For loads, rs2 encodes whether the value is
+    /// zero-extended, while `hlvx` (rs2 = 3) is not supported.
+    fn decode_hypervisor_mem(&self, raw_instr: usize) -> IllegalInst {
+        let funct7 = raw_instr >> 25;
+        if funct7 & 0b1111000 != 0b0110000 {
+            return IllegalInst::Unknown;
+        }
+
+        let rs2 = (raw_instr >> 20) & 0b11111;
+        let rs1 = Register::from((raw_instr >> 15) & 0b11111);
+        let rd = (raw_instr >> 7) & 0b11111;
+        let len = Width::from(8 << ((funct7 >> 1) & 0b11));
+
+        if funct7 & 0b1 == 1 {
+            if rd != 0 {
+                return IllegalInst::Unknown;
+            }
+            return IllegalInst::Hsv(StoreInstr {
+                rs2: Register::from(rs2),
+                rs1,
+                imm: 0,
+                len,
+                is_compressed: false,
+            });
+        }
+
+        let is_unsigned = match (rs2, len) {
+            (0, _) => false,
+            // There is no zero-extended double word load
+            (1, Width::Byte8) => return IllegalInst::Unknown,
+            (1, _) => true,
+            _ => return IllegalInst::Unknown,
+        };
+        IllegalInst::Hlv(LoadInstr {
+            rd: Register::from(rd),
+            rs1,
+            imm: 0,
+            len,
+            is_compressed: false,
+            is_unsigned,
+        })
+    }
+
+    /// Decodes a FENCE instruction from the MISC-MEM opcode.
+    ///
+    /// The rs1 and rd fields are reserved for finer-grained fences and are ignored, as required
+    /// by the specification.
+    fn decode_fence(&self, raw_instr: usize) -> IllegalInst {
+        if raw_instr & FUNC3_MASK != 0 {
+            // FENCE.I and the CBO instructions are not emulated
+            return IllegalInst::Unknown;
+        }
+
+        let fm = (raw_instr >> 28) & 0b1111;
+        let pred = (raw_instr >> 24) & 0b1111;
+        let succ = (raw_instr >> 20) & 0b1111;
+        IllegalInst::Fence(BarrierKind::from_fence_sets(fm, pred, succ))
+    }
+
     fn decode_register_based_compressed_load(&self, raw: usize) -> LoadInstr {
         let rd = (raw >> 2) & 0b111;
         let rs1 = (raw >> 7) & 0b111;
@@ -386,40 +645,63 @@ impl MiralisContext {
             }
             csr::PMPADDR0..=csr::PMPADDR63 => Csr::Pmpaddr(csr - csr::PMPADDR0),
             csr::MCYCLE => {
-                if self.hw.extensions.has_zicntr {
+                if self.hw.extensions.has_zicntr || config::VCPU_VIRTUALIZE_ZICNTR {
                     Csr::Mcycle
                 } else {
                     Csr::Unknown
                 }
             }
             csr::MINSTRET => {
-                if self.hw.extensions.has_zicntr {
+                if self.hw.extensions.has_zicntr || config::VCPU_VIRTUALIZE_ZICNTR {
                     Csr::Minstret
                 } else {
                     Csr::Unknown
                 }
             }
             csr::CYCLE => {
-                if self.hw.extensions.has_zicntr {
+                if self.hw.extensions.has_zicntr || config::VCPU_VIRTUALIZE_ZICNTR {
                     Csr::Cycle
                 } else {
                     Csr::Unknown
                 }
             }
             csr::TIME => {
-                if self.hw.extensions.has_zicntr {
+                if self.hw.extensions.has_zicntr || config::VCPU_VIRTUALIZE_ZICNTR {
                     Csr::Time
                 } else {
                     Csr::Unknown
                 }
             }
             csr::INSTRET => {
-                if self.hw.extensions.has_zicntr {
+                if self.hw.extensions.has_zicntr || config::VCPU_VIRTUALIZE_ZICNTR {
                     Csr::Instret
                 } else {
                     Csr::Unknown
                 }
             }
+            // The upper halves of the counters only exist on RV32, we expose them as read-only
+            // zero for forward compatibility
+            csr::CYCLEH => {
+                if self.hw.extensions.has_zicntr || config::VCPU_VIRTUALIZE_ZICNTR {
+                    Csr::Cycleh
+                } else {
+                    Csr::Unknown
+                }
+            }
+            csr::TIMEH => {
+                if self.hw.extensions.has_zicntr || config::VCPU_VIRTUALIZE_ZICNTR {
+                    Csr::Timeh
+                } else {
+                    Csr::Unknown
+                }
+            }
+            csr::INSTRETH => {
+                if self.hw.extensions.has_zicntr || config::VCPU_VIRTUALIZE_ZICNTR {
+                    Csr::Instreth
+                } else {
+                    Csr::Unknown
+                }
+            }
             csr::MHPMCOUNTER3..=csr::MHPMCOUNTER31 => {
                 // Mhpm counters start at 3 and end at 31 : we shift them by 3 to start at 0 and end at 29
                 if self.hw.extensions.has_zihpm_extension {
@@ -512,13 +794,7 @@ impl MiralisContext {
                     Csr::Tdata3
                 }
             }
-            csr::MCONTEXT => {
-                if true {
-                    Csr::Unknown
-                } else {
-                    Csr::Mcontext
-                }
-            }
+            csr::MCONTEXT => Csr::Mcontext,
             csr::DCSR => {
                 if true {
                     Csr::Unknown
@@ -814,6 +1090,16 @@ impl MiralisContext {
                 }
             }
 
+            // Floating-point extension, unless the F registers are replaced by Zfinx
+            csr::FFLAGS | csr::FRM | csr::FCSR
+                if !self.hw.extensions.has_f_extension || self.hw.extensions.has_zfinx =>
+            {
+                Csr::Unknown
+            }
+            csr::FFLAGS => Csr::Fflags,
+            csr::FRM => Csr::Frm,
+            csr::FCSR => Csr::Fcsr,
+
             // Vector extension
             csr::VSTART => {
                 if !self.hw.extensions.has_v_extension {
@@ -928,6 +1214,177 @@ mod tests {
                 rs2: Register::X19
             }
         );
+        // SINVAL.VMA a0, a1: Fine-grained address-translation cache invalidation.
+        assert_eq!(
+            mctx.decode_illegal_instruction(0x16b50073),
+            IllegalInst::Sinvalvma {
+                rs1: Register::X10,
+                rs2: Register::X11
+            }
+        );
+        // HLV.D a0, (a1): Hypervisor virtual-machine load.
+        assert_eq!(
+            mctx.decode_illegal_instruction(0x6c05c573),
+            IllegalInst::Hlv(LoadInstr {
+                rd: Register::X10,
+                rs1: Register::X11,
+                imm: 0,
+                len: Width::Byte8,
+                is_compressed: false,
+                is_unsigned: false,
+            })
+        );
+        // HLV.BU a0, (a1)
+        assert_eq!(
+            mctx.decode_illegal_instruction(0x6015c573),
+            IllegalInst::Hlv(LoadInstr {
+                rd: Register::X10,
+                rs1: Register::X11,
+                imm: 0,
+                len: Width::Byte,
+                is_compressed: false,
+                is_unsigned: true,
+            })
+        );
+        // HLVX.HU a0, (a1): not supported.
+        assert_eq!(
+            mctx.decode_illegal_instruction(0x6435c573),
+            IllegalInst::Unknown
+        );
+        // HSV.W a2, (a1): Hypervisor virtual-machine store.
+        assert_eq!(
+            mctx.decode_illegal_instruction(0x6ac5c073),
+            IllegalInst::Hsv(StoreInstr {
+                rs2: Register::X12,
+                rs1: Register::X11,
+                imm: 0,
+                len: Width::Byte4,
+                is_compressed: false,
+            })
+        );
+        // SFENCE.W.INVAL and SFENCE.INVAL.IR: Svinval ordering fences.
+        assert_eq!(
+            mctx.decode_illegal_instruction(0x18000073),
+            IllegalInst::Sfencewinval
+        );
+        assert_eq!(
+            mctx.decode_illegal_instruction(0x18100073),
+            IllegalInst::Sfenceinvalir
+        );
+        // WRS.NTO and WRS.STO: Zawrs wait on reservation set.
+        assert_eq!(
+            mctx.decode_illegal_instruction(0x00d00073),
+            IllegalInst::Wrsnto
+        );
+        assert_eq!(
+            mctx.decode_illegal_instruction(0x01d00073),
+            IllegalInst::Wrssto
+        );
+    }
+
+    #[test]
+    fn amocas_instructions() {
+        let mctx = MiralisContext::new(unsafe { arch::detect_hardware() }, 0x100000, 0x2000);
+
+        // AMOCAS.W a0, a2, (a1)
+        assert_eq!(
+            mctx.decode_amocas(0x28c5a52f),
+            Some(AmoCasInstr {
+                rd: Register::X10,
+                rs1: Register::X11,
+                rs2: Register::X12,
+                len: 4,
+                aq: false,
+                rl: false,
+            })
+        );
+        // AMOCAS.D.AQRL a0, a2, (a1)
+        assert_eq!(
+            mctx.decode_amocas(0x2ec5b52f),
+            Some(AmoCasInstr {
+                rd: Register::X10,
+                rs1: Register::X11,
+                rs2: Register::X12,
+                len: 8,
+                aq: true,
+                rl: true,
+            })
+        );
+        // AMOCAS.Q.RL a0, a2, (a1)
+        assert_eq!(
+            mctx.decode_amocas(0x2ac5c52f).map(|instr| instr.len),
+            Some(16)
+        );
+        // AMOSWAP.W a0, a2, (a1)
+        assert_eq!(mctx.decode_amocas(0x08c5a52f), None);
+        // Invalid width
+        assert_eq!(mctx.decode_amocas(0x28c5d52f), None);
+    }
+
+    #[test]
+    fn zicond_instructions() {
+        let mctx = MiralisContext::new(unsafe { arch::detect_hardware() }, 0x100000, 0x2000);
+
+        // CZERO.EQZ a0, a1, a2
+        assert_eq!(
+            mctx.decode_zicond(0x0ec5d533),
+            Some(ZicondInstr {
+                rd: Register::X10,
+                rs1: Register::X11,
+                rs2: Register::X12,
+                op: ZicondOp::CzeroEqz,
+            })
+        );
+        // CZERO.NEZ a0, a1, a2
+        assert_eq!(
+            mctx.decode_zicond(0x0ec5f533).map(|instr| instr.op),
+            Some(ZicondOp::CzeroNez)
+        );
+        // ADD a0, a1, a2
+        assert_eq!(mctx.decode_zicond(0x00c58533), None);
+        // Invalid funct3
+        assert_eq!(mctx.decode_zicond(0x0ec5c533), None);
+    }
+
+    #[test]
+    fn fence_instructions() {
+        let mctx = MiralisContext::new(unsafe { arch::detect_hardware() }, 0x100000, 0x2000);
+
+        // FENCE RW, RW: Full memory fence.
+        assert_eq!(
+            mctx.decode_illegal_instruction(0x0330000f),
+            IllegalInst::Fence(BarrierKind::RwRw)
+        );
+        // FENCE R, R: Order loads.
+        assert_eq!(
+            mctx.decode_illegal_instruction(0x0220000f),
+            IllegalInst::Fence(BarrierKind::RR)
+        );
+        // FENCE W, R: Order stores before loads.
+        assert_eq!(
+            mctx.decode_illegal_instruction(0x0120000f),
+            IllegalInst::Fence(BarrierKind::WR)
+        );
+        // FENCE.TSO: Total store ordering fence.
+        assert_eq!(
+            mctx.decode_illegal_instruction(0x8330000f),
+            IllegalInst::Fence(BarrierKind::Tso)
+        );
+        // FENCE IORW, IORW: Device and memory fence.
+        assert_eq!(
+            mctx.decode_illegal_instruction(0x0ff0000f),
+            IllegalInst::Fence(BarrierKind::IoRwIoRw)
+        );
+        // FENCE W, 0: Empty successor set.
+        assert_eq!(
+            mctx.decode_illegal_instruction(0x0100000f),
+            IllegalInst::Fence(BarrierKind::None)
+        );
+        // FENCE.I: Not emulated.
+        assert_eq!(
+            mctx.decode_illegal_instruction(0x0000100f),
+            IllegalInst::Unknown
+        );
     }
 
     #[test]
@@ -24,7 +24,7 @@ pub const CLINT_SIZE: usize = 0x10000;
 ///
 /// NOTE: remember to update the factor in front of [size_of] to the number of timestamp fields in
 /// [TimestampEntry].
-const TIMESTAMP_PADDING_SIZE: usize = 64 - 2 * size_of::<AtomicUsize>();
+const TIMESTAMP_PADDING_SIZE: usize = 64 - 3 * size_of::<AtomicUsize>();
 
 /// A collection of timestamps entries for a given hart.
 ///
@@ -34,6 +34,8 @@ const TIMESTAMP_PADDING_SIZE: usize = 64 - 2 * size_of::<AtomicUsize>();
 #[repr(C, align(64))]
 #[derive(Debug)]
 struct TimestampEntry {
+    /// The virtual `mtimecmp` register, as seen by the firmware.
+    mtimecmp: AtomicUsize,
     deadline_firmware: AtomicUsize,
     deadline_payload: AtomicUsize,
     _padding: [u8; TIMESTAMP_PADDING_SIZE],
@@ -42,6 +44,7 @@ struct TimestampEntry {
 impl TimestampEntry {
     const fn max_value() -> Self {
         TimestampEntry {
+            mtimecmp: AtomicUsize::new(usize::MAX),
             deadline_firmware: AtomicUsize::new(usize::MAX),
             deadline_payload: AtomicUsize::new(usize::MAX),
             _padding: [0; TIMESTAMP_PADDING_SIZE],
@@ -170,7 +173,11 @@ impl VirtClint {
             }
             (o, Width::Byte8) if (MTIMECMP_OFFSET..MTIME_OFFSET).contains(&o) => {
                 let hart = (o - MTIMECMP_OFFSET) / MTIMECMP_WIDTH.to_bytes();
-                self.driver.read_mtimecmp(hart)
+                if hart >= PLATFORM_NB_HARTS {
+                    return Err("Invalid hart when reading mtimecmp");
+                }
+                // The physical mtimecmp is shared with the payload, hence we return the virtual one
+                Ok(self.next_timestamps[hart].mtimecmp.load(Ordering::SeqCst))
             }
             (o, Width::Byte8) if o == MTIME_OFFSET => Ok(self.driver.read_mtime()),
             // We also handle the case of 4 bytes reads to mtime
@@ -272,24 +279,36 @@ impl VirtClint {
                 let mtime = self.driver.read_mtime();
                 let hart = (o - MTIMECMP_OFFSET) / MTIMECMP_WIDTH.to_bytes();
                 if hart >= PLATFORM_NB_HARTS {
-                    return Err("Invalid hart when writting MSIP");
+                    return Err("Invalid hart when writting mtimecmp");
                 }
+                let timestamps = &self.next_timestamps[hart];
+                timestamps.mtimecmp.store(value, Ordering::SeqCst);
+
                 if hart != ctx.hart_id {
-                    todo!("Setting mtime for another hart is not yet supported");
+                    // We can not update the virtual `mip` of a remote hart, instead we program
+                    // its physical timer so that the remote hart injects the virtual interrupt
+                    // itself (immediately if the deadline already passed).
+                    //
+                    // NOTE: a pending virtual timer interrupt on the remote hart is not cleared
+                    // until it takes its next timer interrupt or writes its own mtimecmp.
+                    timestamps.deadline_firmware.store(value, Ordering::SeqCst);
+                    self.update_deadline(hart);
+                    return Ok(());
                 }
 
                 // Update the virtual `mip` according to the relative ordering of mtime and
                 // mtimecmp.
                 if mtime >= value {
                     ctx.csr.mip |= mie::MTIE_FILTER;
+                    timestamps
+                        .deadline_firmware
+                        .store(usize::MAX, Ordering::SeqCst);
                 } else {
                     // Register a timer to trigger the virtual interrupt once appropriate
-                    self.next_timestamps[ctx.hart_id]
-                        .deadline_firmware
-                        .store(value, Ordering::SeqCst);
-                    self.update_deadline(hart);
+                    timestamps.deadline_firmware.store(value, Ordering::SeqCst);
                     ctx.csr.mip &= !mie::MTIE_FILTER;
                 }
+                self.update_deadline(hart);
 
                 Ok(())
             }
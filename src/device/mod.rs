@@ -1,6 +1,10 @@
 //! Base device classes
 
+use core::mem::MaybeUninit;
+use core::ptr;
+
 use crate::arch::Width;
+use crate::utils::const_str_eq;
 use crate::virt::VirtContext;
 
 pub mod clint;
@@ -10,6 +14,7 @@ pub mod tester;
 // ———————————————————————————— Virtual Devices ————————————————————————————— //
 
 /// Represents a virtual memory-mapped device
+#[derive(Clone, Copy)]
 pub struct VirtDevice {
     pub start_addr: usize,
     pub size: usize,
@@ -23,6 +28,54 @@ pub fn find_matching_device(address: usize, devices: &[VirtDevice]) -> Option<&V
         .find(|device| address >= device.start_addr && address < device.start_addr + device.size)
 }
 
+/// Returns true if the device name is part of the disabled list.
+const fn is_disabled(name: &str, disabled: &[&str]) -> bool {
+    let mut i = 0;
+    while i < disabled.len() {
+        if const_str_eq(name, disabled[i]) {
+            return true;
+        }
+        i += 1;
+    }
+    false
+}
+
+/// Returns the number of devices which are not disabled.
+pub const fn nb_enabled_devices(devices: &[VirtDevice], disabled: &[&str]) -> usize {
+    let mut count = 0;
+    let mut i = 0;
+    while i < devices.len() {
+        if !is_disabled(devices[i].name, disabled) {
+            count += 1;
+        }
+        i += 1;
+    }
+    count
+}
+
+/// Returns the devices which are not disabled, evaluated at compile time by the platforms.
+///
+/// `N` must be equal to [nb_enabled_devices] for the same arguments.
+pub const fn enabled_devices<const N: usize>(
+    devices: &[VirtDevice],
+    disabled: &[&str],
+) -> [VirtDevice; N] {
+    let mut enabled = [const { MaybeUninit::<VirtDevice>::uninit() }; N];
+    let mut count = 0;
+    let mut i = 0;
+    while i < devices.len() {
+        if !is_disabled(devices[i].name, disabled) {
+            enabled[count] = MaybeUninit::new(devices[i]);
+            count += 1;
+        }
+        i += 1;
+    }
+    assert!(count == N, "Invalid number of enabled devices");
+
+    // SAFETY: we checked that all the N entries have been initialized
+    unsafe { ptr::read(&enabled as *const _ as *const [VirtDevice; N]) }
+}
+
 pub trait DeviceAccess: Sync + Send {
     fn read_device(
         &self,
@@ -38,3 +91,64 @@ pub trait DeviceAccess: Sync + Send {
         ctx: &mut VirtContext,
     ) -> Result<(), &'static str>;
 }
+
+// ————————————————————————————————— Tests —————————————————————————————————— //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct DummyDevice;
+
+    impl DeviceAccess for DummyDevice {
+        fn read_device(
+            &self,
+            _offset: usize,
+            _r_width: Width,
+            _ctx: &mut VirtContext,
+        ) -> Result<usize, &'static str> {
+            Ok(0)
+        }
+
+        fn write_device(
+            &self,
+            _offset: usize,
+            _w_width: Width,
+            _value: usize,
+            _ctx: &mut VirtContext,
+        ) -> Result<(), &'static str> {
+            Ok(())
+        }
+    }
+
+    static DUMMY_DEVICE: DummyDevice = DummyDevice;
+    static ALL_DEVICES: &[VirtDevice; 2] = &[
+        VirtDevice {
+            start_addr: 0x2000000,
+            size: 0x10000,
+            name: "CLINT",
+            device_interface: &DUMMY_DEVICE,
+        },
+        VirtDevice {
+            start_addr: 0x2020000,
+            size: 0x1000,
+            name: "TEST",
+            device_interface: &DUMMY_DEVICE,
+        },
+    ];
+    const DISABLED: &[&str] = &["TEST"];
+    static DEVICES: &[VirtDevice; nb_enabled_devices(ALL_DEVICES, DISABLED)] =
+        &enabled_devices(ALL_DEVICES, DISABLED);
+
+    #[test]
+    fn disabled_devices() {
+        assert_eq!(nb_enabled_devices(ALL_DEVICES, &[]), 2);
+        assert_eq!(DEVICES.len(), 1);
+
+        // The range of the disabled device is not trapped anymore
+        assert!(find_matching_device(0x2020010, ALL_DEVICES).is_some());
+        assert!(find_matching_device(0x2020010, DEVICES).is_none());
+        let clint = find_matching_device(0x2000010, DEVICES).expect("CLINT should be enabled");
+        assert_eq!(clint.name, "CLINT");
+    }
+}
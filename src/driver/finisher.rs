@@ -0,0 +1,87 @@
+//! # Test Finisher Driver
+//!
+//! This module implements a driver for the SiFive test finisher (`sifive_test`), which is used by
+//! QEMU to terminate the emulation with a given exit status.
+
+use core::{hint, ptr};
+
+/// The size of the finisher MMIO region.
+pub const FINISHER_SIZE: usize = 0x1000;
+
+/// Value requesting a successful exit.
+const FINISHER_PASS: u32 = 0x5555;
+/// Value requesting an exit with an error code, stored in the upper 16 bits.
+const FINISHER_FAIL: u32 = 0x3333;
+
+/// The exit status to report.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FinisherStatus {
+    Pass,
+    Fail(u16),
+}
+
+impl FinisherStatus {
+    /// The value to write to the finisher to request the exit.
+    const fn code(self) -> u32 {
+        match self {
+            FinisherStatus::Pass => FINISHER_PASS,
+            FinisherStatus::Fail(code) => ((code as u32) << 16) | FINISHER_FAIL,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FinisherDriver {
+    /// The base address of the finisher.
+    base: usize,
+}
+
+impl FinisherDriver {
+    /// Creates a new finisher driver from the base address of the device.
+    ///
+    /// # Safety
+    ///
+    /// This function assumes that the base address corresponds to the base address of a
+    /// `sifive_test` compatible device.
+    pub const unsafe fn new(base: usize) -> Self {
+        Self { base }
+    }
+
+    /// Request the platform to exit with the given status.
+    ///
+    /// The platform might take some time to act on the request, see [FinisherDriver::exit] to
+    /// wait until it does.
+    pub fn request_exit(&self, status: FinisherStatus) {
+        // SAFETY: the base points to a valid finisher device, and the write must be volatile to
+        // ensure it is not optimized away.
+        unsafe { ptr::write_volatile(self.base as *mut u32, status.code()) };
+    }
+
+    /// Exit with the given status, looping forever if the platform does not shut down.
+    pub fn exit(&self, status: FinisherStatus) -> ! {
+        self.request_exit(status);
+        loop {
+            hint::spin_loop();
+        }
+    }
+}
+
+// ————————————————————————————————— Tests —————————————————————————————————— //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes() {
+        // A fake finisher backed by memory
+        let mut register = 0u32;
+        let finisher = unsafe { FinisherDriver::new(&raw mut register as usize) };
+
+        finisher.request_exit(FinisherStatus::Pass);
+        assert_eq!(register, 0x5555);
+
+        finisher.request_exit(FinisherStatus::Fail(1));
+        assert_eq!(register, 0x1_3333);
+    }
+}
@@ -5,5 +5,6 @@
 //! interrupts, such as the CLINT and PLIC.
 
 pub mod clint;
+pub mod finisher;
 pub mod plic;
 pub mod uart;
@@ -5,6 +5,25 @@
 use core::fmt::Write;
 use core::{fmt, ptr};
 
+use spin::Mutex;
+
+/// Line Status Register
+pub const LSR_OFFSET: usize = 0x05;
+/// Transmit Holding Register Empty
+const LSR_THRE: u8 = 0x20;
+/// Transmitter Empty: both the holding register and the shift register are empty
+const LSR_TEMT: u8 = 0x40;
+
+/// Flushes the UART protected by `uart` with `flush`, unless the UART is in use.
+///
+/// Do not wait on the lock, as we might be exiting while the UART is in use (e.g. when panicking
+/// while printing).
+pub fn try_flush<T>(uart: &Mutex<T>, flush: impl FnOnce(&mut T)) {
+    if let Some(mut uart) = uart.try_lock() {
+        flush(&mut uart);
+    }
+}
+
 pub struct UartDriver {
     serial_port_base_addr: usize,
     size_per_register: usize,
@@ -31,13 +50,22 @@ impl UartDriver {
         }
     }
 
+    /// Wait until all the pending bytes have been transmitted.
+    ///
+    /// Some UARTs buffer bytes in a transmit FIFO, which is lost if the machine halts before the
+    /// FIFO is drained.
+    pub fn flush(&mut self) {
+        while self.read_lsr() & LSR_TEMT == 0 {
+            core::hint::spin_loop();
+        }
+    }
+
     fn is_line_busy(&mut self) -> bool {
-        // Line Status Register
-        const LSR_OFFSET: usize = 0x05;
-        // Transmit Holding Register Empty
-        const LSR_THRE: u8 = 0x20;
+        self.read_lsr() & LSR_THRE == 0
+    }
 
-        unsafe { ptr::read_volatile(self.get_register(LSR_OFFSET) as *const u8) & LSR_THRE == 0 }
+    fn read_lsr(&mut self) -> u8 {
+        unsafe { ptr::read_volatile(self.get_register(LSR_OFFSET) as *const u8) }
     }
 }
 impl Write for UartDriver {
@@ -48,3 +76,24 @@ impl Write for UartDriver {
         Ok(())
     }
 }
+
+// ————————————————————————————————— Tests —————————————————————————————————— //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flush() {
+        // A fake UART whose transmitter is always empty
+        let mut registers = [0u32; 8];
+        registers[LSR_OFFSET] = (LSR_THRE | LSR_TEMT) as u32;
+        let mut uart = UartDriver::new(registers.as_mut_ptr() as usize, 4);
+
+        uart.write_str("exit").unwrap();
+        uart.flush();
+
+        // The last byte must have been written to the transmit holding register
+        assert_eq!(registers[0], 't' as u32);
+    }
+}
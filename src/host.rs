@@ -3,9 +3,23 @@
 //! This module exposes the host context as [MiralisCtx], which holds Miralis's own configuration registers.
 
 use crate::arch::HardwareCapability;
-use crate::arch::pmp::PmpGroup;
-use crate::device;
-use crate::platform::{Plat, Platform};
+use crate::arch::pmp::{PmpGroup, Segment};
+use crate::benchmark::exit_rate::ExitRateSampler;
+use crate::debug::ProgressWatchdog;
+use crate::platform::{MemoryKind, Plat, Platform};
+use crate::rng::Rng;
+use crate::{config, device};
+
+/// The kind of a physical address, from the point of view of Miralis.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressKind {
+    /// The memory of Miralis itself.
+    Miralis,
+    /// A device emulated by Miralis.
+    Device,
+    /// Any other memory, including the DMA regions of the platform.
+    Memory,
+}
 
 /// The Miralis Context, holding configuration registers for Miralis.
 pub struct MiralisContext {
@@ -15,6 +29,16 @@ pub struct MiralisContext {
     pub hw: HardwareCapability,
     /// List of device with PMP
     pub devices: &'static [device::VirtDevice],
+    /// Memory accessed by devices through DMA, never protected by Miralis
+    pub dma_regions: &'static [Segment],
+    /// Source of random values for the policies
+    pub rng: Rng,
+    /// The memory of Miralis itself, never accessible to the firmware and payload
+    pub miralis_memory: Segment,
+    /// Periodic sampling of the number of exits, if enabled
+    pub exit_rate: Option<ExitRateSampler>,
+    /// Detection of guests making no progress, if enabled
+    pub progress: Option<ProgressWatchdog>,
 }
 
 impl MiralisContext {
@@ -24,6 +48,139 @@ impl MiralisContext {
             pmp: PmpGroup::init_pmp_group(hw.available_reg.nb_pmp, start, size),
             hw,
             devices: Plat::get_virtual_devices(),
+            dma_regions: Plat::dma_regions(),
+            rng: Rng::new(Plat::rng()),
+            miralis_memory: Segment::new(start, size),
+            exit_rate: config::DEBUG_EXIT_RATE_INTERVAL
+                .map(|interval| ExitRateSampler::new(interval, Plat::TIMEBASE_FREQUENCY)),
+            progress: config::PROGRESS_TIMEOUT
+                .map(|timeout| ProgressWatchdog::new(timeout, Plat::TIMEBASE_FREQUENCY)),
+        }
+    }
+
+    /// Classifies a physical address.
+    ///
+    /// DMA regions are normal memory, Miralis refuses to boot if they overlap memory it denies
+    /// (itself or a device), see [PmpGroup::init_pmp_group].
+    pub fn classify_address(&self, address: usize) -> AddressKind {
+        let contains = |segment: Segment| segment.contain(Segment::new(address, 1));
+        if contains(self.miralis_memory) {
+            AddressKind::Miralis
+        } else if device::find_matching_device(address, self.devices).is_some() {
+            AddressKind::Device
+        } else {
+            AddressKind::Memory
+        }
+    }
+
+    /// Checks that a memory region is guest RAM.
+    ///
+    /// The region must be main memory, outside of Miralis, of the devices and of their DMA
+    /// buffers. Returns a description of the offending memory otherwise.
+    pub fn check_guest_ram(&self, region: Segment) -> Result<(), &'static str> {
+        if region.overlap(self.miralis_memory) {
+            return Err("Miralis memory");
         }
+        let overlaps = |segment: Segment| region.overlap(segment);
+        if self
+            .devices
+            .iter()
+            .any(|device| overlaps(Segment::new(device.start_addr, device.size)))
+            || self.dma_regions.iter().any(|dma| overlaps(*dma))
+        {
+            return Err("device memory");
+        }
+
+        // When the platform describes its RAM, the region must be part of it
+        let memory_map = Plat::get_memory_map();
+        if memory_map
+            .iter()
+            .any(|mem| mem.kind != MemoryKind::Ram && overlaps(Segment::new(mem.base, mem.size)))
+        {
+            return Err("non-RAM memory");
+        }
+        let mut ram = memory_map.iter().filter(|mem| mem.kind == MemoryKind::Ram);
+        if ram.clone().next().is_some()
+            && !ram.any(|mem| Segment::new(mem.base, mem.size).contain(region))
+        {
+            return Err("memory outside of RAM");
+        }
+
+        Ok(())
+    }
+
+    /// Checks that a page shared by Miralis with the firmware or host is aligned and in guest RAM.
+    pub fn check_shared_page(&self, addr: usize, size: usize) -> Result<(), &'static str> {
+        if !addr.is_multiple_of(size) {
+            return Err("misaligned page");
+        }
+        self.check_guest_ram(Segment::new(addr, size))
+    }
+
+    /// Returns the number of exits per second over the last sampling interval.
+    ///
+    /// Returns None if the exit rate is not sampled (see `MIRALIS_DEBUG_EXIT_RATE_INTERVAL`), or
+    /// if no interval has elapsed yet.
+    pub fn exits_per_second(&self) -> Option<usize> {
+        self.exit_rate
+            .and_then(|sampler| sampler.exits_per_second())
+    }
+}
+
+// ————————————————————————————————— Tests —————————————————————————————————— //
+
+#[cfg(test)]
+mod tests {
+    use super::{AddressKind, MiralisContext};
+    use crate::arch;
+    use crate::arch::pmp::Segment;
+
+    #[test]
+    fn dma_regions() {
+        let hw = unsafe { arch::detect_hardware() };
+        let mut mctx = MiralisContext::new(hw, 0x10000, 0x2000);
+        let device = mctx.devices[0];
+        let dma = Segment::new(0x8800_0000, 0x10_0000);
+
+        assert_eq!(mctx.classify_address(0x10000), AddressKind::Miralis);
+        assert_eq!(
+            mctx.classify_address(device.start_addr),
+            AddressKind::Device
+        );
+        assert_eq!(mctx.classify_address(dma.start()), AddressKind::Memory);
+
+        // DMA regions are normal memory
+        static DMA_REGIONS: [Segment; 1] = [Segment::new(0x8800_0000, 0x10_0000)];
+        mctx.dma_regions = &DMA_REGIONS;
+        assert_eq!(mctx.classify_address(dma.start()), AddressKind::Memory);
+        assert_eq!(mctx.classify_address(dma.end() - 1), AddressKind::Memory);
+        assert_eq!(mctx.classify_address(0x10000), AddressKind::Miralis);
+
+        // Miralis never denies DMA regions, and rejects those overlapping itself or a device
+        assert_eq!(mctx.pmp.denied_overlap(dma), None);
+        assert_eq!(
+            mctx.pmp.denied_overlap(Segment::new(0x10000, 0x1000)),
+            Some(Segment::new(0x10000, 0x2000))
+        );
+        assert_eq!(
+            mctx.pmp
+                .denied_overlap(Segment::new(device.start_addr, 0x1000)),
+            Some(Segment::new(device.start_addr, device.size))
+        );
+    }
+
+    #[test]
+    fn shared_page() {
+        let hw = unsafe { arch::detect_hardware() };
+        let mut mctx = MiralisContext::new(hw, 0x10000, 0x2000);
+        let device = mctx.devices[0];
+        static DMA_REGIONS: [Segment; 1] = [Segment::new(0x8800_0000, 0x10_0000)];
+        mctx.dma_regions = &DMA_REGIONS;
+
+        assert_eq!(mctx.check_shared_page(0x8060_0000, 0x1000), Ok(()));
+        assert!(mctx.check_shared_page(0x8060_0008, 0x1000).is_err());
+        assert!(mctx.check_shared_page(0x11000, 0x1000).is_err());
+        assert!(mctx.check_shared_page(device.start_addr, 0x1000).is_err());
+        assert!(mctx.check_shared_page(0x8800_1000, 0x1000).is_err());
     }
 }
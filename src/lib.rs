@@ -1,7 +1,7 @@
 //! Miralis
 //!
 //! The Miralis library, which needs to be embedded into an executable.
-//! This library exposes two main functions: [init] and [main_loop].
+//! This library exposes three main functions: [init], [main_loop] and [shutdown].
 
 // Mark the crate as no_std and no_main, but only when not running tests.
 // We need both std and main to be able to run tests in user-space on the host architecture.
@@ -9,6 +9,7 @@
 #![cfg_attr(not(any(test, feature = "userspace")), no_main)]
 
 pub mod arch;
+pub mod attestation;
 pub mod benchmark;
 pub mod debug;
 pub mod decoder;
@@ -19,10 +20,13 @@ pub mod logger;
 pub mod modules;
 pub mod platform;
 pub mod policy;
+pub mod rng;
 pub mod utils;
 pub mod virt;
 
-use arch::{Csr, Register};
+use arch::pmp::pmplayout::READ_ONLY_OFFSET;
+use arch::{Csr, MCause, Register};
+use benchmark::folded::Scope;
 use host::MiralisContext;
 use miralis_config as config;
 pub use platform::init;
@@ -31,22 +35,129 @@ use virt::traits::*;
 use virt::{ExecutionMode, ExitResult, VirtContext};
 
 use crate::arch::write_pmp;
+use crate::benchmark::counter::CounterBenchmark;
 use crate::modules::{MainModule, Module};
 
+/// Whether the exit counter benchmark is enabled. The instructions retired by the guest and the
+/// cycles spent in world switches are only measured for that benchmark.
+const EXIT_COUNTER_BENCHMARK: bool = MainModule::is_enabled(CounterBenchmark::NAME);
+
 /// The virtual firmware monitor main loop.
 ///
 /// Runs the firmware and payload in a loop, handling the traps and interrupts and switching world
-/// when required.
+/// when required. Returns once the firmware or payload requested to terminate the execution,
+/// either with [ExitResult::Done] or [ExitResult::Failure].
 ///
 /// # Safety
 ///
 /// This function will start by passing control to the firmware. The hardware must have
 /// been initialized properly (including calling `miralis::init` and loading the firmware).
-pub unsafe fn main_loop(ctx: &mut VirtContext, mctx: &mut MiralisContext, module: &mut MainModule) {
-    unsafe { arch::run_vcpu(ctx) };
+pub unsafe fn main_loop(
+    ctx: &mut VirtContext,
+    mctx: &mut MiralisContext,
+    module: &mut MainModule,
+) -> ExitResult {
+    let run = |ctx: &mut VirtContext| {
+        enter_scope(ctx, Scope::RunVCPU);
+        if EXIT_COUNTER_BENCHMARK {
+            let instret = arch::read_csr(Csr::Minstret);
+            unsafe { arch::run_vcpu(ctx) };
+            ctx.last_run_instret += arch::read_csr(Csr::Minstret).wrapping_sub(instret);
+        } else {
+            unsafe { arch::run_vcpu(ctx) };
+        }
+        exit_scope(ctx);
+    };
+    run_vcpu_with_retries(ctx, config::VCPU_MAX_TRANSIENT_RETRIES, run);
+
+    loop {
+        enter_scope(ctx, Scope::HandleTrap);
+        let result = handle_trap(ctx, mctx, module);
+        exit_scope(ctx);
+        sample_exit_rate(ctx, mctx);
+        check_progress(ctx, mctx, module);
+
+        match result {
+            ExitResult::Continue => {
+                run_vcpu_with_retries(ctx, config::VCPU_MAX_TRANSIENT_RETRIES, run)
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Samples the number of exits, logging the exit rate once per sampling interval.
+///
+/// Does nothing unless `MIRALIS_DEBUG_EXIT_RATE_INTERVAL` is set.
+fn sample_exit_rate(ctx: &VirtContext, mctx: &mut MiralisContext) {
+    if let Some(sampler) = &mut mctx.exit_rate {
+        let now = Plat::get_clint().read_mtime();
+        if let Some(rate) = sampler.sample(ctx.nb_exits, now) {
+            log::info!("{}", rate);
+        }
+    }
+}
+
+/// Samples the address of the last trap, exiting if the guest made no progress for too long.
+///
+/// Does nothing unless `MIRALIS_PROGRESS_TIMEOUT` is set.
+fn check_progress(ctx: &mut VirtContext, mctx: &mut MiralisContext, module: &mut MainModule) {
+    if let Some(watchdog) = &mut mctx.progress {
+        let now = Plat::get_clint().read_mtime();
+        if let Some(no_progress) = watchdog.sample(ctx.trap_info.mepc, now) {
+            log::error!("{} ({} exits)", no_progress, ctx.nb_exits);
+            log::error!("{}", debug::CrashReport::new(ctx, mctx));
+            shutdown(ctx, mctx, module, false);
+        }
+    }
+}
+
+/// Run the vCPU with `run`, resuming it right away on spurious interrupts.
+///
+/// The vCPU is resumed at most `max_retries` consecutive times, the trap is then handled as any
+/// other trap. See [arch::TrapInfo::is_spurious_interrupt] for the set of retryable traps.
+fn run_vcpu_with_retries(
+    ctx: &mut VirtContext,
+    max_retries: usize,
+    mut run: impl FnMut(&mut VirtContext),
+) {
+    // The retries are part of the same run, their instructions are accumulated by `run`
+    ctx.last_run_instret = 0;
+    run(ctx);
 
-    while handle_trap(ctx, mctx, module) != ExitResult::Done {
-        unsafe { arch::run_vcpu(ctx) };
+    let mut retries = 0;
+    while retries < max_retries && ctx.trap_info.is_spurious_interrupt() {
+        logger::debug!(
+            "Retrying after spurious {:?} ({}/{})",
+            ctx.trap_info.get_cause(),
+            retries + 1,
+            max_retries
+        );
+        retries += 1;
+        ctx.nb_transient_retries += 1;
+        run(ctx);
+    }
+}
+
+/// Shut Miralis down, notifying the modules before exiting the platform.
+///
+/// Exits with a success status if `success` is true, and with a failure status otherwise.
+pub fn shutdown(
+    ctx: &mut VirtContext,
+    mctx: &mut MiralisContext,
+    module: &mut MainModule,
+    success: bool,
+) -> ! {
+    module.on_shutdown(ctx, mctx);
+    if config::BENCHMARK_FOLDED_STACKS {
+        for line in ctx.folded_stacks.lines() {
+            log::info!("{}", line);
+        }
+    }
+    if success {
+        Plat::exit_success();
+    } else {
+        Plat::exit_failure();
     }
 }
 
@@ -63,27 +174,36 @@ fn handle_trap(
         && ctx.nb_exits + 1 >= max_exit
     {
         log::error!("Reached maximum number of exits: {}", ctx.nb_exits);
-        module.on_shutdown();
-        Plat::exit_failure();
+        shutdown(ctx, mctx, module, false);
     }
 
     if ctx.trap_info.is_from_mmode() {
         // Trap comes from M mode: Miralis
-        handle_miralis_trap(ctx);
-        return ExitResult::Continue;
+        handle_miralis_trap(ctx, mctx, module);
     }
 
     // Perform emulation
     let exec_mode = ctx.mode.to_exec_mode();
     // Keep track of the number of exit
     ctx.nb_exits += 1;
+    if config::DEBUG_TRAP_HISTORY {
+        ctx.trap_history.record(&ctx.trap_info);
+    }
+    enter_scope(ctx, Scope::Emulate);
     let result = match exec_mode {
         ExecutionMode::Firmware => ctx.handle_firmware_trap(mctx, module),
         ExecutionMode::Payload => ctx.handle_payload_trap(mctx, module),
     };
+    exit_scope(ctx);
 
     // Inject interrupts if required
-    ctx.check_and_inject_interrupts();
+    ctx.check_and_inject_interrupts(exec_mode);
+
+    // Catch emulation bugs as close as possible to their source
+    #[cfg(debug_assertions)]
+    if let Err(err) = ctx.validate() {
+        panic!("Invalid virtual context after emulation: {}", err);
+    }
 
     // At this point the next mode is fixed
     module.decided_next_exec_mode(ctx, exec_mode, ctx.mode.to_exec_mode());
@@ -92,6 +212,8 @@ fn handle_trap(
     match (exec_mode, ctx.mode.to_exec_mode()) {
         (ExecutionMode::Firmware, ExecutionMode::Payload) => {
             logger::debug!("Execution mode: Firmware -> Payload");
+            enter_scope(ctx, Scope::WorldSwitch);
+            let world_switch_start = read_mcycle_if_measured();
             unsafe { ctx.switch_from_firmware_to_payload(mctx) };
             module.switch_from_firmware_to_payload(ctx, mctx);
 
@@ -99,6 +221,10 @@ fn handle_trap(
                 // Commit the PMP to hardware
                 write_pmp(&mctx.pmp).flush();
             }
+
+            let cycles = read_mcycle_if_measured().wrapping_sub(world_switch_start);
+            module.world_switch_done(ctx, cycles);
+            exit_scope(ctx);
         }
         (ExecutionMode::Payload, ExecutionMode::Firmware) => {
             logger::debug!(
@@ -106,6 +232,8 @@ fn handle_trap(
                 ctx.trap_info.get_cause()
             );
 
+            enter_scope(ctx, Scope::WorldSwitch);
+            let world_switch_start = read_mcycle_if_measured();
             module.switch_from_payload_to_firmware(ctx, mctx);
             unsafe { ctx.switch_from_payload_to_firmware(mctx) };
 
@@ -113,6 +241,10 @@ fn handle_trap(
                 // Commit the PMP to hardware
                 write_pmp(&mctx.pmp).flush();
             }
+
+            let cycles = read_mcycle_if_measured().wrapping_sub(world_switch_start);
+            module.world_switch_done(ctx, cycles);
+            exit_scope(ctx);
         }
         _ => {} // No execution mode transition
     }
@@ -121,20 +253,56 @@ fn handle_trap(
 }
 
 /// Handle the trap coming from miralis
-fn handle_miralis_trap(ctx: &mut VirtContext) {
-    let trap = &ctx.trap_info;
+///
+/// Traps from Miralis are never expected, we dump a crash report and halt the platform.
+fn handle_miralis_trap(
+    ctx: &mut VirtContext,
+    mctx: &mut MiralisContext,
+    module: &mut MainModule,
+) -> ! {
     log::error!("Unexpected trap while executing Miralis");
-    log::error!("  cause:   {} ({:?})", trap.mcause, trap.get_cause());
-    log::error!("  mepc:    0x{:x}", trap.mepc);
-    log::error!("  mtval:   0x{:x}", trap.mtval);
-    log::error!("  mstatus: 0x{:x}", trap.mstatus);
-    log::error!("  mip:     0x{:x}", trap.mip);
+    if config::DEBUG_PROTECT_READ_ONLY
+        && ctx.trap_info.get_cause() == MCause::StoreAccessFault
+        && mctx.pmp.is_read_only(READ_ONLY_OFFSET, ctx.trap_info.mtval)
+    {
+        log::error!(
+            "Miralis wrote to its own code at 0x{:x} (pc: 0x{:x})",
+            ctx.trap_info.mtval,
+            ctx.trap_info.mepc
+        );
+    }
+    log::error!("{}", debug::CrashReport::new(ctx, mctx));
 
-    todo!("Miralis trap handler entered");
+    shutdown(ctx, mctx, module, false);
 }
 
 // —————————————————————————————— Debug Helper —————————————————————————————— //
 
+/// Read `mcycle` to measure world switches, if the exit counter benchmark is enabled.
+///
+/// Returns 0 otherwise, in which case the world switches are reported as taking no cycles.
+fn read_mcycle_if_measured() -> usize {
+    if EXIT_COUNTER_BENCHMARK {
+        arch::read_csr(Csr::Mcycle)
+    } else {
+        0
+    }
+}
+
+/// Open a benchmark scope, if folded stacks are enabled.
+fn enter_scope(ctx: &mut VirtContext, scope: Scope) {
+    if config::BENCHMARK_FOLDED_STACKS {
+        ctx.folded_stacks.enter(scope, arch::read_csr(Csr::Mcycle));
+    }
+}
+
+/// Close the innermost benchmark scope, if folded stacks are enabled.
+fn exit_scope(ctx: &mut VirtContext) {
+    if config::BENCHMARK_FOLDED_STACKS {
+        ctx.folded_stacks.exit(arch::read_csr(Csr::Mcycle));
+    }
+}
+
 /// Log the current context using the trace log level.
 fn log_ctx(ctx: &VirtContext) {
     let trap_info = &ctx.trap_info;
@@ -235,11 +403,11 @@ fn log_ctx(ctx: &VirtContext) {
 /// In case of an interrupt, Mip must be cleared: avoid Miralis to trap again.
 #[cfg(test)]
 mod tests {
-    use crate::arch::{MCause, Mode, mstatus};
+    use crate::arch::{MCause, Mode, mie, mstatus};
     use crate::host::MiralisContext;
     use crate::modules::{MainModule, Module};
-    use crate::virt::VirtContext;
-    use crate::{arch, handle_trap};
+    use crate::virt::{VirtContext, inject_trap};
+    use crate::{arch, handle_trap, run_vcpu_with_retries};
 
     #[test]
     fn handle_trap_state() {
@@ -256,14 +424,15 @@ mod tests {
         ctx.csr.mtvec = 0x80200024; // Dummy mtvec
 
         // Simulating a trap
-        ctx.trap_info.mepc = 0x80200042; // Dummy address
+        inject_trap(&mut ctx, MCause::Breakpoint, 0, 0x80200042); // TODO : use a real int.
         ctx.trap_info.mstatus = 0b10000000;
-        ctx.trap_info.mcause = MCause::Breakpoint as usize; // TODO : use a real int.
         ctx.trap_info.mip = 0b1;
-        ctx.trap_info.mtval = 0;
+        ctx.validate().expect("Invalid initial context");
 
         handle_trap(&mut ctx, &mut mctx, &mut module);
 
+        ctx.validate().expect("Invalid context after trap");
+
         assert_eq!(ctx.pc, 0x80200024, "pc must be at handler start");
         assert_eq!(ctx.csr.mip, 0b1, "mip must to be updated");
         assert_eq!(ctx.csr.mie, 1, "mie must not change");
@@ -275,4 +444,90 @@ mod tests {
             "mstatus.MPIE must be set to trap_info.mstatus.MPIE"
         );
     }
+
+    /// An interrupt that stays pending across an `sret` emulated for the firmware is injected
+    /// again by the main loop once the lower privilege mode is entered.
+    #[test]
+    fn sret_pending_interrupt() {
+        let hw = unsafe { arch::detect_hardware() };
+        let mut mctx = MiralisContext::new(hw, 0x10000, 0x2000);
+        let mut module = MainModule::init();
+        let mut ctx = VirtContext::new(0, mctx.hw.available_reg.nb_pmp, mctx.hw.extensions.clone());
+
+        // The firmware masks a pending timer interrupt with mstatus.MIE, and returns to S-mode
+        ctx.mode = Mode::M;
+        ctx.csr.misa |= arch::misa::S;
+        ctx.csr.mstatus = Mode::S.to_bits() << mstatus::SPP_OFFSET;
+        ctx.csr.mideleg = mie::MIDELEG_READ_ONLY_ONE;
+        ctx.csr.mie = mie::MTIE_FILTER;
+        ctx.csr.mip = mie::MTIE_FILTER;
+        ctx.csr.mtvec = 0x80200024;
+        ctx.csr.sepc = 0x80400000;
+        ctx.pc = 0x80200100;
+
+        // sret
+        inject_trap(&mut ctx, MCause::IllegalInstr, 0x10200073, 0x80200100);
+        ctx.trap_info.mip = mie::MTIE_FILTER;
+        handle_trap(&mut ctx, &mut mctx, &mut module);
+
+        // The interrupt is taken on the first instruction after the sret
+        assert_eq!(ctx.mode, Mode::M, "The pending interrupt must be injected");
+        assert_eq!(ctx.csr.mcause, MCause::MachineTimerInt as usize);
+        assert_eq!(ctx.csr.mepc, 0x80400000);
+        assert_eq!(ctx.pc, 0x80200024);
+        assert_eq!(
+            (ctx.csr.mstatus & mstatus::MPP_FILTER) >> mstatus::MPP_OFFSET,
+            Mode::S.to_bits()
+        );
+    }
+
+    /// Spurious interrupts are retried a bounded number of times, other traps are never retried.
+    #[test]
+    fn transient_retries() {
+        let hw = unsafe { arch::detect_hardware() };
+        let mctx = MiralisContext::new(hw, 0x10000, 0x2000);
+        let mut ctx = VirtContext::new(0, mctx.hw.available_reg.nb_pmp, mctx.hw.extensions.clone());
+
+        // Returns `nb_spurious` spurious timer interrupts, followed by a breakpoint
+        let runner = |nb_spurious: usize| {
+            let mut nb_runs = 0;
+            move |ctx: &mut VirtContext| {
+                ctx.trap_info.mcause = if nb_runs < nb_spurious {
+                    MCause::MachineTimerInt as usize
+                } else {
+                    MCause::Breakpoint as usize
+                };
+                ctx.trap_info.mip = 0;
+                ctx.last_run_instret += 10;
+                nb_runs += 1;
+            }
+        };
+
+        run_vcpu_with_retries(&mut ctx, 3, runner(2));
+        assert_eq!(ctx.trap_info.get_cause(), MCause::Breakpoint);
+        assert_eq!(ctx.nb_transient_retries, 2);
+        assert_eq!(
+            ctx.last_run_instret, 30,
+            "Retried runs are part of the same run"
+        );
+
+        // Once the bound is reached the spurious interrupt is handled as a regular trap
+        run_vcpu_with_retries(&mut ctx, 3, runner(5));
+        assert_eq!(ctx.trap_info.get_cause(), MCause::MachineTimerInt);
+        assert_eq!(ctx.nb_transient_retries, 5);
+        assert_eq!(ctx.last_run_instret, 40);
+
+        // Pending interrupts are not spurious
+        let mut pending_timer = |ctx: &mut VirtContext| {
+            ctx.trap_info.mcause = MCause::MachineTimerInt as usize;
+            ctx.trap_info.mip = mie::MTIE_FILTER;
+        };
+        run_vcpu_with_retries(&mut ctx, 3, &mut pending_timer);
+        assert_eq!(ctx.nb_transient_retries, 5);
+
+        // Retries are disabled by default
+        run_vcpu_with_retries(&mut ctx, 0, runner(1));
+        assert_eq!(ctx.trap_info.get_cause(), MCause::MachineTimerInt);
+        assert_eq!(ctx.nb_transient_retries, 5);
+    }
 }
@@ -1,10 +1,17 @@
 //! Structured logging implementation
 
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::fmt;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering, fence};
 
 use log::{Level, LevelFilter, Metadata, Record};
 use miralis_config as config;
+use miralis_core::log_ring as layout;
+use spin::Mutex;
 
+use crate::arch::pmp::pmpcfg;
+use crate::arch::pmp::pmplayout::LOG_PAGE_OFFSET;
+use crate::host::MiralisContext;
 use crate::platform::{Plat, Platform};
 use crate::utils::const_str_eq;
 
@@ -19,27 +26,52 @@ impl log::Log for Logger {
 
     fn log(&self, record: &Record) {
         if self.enabled(record.metadata()) {
-            // Writes the log
-            if Plat::name() == "Miralis" {
-                // No need for formatting, the host Miralis will handle it
-                Plat::debug_print(record.level(), format_args!("{}", record.args()))
-            } else {
-                // Otherwise we format the logs properly
-                Plat::debug_print(
+            if config::LOG_DEDUPLICATE {
+                // The lock is released before writing, so that other harts are not serialized on
+                // the (potentially slow) backends.
+                let dedup = LAST_MESSAGE.lock().deduplicate(
                     record.level(),
-                    format_args!(
-                        "[{} | {}] {}\n",
-                        level_display(record.level()),
-                        record.target(),
-                        record.args()
-                    ),
-                )
+                    record.target(),
+                    *record.args(),
+                );
+                let Dedup::Emit { previous } = dedup else {
+                    return;
+                };
+                if let Some((level, repeated)) = previous {
+                    write_repetitions(level, repeated);
+                }
             }
+            write_log(record.level(), record.target(), *record.args());
         }
     }
 
     fn flush(&self) {}
 }
+
+/// Writes the log to the backends selected by the configuration
+fn write_log(level: Level, target: &str, args: fmt::Arguments) {
+    if LOG_BACKEND != Backend::SharedPage {
+        write_uart(level, target, args);
+    }
+    if LOG_BACKEND != Backend::Uart {
+        write_shared_page(level, target, args);
+    }
+}
+
+/// Writes the log to the platform console
+fn write_uart(level: Level, target: &str, args: fmt::Arguments) {
+    if Plat::name() == "Miralis" {
+        // No need for formatting, the host Miralis will handle it
+        Plat::debug_print(level, format_args!("{}", args))
+    } else {
+        // Otherwise we format the logs properly
+        Plat::debug_print(
+            level,
+            format_args!("[{} | {}] {}\n", level_display(level), target, args),
+        )
+    }
+}
+
 pub fn init() {
     static IS_INITIALIZED: AtomicBool = AtomicBool::new(false);
 
@@ -54,6 +86,300 @@ pub fn init() {
     };
 }
 
+// ——————————————————————————— Log Deduplication ———————————————————————————— //
+
+/// Maximum length of a message that can be deduplicated, longer messages are always emitted.
+const MAX_DEDUPLICATED_LEN: usize = 256;
+
+/// The last message emitted, shared by all harts.
+static LAST_MESSAGE: Mutex<Deduplicator> = Mutex::new(Deduplicator::new());
+
+/// Coalesces consecutive identical messages.
+///
+/// The last message is kept in a small buffer, and repetitions of that message are counted rather
+/// than emitted. The number of repetitions is emitted once a different message is logged.
+struct Deduplicator {
+    buffer: [u8; MAX_DEDUPLICATED_LEN],
+    /// Length of the last message, or `None` if it did not fit in the buffer.
+    len: Option<usize>,
+    /// Level of the last message.
+    level: Level,
+    /// Number of times the last message has been repeated.
+    repeated: usize,
+}
+
+impl Deduplicator {
+    const fn new() -> Self {
+        Deduplicator {
+            buffer: [0; MAX_DEDUPLICATED_LEN],
+            len: None,
+            level: Level::Info,
+            repeated: 0,
+        }
+    }
+
+    /// Records the message, and returns whether it must be emitted.
+    ///
+    /// Messages identical to the previous one are not emitted. Otherwise the number of times the
+    /// previous message was repeated, if any, must be reported before the new message.
+    fn deduplicate(&mut self, level: Level, target: &str, args: fmt::Arguments) -> Dedup {
+        // The fields are separated so that different messages never share the same key
+        let mut message = MessageBuffer::<MAX_DEDUPLICATED_LEN>::new();
+        let fits = write!(message, "{}\0{}\0{}", level, target, args).is_ok() && !message.overflow;
+
+        if fits
+            && self.len == Some(message.len)
+            && self.buffer[..message.len] == message.as_bytes()[..]
+        {
+            self.repeated += 1;
+            return Dedup::Repeated;
+        }
+
+        let previous = (self.repeated > 0).then_some((self.level, self.repeated));
+        self.buffer = message.buffer;
+        self.len = if fits { Some(message.len) } else { None };
+        self.level = level;
+        self.repeated = 0;
+
+        Dedup::Emit { previous }
+    }
+}
+
+/// The outcome of deduplicating a message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Dedup {
+    /// The message repeats the previous one, and must not be emitted.
+    Repeated,
+    /// The message must be emitted, after reporting the level and number of repetitions of the
+    /// previous message, if it was repeated.
+    Emit { previous: Option<(Level, usize)> },
+}
+
+/// Reports the number of repetitions of the last emitted message.
+fn write_repetitions(level: Level, repeated: usize) {
+    write_log(
+        level,
+        module_path!(),
+        format_args!("(last message repeated {} times)", repeated),
+    );
+}
+
+/// A fixed-size buffer to format messages into.
+struct MessageBuffer<const N: usize> {
+    buffer: [u8; N],
+    len: usize,
+    overflow: bool,
+}
+
+impl<const N: usize> MessageBuffer<N> {
+    const fn new() -> Self {
+        MessageBuffer {
+            buffer: [0; N],
+            len: 0,
+            overflow: false,
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+}
+
+impl<const N: usize> Write for MessageBuffer<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let bytes = s.as_bytes();
+        let Some(dest) = self.buffer.get_mut(self.len..self.len + bytes.len()) else {
+            self.overflow = true;
+            return Ok(());
+        };
+        dest.copy_from_slice(bytes);
+        self.len += bytes.len();
+        Ok(())
+    }
+}
+
+// ——————————————————————————— Shared Page Backend —————————————————————————— //
+
+/// The places log records can be written to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Backend {
+    Uart,
+    SharedPage,
+    Both,
+}
+
+/// The log backend, selected at compile time by the configuration.
+const LOG_BACKEND: Backend = match config::LOG_BACKEND {
+    None => Backend::Uart,
+    Some(s) => {
+        let backend = match s.as_bytes() {
+            b"uart" => Backend::Uart,
+            b"shared-page" => Backend::SharedPage,
+            b"both" => Backend::Both,
+            _ => panic!("Invalid log backend, expected uart, shared-page or both"),
+        };
+        if !matches!(backend, Backend::Uart) && config::LOG_SHARED_PAGE.is_none() {
+            panic!("Logging to a shared page requires MIRALIS_LOG_SHARED_PAGE");
+        }
+        backend
+    }
+};
+
+/// Address of the page holding the log ring buffer, if the logs are written to a shared page.
+pub const SHARED_PAGE: Option<usize> = match LOG_BACKEND {
+    Backend::Uart => None,
+    Backend::SharedPage | Backend::Both => config::LOG_SHARED_PAGE,
+};
+
+/// Maximum length of a message written to the shared page, longer messages are truncated.
+const MAX_SHARED_PAGE_MESSAGE_LEN: usize = 256;
+
+/// The log ring buffer, shared by all harts.
+///
+/// Empty until the shared page has been validated, records logged before are not written to it.
+static LOG_RING: Mutex<Option<LogRing<'static>>> = Mutex::new(None);
+
+/// Set when Miralis panics, the shared page backend must then not wait on the ring buffer lock.
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+/// Validates the shared page and starts writing the logs to it, if configured.
+///
+/// The page must be aligned and in guest RAM. It is made read-only to the firmware and payload,
+/// which can drain the logs but not tamper with the ring buffer. Must be called on every hart.
+pub fn init_shared_page(mctx: &mut MiralisContext) {
+    let Some(addr) = SHARED_PAGE else {
+        return;
+    };
+
+    if let Err(err) = mctx.check_shared_page(addr, layout::PAGE_SIZE) {
+        log::error!("Invalid log shared page 0x{:x}: {}", addr, err);
+        Plat::exit_failure();
+    }
+    mctx.pmp
+        .set_napot(LOG_PAGE_OFFSET, addr, layout::PAGE_SIZE, pmpcfg::R);
+
+    LOG_RING.lock().get_or_insert_with(|| {
+        // SAFETY: the page has been validated above, it is in RAM and not used by Miralis for
+        // anything else.
+        let page = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, layout::PAGE_SIZE) };
+        LogRing::new(page)
+    });
+}
+
+/// Signals that Miralis is panicking, from now on the logs must not wait on locks that might be
+/// held by the panicking code.
+pub fn set_panicking() {
+    PANICKING.store(true, Ordering::SeqCst);
+}
+
+/// Appends the log to the ring buffer in the shared page.
+fn write_shared_page(level: Level, target: &str, args: fmt::Arguments) {
+    let mut message = MessageBuffer::<MAX_SHARED_PAGE_MESSAGE_LEN>::new();
+    let _ = write!(message, "{}: {}", target, args);
+
+    // Do not wait on the lock when panicking, as we might be panicking while writing a record.
+    let mut ring = if PANICKING.load(Ordering::SeqCst) {
+        let Some(ring) = LOG_RING.try_lock() else {
+            return;
+        };
+        ring
+    } else {
+        LOG_RING.lock()
+    };
+    if let Some(ring) = ring.as_mut() {
+        ring.push(level as u8, message.as_bytes());
+    }
+}
+
+/// A ring buffer of log records, following the layout of [miralis_core::log_ring].
+///
+/// The records are only appended, readers drain the buffer on their own using the number of bytes
+/// written from the header.
+///
+/// The page is shared, so the position of the writer is kept by Miralis and only published to the
+/// header: a corrupted header must never make Miralis write outside of the buffer.
+struct LogRing<'a> {
+    page: &'a mut [u8],
+    /// Total number of bytes written to the buffer.
+    written: usize,
+}
+
+impl<'a> LogRing<'a> {
+    /// Initializes an empty ring buffer covering the whole page.
+    fn new(page: &'a mut [u8]) -> Self {
+        let mut ring = LogRing { page, written: 0 };
+        let capacity = ring.capacity();
+        assert!(
+            capacity > layout::RECORD_HEADER_LEN
+                && capacity <= u16::MAX as usize
+                && capacity.is_multiple_of(layout::RECORD_ALIGN),
+            "Invalid log ring buffer size"
+        );
+
+        // Write the magic last, so that readers never see a valid header with stale content
+        ring.set_word(layout::MAGIC_IDX, 0);
+        ring.set_word(layout::VERSION_IDX, layout::VERSION);
+        ring.set_word(layout::CAPACITY_IDX, capacity as u64);
+        ring.set_word(layout::WRITTEN_IDX, 0);
+        fence(Ordering::SeqCst);
+        ring.set_word(layout::MAGIC_IDX, layout::MAGIC);
+
+        ring
+    }
+
+    /// Size of the ring buffer, in bytes.
+    fn capacity(&self) -> usize {
+        self.page.len() - layout::HEADER_LEN * size_of::<u64>()
+    }
+
+    /// Appends a record, truncating the message if it does not fit in the buffer.
+    fn push(&mut self, level: u8, message: &[u8]) {
+        let capacity = self.capacity();
+        let message = &message[..message.len().min(capacity - layout::RECORD_HEADER_LEN)];
+        let record_len =
+            (layout::RECORD_HEADER_LEN + message.len()).next_multiple_of(layout::RECORD_ALIGN);
+
+        let mut offset = self.written % capacity;
+        if offset + record_len > capacity {
+            // Records never wrap around, pad the end of the buffer and start over
+            let padding = capacity - offset;
+            self.write_record_header(
+                offset,
+                padding - layout::RECORD_HEADER_LEN,
+                layout::PADDING_LEVEL,
+            );
+            self.written += padding;
+            offset = 0;
+        }
+
+        self.write_record_header(offset, message.len(), level);
+        let start = self.data_offset(offset) + layout::RECORD_HEADER_LEN;
+        self.page[start..start + message.len()].copy_from_slice(message);
+
+        // Publish the record only once it is complete
+        self.written += record_len;
+        fence(Ordering::SeqCst);
+        self.set_word(layout::WRITTEN_IDX, self.written as u64);
+    }
+
+    fn write_record_header(&mut self, offset: usize, len: usize, level: u8) {
+        let start = self.data_offset(offset);
+        let [len_low, len_high] = (len as u16).to_le_bytes();
+        self.page[start..start + layout::RECORD_HEADER_LEN]
+            .copy_from_slice(&[len_low, len_high, level, 0]);
+    }
+
+    /// Returns the offset in the page of an offset in the ring buffer.
+    fn data_offset(&self, offset: usize) -> usize {
+        layout::HEADER_LEN * size_of::<u64>() + offset
+    }
+
+    fn set_word(&mut self, idx: usize, value: u64) {
+        let start = idx * size_of::<u64>();
+        self.page[start..start + size_of::<u64>()].copy_from_slice(&value.to_le_bytes());
+    }
+}
+
 // —————————————————————————— Const Log Filtering ——————————————————————————— //
 // We want to enable the filtering of logs at compile time on the critical
 // path.
@@ -225,4 +551,118 @@ mod tests {
         assert!(contains_target(&["car", "train", "boat"], "train"));
         assert!(contains_target(&["car", "train", "boat"], "boat"));
     }
+
+    #[test]
+    fn deduplicate() {
+        let mut dedup = Deduplicator::new();
+        let mut log = |level: Level, target: &str, message: &str| {
+            dedup.deduplicate(level, target, format_args!("{}", message))
+        };
+        let emit = Dedup::Emit { previous: None };
+
+        assert_eq!(log(Level::Info, "test", "Hello"), emit);
+        assert_eq!(log(Level::Info, "test", "trap"), emit);
+        assert_eq!(log(Level::Info, "test", "trap"), Dedup::Repeated);
+        assert_eq!(log(Level::Info, "test", "trap"), Dedup::Repeated);
+
+        // The repetitions are reported when the key changes
+        assert_eq!(
+            log(Level::Warn, "test", "trap"),
+            Dedup::Emit {
+                previous: Some((Level::Info, 2))
+            }
+        );
+        assert_eq!(log(Level::Warn, "other", "trap"), emit);
+        assert_eq!(log(Level::Info, "test", "Goodbye"), emit);
+        assert_eq!(log(Level::Info, "test", "Goodbye"), Dedup::Repeated);
+
+        // The fields of the key are separated
+        assert_eq!(
+            log(Level::Info, "test", "x"),
+            Dedup::Emit {
+                previous: Some((Level::Info, 1))
+            }
+        );
+        assert_eq!(log(Level::Info, "testx", ""), emit);
+
+        // Messages too long to be buffered are never coalesced
+        let long_message = "x".repeat(MAX_DEDUPLICATED_LEN);
+        assert_eq!(log(Level::Info, "test", &long_message), emit);
+        assert_eq!(log(Level::Info, "test", &long_message), emit);
+    }
+
+    /// Reads a word of the page header.
+    fn word(page: &[u8], idx: usize) -> u64 {
+        let start = idx * size_of::<u64>();
+        u64::from_le_bytes(page[start..start + size_of::<u64>()].try_into().unwrap())
+    }
+
+    /// Reads the records written between two positions, as a reader draining the page would.
+    fn read_records(page: &[u8], mut from: usize, to: usize) -> Vec<(u8, String)> {
+        let header = layout::HEADER_LEN * size_of::<u64>();
+        let capacity = page.len() - header;
+        let mut records = Vec::new();
+        while from < to {
+            let start = header + from % capacity;
+            let len = u16::from_le_bytes([page[start], page[start + 1]]) as usize;
+            let level = page[start + 2];
+            let message = &page[start + layout::RECORD_HEADER_LEN..][..len];
+            if level != layout::PADDING_LEVEL {
+                records.push((level, String::from_utf8(message.to_vec()).unwrap()));
+            }
+            from += (layout::RECORD_HEADER_LEN + len).next_multiple_of(layout::RECORD_ALIGN);
+        }
+        records
+    }
+
+    #[test]
+    fn log_ring() {
+        // A ring buffer of 32 bytes
+        let mut page = [0xff_u8; layout::HEADER_LEN * 8 + 32];
+        let mut ring = LogRing::new(&mut page);
+        let written = |ring: &LogRing| word(ring.page, layout::WRITTEN_IDX) as usize;
+
+        ring.push(Level::Info as u8, b"hello");
+        ring.push(Level::Warn as u8, b"world!!");
+        assert_eq!(written(&ring), 24, "Records are padded to 4 bytes");
+        assert_eq!(
+            read_records(ring.page, 0, 24),
+            [(3, String::from("hello")), (2, String::from("world!!"))]
+        );
+
+        // The next record does not fit in the last 8 bytes, it wraps to the start of the buffer
+        ring.push(Level::Error as u8, b"wrapped");
+        assert_eq!(written(&ring), 44, "The end of the buffer is padded");
+        assert_eq!(&ring.page[56..60], &[4, 0, layout::PADDING_LEVEL, 0]);
+        assert_eq!(&ring.page[32..36], &[7, 0, 1, 0]);
+        assert_eq!(
+            read_records(ring.page, 24, 44),
+            [(1, String::from("wrapped"))]
+        );
+        assert_eq!(
+            read_records(ring.page, 12, 44),
+            [(2, String::from("world!!")), (1, String::from("wrapped"))],
+            "Records not yet overwritten are still readable"
+        );
+
+        // Messages longer than the buffer are truncated
+        let long_message = "x".repeat(64);
+        ring.push(Level::Debug as u8, long_message.as_bytes());
+        assert_eq!(written(&ring), 96);
+        assert_eq!(read_records(ring.page, 64, 96), [(4, "x".repeat(28))]);
+
+        let header: Vec<u64> = (0..layout::HEADER_LEN)
+            .map(|idx| word(ring.page, idx))
+            .collect();
+        assert_eq!(header, [layout::MAGIC, layout::VERSION, 32, 96]);
+
+        // The position published in the page is never read back
+        ring.set_word(layout::WRITTEN_IDX, u64::MAX - 2);
+        ring.push(Level::Info as u8, b"again");
+        assert_eq!(written(&ring), 108);
+        assert_eq!(
+            read_records(ring.page, 96, 108),
+            [(3, String::from("again"))]
+        );
+    }
 }
@@ -14,15 +14,16 @@ use core::arch::global_asm;
 
 use miralis::arch;
 use miralis::arch::perf_counters::DELGATE_PERF_COUNTERS_MASK;
-use miralis::arch::{Csr, Mode, Register, misa, set_mpp, write_pmp};
+use miralis::arch::pmp::pmplayout;
+use miralis::arch::{Csr, Mode, Register, misa, mstatus, set_mpp, write_pmp};
 use miralis::host::MiralisContext;
 use miralis::modules::{MainModule, Module};
 use miralis::platform::{Plat, Platform, init};
-use miralis::virt::VirtContext;
 use miralis::virt::traits::*;
+use miralis::virt::{ExitResult, VirtContext};
 use miralis_config::{
-    DELEGATE_PERF_COUNTER, PLATFORM_BOOT_HART_ID, PLATFORM_NAME, PLATFORM_NB_HARTS,
-    TARGET_STACK_SIZE,
+    BREAK_ON_ENTRY, DEBUG_PROTECT_READ_ONLY, DELEGATE_PERF_COUNTER, PLATFORM_BOOT_HART_ID,
+    PLATFORM_NAME, PLATFORM_NB_HARTS, TARGET_FIRMWARE_ARGS, TARGET_STACK_SIZE,
 };
 
 // Memory layout, defined in the linker script.
@@ -31,6 +32,7 @@ unsafe extern "C" {
     static _bss_start: u8;
     static _bss_stop: u8;
     static _start_address: u8;
+    static _text_stop: u8;
 }
 
 pub(crate) extern "C" fn main(_hart_id: usize, device_tree_blob_addr: usize) -> ! {
@@ -64,9 +66,19 @@ pub(crate) extern "C" fn main(_hart_id: usize, device_tree_blob_addr: usize) ->
     let hw = unsafe { arch::detect_hardware() };
     // Initialize Miralis's own context
     let mut mctx = MiralisContext::new(hw, Plat::get_miralis_start(), get_miralis_size());
+    if DEBUG_PROTECT_READ_ONLY {
+        mctx.pmp.protect_read_only(
+            pmplayout::READ_ONLY_OFFSET,
+            &raw const _start_address as usize,
+            &raw const _text_stop as usize,
+        );
+    }
+    miralis::logger::init_shared_page(&mut mctx);
+    miralis::benchmark::check_shared_page(&mctx);
 
     // Initialize the virtual context and configure architecture
     let mut ctx = VirtContext::new(hart_id, mctx.pmp.nb_virt_pmp, mctx.hw.extensions.clone());
+    ctx.trap_satp = module.traps_satp_writes();
     unsafe {
         // Set return address, mode and PMP permissions
         set_mpp(Mode::U);
@@ -76,9 +88,21 @@ pub(crate) extern "C" fn main(_hart_id: usize, device_tree_blob_addr: usize) ->
         // Configure the firmware context
         ctx.set(Register::X10, hart_id);
         ctx.set(Register::X11, device_tree_blob_addr);
+        for &(register, value) in TARGET_FIRMWARE_ARGS {
+            ctx.set(Register::from(register), value);
+        }
         ctx.csr.misa = arch::read_csr(Csr::Misa) & !misa::DISABLED;
         ctx.pc = firmware_addr;
 
+        // The firmware starts with the FP unit Off (the virtual mstatus.FS is zero), the physical
+        // FS must match so that the FP instructions of the firmware trap until it enables FS.
+        arch::clear_csr_bits(Csr::Mstatus, mstatus::FS_FILTER);
+
+        // Miralis relies on mcycle and minstret for its own measurements
+        if mctx.hw.extensions.has_zicntr {
+            miralis::benchmark::enable_counters();
+        }
+
         if DELEGATE_PERF_COUNTER {
             log::info!("Delegating performance counters");
             arch::write_csr(Csr::Mcounteren, DELGATE_PERF_COUNTERS_MASK);
@@ -94,18 +118,20 @@ pub(crate) extern "C" fn main(_hart_id: usize, device_tree_blob_addr: usize) ->
         Plat::exit_success();
     }
 
+    // Inspection point before any firmware code runs
+    if BREAK_ON_ENTRY {
+        log::info!("{}", miralis::debug::EntryDump::new(&ctx));
+    }
+
     // SAFETY: At this point we initialized the hardware, loaded the firmware, and configured the
     // initial register values.
-    unsafe {
-        miralis::main_loop(&mut ctx, &mut mctx, &mut module);
-    }
+    let result = unsafe { miralis::main_loop(&mut ctx, &mut mctx, &mut module) };
 
-    // If we reach here it means the firmware exited successfully.
-    module.on_shutdown();
+    // If we reach here it means the firmware or payload requested to terminate the execution.
     unsafe {
         miralis::debug::log_stack_usage(&raw const _stack_start as usize);
     }
-    Plat::exit_success();
+    miralis::shutdown(&mut ctx, &mut mctx, &mut module, result == ExitResult::Done);
 }
 
 /// Return the size of Miralis, including the stacks, rounded up the nearest power of two.
@@ -123,8 +149,10 @@ fn get_miralis_size() -> usize {
 #[panic_handler]
 #[cfg(not(any(test, feature = "userspace")))]
 fn panic(info: &core::panic::PanicInfo) -> ! {
+    miralis::logger::set_panicking();
     log::error!("Panicked at {:#?} ", info);
     unsafe { miralis::debug::log_stack_usage(&raw const _stack_start as usize) };
+    // Exiting flushes the console
     Plat::exit_failure();
 }
 
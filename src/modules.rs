@@ -7,8 +7,11 @@ use module_macro::{build_modules, for_each_module};
 
 use crate::arch;
 use crate::arch::Csr;
+use crate::arch::pmp::Segment;
+use crate::arch::pmp::pmplayout::MODULE_OFFSET;
 use crate::config::PLATFORM_BOOT_HART_ID;
 use crate::host::MiralisContext;
+use crate::utils::const_str_eq;
 use crate::virt::{ExecutionMode, VirtContext};
 
 // ———————————————————————————— Module Interface ———————————————————————————— //
@@ -32,6 +35,15 @@ pub trait Module {
     /// The number of PMP entries used by the module.
     const NUMBER_PMPS: usize = 0;
 
+    /// The index of the first of the [Module::NUMBER_PMPS] entries reserved for the module.
+    ///
+    /// Each selected module is allocated its own range of PMP entries, so that modules can be
+    /// composed without overwriting each other's entries.
+    const PMP_OFFSET: usize = MainModule::pmp_offset(Self::NAME);
+
+    /// The SBI extensions (EIDs) handled by the module instead of the firmware.
+    const SBI_EXTENSIONS: &'static [usize] = &[];
+
     /// The initialization function of the module, called by Miralis at boot time.
     fn init() -> Self;
 
@@ -39,6 +51,41 @@ pub trait Module {
         Self::NAME
     }
 
+    /// Whether the module handles the SBI extension `eid` instead of the firmware.
+    fn virtualizes_sbi(&self, eid: usize) -> bool {
+        Self::SBI_EXTENSIONS.contains(&eid)
+    }
+
+    /// Whether the module allows the firmware to ask Miralis to zero the memory `region`.
+    ///
+    /// See `MIRALIS_ZERO_REGION_FID`.
+    fn allows_zeroing(&self, region: Segment) -> bool {
+        let _ = region;
+        true
+    }
+
+    /// Whether the module validates the `satp` writes of the payload, see [Module::on_satp_write].
+    fn traps_satp_writes(&self) -> bool {
+        false
+    }
+
+    /// Validates a write of `new_satp` to `satp` by the payload, before it takes effect.
+    ///
+    /// This hook is only called if one of the selected modules traps `satp` writes. Returning
+    /// false rejects the write, in which case the payload receives an illegal instruction
+    /// exception instead.
+    fn on_satp_write(
+        &mut self,
+        ctx: &mut VirtContext,
+        mctx: &mut MiralisContext,
+        new_satp: usize,
+    ) -> bool {
+        let _ = ctx;
+        let _ = mctx;
+        let _ = new_satp;
+        true
+    }
+
     /// Handle an ecall from the virtualized firmware.
     ///
     /// Note that ecalls are a subset of traps.
@@ -113,6 +160,16 @@ pub trait Module {
         let _ = mctx;
     }
 
+    /// Called once a world switch completed, in either direction.
+    ///
+    /// `cycles` is the number of cycles spent in the world switch, from the start of the context
+    /// save and restore to the end of the PMP flush. This hook is intended for benchmarking, the
+    /// cycles are only measured when the exit counter benchmark is enabled and are 0 otherwise.
+    fn world_switch_done(&mut self, ctx: &mut VirtContext, cycles: usize) {
+        let _ = ctx;
+        let _ = cycles;
+    }
+
     /// Interpose after the next mode has been decided, but before world switch if any.
     ///
     /// This module hook can be useful for collecting statistics about traps to firmware, such as
@@ -139,8 +196,28 @@ pub trait Module {
         let _ = mctx;
     }
 
+    /// Hook called after each payload instruction while single-stepping is enabled.
+    ///
+    /// Single-stepping is enabled through the `MIRALIS_ENABLE_SINGLESTEP_FID` ecall, the context
+    /// holds the state of the payload before executing the next instruction.
+    fn on_step(&mut self, ctx: &mut VirtContext, mctx: &mut MiralisContext) {
+        let _ = ctx;
+        let _ = mctx;
+    }
+
     /// Hook called before shutting down.
-    fn on_shutdown(&mut self) {}
+    ///
+    /// The hook is called when Miralis exits, either because the firmware or payload reported
+    /// success or failure, or because Miralis itself aborted the execution. This gives a chance
+    /// to policies to scrub sensitive state or report final statistics.
+    ///
+    /// The hook is only called on the hart that shuts Miralis down. It is not called when the
+    /// firmware exits the platform on its own, for instance by writing to the QEMU finisher when
+    /// serving an SBI system reset (SRST) call, as the finisher is not virtualized.
+    fn on_shutdown(&mut self, ctx: &mut VirtContext, mctx: &mut MiralisContext) {
+        let _ = ctx;
+        let _ = mctx;
+    }
 }
 
 /// Outcome of a module hook.
@@ -182,17 +259,61 @@ build_modules! {
     "keystone" => crate::policy::keystone::KeystonePolicy
     "protect_payload" => crate::policy::protect_payload::ProtectPayloadPolicy
     "offload" => crate::policy::offload::OffloadPolicy
+    "entropy_test" => crate::policy::entropy_test::EntropyTestPolicy
+    "hsm" => crate::policy::hsm::HsmPolicy
+    "single_step_test" => crate::policy::single_step_test::SingleStepTestPolicy
+    "shutdown_test" => crate::policy::shutdown_test::ShutdownTestPolicy
+    "audit" => crate::policy::audit::AuditPolicy
+    "satp_test" => crate::policy::satp_test::SatpTestPolicy
     "exit_counter" => crate::benchmark::counter::CounterBenchmark
-    "exit_counter_per_cause" => crate::benchmark::counter_per_cause::CounterPerMcauseBenchmark
+    "exit_counter_per_cause" => crate::benchmark::counter_per_mcause::CounterPerMcauseBenchmark
     "boot_counter" => crate::benchmark::boot::BootBenchmark
 }
 
+impl MainModule {
+    /// Returns the index of the first PMP entry reserved for the module named `name`.
+    const fn pmp_offset(name: &str) -> usize {
+        pmp_offset_in(Self::MODULE_PMPS, MODULE_OFFSET, name)
+    }
+
+    /// Returns true if the module named `name` is selected at compile time.
+    pub const fn is_enabled(name: &str) -> bool {
+        let mut idx = 0;
+        while idx < Self::MODULE_PMPS.len() {
+            if const_str_eq(Self::MODULE_PMPS[idx].0, name) {
+                return true;
+            }
+            idx += 1;
+        }
+        false
+    }
+}
+
+/// Returns the index of the first PMP entry of the module `name`, where each of the `modules`
+/// (given as name and number of PMP entries) is allocated its entries in order from `base`.
+///
+/// Modules that are not part of `modules` are never executed, and do not own any entry.
+const fn pmp_offset_in(modules: &[(&str, usize)], base: usize, name: &str) -> usize {
+    let mut offset = base;
+    let mut idx = 0;
+    while idx < modules.len() {
+        let (module, nb_pmps) = modules[idx];
+        if const_str_eq(module, name) {
+            return offset;
+        }
+        offset += nb_pmps;
+        idx += 1;
+    }
+    offset
+}
+
 impl Module for MainModule {
     const NAME: &'static str = "Main Module";
 
     /// The total number of PMPs is computed as the sum of the number of PMPs for each selected
     /// module.
     const NUMBER_PMPS: usize = MainModule::TOTAL_PMPS;
+    const PMP_OFFSET: usize = MODULE_OFFSET;
 
     fn init() -> Self {
         let module = for_each_module!(
@@ -341,6 +462,79 @@ impl Module for MainModule {
         );
     }
 
+    fn virtualizes_sbi(&self, eid: usize) -> bool {
+        // Remove "unused" warning when building with no modules
+        let _ = &eid;
+
+        for_each_module!(
+            $(
+                if self.$module.virtualizes_sbi(eid) {
+                    return true;
+                }
+            )*
+        );
+        false
+    }
+
+    fn allows_zeroing(&self, region: Segment) -> bool {
+        // Remove "unused" warning when building with no modules
+        let _ = &region;
+
+        for_each_module!(
+            $(
+                if !self.$module.allows_zeroing(region) {
+                    return false;
+                }
+            )*
+        );
+        true
+    }
+
+    fn traps_satp_writes(&self) -> bool {
+        for_each_module!(
+            $(
+                if self.$module.traps_satp_writes() {
+                    return true;
+                }
+            )*
+        );
+        false
+    }
+
+    fn on_satp_write(
+        &mut self,
+        ctx: &mut VirtContext,
+        mctx: &mut MiralisContext,
+        new_satp: usize,
+    ) -> bool {
+        // Remove "unused" warning when building with no modules
+        let _ = &mctx;
+        let _ = &ctx;
+        let _ = &new_satp;
+
+        // The write must be approved by all modules
+        for_each_module!(
+            $(
+                if !self.$module.on_satp_write(ctx, mctx, new_satp) {
+                    return false;
+                }
+            )*
+        );
+        true
+    }
+
+    fn world_switch_done(&mut self, ctx: &mut VirtContext, cycles: usize) {
+        // Remove "unused" warning when building with no modules
+        let _ = &ctx;
+        let _ = &cycles;
+
+        for_each_module!(
+            $(
+                self.$module.world_switch_done(ctx, cycles);
+            )*
+        );
+    }
+
     fn on_interrupt(&mut self, ctx: &mut VirtContext, mctx: &mut MiralisContext) {
         // Remove "unused" warning when building with no modules
         let _ = &mctx;
@@ -353,10 +547,26 @@ impl Module for MainModule {
         );
     }
 
-    fn on_shutdown(&mut self) {
+    fn on_step(&mut self, ctx: &mut VirtContext, mctx: &mut MiralisContext) {
+        // Remove "unused" warning when building with no modules
+        let _ = &mctx;
+        let _ = &ctx;
+
+        for_each_module!(
+            $(
+                self.$module.on_step(ctx, mctx);
+            )*
+        );
+    }
+
+    fn on_shutdown(&mut self, ctx: &mut VirtContext, mctx: &mut MiralisContext) {
+        // Remove "unused" warning when building with no modules
+        let _ = &mctx;
+        let _ = &ctx;
+
         for_each_module!(
             $(
-                self.$module.on_shutdown();
+                self.$module.on_shutdown(ctx, mctx);
             )*
         );
     }
@@ -386,3 +596,24 @@ impl MainModule {
         }
     }
 }
+
+// ————————————————————————————————— Tests —————————————————————————————————— //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn composed_pmp_offsets() {
+        let modules = [("Keystone", 4), ("Audit", 0), ("Protect Payload", 2)];
+
+        // Each module gets its own range of entries, in order
+        assert_eq!(pmp_offset_in(&modules, 5, "Keystone"), 5);
+        assert_eq!(pmp_offset_in(&modules, 5, "Audit"), 9);
+        assert_eq!(pmp_offset_in(&modules, 5, "Protect Payload"), 9);
+
+        // Modules that are not selected do not overlap with the selected ones
+        assert_eq!(pmp_offset_in(&modules, 5, "Offload"), 11);
+        assert_eq!(pmp_offset_in(&[], 5, "Keystone"), 5);
+    }
+}
@@ -4,11 +4,13 @@ use core::fmt;
 
 use log::Level;
 use miralis_abi::{failure, miralis_log_fmt, success};
+use miralis_core::pmp;
 
 use crate::Platform;
-use crate::device::VirtDevice;
+use crate::config::DISABLED_DEVICES;
 use crate::device::clint::{CLINT_SIZE, VirtClint};
 use crate::device::tester::{TEST_DEVICE_SIZE, VirtTestDevice};
+use crate::device::{VirtDevice, enabled_devices, nb_enabled_devices};
 use crate::driver::clint::ClintDriver;
 
 // —————————————————————————— Platform Parameters ——————————————————————————— //
@@ -30,8 +32,8 @@ static VIRT_CLINT: VirtClint = VirtClint::new(&CLINT_MUTEX);
 /// The virtual test device.
 static VIRT_TEST_DEVICE: VirtTestDevice = VirtTestDevice::new();
 
-/// The list of virtual devices exposed on the platform.
-static VIRT_DEVICES: &[VirtDevice; 2] = &[
+/// All the virtual devices of the platform, including the disabled ones.
+static ALL_VIRT_DEVICES: &[VirtDevice; 2] = &[
     VirtDevice {
         start_addr: CLINT_BASE,
         size: CLINT_SIZE,
@@ -46,6 +48,10 @@ static VIRT_DEVICES: &[VirtDevice; 2] = &[
     },
 ];
 
+/// The list of virtual devices exposed on the platform.
+static VIRT_DEVICES: &[VirtDevice; nb_enabled_devices(ALL_VIRT_DEVICES, DISABLED_DEVICES)] =
+    &enabled_devices(ALL_VIRT_DEVICES, DISABLED_DEVICES);
+
 // ———————————————————————————————— Platform ———————————————————————————————— //
 
 pub struct MiralisPlatform {}
@@ -53,6 +59,7 @@ pub struct MiralisPlatform {}
 impl Platform for MiralisPlatform {
     const NB_HARTS: usize = usize::MAX;
     const NB_VIRT_DEVICES: usize = VIRT_DEVICES.len();
+    const NB_PROTECTED_REGIONS: usize = pmp::protected_regions::MIRALIS;
 
     fn name() -> &'static str {
         "Miralis"
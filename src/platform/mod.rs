@@ -3,6 +3,7 @@ mod premierp550;
 mod virt;
 mod visionfive2;
 
+use core::sync::atomic::{AtomicUsize, Ordering};
 use core::{fmt, hint};
 
 use config_select::select_env;
@@ -13,10 +14,16 @@ pub use virt::VirtPlatform;
 pub use visionfive2::VisionFive2Platform;
 
 // Re-export virt platform by default for now
-use crate::arch;
-use crate::config::{TARGET_FIRMWARE_ADDRESS, TARGET_START_ADDRESS};
+use crate::arch::pmp::Segment;
+use crate::arch::{self, Csr};
+use crate::config::{
+    PLATFORM_BOOT_HART_ID, PLATFORM_STRICT_BOOT_HART, TARGET_FIRMWARE_ADDRESS,
+    TARGET_FIRMWARE_ALIGNMENT, TARGET_PAYLOAD_ADDRESS, TARGET_START_ADDRESS,
+};
 use crate::device::clint::VirtClint;
 use crate::driver::clint::ClintDriver;
+use crate::driver::finisher::{FinisherDriver, FinisherStatus};
+use crate::rng::EntropySource;
 use crate::{debug, device, logger};
 
 // ——————————————————————————— Platform Constants ——————————————————————————— //
@@ -50,6 +57,49 @@ pub type Plat = select_env!["MIRALIS_PLATFORM_NAME":
 
 ];
 
+// ——————————————————————————————— Memory Map ——————————————————————————————— //
+
+/// The kind of a region of the platform physical memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryKind {
+    /// Main memory, accessible to the firmware.
+    Ram,
+    /// Memory-mapped device registers.
+    Device,
+    /// Memory that must not be accessed by the firmware nor the payload.
+    Reserved,
+    /// Memory used by Miralis itself.
+    MiralisSelf,
+}
+
+/// A region of the platform physical memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryRegion {
+    pub base: usize,
+    pub size: usize,
+    pub kind: MemoryKind,
+}
+
+impl MemoryRegion {
+    /// Returns true if the region must be protected from the firmware and payload.
+    pub const fn is_protected(&self) -> bool {
+        matches!(self.kind, MemoryKind::Reserved | MemoryKind::MiralisSelf)
+    }
+}
+
+/// Returns the number of regions of the memory map that must be protected.
+pub const fn count_protected_regions(memory_map: &[MemoryRegion]) -> usize {
+    let mut count = 0;
+    let mut idx = 0;
+    while idx < memory_map.len() {
+        if memory_map[idx].is_protected() {
+            count += 1;
+        }
+        idx += 1;
+    }
+    count
+}
+
 // ————————————————————————————— Platform Trait ————————————————————————————— //
 
 pub trait Platform {
@@ -59,13 +109,64 @@ pub trait Platform {
     fn get_clint() -> &'static ClintDriver;
     fn get_vclint() -> &'static VirtClint;
 
+    /// Returns the physical memory map of the platform.
+    ///
+    /// Protected regions (see [MemoryRegion::is_protected]) are denied to the firmware and
+    /// payload using PMP entries.
+    fn get_memory_map() -> &'static [MemoryRegion] {
+        &[]
+    }
+
+    /// Returns the memory regions used as DMA buffers by the devices of the platform.
+    ///
+    /// Devices access those regions directly, bypassing the PMP, so Miralis must never deny them
+    /// to the firmware and payload, and refuses to boot if they overlap memory it protects. See
+    /// [MiralisContext::classify_address].
+    ///
+    /// [MiralisContext::classify_address]: crate::host::MiralisContext::classify_address
+    fn dma_regions() -> &'static [Segment] {
+        &[]
+    }
+
+    /// Returns the hardware entropy source of the platform, if any.
+    ///
+    /// When None, the [Rng](crate::rng::Rng) falls back to a software generator.
+    fn rng() -> Option<&'static dyn EntropySource> {
+        None
+    }
+
+    /// Returns the device key used to authenticate attestation measurements, if any.
+    ///
+    /// The key must be read from a platform-specific secure store (e.g. OTP fuses or sealed
+    /// memory), and never be exposed to the firmware or payload. See [crate::attestation].
+    fn get_attestation_key() -> Option<&'static [u8]> {
+        None
+    }
+
     // Platform specific initialization.
     fn init() {}
 
+    /// Returns the device used to terminate the platform, if any.
+    ///
+    /// When None, [Platform::exit_success] and [Platform::exit_failure] halt the hart instead.
+    fn get_finisher() -> Option<&'static FinisherDriver> {
+        None
+    }
+
+    /// Wait until all the bytes written to the console have been emitted.
+    ///
+    /// Must be called before halting, otherwise the last log lines might be lost. The default
+    /// implementation does nothing, which is suitable for consoles without transmit buffer.
+    fn console_flush() {}
+
     /// Halt Miralis and signal a success.
     ///
     /// The exact behavior is platform dependant.
     fn exit_success() -> ! {
+        Self::console_flush();
+        if let Some(finisher) = Self::get_finisher() {
+            finisher.exit(FinisherStatus::Pass);
+        }
         loop {
             arch::wfi();
             hint::spin_loop();
@@ -76,6 +177,10 @@ pub trait Platform {
     ///
     /// The exact behavior is platform dependant.
     fn exit_failure() -> ! {
+        Self::console_flush();
+        if let Some(finisher) = Self::get_finisher() {
+            finisher.exit(FinisherStatus::Fail(1));
+        }
         loop {
             arch::wfi();
             hint::spin_loop();
@@ -94,7 +199,23 @@ pub trait Platform {
     }
 
     /// Load the firmware (virtual M-mode software) and return its address.
+    ///
+    /// The firmware start address is validated against the configuration, and Miralis exits with
+    /// an error if it is invalid.
     fn load_firmware() -> usize {
+        if let Err(err) = check_firmware_address(
+            TARGET_FIRMWARE_ADDRESS,
+            TARGET_PAYLOAD_ADDRESS,
+            TARGET_FIRMWARE_ALIGNMENT,
+        ) {
+            log::error!(
+                "Invalid firmware address 0x{:x}: {}",
+                TARGET_FIRMWARE_ADDRESS,
+                err
+            );
+            Self::exit_failure();
+        }
+
         TARGET_FIRMWARE_ADDRESS
     }
 
@@ -135,10 +256,51 @@ pub trait Platform {
 
     const NB_HARTS: usize;
     const NB_VIRT_DEVICES: usize;
+    const NB_PROTECTED_REGIONS: usize = 0;
+    /// Frequency of `mtime`, in Hz.
+    const TIMEBASE_FREQUENCY: usize = 10_000_000;
 }
 
 // ————————————————————————————— Platform Utils ————————————————————————————— //
 
+/// Checks that the firmware start address is properly aligned and that the firmware region does
+/// not start past the payload, in which case the two images would overlap.
+pub fn check_firmware_address(
+    firmware_addr: usize,
+    payload_addr: usize,
+    alignment: usize,
+) -> Result<(), &'static str> {
+    if !alignment.is_power_of_two() {
+        return Err("the firmware alignment must be a power of two");
+    }
+    if !firmware_addr.is_multiple_of(alignment) {
+        return Err("the firmware address is not properly aligned");
+    }
+    if firmware_addr >= payload_addr {
+        return Err("the firmware must be loaded before the payload");
+    }
+
+    Ok(())
+}
+
+/// The first hart to enter [init], or `usize::MAX` if no hart did so yet.
+static PRIMARY_HART: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Registers `hart_id` as the primary hart if no hart booted before it.
+///
+/// Returns an error if `hart_id` is the primary hart, i.e. the first hart to boot Miralis, but not
+/// the configured boot hart.
+fn check_primary_hart(primary_hart: &AtomicUsize, hart_id: usize) -> Result<(), &'static str> {
+    let is_primary = primary_hart
+        .compare_exchange(usize::MAX, hart_id, Ordering::SeqCst, Ordering::SeqCst)
+        .is_ok();
+    if is_primary && hart_id != PLATFORM_BOOT_HART_ID {
+        return Err("the first hart to boot is not the boot hart, check `platform.boot_hart_id`");
+    }
+
+    Ok(())
+}
+
 /// Initializes the platform.
 ///
 /// Mut be called as the first action when booting Miralis.
@@ -149,6 +311,27 @@ pub fn init() {
     // Trap handler
     arch::init();
 
+    // The first hart to reach this point is the primary one, it must match the configuration.
+    let hart_id = arch::read_csr(Csr::Mhartid);
+    if let Err(err) = check_primary_hart(&PRIMARY_HART, hart_id) {
+        if PLATFORM_STRICT_BOOT_HART {
+            log::error!(
+                "Hart {} booted first but the boot hart is {}: {}",
+                hart_id,
+                PLATFORM_BOOT_HART_ID,
+                err
+            );
+            Plat::exit_failure();
+        } else {
+            log::warn!(
+                "Hart {} booted first but the boot hart is {}: {}",
+                hart_id,
+                PLATFORM_BOOT_HART_ID,
+                err
+            );
+        }
+    }
+
     // Ideally we would like to check this statically, until we find a good solution we assert it
     // at runtime.
     assert_eq!(
@@ -156,4 +339,43 @@ pub fn init() {
         Plat::get_virtual_devices().len(),
         "Mismatch between advertised number of devices and returned value"
     );
+    assert_eq!(
+        Plat::NB_PROTECTED_REGIONS,
+        count_protected_regions(Plat::get_memory_map()),
+        "Mismatch between advertised number of protected regions and memory map"
+    );
+}
+
+// ————————————————————————————————— Tests —————————————————————————————————— //
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::{PLATFORM_BOOT_HART_ID, check_firmware_address, check_primary_hart};
+
+    #[test]
+    fn firmware_address() {
+        assert!(check_firmware_address(0x80200000, 0x80400000, 0x1000).is_ok());
+        assert!(check_firmware_address(0x80200008, 0x80400000, 0x1000).is_err());
+        assert!(check_firmware_address(0x80200000, 0x80400000, 0x1001).is_err());
+        assert!(check_firmware_address(0x80400000, 0x80200000, 0x1000).is_err());
+    }
+
+    #[test]
+    fn boot_hart_mismatch() {
+        let other_hart = PLATFORM_BOOT_HART_ID + 1;
+
+        // The boot hart boots first, other harts follow
+        let primary = AtomicUsize::new(usize::MAX);
+        assert!(check_primary_hart(&primary, PLATFORM_BOOT_HART_ID).is_ok());
+        assert!(check_primary_hart(&primary, other_hart).is_ok());
+        assert_eq!(primary.load(Ordering::SeqCst), PLATFORM_BOOT_HART_ID);
+
+        // Another hart boots first, only that hart reports the mismatch
+        let primary = AtomicUsize::new(usize::MAX);
+        assert!(check_primary_hart(&primary, other_hart).is_err());
+        assert!(check_primary_hart(&primary, PLATFORM_BOOT_HART_ID).is_ok());
+        assert_eq!(primary.load(Ordering::SeqCst), other_hart);
+    }
 }
@@ -4,14 +4,16 @@ use core::fmt;
 use core::fmt::Write;
 
 use log::Level;
+use miralis_core::pmp;
 use spin::Mutex;
 
 use crate::Platform;
 use crate::arch::{read_custom_csr, write_custom_csr};
-use crate::device::VirtDevice;
+use crate::config::DISABLED_DEVICES;
 use crate::device::clint::{CLINT_SIZE, VirtClint};
+use crate::device::{VirtDevice, enabled_devices, nb_enabled_devices};
 use crate::driver::clint::ClintDriver;
-use crate::driver::uart::UartDriver;
+use crate::driver::uart::{self, UartDriver};
 
 // —————————————————————————— Platform Parameters ——————————————————————————— //
 
@@ -33,14 +35,18 @@ pub static WRITER: Mutex<UartDriver> = Mutex::new(UartDriver::new(
     (1 << EIC770X_UART_REG_SHIFT) as usize,
 ));
 
-/// The list of virtual devices exposed on the platform.
-static VIRT_DEVICES: &[VirtDevice; 1] = &[VirtDevice {
+/// All the virtual devices of the platform, including the disabled ones.
+static ALL_VIRT_DEVICES: &[VirtDevice; 1] = &[VirtDevice {
     start_addr: CLINT_BASE,
     size: CLINT_SIZE,
     name: "CLINT",
     device_interface: &VIRT_CLINT,
 }];
 
+/// The list of virtual devices exposed on the platform.
+static VIRT_DEVICES: &[VirtDevice; nb_enabled_devices(ALL_VIRT_DEVICES, DISABLED_DEVICES)] =
+    &enabled_devices(ALL_VIRT_DEVICES, DISABLED_DEVICES);
+
 // ———————————————————————————————— Platform ———————————————————————————————— //
 
 pub struct PremierP550Platform {}
@@ -48,6 +54,8 @@ pub struct PremierP550Platform {}
 impl Platform for PremierP550Platform {
     const NB_HARTS: usize = 4;
     const NB_VIRT_DEVICES: usize = VIRT_DEVICES.len();
+    const NB_PROTECTED_REGIONS: usize = pmp::protected_regions::PREMIERP550;
+    const TIMEBASE_FREQUENCY: usize = 1_000_000;
 
     fn name() -> &'static str {
         "Premier P550 board"
@@ -67,6 +75,10 @@ impl Platform for PremierP550Platform {
         writer.write_str("\r").unwrap();
     }
 
+    fn console_flush() {
+        uart::try_flush(&WRITER, UartDriver::flush);
+    }
+
     fn get_virtual_devices() -> &'static [VirtDevice] {
         VIRT_DEVICES
     }
@@ -146,8 +158,6 @@ const UART_FCR_OFFSET: u32 = 2;
 const UART_LCR_OFFSET: u32 = 3;
 /// Out: Modem Control Register
 const UART_MCR_OFFSET: u32 = 4;
-/// In:  Line Status Register
-const UART_LSR_OFFSET: u32 = 5;
 /// I/O: Scratch Register
 const UART_SCR_OFFSET: u32 = 7;
 
@@ -213,7 +223,7 @@ fn uart8250_init() {
     // No modem control DTR RTS
     set_reg(UART_MCR_OFFSET, 0x00);
     // Clear line status
-    get_reg(UART_LSR_OFFSET);
+    get_reg(uart::LSR_OFFSET as u32);
     // Read receive buffer
     get_reg(UART_RBR_OFFSET);
     // Set scratchpad
@@ -4,23 +4,35 @@ use core::fmt::Write;
 use core::{fmt, ptr};
 
 use log::Level;
+use miralis_core::pmp;
 use spin::Mutex;
 use uart_16550::MmioSerialPort;
 
-use super::Platform;
-use crate::config::PLATFORM_NAME;
-use crate::device::VirtDevice;
+use super::{MemoryKind, MemoryRegion, Platform};
+use crate::config::{DISABLED_DEVICES, PLATFORM_NAME};
 use crate::device::clint::{CLINT_SIZE, VirtClint};
 use crate::device::plic::VirtPlic;
 use crate::device::tester::{TEST_DEVICE_SIZE, VirtTestDevice};
+use crate::device::{VirtDevice, enabled_devices, nb_enabled_devices};
 use crate::driver::clint::ClintDriver;
+use crate::driver::finisher::{FINISHER_SIZE, FinisherDriver, FinisherStatus};
 use crate::driver::plic::PlicDriver;
+use crate::driver::uart::{self, UartDriver};
 
 const SERIAL_PORT_BASE_ADDRESS: usize = 0x10000000;
-const TEST_MMIO_ADDRESS: usize = 0x100000;
+const FINISHER_BASE: usize = 0x100000;
 const CLINT_BASE: usize = 0x2000000;
 const PLIC_BASE: usize = 0xC000000;
 const TEST_DEVICE_BASE: usize = 0x2020000;
+const SERIAL_PORT_SIZE: usize = 0x100;
+const PLIC_SIZE: usize = 0x4000000;
+
+/// The attestation key of the platform.
+///
+/// The emulators have no secure storage, so the key is fixed and publicly known: it is only
+/// provided to test builds, and deployments on the emulators have no attestation key.
+#[cfg(any(test, feature = "userspace"))]
+const ATTESTATION_KEY: &[u8; 32] = b"Miralis test attestation key 000";
 
 // —————————————————————————— Spike Parameters ——————————————————————————— //
 
@@ -46,6 +58,11 @@ static SERIAL_PORT: Mutex<Option<MmioSerialPort>> = Mutex::new(None);
 /// otherwise access the CLINT.
 static CLINT_DRIVER: ClintDriver = unsafe { ClintDriver::new(CLINT_BASE) };
 
+/// The QEMU test finisher, used to exit the emulator.
+///
+/// SAFETY: the QEMU virt board exposes a `sifive_test` device at this address.
+static FINISHER: FinisherDriver = unsafe { FinisherDriver::new(FINISHER_BASE) };
+
 /// The virtual CLINT device.
 static VIRT_CLINT: VirtClint = VirtClint::new(&CLINT_DRIVER);
 
@@ -66,8 +83,8 @@ static VIRT_PLIC: VirtPlic = VirtPlic::new(&PLIC_MUTEX);
 /// The virtual test device.
 static VIRT_TEST_DEVICE: VirtTestDevice = VirtTestDevice::new();
 
-/// The list of virtual devices exposed on the platform.
-static VIRT_DEVICES: &[VirtDevice; 2] = &[
+/// All the virtual devices of the platform, including the disabled ones.
+static ALL_VIRT_DEVICES: &[VirtDevice; 2] = &[
     VirtDevice {
         start_addr: CLINT_BASE,
         size: CLINT_SIZE,
@@ -82,6 +99,42 @@ static VIRT_DEVICES: &[VirtDevice; 2] = &[
     },
 ];
 
+/// The list of virtual devices exposed on the platform.
+static VIRT_DEVICES: &[VirtDevice; nb_enabled_devices(ALL_VIRT_DEVICES, DISABLED_DEVICES)] =
+    &enabled_devices(ALL_VIRT_DEVICES, DISABLED_DEVICES);
+
+/// The physical memory map of the platform.
+///
+/// The size of the RAM is chosen when starting the emulator, the firmware discovers it from the
+/// device tree.
+static MEMORY_MAP: &[MemoryRegion; 5] = &[
+    MemoryRegion {
+        base: FINISHER_BASE,
+        size: FINISHER_SIZE,
+        kind: MemoryKind::Device,
+    },
+    MemoryRegion {
+        base: CLINT_BASE,
+        size: CLINT_SIZE,
+        kind: MemoryKind::Device,
+    },
+    MemoryRegion {
+        base: TEST_DEVICE_BASE,
+        size: TEST_DEVICE_SIZE,
+        kind: MemoryKind::Device,
+    },
+    MemoryRegion {
+        base: PLIC_BASE,
+        size: PLIC_SIZE,
+        kind: MemoryKind::Device,
+    },
+    MemoryRegion {
+        base: SERIAL_PORT_BASE_ADDRESS,
+        size: SERIAL_PORT_SIZE,
+        kind: MemoryKind::Device,
+    },
+];
+
 // ———————————————————————————————— Platform ———————————————————————————————— //
 
 pub struct VirtPlatform {}
@@ -89,6 +142,7 @@ pub struct VirtPlatform {}
 impl Platform for VirtPlatform {
     const NB_HARTS: usize = usize::MAX;
     const NB_VIRT_DEVICES: usize = VIRT_DEVICES.len();
+    const NB_PROTECTED_REGIONS: usize = pmp::protected_regions::VIRT;
 
     fn name() -> &'static str {
         match PLATFORM_NAME {
@@ -114,17 +168,37 @@ impl Platform for VirtPlatform {
         };
     }
 
-    fn exit_success() -> ! {
+    fn console_flush() {
+        uart::try_flush(&SERIAL_PORT, |serial_port| {
+            // The serial port is a 16550 UART with byte-wide registers. Holding the lock
+            // guarantees no one else is writing to it.
+            if serial_port.is_some() {
+                UartDriver::new(SERIAL_PORT_BASE_ADDRESS, 1).flush();
+            }
+        });
+    }
+
+    fn get_finisher() -> Option<&'static FinisherDriver> {
         match PLATFORM_NAME {
-            "spike" => exit_spike(true),
-            _ => exit_qemu(true),
+            // Spike has no finisher, exits go through the HTIF instead
+            "spike" => None,
+            _ => Some(&FINISHER),
+        }
+    }
+
+    fn exit_success() -> ! {
+        Self::console_flush();
+        match Self::get_finisher() {
+            Some(finisher) => finisher.exit(FinisherStatus::Pass),
+            None => exit_spike(true),
         }
     }
 
     fn exit_failure() -> ! {
-        match PLATFORM_NAME {
-            "spike" => exit_spike(false),
-            _ => exit_qemu(false),
+        Self::console_flush();
+        match Self::get_finisher() {
+            Some(finisher) => finisher.exit(FinisherStatus::Fail(1)),
+            None => exit_spike(false),
         }
     }
 
@@ -136,23 +210,20 @@ impl Platform for VirtPlatform {
         &CLINT_DRIVER
     }
 
+    fn get_memory_map() -> &'static [MemoryRegion] {
+        MEMORY_MAP
+    }
+
     fn get_vclint() -> &'static VirtClint {
         &VIRT_CLINT
     }
-}
-
-/// Exit the QEMU emulator.
-fn exit_qemu(success: bool) -> ! {
-    let code = if success { 0x5555 } else { (1 << 16) | 0x3333 };
 
-    unsafe {
-        let mmio_addr = TEST_MMIO_ADDRESS as *mut i32;
-        ptr::write_volatile(mmio_addr, code);
-    }
+    fn get_attestation_key() -> Option<&'static [u8]> {
+        #[cfg(any(test, feature = "userspace"))]
+        return Some(ATTESTATION_KEY);
 
-    // Loop forever if shutdown failed
-    loop {
-        core::hint::spin_loop();
+        #[cfg(not(any(test, feature = "userspace")))]
+        None
     }
 }
 
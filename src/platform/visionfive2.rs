@@ -4,13 +4,15 @@ use core::fmt::Write;
 use core::{fmt, ptr};
 
 use log::Level;
+use miralis_core::pmp;
 use spin::Mutex;
 
 use crate::Platform;
-use crate::device::VirtDevice;
+use crate::config::DISABLED_DEVICES;
 use crate::device::clint::{CLINT_SIZE, VirtClint};
+use crate::device::{VirtDevice, enabled_devices, nb_enabled_devices};
 use crate::driver::clint::ClintDriver;
-use crate::driver::uart::UartDriver;
+use crate::driver::uart::{self, UartDriver};
 
 // —————————————————————————— Platform Parameters ——————————————————————————— //
 
@@ -35,14 +37,18 @@ pub static WRITER: Mutex<UartDriver> = Mutex::new(UartDriver::new(
     UART_SIZE_PER_REGISTER,
 ));
 
-/// The list of virtual devices exposed on the platform.
-static VIRT_DEVICES: &[VirtDevice; 1] = &[VirtDevice {
+/// All the virtual devices of the platform, including the disabled ones.
+static ALL_VIRT_DEVICES: &[VirtDevice; 1] = &[VirtDevice {
     start_addr: CLINT_BASE,
     size: CLINT_SIZE,
     name: "CLINT",
     device_interface: &VIRT_CLINT,
 }];
 
+/// The list of virtual devices exposed on the platform.
+static VIRT_DEVICES: &[VirtDevice; nb_enabled_devices(ALL_VIRT_DEVICES, DISABLED_DEVICES)] =
+    &enabled_devices(ALL_VIRT_DEVICES, DISABLED_DEVICES);
+
 // ———————————————————————————————— Platform ———————————————————————————————— //
 
 pub struct VisionFive2Platform {}
@@ -50,6 +56,8 @@ pub struct VisionFive2Platform {}
 impl Platform for VisionFive2Platform {
     const NB_HARTS: usize = 5;
     const NB_VIRT_DEVICES: usize = VIRT_DEVICES.len();
+    const NB_PROTECTED_REGIONS: usize = pmp::protected_regions::VISIONFIVE2;
+    const TIMEBASE_FREQUENCY: usize = 4_000_000;
 
     fn name() -> &'static str {
         "VisionFive 2 board"
@@ -69,6 +77,10 @@ impl Platform for VisionFive2Platform {
         writer.write_str("\r").unwrap();
     }
 
+    fn console_flush() {
+        uart::try_flush(&WRITER, UartDriver::flush);
+    }
+
     fn get_virtual_devices() -> &'static [VirtDevice] {
         VIRT_DEVICES
     }
@@ -0,0 +1,218 @@
+//! Audit Policy
+//!
+//! A policy recording every world switch between the firmware and the payload into a ring buffer,
+//! for security auditing and debugging. Each entry holds the value of `mtime` at the time of the
+//! switch, the cause of the last trap and the PC.
+//!
+//! The entries can be retrieved by the firmware and the payload with the
+//! `MIRALIS_READ_AUDIT_LOG_FID` ecall.
+
+use miralis_core::{abi, sbi_codes};
+
+use crate::arch::Register;
+use crate::host::MiralisContext;
+use crate::modules::{Module, ModuleAction};
+use crate::platform::{Plat, Platform};
+use crate::virt::VirtContext;
+use crate::virt::traits::*;
+
+/// Number of switches kept in the audit log, older entries are overwritten.
+const AUDIT_LOG_SIZE: usize = 64;
+
+/// The direction of a world switch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SwitchDirection {
+    FirmwareToPayload = 0,
+    PayloadToFirmware = 1,
+}
+
+/// An entry of the audit log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AuditEntry {
+    /// Value of `mtime` when the switch happened.
+    pub time: usize,
+    /// Cause of the trap that led to the switch.
+    pub cause: usize,
+    /// The PC of the payload: where it trapped when switching to the firmware, and where it
+    /// resumes when switching to the payload.
+    pub pc: usize,
+    pub direction: SwitchDirection,
+}
+
+pub struct AuditPolicy {
+    entries: [Option<AuditEntry>; AUDIT_LOG_SIZE],
+    /// Index of the next entry to write.
+    next: usize,
+}
+
+impl Module for AuditPolicy {
+    const NAME: &'static str = "Audit Policy";
+    const NUMBER_PMPS: usize = 0;
+
+    fn init() -> Self {
+        AuditPolicy {
+            entries: [None; AUDIT_LOG_SIZE],
+            next: 0,
+        }
+    }
+
+    fn ecall_from_firmware(
+        &mut self,
+        _mctx: &mut MiralisContext,
+        ctx: &mut VirtContext,
+    ) -> ModuleAction {
+        self.ecall_from_any_mode(ctx)
+    }
+
+    fn ecall_from_payload(
+        &mut self,
+        _mctx: &mut MiralisContext,
+        ctx: &mut VirtContext,
+    ) -> ModuleAction {
+        self.ecall_from_any_mode(ctx)
+    }
+
+    fn switch_from_payload_to_firmware(
+        &mut self,
+        ctx: &mut VirtContext,
+        _mctx: &mut MiralisContext,
+    ) {
+        let time = Plat::get_clint().read_mtime();
+        self.record_switch(ctx, SwitchDirection::PayloadToFirmware, time);
+    }
+
+    fn switch_from_firmware_to_payload(
+        &mut self,
+        ctx: &mut VirtContext,
+        _mctx: &mut MiralisContext,
+    ) {
+        let time = Plat::get_clint().read_mtime();
+        self.record_switch(ctx, SwitchDirection::FirmwareToPayload, time);
+    }
+}
+
+impl AuditPolicy {
+    /// Appends a switch to the audit log, overwriting the oldest entry if the log is full.
+    fn record_switch(&mut self, ctx: &VirtContext, direction: SwitchDirection, time: usize) {
+        self.entries[self.next] = Some(AuditEntry {
+            time,
+            cause: ctx.trap_info.mcause,
+            pc: ctx.pc,
+            direction,
+        });
+        self.next = (self.next + 1) % AUDIT_LOG_SIZE;
+    }
+
+    /// Returns the entry `index` switches ago, 0 being the most recent switch.
+    fn get(&self, index: usize) -> Option<AuditEntry> {
+        if index >= AUDIT_LOG_SIZE {
+            return None;
+        }
+        self.entries[(self.next + AUDIT_LOG_SIZE - 1 - index) % AUDIT_LOG_SIZE]
+    }
+
+    fn ecall_from_any_mode(&mut self, ctx: &mut VirtContext) -> ModuleAction {
+        if ctx.get(Register::X17) == abi::MIRALIS_EID
+            && ctx.get(Register::X16) == abi::MIRALIS_READ_AUDIT_LOG_FID
+        {
+            self.read_entry(ctx);
+            ctx.pc += 4;
+            ModuleAction::Overwrite
+        } else {
+            ModuleAction::Ignore
+        }
+    }
+
+    /// Returns the field a1 of the entry a0, see `MIRALIS_READ_AUDIT_LOG_FID`.
+    fn read_entry(&self, ctx: &mut VirtContext) {
+        let index = ctx.get(Register::X10);
+        let field = ctx.get(Register::X11);
+
+        let value = self.get(index).and_then(|entry| match field {
+            abi::audit::TIME => Some(entry.time),
+            abi::audit::CAUSE => Some(entry.cause),
+            abi::audit::PC => Some(entry.pc),
+            abi::audit::DIRECTION => Some(entry.direction as usize),
+            _ => None,
+        });
+
+        match value {
+            Some(value) => {
+                ctx.set(Register::X10, sbi_codes::SBI_SUCCESS);
+                ctx.set(Register::X11, value);
+            }
+            None => ctx.set(Register::X10, sbi_codes::SBI_ERR_INVALID_PARAM),
+        }
+    }
+}
+
+// ————————————————————————————————— Tests —————————————————————————————————— //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::{self, MCause, Mode};
+
+    /// Reads a field of an entry through the ecall interface.
+    fn read(policy: &mut AuditPolicy, ctx: &mut VirtContext, index: usize, field: usize) -> usize {
+        ctx.set(Register::X17, abi::MIRALIS_EID);
+        ctx.set(Register::X16, abi::MIRALIS_READ_AUDIT_LOG_FID);
+        ctx.set(Register::X10, index);
+        ctx.set(Register::X11, field);
+        assert!(policy.ecall_from_any_mode(ctx).overwrites());
+        assert_eq!(ctx.get(Register::X10), sbi_codes::SBI_SUCCESS);
+        ctx.get(Register::X11)
+    }
+
+    #[test]
+    fn switch_sequence() {
+        let mut policy = AuditPolicy::init();
+        let hw = unsafe { arch::detect_hardware() };
+        let mut ctx = VirtContext::new(0, hw.available_reg.nb_pmp, hw.extensions);
+
+        // The payload traps with an ecall, and the firmware resumes it right after
+        ctx.mode = Mode::S;
+        ctx.pc = 0x8020_0000;
+        ctx.trap_info.mcause = MCause::EcallFromSMode as usize;
+        policy.record_switch(&ctx, SwitchDirection::PayloadToFirmware, 100);
+        ctx.pc = 0x8020_0004;
+        ctx.trap_info.mcause = MCause::IllegalInstr as usize; // The emulated mret
+        policy.record_switch(&ctx, SwitchDirection::FirmwareToPayload, 150);
+
+        assert_eq!(
+            policy.get(1),
+            Some(AuditEntry {
+                time: 100,
+                cause: MCause::EcallFromSMode as usize,
+                pc: 0x8020_0000,
+                direction: SwitchDirection::PayloadToFirmware,
+            })
+        );
+        assert_eq!(
+            policy.get(0),
+            Some(AuditEntry {
+                time: 150,
+                cause: MCause::IllegalInstr as usize,
+                pc: 0x8020_0004,
+                direction: SwitchDirection::FirmwareToPayload,
+            })
+        );
+        assert_eq!(policy.get(2), None);
+
+        // The entries are exposed through the ecall
+        assert_eq!(read(&mut policy, &mut ctx, 1, abi::audit::TIME), 100);
+        assert_eq!(read(&mut policy, &mut ctx, 1, abi::audit::PC), 0x8020_0000);
+        assert_eq!(read(&mut policy, &mut ctx, 0, abi::audit::DIRECTION), 0);
+        ctx.set(Register::X10, 2);
+        policy.read_entry(&mut ctx);
+        assert_eq!(ctx.get(Register::X10), sbi_codes::SBI_ERR_INVALID_PARAM);
+
+        // Older entries are overwritten once the log is full
+        for time in 0..AUDIT_LOG_SIZE {
+            policy.record_switch(&ctx, SwitchDirection::PayloadToFirmware, 200 + time);
+        }
+        assert_eq!(policy.get(0).unwrap().time, 200 + AUDIT_LOG_SIZE - 1);
+        assert_eq!(policy.get(AUDIT_LOG_SIZE - 1).unwrap().time, 200);
+        assert_eq!(policy.get(AUDIT_LOG_SIZE), None);
+    }
+}
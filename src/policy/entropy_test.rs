@@ -0,0 +1,39 @@
+//! Entropy Test Policy
+//!
+//! A policy used to test the randomness source exposed to the policies. On the first ecall from
+//! the firmware it draws two values from the [Rng](crate::rng::Rng) and checks they differ.
+
+use crate::host::MiralisContext;
+use crate::modules::{Module, ModuleAction};
+use crate::virt::VirtContext;
+
+pub struct EntropyTestPolicy {
+    /// Whether the values have already been drawn.
+    done: bool,
+}
+
+impl Module for EntropyTestPolicy {
+    const NAME: &'static str = "Entropy Test Policy";
+
+    fn init() -> Self {
+        EntropyTestPolicy { done: false }
+    }
+
+    fn ecall_from_firmware(
+        &mut self,
+        mctx: &mut MiralisContext,
+        _ctx: &mut VirtContext,
+    ) -> ModuleAction {
+        if !self.done {
+            self.done = true;
+            let first = mctx.rng.next_u64();
+            let second = mctx.rng.next_u64();
+            log::info!("Drew random values 0x{:x} and 0x{:x}", first, second);
+            assert_ne!(first, second, "Random values should differ");
+            log::info!("Entropy test passed");
+        }
+
+        // Let Miralis handle the ecall
+        ModuleAction::Ignore
+    }
+}
@@ -0,0 +1,198 @@
+//! Hart State Management Policy
+//!
+//! This policy implements the SBI Hart State Management (HSM) extension on behalf of the firmware,
+//! allowing the payload to stop, start and suspend harts. Only the boot hart is started at boot,
+//! stopped harts are parked within Miralis in WFI until another hart starts them.
+//!
+//! See https://github.com/riscv-non-isa/riscv-sbi-doc/blob/master/src/ext-hsm.adoc
+
+use miralis_core::sbi_codes;
+use miralis_core::sbi_codes::hsm;
+use spin::Mutex;
+
+use crate::arch::{self, Csr, Mode, Register, mstatus};
+use crate::config::{PLATFORM_BOOT_HART_ID, PLATFORM_NB_HARTS};
+use crate::host::MiralisContext;
+use crate::logger;
+use crate::modules::{Module, ModuleAction};
+use crate::platform::{Plat, Platform};
+use crate::virt::VirtContext;
+use crate::virt::traits::{RegisterContextGetter, RegisterContextSetter};
+
+/// The HSM state of a hart, and the entry point to use when starting it.
+struct HartState {
+    status: usize,
+    start_addr: usize,
+    opaque: usize,
+}
+
+impl HartState {
+    const fn new(status: usize) -> Self {
+        HartState {
+            status,
+            start_addr: 0,
+            opaque: 0,
+        }
+    }
+}
+
+/// The HSM state of each hart, only the boot hart is started at boot.
+static HARTS: [Mutex<HartState>; PLATFORM_NB_HARTS] = {
+    let mut harts = [const { Mutex::new(HartState::new(hsm::STOPPED)) }; PLATFORM_NB_HARTS];
+    harts[PLATFORM_BOOT_HART_ID] = Mutex::new(HartState::new(hsm::STARTED));
+    harts
+};
+
+pub struct HsmPolicy {}
+
+impl Module for HsmPolicy {
+    const NAME: &'static str = "HSM Policy";
+    const SBI_EXTENSIONS: &'static [usize] = &[sbi_codes::HSM_EXTENSION_EID];
+
+    fn init() -> Self {
+        HsmPolicy {}
+    }
+
+    fn ecall_from_payload(
+        &mut self,
+        _mctx: &mut MiralisContext,
+        ctx: &mut VirtContext,
+    ) -> ModuleAction {
+        if ctx.get(Register::X17) != sbi_codes::HSM_EXTENSION_EID {
+            return ModuleAction::Ignore;
+        }
+
+        let result = match ctx.get(Register::X16) {
+            sbi_codes::HART_START_FID => Self::hart_start(
+                ctx.get(Register::X10),
+                ctx.get(Register::X11),
+                ctx.get(Register::X12),
+            ),
+            sbi_codes::HART_STOP_FID => {
+                // On success the hart does not return from the call
+                Self::hart_stop(ctx);
+                return ModuleAction::Overwrite;
+            }
+            sbi_codes::HART_GET_STATUS_FID => Self::hart_get_status(ctx.get(Register::X10)),
+            sbi_codes::HART_SUSPEND_FID => match ctx.get(Register::X10) {
+                hsm::SUSPEND_DEFAULT_RETENTIVE => {
+                    Self::suspend(ctx.hart_id);
+                    Ok(0)
+                }
+                hsm::SUSPEND_DEFAULT_NON_RETENTIVE => {
+                    let resume_addr = ctx.get(Register::X11);
+                    let opaque = ctx.get(Register::X12);
+                    Self::suspend(ctx.hart_id);
+                    Self::enter_supervisor(ctx, resume_addr, opaque);
+                    return ModuleAction::Overwrite;
+                }
+                _ => Err(sbi_codes::SBI_ERR_INVALID_PARAM),
+            },
+            _ => Err(sbi_codes::SBI_ERR_NOT_SUPPORTED),
+        };
+
+        match result {
+            Ok(value) => {
+                ctx.set(Register::X10, sbi_codes::SBI_SUCCESS);
+                ctx.set(Register::X11, value);
+            }
+            Err(error) => {
+                ctx.set(Register::X10, error);
+                ctx.set(Register::X11, 0);
+            }
+        }
+        ctx.pc += 4;
+        ModuleAction::Overwrite
+    }
+
+    fn switch_from_firmware_to_payload(
+        &mut self,
+        ctx: &mut VirtContext,
+        _mctx: &mut MiralisContext,
+    ) {
+        // Secondary harts are stopped until the payload starts them
+        if HARTS[ctx.hart_id].lock().status == hsm::STOPPED {
+            Self::park(ctx);
+        }
+    }
+}
+
+impl HsmPolicy {
+    fn hart_start(hart_id: usize, start_addr: usize, opaque: usize) -> Result<usize, usize> {
+        logger::debug!("HSM: starting hart {} at 0x{:x}", hart_id, start_addr);
+        let mut hart = HARTS
+            .get(hart_id)
+            .ok_or(sbi_codes::SBI_ERR_INVALID_PARAM)?
+            .lock();
+
+        match hart.status {
+            hsm::STOPPED => {
+                hart.start_addr = start_addr;
+                hart.opaque = opaque;
+                hart.status = hsm::START_PENDING;
+
+                // Wake up the parked hart
+                Plat::broadcast_policy_interrupt(1 << hart_id);
+                Ok(0)
+            }
+            hsm::START_PENDING => Err(sbi_codes::SBI_ERR_ALREADY_STARTED),
+            _ => Err(sbi_codes::SBI_ERR_ALREADY_AVAILABLE),
+        }
+    }
+
+    fn hart_stop(ctx: &mut VirtContext) {
+        logger::debug!("HSM: stopping hart {}", ctx.hart_id);
+        HARTS[ctx.hart_id].lock().status = hsm::STOPPED;
+        Self::park(ctx);
+    }
+
+    /// Park the hart in WFI until another hart starts it, then jump to the requested address.
+    ///
+    /// The hart is woken up by the policy interrupt sent by `hart_start`, which is then handled as
+    /// usual once the payload resumes.
+    fn park(ctx: &mut VirtContext) {
+        let (start_addr, opaque) = loop {
+            {
+                let mut hart = HARTS[ctx.hart_id].lock();
+                if hart.status == hsm::START_PENDING {
+                    hart.status = hsm::STARTED;
+                    break (hart.start_addr, hart.opaque);
+                }
+            }
+            arch::wfi();
+        };
+
+        Self::enter_supervisor(ctx, start_addr, opaque);
+    }
+
+    fn hart_get_status(hart_id: usize) -> Result<usize, usize> {
+        let hart = HARTS
+            .get(hart_id)
+            .ok_or(sbi_codes::SBI_ERR_INVALID_PARAM)?
+            .lock();
+        Ok(hart.status)
+    }
+
+    /// Wait for an interrupt, the hart is reported as suspended in the meantime.
+    fn suspend(hart_id: usize) {
+        HARTS[hart_id].lock().status = hsm::SUSPENDED;
+        arch::wfi();
+        HARTS[hart_id].lock().status = hsm::STARTED;
+    }
+
+    /// Resume the payload at `addr` in the state mandated by the specification: supervisor mode
+    /// with `a0` holding the hart ID, `a1` the opaque value, translation and interrupts disabled.
+    fn enter_supervisor(ctx: &mut VirtContext, addr: usize, opaque: usize) {
+        ctx.pc = addr;
+        ctx.mode = Mode::S;
+        ctx.set(Register::X10, ctx.hart_id);
+        ctx.set(Register::X11, opaque);
+
+        // The payload S-mode registers are installed on the hardware
+        unsafe {
+            arch::write_csr(Csr::Satp, 0);
+            arch::clear_csr_bits(Csr::Mstatus, mstatus::SIE_FILTER);
+        }
+        arch::sfencevma(None, None);
+    }
+}
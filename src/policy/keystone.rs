@@ -4,24 +4,28 @@
 //! enclaves by leveraging PMP for memory isolation.
 
 use core::cmp::PartialEq;
-use core::ptr;
+use core::{ptr, slice};
 
 use miralis_config::DELEGATE_PERF_COUNTER;
+use miralis_core::pmp;
+use tiny_keccak::{Hasher, Sha3};
 
 use crate::arch::perf_counters::DELGATE_PERF_COUNTERS_MASK;
-use crate::arch::pmp::pmplayout::MODULE_OFFSET;
 use crate::arch::pmp::{Segment, pmpcfg};
 use crate::arch::{Csr, MCause, Mode, Register, parse_mpp_return_mode, set_mpp, write_pmp};
 use crate::host::MiralisContext;
 use crate::modules::{Module, ModuleAction};
 use crate::policy::keystone::ReturnCode::IllegalArgument;
 use crate::virt::traits::*;
-use crate::{RegisterContextGetter, VirtContext, arch, logger};
+use crate::{RegisterContextGetter, VirtContext, arch, attestation, logger};
 
 /// Keystone parameters
 ///
 /// See https://github.com/keystone-enclave/keystone/blob/80ffb2f9d4e774965589ee7c67609b0af051dc8b/sm/src/platform/generic/platform.h#L11
 const ENCL_MAX: usize = 1; // Maximum number of enclaves
+const _: () = assert!(ENCL_MAX * 2 == pmp::modules::KEYSTONE); // 2 PMPs per enclave for TOR
+const ATTEST_DATA_MAX: usize = 1024; // Maximum size of the data attested with an enclave
+const MDSIZE: usize = 32; // Size of the enclave hash
 
 /// Keystone EID & FIDs
 ///
@@ -95,6 +99,18 @@ impl From<usize> for StoppedReason {
     }
 }
 
+/// Attestation report of an enclave
+///
+/// Keystone signs the reports with the device key, Miralis authenticates them with a MAC instead
+/// (see [attestation]). The MAC covers the hash, the data length and the data.
+#[repr(C)]
+struct Report {
+    hash: [u8; MDSIZE],
+    data_len: u64,
+    data: [u8; ATTEST_DATA_MAX],
+    mac: [u8; attestation::MAC_SIZE],
+}
+
 /// Enclave definitions
 ///
 /// See https://github.com/keystone-enclave/keystone/blob/80ffb2f9d4e774965589ee7c67609b0af051dc8b/sm/src/enclave.h
@@ -166,6 +182,7 @@ struct Enclave {
     utm: Segment,        // The untrusted physical memory region shared by the enclave and the OS.
     state: EnclaveState, // State of the enclave
     ctx: EnclaveCtx,     // Enclave context
+    hash: [u8; MDSIZE],  // Hash of the enclave memory at creation
 }
 
 /// The keystone policy module
@@ -174,7 +191,6 @@ struct Enclave {
 #[derive(Default)]
 pub struct KeystonePolicy {
     enclaves: [Enclave; ENCL_MAX], // TODO: Accessing enclaves is not thread-safe
-    nonce: usize,                  // TODO: Use a better rng
 }
 
 impl KeystonePolicy {
@@ -199,7 +215,7 @@ impl KeystonePolicy {
 
     /// Configure PMPs so that the enclave cannot be accessed
     fn lock_enclave(mctx: &mut MiralisContext, enclave: &mut Enclave) {
-        let pmp_id = MODULE_OFFSET + enclave.eid * 2;
+        let pmp_id = Self::PMP_OFFSET + enclave.eid * 2;
         mctx.pmp.set_inactive(pmp_id, enclave.epm.start());
         mctx.pmp.set_tor(
             pmp_id + 1,
@@ -214,7 +230,7 @@ impl KeystonePolicy {
 
     /// Configure PMPs so that only the enclave and the untrusted memory can be accessed
     fn unlock_enclave(mctx: &mut MiralisContext, enclave: &mut Enclave) {
-        let pmp_id = MODULE_OFFSET + enclave.eid * 2;
+        let pmp_id = Self::PMP_OFFSET + enclave.eid * 2;
 
         // Grant access to the enclave physical memory
         mctx.pmp.set_inactive(pmp_id, enclave.epm.start());
@@ -327,6 +343,7 @@ impl KeystonePolicy {
         enclave.state = EnclaveState::Fresh;
         enclave.epm = Segment::new(args.epm_paddr, args.epm_size);
         enclave.utm = Segment::new(args.utm_paddr, args.utm_size);
+        enclave.hash = measure_enclave(args.epm_paddr, args.free_paddr);
 
         // Set initial enclave context
         enclave.ctx = EnclaveCtx::default();
@@ -377,7 +394,7 @@ impl KeystonePolicy {
         self.enclaves[eid].state = EnclaveState::Invalid;
 
         // Clear enclave PMPs
-        let pmp_id = MODULE_OFFSET + eid * 2;
+        let pmp_id = Self::PMP_OFFSET + eid * 2;
         mctx.pmp.set_inactive(pmp_id, 0);
         mctx.pmp.set_inactive(pmp_id + 1, 0);
         unsafe {
@@ -399,10 +416,49 @@ impl KeystonePolicy {
         ReturnCode::Success
     }
 
-    fn random(&mut self, ctx: &mut VirtContext) -> ReturnCode {
+    fn random(&mut self, mctx: &mut MiralisContext, ctx: &mut VirtContext) -> ReturnCode {
         logger::debug!("Keystone: Random");
-        ctx.set(Register::X11, self.nonce);
-        self.nonce += 1;
+        ctx.set(Register::X11, mctx.rng.next_u64() as usize);
+        ReturnCode::Success
+    }
+
+    fn attest_enclave(&mut self, ctx: &mut VirtContext) -> ReturnCode {
+        logger::debug!("Keystone: Attest enclave");
+        let report_addr = ctx.get(Register::X10);
+        let data_addr = ctx.get(Register::X11);
+        let data_len = ctx.get(Register::X12);
+        let enclave = self.get_active_enclave(ctx).unwrap();
+
+        // The report and the data must belong to the enclave
+        let in_enclave = |addr: usize, size: usize| {
+            addr.checked_add(size).is_some() && enclave.epm.contain(Segment::new(addr, size))
+        };
+        if data_len > ATTEST_DATA_MAX
+            || !in_enclave(report_addr, size_of::<Report>())
+            || !in_enclave(data_addr, data_len)
+        {
+            return ReturnCode::IllegalArgument;
+        }
+
+        // SAFETY: the data is part of the enclave memory, which is not accessible to the firmware
+        // and payload while the enclave is running.
+        let data = unsafe { slice::from_raw_parts(data_addr as *const u8, data_len) };
+        let mut report = Report {
+            hash: enclave.hash,
+            data_len: data_len as u64,
+            data: [0; ATTEST_DATA_MAX],
+            mac: [0; attestation::MAC_SIZE],
+        };
+        report.data[..data_len].copy_from_slice(data);
+        let Some(mac) =
+            attestation::mac_measurement(&[&report.hash, &report.data_len.to_le_bytes(), data])
+        else {
+            return ReturnCode::NotImplemented;
+        };
+        report.mac = mac;
+
+        // SAFETY: the report fits in the enclave memory, checked above.
+        unsafe { ptr::write_unaligned(report_addr as *mut Report, report) };
         ReturnCode::Success
     }
 
@@ -442,6 +498,14 @@ impl Module for KeystonePolicy {
         Self::default()
     }
 
+    fn allows_zeroing(&self, region: Segment) -> bool {
+        // The memory of live enclaves belongs to the enclaves, not to the firmware
+        !self
+            .enclaves
+            .iter()
+            .any(|e| e.state != EnclaveState::Invalid && e.epm.overlap(region))
+    }
+
     fn ecall_from_payload(
         &mut self,
         mctx: &mut MiralisContext,
@@ -460,7 +524,8 @@ impl Module for KeystonePolicy {
             (false, sbi::DESTROY_ENCLAVE_FID) => self.destroy_enclave(mctx, ctx),
             (false, sbi::RUN_ENCLAVE_FID) => self.run_enclave(mctx, ctx),
             (false, sbi::RESUME_ENCLAVE_FID) => self.resume_enclave(mctx, ctx),
-            (true, sbi::RANDOM_FID) => self.random(ctx),
+            (true, sbi::RANDOM_FID) => self.random(mctx, ctx),
+            (true, sbi::ATTEST_ENCLAVE_FID) => self.attest_enclave(ctx),
             (true, sbi::STOP_ENCLAVE_FID) => self.stop_enclave(mctx, ctx),
             (true, sbi::EXIT_ENCLAVE_FID) => self.exit_enclave(mctx, ctx),
             _ => ReturnCode::NotImplemented,
@@ -496,5 +561,87 @@ impl Module for KeystonePolicy {
         }
     }
 
-    const NUMBER_PMPS: usize = ENCL_MAX * 2; // Each enclave uses 2 PMPs because of TOR addressing
+    const NUMBER_PMPS: usize = pmp::modules::KEYSTONE;
+}
+
+/// Hashes the enclave memory in `[start, end)`.
+fn measure_enclave(start: usize, end: usize) -> [u8; MDSIZE] {
+    let mut hasher = Sha3::v256();
+
+    // SAFETY: the range has been validated to be part of the enclave memory, which is locked
+    // from the firmware and payload from now on.
+    hasher.update(unsafe { slice::from_raw_parts(start as *const u8, end - start) });
+
+    let mut hash = [0; MDSIZE];
+    hasher.finalize(&mut hash);
+    hash
+}
+
+// ————————————————————————————————— Tests —————————————————————————————————— //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::Module;
+
+    /// The attestation report of an enclave is authenticated with the device key.
+    #[test]
+    fn attest_enclave() {
+        let hw = unsafe { arch::detect_hardware() };
+        let mut mctx = MiralisContext::new(hw, 0x10000, 0x2000);
+        let mut ctx = VirtContext::new(0, mctx.hw.available_reg.nb_pmp, mctx.hw.extensions.clone());
+        let mut policy = KeystonePolicy::init();
+
+        // A running enclave, with its memory backed by a buffer
+        let mut epm = vec![0x5a_u8; 0x1000 + size_of::<Report>()];
+        let epm_start = epm.as_mut_ptr() as usize;
+        let enclave = &mut policy.enclaves[0];
+        enclave.epm = Segment::new(epm_start, epm.len());
+        enclave.hash = measure_enclave(epm_start, epm_start + 0x1000);
+        enclave.state = EnclaveState::Running;
+        let hash = enclave.hash;
+
+        let mut attest = |ctx: &mut VirtContext, report: usize, data: &[u8]| {
+            unsafe { ptr::copy(data.as_ptr(), (epm_start + 8) as *mut u8, data.len()) };
+            ctx.set(Register::X17, sbi::KEYSTONE_EID);
+            ctx.set(Register::X16, sbi::ATTEST_ENCLAVE_FID);
+            ctx.set(Register::X10, report);
+            ctx.set(Register::X11, epm_start + 8);
+            ctx.set(Register::X12, data.len());
+            policy.ecall_from_payload(&mut mctx, ctx);
+            ctx.get(Register::X10)
+        };
+
+        let report_addr = epm_start + 0x1000;
+        assert_eq!(
+            attest(&mut ctx, report_addr, b"nonce"),
+            ReturnCode::Success as usize
+        );
+        let report = unsafe { ptr::read_unaligned(report_addr as *const Report) };
+        assert_eq!(report.hash, hash);
+        assert_eq!(report.data_len, 5);
+        assert_eq!(&report.data[..5], b"nonce");
+        assert_eq!(
+            Some(report.mac),
+            attestation::mac_measurement(&[&hash, &5_u64.to_le_bytes(), b"nonce"])
+        );
+
+        // Reports are deterministic, and bound to the data
+        attest(&mut ctx, report_addr, b"nonce");
+        let same = unsafe { ptr::read_unaligned(report_addr as *const Report) };
+        assert_eq!(same.mac, report.mac);
+        attest(&mut ctx, report_addr, b"other");
+        let other = unsafe { ptr::read_unaligned(report_addr as *const Report) };
+        assert_ne!(other.mac, report.mac);
+
+        // The report must be written to the enclave memory
+        assert_eq!(
+            attest(&mut ctx, report_addr + 1, b"nonce"),
+            ReturnCode::IllegalArgument as usize
+        );
+        assert_eq!(
+            attest(&mut ctx, report_addr, &[0; ATTEST_DATA_MAX + 1]),
+            ReturnCode::IllegalArgument as usize
+        );
+    }
 }
@@ -2,6 +2,12 @@
 //!
 //! This module holds the definitions of policy modules for Miralis.
 
+pub mod audit;
+pub mod entropy_test;
+pub mod hsm;
 pub mod keystone;
 pub mod offload;
 pub mod protect_payload;
+pub mod satp_test;
+pub mod shutdown_test;
+pub mod single_step_test;
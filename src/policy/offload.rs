@@ -6,6 +6,7 @@ use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use miralis_core::sbi_codes;
 
 use crate::arch;
+use crate::arch::perf_counters::DELEGATE_TIME_MASK;
 use crate::arch::{Csr, MCause, Mode, PAGE_SIZE, Register, get_raw_faulting_instr, mie};
 use crate::config::PLATFORM_NB_HARTS;
 use crate::host::MiralisContext;
@@ -38,6 +39,11 @@ pub struct OffloadPolicy {}
 impl Module for OffloadPolicy {
     const NUMBER_PMPS: usize = 0;
     const NAME: &'static str = OFFLOAD_POLICY_NAME;
+    const SBI_EXTENSIONS: &'static [usize] = &[
+        sbi_codes::SBI_TIMER_EID,
+        sbi_codes::IPI_EXTENSION_EID,
+        sbi_codes::RFENCE_EXTENSION_EID,
+    ];
 
     fn init() -> Self {
         OffloadPolicy {}
@@ -77,6 +83,16 @@ impl Module for OffloadPolicy {
                 let is_time_register: bool = (instr >> 20) == 0b1100_0000_0001;
 
                 if is_privileged_op && is_time_register {
+                    // The firmware emulates `time` on behalf of S-mode, but U-mode reads are
+                    // still gated by `scounteren`. Disabled reads raise an illegal instruction
+                    // which is delivered according to `medeleg`. The payload S-mode registers are
+                    // installed on the hardware, so the virtual copy might be stale.
+                    if ctx.mode == Mode::U
+                        && arch::read_csr(Csr::Scounteren) & DELEGATE_TIME_MASK == 0
+                    {
+                        return ModuleAction::Ignore;
+                    }
+
                     let rd = (instr >> 7) & 0b11111;
                     let _rs1 = (instr >> 15) & 0b11111;
 
@@ -2,13 +2,13 @@
 use core::slice;
 use core::sync::atomic::{AtomicBool, Ordering};
 
-use miralis_config::TARGET_PAYLOAD_ADDRESS;
-use miralis_core::sbi_codes;
+use miralis_config::{PROTECT_PAYLOAD_TRAP_SATP, TARGET_PAYLOAD_ADDRESS};
 use miralis_core::sbi_codes::SBI_ERR_DENIED;
+use miralis_core::{pmp, sbi_codes};
 use tiny_keccak::{Hasher, Sha3};
 
-use crate::arch::pmp::pmpcfg;
-use crate::arch::pmp::pmplayout::MODULE_OFFSET;
+use crate::arch::pmp::{Segment, pmpcfg};
+use crate::arch::satp::{self, SatpMode};
 use crate::arch::{MCause, Register, get_raw_faulting_instr, mie, mstatus};
 use crate::host::MiralisContext;
 use crate::logger;
@@ -45,8 +45,9 @@ pub struct ProtectPayloadPolicy {
 }
 
 impl Module for ProtectPayloadPolicy {
-    const NUMBER_PMPS: usize = 2;
+    const NUMBER_PMPS: usize = pmp::modules::PROTECT_PAYLOAD;
     const NAME: &'static str = "Protect Payload Policy";
+    const SBI_EXTENSIONS: &'static [usize] = &[sbi_codes::SBI_DEBUG_CONSOLE_EXTENSION_EID];
 
     fn init() -> Self {
         ProtectPayloadPolicy {
@@ -59,6 +60,38 @@ impl Module for ProtectPayloadPolicy {
         }
     }
 
+    fn allows_zeroing(&self, region: Segment) -> bool {
+        // The firmware can not tamper with the payload memory, see the PMP configuration in
+        // `switch_from_payload_to_firmware`
+        region.end() <= TARGET_PAYLOAD_ADDRESS
+    }
+
+    fn traps_satp_writes(&self) -> bool {
+        PROTECT_PAYLOAD_TRAP_SATP
+    }
+
+    fn on_satp_write(
+        &mut self,
+        _ctx: &mut VirtContext,
+        _mctx: &mut MiralisContext,
+        new_satp: usize,
+    ) -> bool {
+        // The page tables must live in the payload memory, which the firmware can not tamper with
+        let root_page_table = (new_satp & satp::PPN_FILTER) << 12;
+        match satp::mode_of_bits(new_satp) {
+            // Writes with a reserved mode are ignored by the hardware
+            Some(SatpMode::Bare) | None => true,
+            Some(_) if root_page_table >= TARGET_PAYLOAD_ADDRESS => true,
+            Some(_) => {
+                log::warn!(
+                    "Rejecting satp 0x{:x}: root page table outside of the payload memory",
+                    new_satp
+                );
+                false
+            }
+        }
+    }
+
     fn trap_from_firmware(
         &mut self,
         mctx: &mut MiralisContext,
@@ -120,9 +153,10 @@ impl Module for ProtectPayloadPolicy {
         self.clear_supervisor_csr(ctx);
 
         // Lock memory
-        mctx.pmp.set_inactive(MODULE_OFFSET, TARGET_PAYLOAD_ADDRESS);
         mctx.pmp
-            .set_tor(MODULE_OFFSET + 1, usize::MAX, pmpcfg::NO_PERMISSIONS);
+            .set_inactive(Self::PMP_OFFSET, TARGET_PAYLOAD_ADDRESS);
+        mctx.pmp
+            .set_tor(Self::PMP_OFFSET + 1, usize::MAX, pmpcfg::NO_PERMISSIONS);
     }
 
     fn switch_from_firmware_to_payload(
@@ -139,8 +173,10 @@ impl Module for ProtectPayloadPolicy {
         }
 
         // Unlock memory
-        mctx.pmp.set_inactive(MODULE_OFFSET, TARGET_PAYLOAD_ADDRESS);
-        mctx.pmp.set_tor(MODULE_OFFSET + 1, usize::MAX, pmpcfg::RWX);
+        mctx.pmp
+            .set_inactive(Self::PMP_OFFSET, TARGET_PAYLOAD_ADDRESS);
+        mctx.pmp
+            .set_tor(Self::PMP_OFFSET + 1, usize::MAX, pmpcfg::RWX);
 
         // We restore the supervisor csr registers
         self.restore_supervisor_csr(ctx);
@@ -173,9 +209,10 @@ impl Module for ProtectPayloadPolicy {
     // In this policy module, if we receive an interrupt from Miralis, it implies we need to lock the memory
     fn on_interrupt(&mut self, _ctx: &mut VirtContext, mctx: &mut MiralisContext) {
         // Lock memory
-        mctx.pmp.set_inactive(MODULE_OFFSET, TARGET_PAYLOAD_ADDRESS);
         mctx.pmp
-            .set_tor(MODULE_OFFSET + 1, usize::MAX, pmpcfg::NO_PERMISSIONS);
+            .set_inactive(Self::PMP_OFFSET, TARGET_PAYLOAD_ADDRESS);
+        mctx.pmp
+            .set_tor(Self::PMP_OFFSET + 1, usize::MAX, pmpcfg::NO_PERMISSIONS);
     }
 }
 
@@ -0,0 +1,41 @@
+//! Satp Test Policy
+//!
+//! A policy used to test the validation of the payload `satp` writes. It logs and approves every
+//! write, and logs the total on shutdown.
+
+use crate::host::MiralisContext;
+use crate::logger;
+use crate::modules::Module;
+use crate::virt::VirtContext;
+
+pub struct SatpTestPolicy {
+    /// Number of `satp` writes validated so far.
+    nb_writes: usize,
+}
+
+impl Module for SatpTestPolicy {
+    const NAME: &'static str = "Satp Test Policy";
+
+    fn init() -> Self {
+        SatpTestPolicy { nb_writes: 0 }
+    }
+
+    fn traps_satp_writes(&self) -> bool {
+        true
+    }
+
+    fn on_satp_write(
+        &mut self,
+        ctx: &mut VirtContext,
+        _mctx: &mut MiralisContext,
+        new_satp: usize,
+    ) -> bool {
+        logger::debug!("Approving satp write 0x{:x} at 0x{:x}", new_satp, ctx.pc);
+        self.nb_writes += 1;
+        true
+    }
+
+    fn on_shutdown(&mut self, _ctx: &mut VirtContext, _mctx: &mut MiralisContext) {
+        log::info!("Validated {} satp writes", self.nb_writes);
+    }
+}
@@ -0,0 +1,29 @@
+//! Shutdown Test Policy
+//!
+//! A policy used to test the shutdown hook. It records whether the hook has been called and logs
+//! the number of exits handled before shutting down.
+
+use crate::host::MiralisContext;
+use crate::modules::Module;
+use crate::virt::VirtContext;
+
+pub struct ShutdownTestPolicy {
+    /// Whether the shutdown hook has already been called.
+    has_shut_down: bool,
+}
+
+impl Module for ShutdownTestPolicy {
+    const NAME: &'static str = "Shutdown Test Policy";
+
+    fn init() -> Self {
+        ShutdownTestPolicy {
+            has_shut_down: false,
+        }
+    }
+
+    fn on_shutdown(&mut self, ctx: &mut VirtContext, _mctx: &mut MiralisContext) {
+        assert!(!self.has_shut_down, "Shutdown hook called twice");
+        self.has_shut_down = true;
+        log::info!("Shutdown hook called after {} exits", ctx.nb_exits);
+    }
+}
@@ -0,0 +1,31 @@
+//! Single Step Test Policy
+//!
+//! A policy used to test single-stepping of the payload. It counts the number of steps reported
+//! by Miralis and logs the total on shutdown.
+
+use crate::host::MiralisContext;
+use crate::logger;
+use crate::modules::Module;
+use crate::virt::VirtContext;
+
+pub struct SingleStepTestPolicy {
+    /// Number of payload instructions stepped so far.
+    nb_steps: usize,
+}
+
+impl Module for SingleStepTestPolicy {
+    const NAME: &'static str = "Single Step Test Policy";
+
+    fn init() -> Self {
+        SingleStepTestPolicy { nb_steps: 0 }
+    }
+
+    fn on_step(&mut self, ctx: &mut VirtContext, _mctx: &mut MiralisContext) {
+        logger::debug!("Single step at 0x{:x}", ctx.pc);
+        self.nb_steps += 1;
+    }
+
+    fn on_shutdown(&mut self, _ctx: &mut VirtContext, _mctx: &mut MiralisContext) {
+        log::info!("Single-stepped {} payload instructions", self.nb_steps);
+    }
+}
@@ -68,6 +68,88 @@ pub const fn const_str_eq(a: &str, b: &str) -> bool {
     true
 }
 
+// ——————————————————————————————— Fixed Map ———————————————————————————————— //
+
+/// A map with a fixed capacity, backed by an array.
+///
+/// This map does not rely on heap allocations and can therefore be used in Miralis, for instance
+/// to collect statistics indexed by trap causes or CSRs. Lookups are linear in the number of
+/// entries, hence the map is meant to stay small.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FixedMap<K, V, const N: usize> {
+    entries: [Option<(K, V)>; N],
+    len: usize,
+}
+
+impl<K: Copy + Eq, V, const N: usize> FixedMap<K, V, N> {
+    /// Creates an empty map.
+    pub const fn new() -> Self {
+        FixedMap {
+            entries: [const { None }; N],
+            len: 0,
+        }
+    }
+
+    /// Inserts a value for the given key, returning the previous value if any.
+    ///
+    /// Returns an error if the key is not yet in the map and the map is full.
+    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>, &'static str> {
+        if let Some(previous) = self.get_mut(key) {
+            return Ok(Some(core::mem::replace(previous, value)));
+        }
+
+        if self.len >= N {
+            return Err("FixedMap is full");
+        }
+        self.entries[self.len] = Some((key, value));
+        self.len += 1;
+        Ok(None)
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get(&self, key: K) -> Option<&V> {
+        self.iter().find(|(k, _)| *k == key).map(|(_, v)| v)
+    }
+
+    /// Returns a mutable reference to the value corresponding to the key.
+    pub fn get_mut(&mut self, key: K) -> Option<&mut V> {
+        self.entries[..self.len]
+            .iter_mut()
+            .flatten()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| v)
+    }
+
+    /// Returns an iterator over the entries, in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = (K, &V)> {
+        self.entries[..self.len]
+            .iter()
+            .flatten()
+            .map(|(k, v)| (*k, v))
+    }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the map contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the maximum number of entries in the map.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<K: Copy + Eq, V, const N: usize> Default for FixedMap<K, V, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -106,4 +188,43 @@ mod tests {
         // Also check that the function is const
         assert!(const { const_str_eq("foo", "foo") });
     }
+
+    #[test]
+    fn fixed_map() {
+        let mut map: FixedMap<usize, usize, 4> = FixedMap::new();
+        assert!(map.is_empty());
+        assert_eq!(map.capacity(), 4);
+        assert_eq!(map.get(0), None);
+
+        // Keys in a contiguous range do not collide
+        for key in 0..4 {
+            assert_eq!(map.insert(key, key * 10), Ok(None));
+        }
+        assert_eq!(map.len(), 4);
+        for key in 0..4 {
+            assert_eq!(map.get(key), Some(&(key * 10)));
+        }
+
+        // Updating an existing key works even when the map is full
+        assert_eq!(map.insert(2, 42), Ok(Some(20)));
+        *map.get_mut(3).unwrap() += 1;
+        assert_eq!(map.get(2), Some(&42));
+        assert_eq!(map.get(3), Some(&31));
+
+        // But new keys are rejected once the capacity is reached
+        assert!(map.insert(4, 40).is_err());
+        assert_eq!(map.get(4), None);
+        assert_eq!(map.len(), 4);
+
+        // Entries are iterated in insertion order
+        let entries: Vec<(usize, usize)> = map.iter().map(|(k, v)| (k, *v)).collect();
+        assert_eq!(entries, vec![(0, 0), (1, 10), (2, 42), (3, 31)]);
+    }
+
+    #[test]
+    fn fixed_map_zero_capacity() {
+        let mut map: FixedMap<u8, (), 0> = FixedMap::new();
+        assert!(map.insert(0, ()).is_err());
+        assert_eq!(map.iter().count(), 0);
+    }
 }
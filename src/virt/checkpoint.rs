@@ -0,0 +1,204 @@
+//! Checkpoint and Restore
+//!
+//! This module serializes the state of a virtual firmware, that is its [VirtContext] and the PMP
+//! configuration of Miralis, to a memory buffer. The buffer can then be restored by a new Miralis
+//! image to resume the firmware where it left off, which is used for live-update experiments.
+//!
+//! Checkpoints are raw copies of the in-memory representation of the structures, prefixed by a
+//! small header. They can therefore only be restored by a Miralis image built from the same
+//! sources (or at least with the same layout for the checkpointed structures), which is checked
+//! on a best-effort basis by comparing the size of the structures.
+
+use core::mem::size_of;
+use core::ptr;
+
+use super::VirtContext;
+use crate::arch::pmp::PmpGroup;
+
+/// Magic value identifying a [VirtContext] checkpoint.
+const VIRT_CONTEXT_MAGIC: usize = 0x4d49_5241_5643_5458; // "MIRAVCTX"
+/// Magic value identifying a [PmpGroup] checkpoint.
+const PMP_GROUP_MAGIC: usize = 0x4d49_5241_5f50_4d50; // "MIRA_PMP"
+
+/// The header preceding each checkpointed structure.
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Header {
+    magic: usize,
+    size: usize,
+}
+
+impl VirtContext {
+    /// Serialize the context into `buf`, returning the number of bytes written.
+    ///
+    /// Panics if the buffer is too small, [VirtContext::checkpoint_size] returns the required
+    /// size.
+    pub fn checkpoint(&self, buf: &mut [u8]) -> usize {
+        checkpoint_raw(self, VIRT_CONTEXT_MAGIC, buf)
+    }
+
+    /// Restore a context from a buffer filled by [VirtContext::checkpoint].
+    ///
+    /// Panics if the buffer does not contain a checkpoint of a context with the same size.
+    ///
+    /// # Safety
+    ///
+    /// The checkpoint must have been produced by a Miralis image with the same layout for
+    /// [VirtContext], otherwise the restored context might contain invalid values.
+    pub unsafe fn restore(buf: &[u8]) -> Self {
+        unsafe { restore_raw(VIRT_CONTEXT_MAGIC, buf) }
+    }
+
+    /// The size of a checkpoint of the context, in bytes.
+    pub const fn checkpoint_size() -> usize {
+        size_of::<Header>() + size_of::<VirtContext>()
+    }
+}
+
+impl PmpGroup {
+    /// Serialize the PMP configuration into `buf`, returning the number of bytes written.
+    ///
+    /// Panics if the buffer is too small, [PmpGroup::checkpoint_size] returns the required size.
+    pub fn checkpoint(&self, buf: &mut [u8]) -> usize {
+        checkpoint_raw(self, PMP_GROUP_MAGIC, buf)
+    }
+
+    /// Restore a PMP configuration from a buffer filled by [PmpGroup::checkpoint].
+    ///
+    /// The configuration is not installed on the hardware, this must be done with
+    /// [crate::arch::write_pmp] once restored.
+    ///
+    /// # Safety
+    ///
+    /// The checkpoint must have been produced by a Miralis image with the same layout for
+    /// [PmpGroup].
+    pub unsafe fn restore(buf: &[u8]) -> Self {
+        unsafe { restore_raw(PMP_GROUP_MAGIC, buf) }
+    }
+
+    /// The size of a checkpoint of the PMP configuration, in bytes.
+    pub const fn checkpoint_size() -> usize {
+        size_of::<Header>() + size_of::<PmpGroup>()
+    }
+}
+
+/// Write a header followed by the bytes of `value` into `buf`.
+fn checkpoint_raw<T>(value: &T, magic: usize, buf: &mut [u8]) -> usize {
+    let total_size = size_of::<Header>() + size_of::<T>();
+    assert!(
+        buf.len() >= total_size,
+        "Checkpoint buffer is too small: {} bytes but {} are needed",
+        buf.len(),
+        total_size
+    );
+
+    let header = Header {
+        magic,
+        size: size_of::<T>(),
+    };
+
+    // SAFETY: we checked that the buffer is large enough, and unaligned writes are used as the
+    // buffer has no alignment constraints.
+    unsafe {
+        let dst = buf.as_mut_ptr();
+        ptr::write_unaligned(dst as *mut Header, header);
+        ptr::copy_nonoverlapping(
+            value as *const T as *const u8,
+            dst.add(size_of::<Header>()),
+            size_of::<T>(),
+        );
+    }
+
+    total_size
+}
+
+/// Read a value written by [checkpoint_raw] from `buf`.
+///
+/// # Safety
+///
+/// The bytes following the header must be a valid representation of `T`.
+unsafe fn restore_raw<T>(magic: usize, buf: &[u8]) -> T {
+    assert!(
+        buf.len() >= size_of::<Header>(),
+        "Checkpoint buffer is too small"
+    );
+
+    // SAFETY: we checked that the buffer can hold a header, all bit patterns are valid headers.
+    let header = unsafe { ptr::read_unaligned(buf.as_ptr() as *const Header) };
+    assert_eq!(header.magic, magic, "Invalid checkpoint magic value");
+    assert_eq!(
+        header.size,
+        size_of::<T>(),
+        "Checkpoint was produced with a different layout"
+    );
+    assert!(
+        buf.len() >= size_of::<Header>() + size_of::<T>(),
+        "Checkpoint buffer is truncated"
+    );
+
+    // SAFETY: the buffer is large enough, and the caller guarantees the validity of the bytes.
+    unsafe { ptr::read_unaligned(buf.as_ptr().add(size_of::<Header>()) as *const T) }
+}
+
+// ————————————————————————————————— Tests —————————————————————————————————— //
+
+#[cfg(test)]
+mod tests {
+    use crate::arch::pmp::PmpGroup;
+    use crate::arch::{self, Mode, Register};
+    use crate::virt::VirtContext;
+    use crate::virt::traits::RegisterContextSetter;
+
+    const BUFFER_SIZE: usize = 8192;
+
+    #[test]
+    fn checkpoint_restore_virt_context() {
+        let hw = unsafe { arch::detect_hardware() };
+        let mut ctx = VirtContext::new(1, hw.available_reg.nb_pmp, hw.extensions);
+        ctx.set(Register::X10, 0x42);
+        ctx.set(Register::X2, 0x8020_0000);
+        ctx.pc = 0x8000_1234;
+        ctx.mode = Mode::S;
+        ctx.csr.mstatus = 0xa_0000_1800;
+        ctx.csr.mepc = 0x8000_0000;
+        ctx.csr.pmpaddr[3] = 0x2000_0000;
+        ctx.nb_exits = 1337;
+        ctx.is_wfi = true;
+
+        assert!(VirtContext::checkpoint_size() <= BUFFER_SIZE);
+        let mut buf = [0u8; BUFFER_SIZE];
+        let size = ctx.checkpoint(&mut buf);
+        assert_eq!(size, VirtContext::checkpoint_size());
+
+        let restored = unsafe { VirtContext::restore(&buf[..size]) };
+        assert_eq!(restored, ctx, "The restored context must be identical");
+    }
+
+    #[test]
+    fn checkpoint_restore_pmp() {
+        let mut pmp = PmpGroup::new(16);
+        pmp.set_napot(2, 0x8000_0000, 0x1000, 0b111);
+        pmp.set_tor(5, 0x9000_0000, 0b011);
+
+        let mut buf = [0u8; BUFFER_SIZE];
+        let size = pmp.checkpoint(&mut buf);
+        let restored = unsafe { PmpGroup::restore(&buf[..size]) };
+
+        assert_eq!(restored.pmpaddr(), pmp.pmpaddr());
+        assert_eq!(restored.pmpcfg(), pmp.pmpcfg());
+        assert_eq!(restored.nb_pmp, pmp.nb_pmp);
+        assert_eq!(restored.nb_virt_pmp, pmp.nb_virt_pmp);
+        assert_eq!(restored.virt_pmp_offset, pmp.virt_pmp_offset);
+        assert_eq!(restored.free_pmp_offset, pmp.free_pmp_offset);
+        assert_eq!(restored.nb_free_pmp, pmp.nb_free_pmp);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid checkpoint magic value")]
+    fn restore_wrong_kind() {
+        let pmp = PmpGroup::new(16);
+        let mut buf = [0u8; BUFFER_SIZE];
+        pmp.checkpoint(&mut buf);
+        unsafe { VirtContext::restore(&buf) };
+    }
+}
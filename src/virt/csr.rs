@@ -5,9 +5,13 @@
 
 use super::{VirtContext, VirtCsr};
 use crate::arch::mie::SSIE_FILTER;
-use crate::arch::pmp::pmpcfg;
-use crate::arch::{Csr, Register, hstatus, menvcfg, mie, misa, mstatus};
-use crate::{MiralisContext, Plat, Platform, arch, debug, logger};
+use crate::arch::pmp::pmplayout::MPRV_EMULATION_OFFSET;
+use crate::arch::pmp::{self, pmpcfg};
+use crate::arch::{
+    Csr, ExtensionsCapability, Mode, Register, debug_context, hgatp, hstatus, mcountinhibit,
+    menvcfg, mhpmevent, mie, misa, mseccfg, mstatus, mtvec,
+};
+use crate::{MiralisContext, Plat, Platform, arch, config, debug, logger};
 
 /// A module exposing the traits to manipulate registers of a virtual context.
 ///
@@ -75,7 +79,13 @@ impl RegisterContextGetter<Csr> for VirtContext {
                 // To properly emulate this we should treat `csrrs(i)` and `csrrc(i)` differently
                 // when accessing `mip`. For now we simply choose the easy solution and hide the
                 // hardware bit from the virtualized firmware.
-                self.csr.mip
+                //
+                // When Sstc is enabled, STIP reflects the stimecmp comparison at the time of the
+                // read.
+                match self.sstc_stip() {
+                    Some(stip) => (self.csr.mip & !mie::STIE_FILTER) | stip,
+                    None => self.csr.mip,
+                }
             }
             Csr::Mtvec => self.csr.mtvec,
             Csr::Mscratch => self.csr.mscratch,
@@ -119,9 +129,11 @@ impl RegisterContextGetter<Csr> for VirtContext {
             }
             Csr::Mcycle => self.csr.mcycle,
             Csr::Minstret => self.csr.minstret,
-            Csr::Mhpmcounter(n) => self.csr.mhpmcounter[n],
+            Csr::Mhpmcounter(n) if n < config::VCPU_NB_HPM_COUNTERS => self.csr.mhpmcounter[n],
+            Csr::Mhpmcounter(_) => 0, // Unimplemented counters are read-only zero
             Csr::Mcountinhibit => self.csr.mcountinhibit as usize,
-            Csr::Mhpmevent(n) => self.csr.mhpmevent[n],
+            Csr::Mhpmevent(n) if n < config::VCPU_NB_HPM_COUNTERS => self.csr.mhpmevent[n],
+            Csr::Mhpmevent(_) => 0,
             Csr::Mcounteren => self.csr.mcounteren as usize,
             Csr::Menvcfg => self.csr.menvcfg,
             Csr::Mseccfg => self.csr.mseccfg,
@@ -141,10 +153,10 @@ impl RegisterContextGetter<Csr> for VirtContext {
                     panic!("Mtval exists only in H mode")
                 }
             }
-            Csr::Tdata1 => todo!(),                 // TODO : normal read
-            Csr::Tdata2 => todo!(),                 // TODO : normal read
-            Csr::Tdata3 => todo!(),                 // TODO : normal read
-            Csr::Mcontext => todo!(),               // TODO : normal read
+            Csr::Tdata1 => todo!(), // TODO : normal read
+            Csr::Tdata2 => todo!(), // TODO : normal read
+            Csr::Tdata3 => todo!(), // TODO : normal read
+            Csr::Mcontext => self.csr.mcontext,
             Csr::Dcsr => todo!(),                   // TODO : normal read
             Csr::Dpc => todo!(),                    // TODO : normal read
             Csr::Dscratch0 => todo!(),              // TODO : normal read
@@ -156,7 +168,7 @@ impl RegisterContextGetter<Csr> for VirtContext {
             Csr::Mtval => self.csr.mtval,
             //Supervisor-level CSRs
             Csr::Sstatus => self.get(Csr::Mstatus) & mstatus::SSTATUS_FILTER,
-            Csr::Sie => self.get(Csr::Mie) & mie::SIE_FILTER & self.get(Csr::Mideleg),
+            Csr::Sie => self.lower_interrupts(self.get(Csr::Mie)),
             Csr::Stvec => self.csr.stvec,
             Csr::Scounteren => self.csr.scounteren as usize,
             Csr::Senvcfg => self.csr.senvcfg,
@@ -164,7 +176,7 @@ impl RegisterContextGetter<Csr> for VirtContext {
             Csr::Sepc => self.csr.sepc & self.pc_alignment_mask(),
             Csr::Scause => self.csr.scause,
             Csr::Stval => self.csr.stval,
-            Csr::Sip => self.get(Csr::Mip) & mie::SIE_FILTER & self.get(Csr::Mideleg),
+            Csr::Sip => self.lower_interrupts(self.get(Csr::Mip)),
             Csr::Satp => self.csr.satp,
             Csr::Scontext => self.csr.scontext,
             Csr::Stimecmp => self.csr.stimecmp,
@@ -214,6 +226,11 @@ impl RegisterContextGetter<Csr> for VirtContext {
             }
             Csr::Vsatp => self.csr.vsatp,
 
+            // Floating-point extension
+            //
+            // The firmware accesses the FP CSRs natively, so the physical registers hold its state.
+            Csr::Fflags | Csr::Frm | Csr::Fcsr => arch::read_csr(register),
+
             // Vector extension
             Csr::Vstart => self.csr.vstart as usize,
             Csr::Vxsat => {
@@ -232,6 +249,7 @@ impl RegisterContextGetter<Csr> for VirtContext {
             Csr::Cycle => self.csr.mcycle,
             Csr::Time => arch::read_csr(Csr::Time),
             Csr::Instret => self.csr.minstret,
+            Csr::Cycleh | Csr::Timeh | Csr::Instreth => 0, // Only used on RV32
 
             // Crypto extension
             // To get a true random value we defer to the hardware.
@@ -242,8 +260,9 @@ impl RegisterContextGetter<Csr> for VirtContext {
 
             // Unknown
             Csr::Unknown => {
+                // Accesses to unallocated CSRs raise an illegal instruction exception before
+                // reaching the emulation, see `is_illegal_csr_access`.
                 log::warn!("Tried to access unknown CSR: {:?}", register);
-                // Official specification returns 0x0 when the register is unknown
                 0x0
             }
         }
@@ -252,6 +271,30 @@ impl RegisterContextGetter<Csr> for VirtContext {
 
 impl HwRegisterContextSetter<Csr> for VirtContext {
     fn set_csr(&mut self, register: Csr, value: usize, mctx: &mut MiralisContext) {
+        if config::TRACE_CSR {
+            let write = self.set_csr_traced(register, value, mctx);
+            log::trace!("{}", write);
+        } else {
+            self.write_csr(register, value, mctx);
+        }
+    }
+}
+
+impl VirtContext {
+    /// Write a CSR, returning its value before and after the (legalized) write.
+    pub fn set_csr_traced(
+        &mut self,
+        register: Csr,
+        value: usize,
+        mctx: &mut MiralisContext,
+    ) -> debug::CsrWrite {
+        let old = self.get(register);
+        self.write_csr(register, value, mctx);
+        debug::CsrWrite::new(register, old, self.get(register))
+    }
+
+    /// Legalize and write a CSR.
+    fn write_csr(&mut self, register: Csr, value: usize, mctx: &mut MiralisContext) {
         let hw = &mctx.hw;
         match register {
             Csr::Mhartid => (), // Read-only
@@ -302,14 +345,12 @@ impl HwRegisterContextSetter<Csr> for VirtContext {
                 if mprv != previous_mprv {
                     logger::trace!("vMPRV set to {:b}", mprv);
                     if mprv != 0 {
-                        mctx.pmp.set_tor(0, usize::MAX, pmpcfg::X);
+                        mctx.pmp
+                            .set_napot(MPRV_EMULATION_OFFSET, 0, usize::MAX, pmpcfg::X);
                     } else {
-                        mctx.pmp.set_inactive(0, usize::MAX);
+                        mctx.pmp.set_inactive(MPRV_EMULATION_OFFSET, 0);
                     }
-                    // TODO: it seems the PMP are not yet written to hardware here,
-                    // that seems like a bug to me. We should investigate.
-                    // unsafe { write_pmp(&mctx.pmp).flush() };
-                    arch::sfencevma(None, None);
+                    unsafe { arch::write_pmp(&mctx.pmp).flush() };
                 }
 
                 if !mctx.hw.extensions.has_s_extension || self.csr.misa & misa::S == 0 {
@@ -328,22 +369,16 @@ impl HwRegisterContextSetter<Csr> for VirtContext {
                     new_value &= !mstatus::FS_FILTER;
                 }
 
-                // We do not support changing endianness (MBE, SBE, UBE)
+                // We do not support changing endianness (MBE, SBE, UBE), the bits are read-only
+                // zero as in `legalize_mstatus` of the Sail model. This also applies to UBE
+                // written through sstatus.
                 new_value &= !(mstatus::MBE_FILTER | mstatus::SBE_FILTER | mstatus::UBE_FILTER);
 
                 // No support for extensions -> XS read-only 0
                 new_value &= !mstatus::XS_FILTER;
 
-                // SD : 63 : read-only 0 (if NO FS/VS/XS)
-                let fs: usize = (new_value & mstatus::FS_FILTER) >> mstatus::FS_OFFSET;
-                let vs: usize = (new_value & mstatus::VS_FILTER) >> mstatus::VS_OFFSET;
-                let dirty = fs == 0b11 || vs == 0b11;
-                VirtCsr::set_csr_field(
-                    &mut new_value,
-                    mstatus::SD_OFFSET,
-                    mstatus::SD_FILTER,
-                    if dirty { 0b1 } else { 0b0 },
-                );
+                // SD : 63 : read-only, summarizes whether FS/VS/XS is Dirty
+                new_value = mstatus::with_sd(new_value);
 
                 // UIE and UPIE should be zero if user-space interrupts are disabled
                 if self.csr.misa & misa::N == 0 {
@@ -356,6 +391,21 @@ impl HwRegisterContextSetter<Csr> for VirtContext {
                     new_value &= !(mstatus::GVA_FILTER | mstatus::MPV_FILTER);
                 }
 
+                // The firmware accesses the FP and vector state natively, therefore the physical
+                // FS and VS must match the virtual ones. The hardware then transitions them to
+                // Dirty on writes.
+                let mut native_state = mstatus::FS_FILTER;
+                if self.extensions.has_v_extension {
+                    native_state |= mstatus::VS_FILTER;
+                }
+                let state_changed = (self.csr.mstatus ^ new_value) & native_state != 0;
+                if state_changed {
+                    let physical_mstatus = arch::read_csr(Csr::Mstatus) & !native_state;
+                    unsafe {
+                        arch::write_csr(Csr::Mstatus, physical_mstatus | (new_value & native_state))
+                    };
+                }
+
                 self.csr.mstatus = new_value;
             }
             Csr::Misa => {} // Read only register, we don't support deactivating extensions in Miralis
@@ -364,10 +414,14 @@ impl HwRegisterContextSetter<Csr> for VirtContext {
                     debug::warn_once!("MEIE bit in 'mie' is not yet supported");
                 }
 
-                self.csr.mie = hw.interrupts & value & mie::MIE_WRITE_FILTER;
+                self.csr.mie = hw.virtual_interrupts() & value & mie::MIE_WRITE_FILTER;
             }
             Csr::Mip => {
-                let value = value & hw.interrupts & mie::MIP_WRITE_FILTER;
+                let mut value = value & hw.virtual_interrupts() & mie::MIP_WRITE_FILTER;
+                if self.sstc_stip().is_some() {
+                    // STIP is read-only when Sstc is enabled, it is driven by stimecmp instead
+                    value = (value & !mie::STIE_FILTER) | (self.csr.mip & mie::STIE_FILTER);
+                }
 
                 // If the firmware wants to read the mip register after cleaning vmip.SEIP, and we don't sync
                 // vmip.SEIP with mip.SEIP, it can't know if there is an interrupt signal from the interrupt
@@ -389,27 +443,13 @@ impl HwRegisterContextSetter<Csr> for VirtContext {
                 // Keep all the non-writeable bits
                 self.csr.mip = value | (self.csr.mip & !mie::MIP_WRITE_FILTER);
             }
-            Csr::Mtvec => {
-                match value & 0b11 {
-                    // Direct mode
-                    0b00 => self.csr.mtvec = value,
-                    // Vector mode
-                    0b01 => self.csr.mtvec = value,
-                    // Reserved mode
-                    _ => {
-                        self.csr.mtvec = (value & !0b11) | (self.csr.mtvec & 0b11);
-                    }
-                }
-            }
+            Csr::Mtvec => self.csr.mtvec = legalize_tvec(self.csr.mtvec, value),
             Csr::Mscratch => self.csr.mscratch = value,
             Csr::Mvendorid => (), // Read-only
             Csr::Marchid => (),   // Read-only
             Csr::Mimpid => (),    // Read-only
             Csr::Pmpcfg(pmp_cfg_idx) => {
                 let mut value = value;
-                if Csr::PMP_CFG_LOCK_MASK & value != 0 {
-                    debug::warn_once!("PMP lock bits are not yet supported");
-                }
                 if pmp_cfg_idx % 2 == 1 {
                     // Should not happen because we are in a RISCV64 setting (the decoder emmits
                     // invalid CSR instead).
@@ -421,11 +461,23 @@ impl HwRegisterContextSetter<Csr> for VirtContext {
                 }
 
                 // Legalize individual pmpcfg entries
+                let mml = self.csr.mseccfg & mseccfg::MML_FILTER != 0;
+                let previous = self.csr.pmpcfg[pmp_cfg_idx / 2];
                 for idx in 0..8 {
                     let offset = idx * 8; // Bit offset
+                    let cfg = (value >> offset) as u8;
+                    let previous_cfg = (previous >> offset) as u8;
+
+                    // Locked entries can not be modified, and with MML locked executable rules
+                    // can not be added, unless the Rule Locking Bypass is set
+                    if !self.is_pmp_rule_writable(previous_cfg, cfg) {
+                        value &= !(0xff << offset);
+                        value |= (previous_cfg as usize) << offset;
+                        continue;
+                    }
 
-                    // W = 1 & R = 0 is reserved
-                    if (value >> offset) & 0b11 == 0b10 {
+                    // W = 1 & R = 0 is reserved, except for the shared regions of MML
+                    if !mml && (value >> offset) & 0b11 == 0b10 {
                         value &= !(0b111 << offset);
                     }
 
@@ -438,22 +490,48 @@ impl HwRegisterContextSetter<Csr> for VirtContext {
                 self.csr.pmpcfg[pmp_cfg_idx / 2] = value
                     & Csr::PMP_CFG_LEGAL_MASK
                     & VirtCsr::get_pmp_cfg_filter(pmp_cfg_idx, self.nb_pmp);
+                self.update_firmware_pmp(mctx);
             }
             Csr::Pmpaddr(pmp_addr_idx) => {
-                if pmp_addr_idx >= mctx.hw.available_reg.nb_pmp {
+                if pmp_addr_idx >= self.nb_pmp {
                     // This PMP is not emulated, ignore
                     return;
                 }
+                if self.is_pmpaddr_locked(pmp_addr_idx) {
+                    return;
+                }
                 self.csr.pmpaddr[pmp_addr_idx] = Csr::PMP_ADDR_LEGAL_MASK & value;
+                self.update_firmware_pmp(mctx);
             }
             Csr::Mcycle => self.csr.mcycle = value,
             Csr::Minstret => self.csr.minstret = value,
-            Csr::Mhpmcounter(_counter_idx) => (), // Read-only 0
+            Csr::Mhpmcounter(counter_idx) => {
+                if counter_idx >= config::VCPU_NB_HPM_COUNTERS {
+                    // This counter is not implemented, ignore
+                    return;
+                }
+                self.csr.mhpmcounter[counter_idx] = value
+            }
             Csr::Mcountinhibit => {
-                let mask = 0b101; // We do not support counters for now
+                // We do not support the HPM counters for now. The virtual mcountinhibit is never
+                // written to hardware, so the guest can not inhibit the counters used by Miralis.
+                let mask = mcountinhibit::CY_FILTER | mcountinhibit::IR_FILTER;
                 self.csr.mcountinhibit = (value & mask) as u32;
             }
-            Csr::Mhpmevent(_event_idx) => (), // Read-only 0
+            Csr::Mhpmevent(event_idx) => {
+                if event_idx >= config::VCPU_NB_HPM_COUNTERS {
+                    // This counter is not implemented, ignore
+                    return;
+                }
+                let mut mask = mhpmevent::event_filter(config::VCPU_HPM_EVENT_BITS);
+                if mctx.hw.extensions.has_sscofpmf_extension {
+                    mask |= mhpmevent::SSCOFPMF_FILTER;
+                }
+                if !mctx.hw.extensions.has_h_extension {
+                    mask &= !(mhpmevent::VSINH_FILTER | mhpmevent::VUINH_FILTER);
+                }
+                self.csr.mhpmevent[event_idx] = value & mask;
+            }
             Csr::Mcounteren => {
                 // Only show IR, TM and CY (for cycle, time and instret counters)
                 let mask = 0b111; // We do not support counters beyond basic ones for now
@@ -473,14 +551,25 @@ impl HwRegisterContextSetter<Csr> for VirtContext {
                     mask &= !menvcfg::CBZE_FILTER;
                 }
 
-                self.csr.menvcfg = value & mask;
+                self.csr.menvcfg = legalize_cbie(value & mask);
                 mctx.hw.extensions.is_sstc_enabled = self.csr.menvcfg & menvcfg::STCE_FILTER != 0;
+                self.update_sstc_stip();
+            }
+            Csr::Mseccfg => {
+                let mut value = value & mseccfg::ALL;
+                // MML and MMWP are sticky, they can only be cleared by a reset
+                value |= self.csr.mseccfg & (mseccfg::MML_FILTER | mseccfg::MMWP_FILTER);
+                // RLB can not be set while it is cleared and some entries are locked
+                if self.csr.mseccfg & mseccfg::RLB_FILTER == 0 && self.has_locked_pmp() {
+                    value &= !mseccfg::RLB_FILTER;
+                }
+                self.csr.mseccfg = value;
+                self.update_firmware_pmp(mctx);
             }
-            Csr::Mseccfg => self.csr.mseccfg = value,
             Csr::Mconfigptr => (), // Read-only
             Csr::Medeleg => self.csr.medeleg = value & !(1 << 11),
             Csr::Mideleg => {
-                self.csr.mideleg = (value & hw.interrupts & !mie::MIDELEG_READ_ONLY_ZERO)
+                self.csr.mideleg = (value & hw.virtual_interrupts() & !mie::MIDELEG_READ_ONLY_ZERO)
                     | mie::MIDELEG_READ_ONLY_ONE;
             }
             Csr::Mtinst => {
@@ -503,20 +592,19 @@ impl HwRegisterContextSetter<Csr> for VirtContext {
             Csr::Tdata1 => todo!(), // TODO : NO INFORMATION IN THE SPECIFICATION
             Csr::Tdata2 => todo!(), // TODO : NO INFORMATION IN THE SPECIFICATION
             Csr::Tdata3 => todo!(), // TODO : NO INFORMATION IN THE SPECIFICATION
-            Csr::Mcontext => todo!(), // TODO : NO INFORMATION IN THE SPECIFICATION
-            Csr::Dcsr => todo!(),   // TODO : NO INFORMATION IN THE SPECIFICATION
-            Csr::Dpc => todo!(),    // TODO : NO INFORMATION IN THE SPECIFICATION
+            Csr::Mcontext => {
+                let filter = debug_context::mcontext_filter(mctx.hw.extensions.has_h_extension);
+                self.csr.mcontext = value & filter;
+            }
+            Csr::Dcsr => todo!(), // TODO : NO INFORMATION IN THE SPECIFICATION
+            Csr::Dpc => todo!(),  // TODO : NO INFORMATION IN THE SPECIFICATION
             Csr::Dscratch0 => todo!(), // TODO : NO INFORMATION IN THE SPECIFICATION
             Csr::Dscratch1 => todo!(), // TODO : NO INFORMATION IN THE SPECIFICATION
             Csr::Mepc => {
                 if value > Plat::get_max_valid_address() {
                     return;
                 }
-                if hw.extensions.has_c_extension {
-                    self.csr.mepc = value & !0b1
-                } else {
-                    self.csr.mepc = value & !0b11
-                }
+                self.csr.mepc = legalize_xepc(value, hw.extensions.has_c_extension);
             }
             Csr::Mcause => self.csr.mcause = value,
             Csr::Mtval => self.csr.mtval = value,
@@ -524,6 +612,10 @@ impl HwRegisterContextSetter<Csr> for VirtContext {
             Csr::Sstatus => {
                 // Clear sstatus bits
                 let mstatus = self.get(Csr::Mstatus) & !mstatus::SSTATUS_FILTER;
+                // UXL is shared with mstatus and read-only, the U-mode XLEN must not be changed
+                // through sstatus (see `legalize_sstatus` in the Sail model).
+                let value =
+                    (value & !mstatus::UXL_FILTER) | (self.csr.mstatus & mstatus::UXL_FILTER);
                 // Set sstatus bits to new value
                 self.set_csr(
                     Csr::Mstatus,
@@ -533,60 +625,26 @@ impl HwRegisterContextSetter<Csr> for VirtContext {
             }
             Csr::Sie => {
                 // Only delegated interrupts can be enabled through `sie`
-                let mideleg = self.get(Csr::Mideleg);
-                self.csr.mie = (self.csr.mie & !mideleg) | (mideleg & value);
-            }
-            Csr::Stvec => {
-                match value & 0b11 {
-                    // Direct mode
-                    0b00 => self.csr.stvec = value,
-                    // Vector mode
-                    0b01 => self.csr.stvec = value,
-                    // Reserved mode
-                    _ => {
-                        self.csr.stvec = (value & !0b11) | (self.csr.stvec & 0b11);
-                    }
-                }
+                let writable = self.supervisor_interrupts() & self.get(Csr::Mideleg);
+                self.csr.mie = (self.csr.mie & !writable) | (writable & value);
             }
+            Csr::Stvec => self.csr.stvec = legalize_tvec(self.csr.stvec, value),
             Csr::Scounteren => {
                 // Only show IR, TM and CY (for cycle, time and instret counters)
                 let mask = 0b111; // We do not support counters beyond basic ones for now
                 self.csr.scounteren = (value & mask) as u32
             }
-            Csr::Senvcfg => {
-                let mut mask = menvcfg::FIOM_FILTER
-                    | menvcfg::CBIE_FILTER
-                    | menvcfg::CBZE_FILTER
-                    | menvcfg::CBCFE_FILTER;
-
-                // Filter valid values based on implemented extensions.
-                if !mctx.hw.extensions.has_zicbom_extension {
-                    mask &= !(menvcfg::CBIE_FILTER | menvcfg::CBCFE_FILTER);
-                }
-                if !mctx.hw.extensions.has_zicboz_extension {
-                    mask &= !menvcfg::CBZE_FILTER;
-                }
-
-                self.csr.senvcfg = value & mask
-            }
+            Csr::Senvcfg => self.csr.senvcfg = legalize_senvcfg(value, &mctx.hw.extensions),
             Csr::Sscratch => self.csr.sscratch = value,
             Csr::Sepc => {
                 if value > Plat::get_max_valid_address() {
                     return;
                 }
-                if hw.extensions.has_c_extension {
-                    self.csr.sepc = value & !0b1
-                } else {
-                    self.csr.sepc = value & !0b11
-                }
+                self.csr.sepc = legalize_xepc(value, hw.extensions.has_c_extension);
             }
             Csr::Scause => self.csr.scause = value,
             Csr::Stval => self.csr.stval = value,
-            Csr::Sip => {
-                if self.csr.mideleg & SSIE_FILTER != 0 {
-                    self.csr.mip = (self.csr.mip & !SSIE_FILTER) | (SSIE_FILTER & value);
-                }
-            }
+            Csr::Sip => self.csr.mip = legalize_sip(self.csr.mip, self.csr.mideleg, value),
             Csr::Satp => {
                 let satp_mode = (value >> 60) & 0b1111;
                 match satp_mode {
@@ -600,8 +658,11 @@ impl HwRegisterContextSetter<Csr> for VirtContext {
                     _ => { /* Nothing to change */ }
                 }
             }
-            Csr::Scontext => (), // TODO: No information from the specification currently
-            Csr::Stimecmp => self.csr.stimecmp = value,
+            Csr::Scontext => self.csr.scontext = value & debug_context::SCONTEXT_FILTER,
+            Csr::Stimecmp => {
+                self.csr.stimecmp = value;
+                self.update_sstc_stip();
+            }
             Csr::Hstatus => {
                 let mut value = value;
 
@@ -675,16 +736,14 @@ impl HwRegisterContextSetter<Csr> for VirtContext {
             Csr::Htimedelta => self.csr.htimedelta = value,
             Csr::Htval => self.csr.htval = value,
             Csr::Htinst => self.csr.htinst = value,
-            Csr::Hgatp => {
-                self.csr.hgatp = value & !(0b11 << 58);
-            }
+            Csr::Hgatp => self.csr.hgatp = legalize_hgatp(self.csr.hgatp, value),
             Csr::Vsstatus => self.csr.vsstatus = value,
             Csr::Vsie => {
                 let write_vsie_mask: usize =
                     !((0b111111 << 10) | (0b111 << 6) | (0b111 << 2) | (0b1));
                 self.csr.vsie = value & write_vsie_mask
             }
-            Csr::Vstvec => self.csr.vstvec = value,
+            Csr::Vstvec => self.csr.vstvec = legalize_tvec(self.csr.vstvec, value),
             Csr::Vsscratch => self.csr.vsscratch = value,
             Csr::Vsepc => self.csr.vsepc = value,
             Csr::Vscause => self.csr.vscause = value,
@@ -696,18 +755,43 @@ impl HwRegisterContextSetter<Csr> for VirtContext {
             }
             Csr::Vsatp => self.csr.vsatp = value,
 
+            // Floating-point extension
+            Csr::Fflags | Csr::Frm | Csr::Fcsr => {
+                unsafe { arch::write_csr(register, value) };
+                self.dirty_f_context();
+            }
+
             // Vector extension
-            Csr::Vstart => self.csr.vstart = (value & 0xff) as u16,
-            Csr::Vxsat => self.csr.vxsat = (value & 0x1) != 0,
-            Csr::Vxrm => self.csr.vxrm = (value & 0b11) as u8,
-            Csr::Vcsr => self.csr.vcsr = (value & 0b111) as u8,
-            Csr::Vl => self.csr.vl = value,
-            Csr::Vtype => self.csr.vtype = value,
+            Csr::Vstart => {
+                self.csr.vstart = (value & 0xff) as u16;
+                self.dirty_v_context();
+            }
+            Csr::Vxsat => {
+                self.csr.vxsat = (value & 0x1) != 0;
+                self.dirty_v_context();
+            }
+            Csr::Vxrm => {
+                self.csr.vxrm = (value & 0b11) as u8;
+                self.dirty_v_context();
+            }
+            Csr::Vcsr => {
+                self.csr.vcsr = (value & 0b111) as u8;
+                self.dirty_v_context();
+            }
+            Csr::Vl => {
+                self.csr.vl = value;
+                self.dirty_v_context();
+            }
+            Csr::Vtype => {
+                self.csr.vtype = value;
+                self.dirty_v_context();
+            }
             Csr::Vlenb => self.csr.vlenb = value,
 
-            Csr::Cycle => (),   // Read only register
-            Csr::Time => (),    // Read only register
-            Csr::Instret => (), // Read only register
+            Csr::Cycle => (),                               // Read only register
+            Csr::Time => (),                                // Read only register
+            Csr::Instret => (),                             // Read only register
+            Csr::Cycleh | Csr::Timeh | Csr::Instreth => (), // Read only register
 
             // Crypto extension
             Csr::Seed => (), // Read only register
@@ -769,4 +853,180 @@ impl VirtContext {
         let cfg = (reg >> (inner_idx * 8)) & 0xff;
         cfg as u8
     }
+
+    /// Returns true if at least one of the virtual PMP entries is locked.
+    fn has_locked_pmp(&self) -> bool {
+        (0..self.nb_pmp).any(|idx| self.get_pmpcfg(idx) & pmpcfg::L != 0)
+    }
+
+    /// Returns true if a PMP entry configured with `previous` can be overwritten with `cfg`.
+    fn is_pmp_rule_writable(&self, previous: u8, cfg: u8) -> bool {
+        if self.csr.mseccfg & mseccfg::RLB_FILTER != 0 {
+            return true;
+        }
+        if previous & pmpcfg::L != 0 {
+            return false;
+        }
+
+        // With MML, M-mode only and shared executable rules can no longer be added
+        let mml = self.csr.mseccfg & mseccfg::MML_FILTER != 0;
+        let executable =
+            pmp::effective_permissions(cfg, self.csr.mseccfg, Mode::M) & pmpcfg::X != 0;
+        !(mml && cfg & pmpcfg::L != 0 && executable)
+    }
+
+    /// Returns true if the pmpaddr register of the given index is locked.
+    ///
+    /// An address is locked if its entry is locked, or if the next entry is a locked TOR entry,
+    /// which uses the address as its lower bound.
+    fn is_pmpaddr_locked(&self, index: usize) -> bool {
+        if self.csr.mseccfg & mseccfg::RLB_FILTER != 0 {
+            return false;
+        }
+        let locked_tor = pmpcfg::L | pmpcfg::TOR;
+        let next_is_locked_tor = index + 1 < self.nb_pmp
+            && self.get_pmpcfg(index + 1) & (pmpcfg::L | pmpcfg::A_MASK) == locked_tor;
+        self.get_pmpcfg(index) & pmpcfg::L != 0 || next_is_locked_tor
+    }
+
+    /// Installs the new virtual PMP configuration, which applies immediately to the firmware.
+    pub(crate) fn update_firmware_pmp(&self, mctx: &mut MiralisContext) {
+        self.load_firmware_pmp(mctx);
+        unsafe { arch::write_pmp(&mctx.pmp).flush() };
+    }
+
+    /// Return the value of `mip.STIP` driven by the Sstc extension, or None if Sstc is disabled.
+    ///
+    /// When `menvcfg.STCE` is set STIP is read-only, and pending if and only if the current time
+    /// is greater or equal to `stimecmp`.
+    fn sstc_stip(&self) -> Option<usize> {
+        if !self.extensions.has_sstc_extension || self.csr.menvcfg & menvcfg::STCE_FILTER == 0 {
+            return None;
+        }
+
+        if arch::read_csr(Csr::Time) >= self.csr.stimecmp {
+            Some(mie::STIE_FILTER)
+        } else {
+            Some(0)
+        }
+    }
+
+    /// Update the virtual `mip.STIP` from the `stimecmp` comparison if Sstc is enabled.
+    fn update_sstc_stip(&mut self) {
+        if let Some(stip) = self.sstc_stip() {
+            self.csr.mip = (self.csr.mip & !mie::STIE_FILTER) | stip;
+        }
+    }
+
+    /// Return the interrupts that can be observed by S-mode through `sie` and `sip`.
+    ///
+    /// The local counter overflow interrupt is only visible if the Sscofpmf extension is
+    /// implemented.
+    fn supervisor_interrupts(&self) -> usize {
+        if self.extensions.has_sscofpmf_extension {
+            mie::SIE_FILTER | mie::LCOFIE_FILTER
+        } else {
+            mie::SIE_FILTER
+        }
+    }
+
+    /// Return the S-mode view of `mie` or `mip`.
+    ///
+    /// Following `lower_mie` and `lower_mip` in the Sail model, only the supervisor interrupts
+    /// that are delegated with `mideleg` are visible. Changing `mideleg` therefore changes the
+    /// bits observed by S-mode.
+    fn lower_interrupts(&self, interrupts: usize) -> usize {
+        interrupts & self.supervisor_interrupts() & self.get(Csr::Mideleg)
+    }
+
+    /// Mark the floating-point state as Dirty, as done by `dirty_fd_context` in the Sail model.
+    fn dirty_f_context(&mut self) {
+        self.csr.mstatus = mstatus::with_sd(self.csr.mstatus | mstatus::FS_FILTER);
+    }
+
+    /// Mark the vector state as Dirty, as done by `dirty_v_context` in the Sail model.
+    fn dirty_v_context(&mut self) {
+        if self.extensions.has_v_extension {
+            self.csr.mstatus = mstatus::with_sd(self.csr.mstatus | mstatus::VS_FILTER);
+        }
+    }
+}
+
+/// Returns the new value of `mip` after a write of `value` to `sip`.
+///
+/// Following the `legalize_sip` function of the Sail model, only the supervisor software
+/// interrupt pending bit is writable through `sip`, and only if that interrupt is delegated.
+pub fn legalize_sip(mip: usize, mideleg: usize, value: usize) -> usize {
+    let writable = SSIE_FILTER & mideleg;
+    (mip & !writable) | (value & writable)
+}
+
+/// Returns the new value of a trap vector register (such as `mtvec`) after a write of `value`.
+///
+/// Following the `legalize_tvec` function of the Sail model, the base is written as is (it is
+/// always 4-byte aligned, as the two lowest bits encode the mode) and writes of a reserved mode
+/// preserve the previous mode.
+pub fn legalize_tvec(tvec: usize, value: usize) -> usize {
+    match value & mtvec::MODE_FILTER {
+        // Direct or vectored mode
+        0b00 | 0b01 => value,
+        // Reserved mode
+        _ => (value & mtvec::BASE_FILTER) | (tvec & mtvec::MODE_FILTER),
+    }
+}
+
+/// Returns the new value of an exception PC register (such as `mepc`) after a write of `value`.
+///
+/// Following the `legalize_xepc` function of the Sail model, bit 0 is always cleared and bit 1 is
+/// only writable if the C extension can be enabled. The read value is further masked depending on
+/// the current value of `misa.C`, see [VirtContext::pc_alignment_mask].
+pub fn legalize_xepc(value: usize, has_c_extension: bool) -> usize {
+    if has_c_extension {
+        value & !0b1
+    } else {
+        value & !0b11
+    }
+}
+
+/// Returns the new value of `hgatp` after a write of `value`.
+///
+/// Similarly to the `legalize_satp64` function of the Sail model, writes selecting an unsupported
+/// G-stage translation mode are ignored and preserve the previous value. Only Bare and Sv39x4 are
+/// supported, as for `satp`. Bits 59:58 are reserved and the two lowest bits of the PPN are
+/// read-only zero in Sv39x4, as the root page table is 16 KiB aligned.
+pub fn legalize_hgatp(hgatp: usize, value: usize) -> usize {
+    let value = value & (hgatp::MODE_FILTER | hgatp::VMID_FILTER | hgatp::PPN_FILTER);
+    match hgatp::mode_of_bits(value) {
+        Some(hgatp::HgatpMode::Bare) => value,
+        Some(hgatp::HgatpMode::Sv39x4) => value & !0b11,
+        _ => hgatp,
+    }
+}
+
+/// Returns the new value of `senvcfg` after a write of `value`.
+///
+/// Following the `legalize_senvcfg` function of the Sail model, only the fields of the implemented
+/// extensions are writable and the reserved CBIE encoding is replaced by 0 (illegal).
+pub fn legalize_senvcfg(value: usize, extensions: &ExtensionsCapability) -> usize {
+    let mut mask =
+        menvcfg::FIOM_FILTER | menvcfg::CBIE_FILTER | menvcfg::CBZE_FILTER | menvcfg::CBCFE_FILTER;
+
+    // Filter valid values based on implemented extensions.
+    if !extensions.has_zicbom_extension {
+        mask &= !(menvcfg::CBIE_FILTER | menvcfg::CBCFE_FILTER);
+    }
+    if !extensions.has_zicboz_extension {
+        mask &= !menvcfg::CBZE_FILTER;
+    }
+
+    legalize_cbie(value & mask)
+}
+
+/// Replaces the reserved CBIE encoding of an `menvcfg` or `senvcfg` value by 0 (illegal).
+fn legalize_cbie(envcfg: usize) -> usize {
+    if menvcfg::cbie(envcfg) == menvcfg::CBIE_RESERVED {
+        envcfg & !menvcfg::CBIE_FILTER
+    } else {
+        envcfg
+    }
 }
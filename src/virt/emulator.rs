@@ -1,28 +1,37 @@
 //! RISC-V privileged instruction emulation
 
-use miralis_core::abi;
+use core::ptr;
+use core::sync::atomic::{Ordering, fence};
+
+use miralis_config::helper::parse_usize_or;
+use miralis_core::{abi, log_ring, sbi_codes};
 
 use super::csr::traits::*;
-use super::{VirtContext, VirtCsr};
+use super::{ExecutionMode, VirtContext, VirtCsr, world_switch};
 use crate::arch::hstatus::{GVA_FILTER, SPV_FILTER, SPVP_FILTER};
+use crate::arch::menvcfg::CboInval;
 use crate::arch::mie::{
-    MEIE_OFFSET, MSIE_OFFSET, MTIE_OFFSET, SEIE_OFFSET, SIE_FILTER, SSIE_FILTER, SSIE_OFFSET,
-    STIE_OFFSET,
+    LCOFIE_OFFSET, MEIE_OFFSET, MSIE_OFFSET, MTIE_OFFSET, SEIE_OFFSET, SIE_FILTER, SSIE_FILTER,
+    SSIE_OFFSET, STIE_OFFSET,
 };
 use crate::arch::mstatus::{
     MPP_FILTER, MPP_OFFSET, MPV_FILTER, SPIE_FILTER, SPIE_OFFSET, SPP_FILTER, SPP_OFFSET,
 };
+use crate::arch::pmp::pmplayout::MPRV_EMULATION_OFFSET;
+use crate::arch::pmp::{PmpGroup, Segment, pmpcfg};
 use crate::arch::{
-    Csr, MCause, Mode, Register, get_raw_faulting_instr, mie, misa, mstatus, mtvec,
-    parse_mpp_return_mode, parse_spp_return_mode,
+    AccessKind, BarrierKind, Csr, MCause, Mode, Register, get_raw_faulting_instr, menvcfg,
+    mhpmevent, mie, misa, mstatus, mtvec, parse_mpp_return_mode, parse_spp_return_mode, pmp,
 };
-use crate::decoder::{IllegalInst, LoadInstr, StoreInstr};
+use crate::decoder::{IllegalInst, LoadInstr, StoreInstr, ZicondInstr, ZicondOp};
 use crate::device::VirtDevice;
-use crate::host::MiralisContext;
+use crate::host::{AddressKind, MiralisContext};
 use crate::modules::{MainModule, Module};
 use crate::platform::{Plat, Platform};
 use crate::utils::sign_extend;
-use crate::{arch, debug, device, logger, utils};
+use crate::virt::memory;
+use crate::virt::memory::emulate_amocas;
+use crate::{arch, benchmark, config, debug, device, logger, utils};
 
 /// Whether to continue execution of the virtual firmware or payload, or terminate the run loop.
 #[derive(PartialEq, Eq, Clone, Copy)]
@@ -31,8 +40,16 @@ pub enum ExitResult {
     Continue,
     /// Terminate execution successfully.
     Done,
+    /// Terminate execution with a failure.
+    Failure,
 }
 
+/// Maximum number of iterations of the emulated `wrs.sto` stall.
+const WRS_STO_ITERATIONS: usize = 1_000;
+
+/// A firmware trap handler, see [VirtContext::firmware_trap_handler].
+type FirmwareTrapHandler = fn(&mut VirtContext, &mut MiralisContext, &mut MainModule) -> ExitResult;
+
 /// A load or store instruction.
 #[derive(Debug)]
 enum LoadStoreInstr {
@@ -46,17 +63,21 @@ impl VirtContext {
     /// Dispatches to the appropriate emulation handler based on the instruction type,
     /// and increments the program counter by 4 bytes for all instructions (except MRET and SRET).
     fn emulate_privileged_instr(&mut self, instr: &IllegalInst, mctx: &mut MiralisContext) {
+        if config::TRACE_INSTRUCTIONS {
+            log::trace!("{}", debug::EmulatedInstr::new(self, instr));
+        }
+
         match instr {
             IllegalInst::Wfi => self.emulate_wfi(mctx),
-            IllegalInst::Csrrw { csr, .. }
-            | IllegalInst::Csrrs { csr, .. }
-            | IllegalInst::Csrrc { csr, .. }
-            | IllegalInst::Csrrwi { csr, .. }
-            | IllegalInst::Csrrsi { csr, .. }
-            | IllegalInst::Csrrci { csr, .. }
-                if csr.is_unknown() =>
-            {
+            _ if is_illegal_csr_access(instr) => {
+                // The trap is forwarded to the firmware, the pc must not be incremented
+                self.emulate_firmware_trap();
+                return;
+            }
+            _ if is_fp_csr_access(instr) && !self.is_fp_enabled() => {
+                // The FP CSRs are not accessible while mstatus.FS is Off
                 self.emulate_firmware_trap();
+                return;
             }
             IllegalInst::Csrrw { csr, rd, rs1 } => self.emulate_csrrw(mctx, *csr, *rd, *rs1),
             IllegalInst::Csrrs { csr, rd, rs1 } => self.emulate_csrrs(mctx, *csr, *rd, *rs1),
@@ -69,6 +90,35 @@ impl VirtContext {
             IllegalInst::Sfencevma { rs1, rs2 } => self.emulate_sfence_vma(mctx, rs1, rs2),
             IllegalInst::Hfencegvma { rs1, rs2 } => self.emulate_hfence_gvma(mctx, rs1, rs2),
             IllegalInst::Hfencevvma { rs1, rs2 } => self.emulate_hfence_vvma(mctx, rs1, rs2),
+            IllegalInst::Fence(kind) => self.emulate_fence(mctx, *kind),
+            IllegalInst::Sinvalvma { .. }
+            | IllegalInst::Sfencewinval
+            | IllegalInst::Sfenceinvalir
+                if !config::VCPU_EMULATE_SVINVAL =>
+            {
+                // Svinval is not emulated, the trap is forwarded to the firmware
+                self.emulate_firmware_trap();
+                return;
+            }
+            IllegalInst::Sinvalvma { .. }
+            | IllegalInst::Sfencewinval
+            | IllegalInst::Sfenceinvalir => self.emulate_svinval(mctx, instr),
+            IllegalInst::Wrsnto | IllegalInst::Wrssto if !config::VCPU_EMULATE_ZAWRS => {
+                // Zawrs is not emulated, the trap is forwarded to the firmware
+                self.emulate_firmware_trap();
+                return;
+            }
+            IllegalInst::Wrsnto | IllegalInst::Wrssto => self.emulate_wrs(instr),
+            IllegalInst::Hlv(load) => {
+                // The pc is updated by the emulation, unless the access traps
+                self.emulate_hlv(mctx, load);
+                return;
+            }
+            IllegalInst::Hsv(store) => {
+                // The pc is updated by the emulation, unless the access traps
+                self.emulate_hsv(mctx, store);
+                return;
+            }
             _ => todo!(
                 "Instruction not yet implemented: {:?} {:x} {:x}",
                 instr,
@@ -170,7 +220,12 @@ impl VirtContext {
     /// - A load/store with MPRV set to 1
     /// - A normal access fault, which should be forwarded.
     fn handle_pmp_fault(&mut self, mctx: &mut MiralisContext, instr: LoadStoreInstr) {
-        if let Some(device) = device::find_matching_device(self.trap_info.mtval, mctx.devices) {
+        let device = match mctx.classify_address(self.trap_info.mtval) {
+            AddressKind::Device => device::find_matching_device(self.trap_info.mtval, mctx.devices),
+            AddressKind::Miralis | AddressKind::Memory => None,
+        };
+
+        if let Some(device) = device {
             // The fault is due to an access to a virtual device
             logger::trace!(
                 "Accessed devices: {} | With instr: {:?}",
@@ -192,21 +247,12 @@ impl VirtContext {
             // not for instruction fetches, thus it is not possible to emulate the MPRV = 1
             // behavior using page tables. Of course the current emulation strategy comes with a
             // performance overhead.
-            //
-            // TODO: make sure virtual address does not get around PMP protection
             logger::trace!(
                 "Access fault {:x?} with a virtual address: 0x{:x}",
                 &instr,
                 self.trap_info.mtval
             );
-            match instr {
-                LoadStoreInstr::Load(instr) => unsafe {
-                    arch::handle_virtual_load(instr, self);
-                },
-                LoadStoreInstr::Store(instr) => unsafe {
-                    arch::handle_virtual_store(instr, self);
-                },
-            }
+            self.emulate_mprv_access(mctx, instr);
         } else {
             logger::trace!(
                 "No matching device found for address: {:x}",
@@ -216,11 +262,64 @@ impl VirtContext {
         }
     }
 
+    /// Emulates a load or store of the virtual firmware with the virtual `mstatus.MPRV` set.
+    ///
+    /// The access uses the privileges of the virtual `mstatus.MPP`: the virtual PMP is enforced
+    /// when MPP is S or U-mode, while M-mode accesses use the regular access rights of the
+    /// firmware (which runs in U-mode). The MPRV emulation entry is disabled during the access,
+    /// and the PMP configuration of the firmware is restored afterward.
+    fn emulate_mprv_access(&mut self, mctx: &mut MiralisContext, instr: LoadStoreInstr) {
+        let virt_mode = self.access_mode(AccessKind::Data);
+        let mode = match virt_mode {
+            Mode::M => Mode::U,
+            mode => mode,
+        };
+
+        mctx.pmp.set_inactive(MPRV_EMULATION_OFFSET, 0);
+        if virt_mode != Mode::M {
+            mctx.pmp.load_with_offset(
+                &self.csr.pmpaddr,
+                &self.csr.pmpcfg,
+                self.csr.mseccfg,
+                virt_mode,
+                mctx.pmp.virt_pmp_offset,
+                self.nb_pmp,
+            );
+            if self.nb_pmp > 0 {
+                let last_pmp_idx = mctx.pmp.nb_pmp as usize - 1;
+                mctx.pmp
+                    .set_napot(last_pmp_idx, 0, usize::MAX, pmpcfg::NO_PERMISSIONS);
+            }
+        }
+        unsafe { arch::write_pmp(&mctx.pmp).flush() };
+
+        match instr {
+            LoadStoreInstr::Load(instr) => unsafe {
+                arch::handle_virtual_load(instr, self, mode);
+            },
+            LoadStoreInstr::Store(instr) => unsafe {
+                arch::handle_virtual_store(instr, self, mode);
+            },
+        }
+
+        // Restore the PMP configuration of the firmware, which traps all loads and stores while
+        // MPRV is set
+        self.load_firmware_pmp(mctx);
+        mctx.pmp
+            .set_napot(MPRV_EMULATION_OFFSET, 0, usize::MAX, pmpcfg::X);
+        unsafe { arch::write_pmp(&mctx.pmp).flush() };
+    }
+
     /// Check if an interrupt should be injected in virtual M-mode, and perform the injection if
     /// any.
     ///
     /// If an interrupt is injected, jumps to the firmware trap handler.
-    pub fn check_and_inject_interrupts(&mut self) {
+    ///
+    /// The `exec_mode` is the execution mode in which the trap was taken. Interrupts delegated to
+    /// S-mode and virtual supervisor interrupts are only emulated when coming from the firmware,
+    /// because the S-mode and VS-mode CSRs are then held by the virtual context. Otherwise the
+    /// hardware delivers them natively.
+    pub fn check_and_inject_interrupts(&mut self, exec_mode: ExecutionMode) {
         // For now, we assume that the vCPU will be run each time this function is called (or
         // rather, that this function is called before each vCPU run). Therefore, by running the
         // vCPU we exit the WFI mode, even if no interrupt is received (spurious wake-ups).
@@ -228,24 +327,237 @@ impl VirtContext {
 
         if let Some(int_id) = self.has_pending_interrupt() {
             self.inject_interrupt(int_id)
+        } else if exec_mode == ExecutionMode::Firmware
+            && let Some(int_id) = self.has_pending_s_interrupt()
+        {
+            self.inject_s_interrupt(int_id)
+        } else if exec_mode == ExecutionMode::Firmware
+            && let Some(int_id) = self.has_pending_vs_interrupt()
+        {
+            self.inject_vs_interrupt(int_id)
         }
     }
 
     /// Return the next pending interrupt, if any.
     fn has_pending_interrupt(&mut self) -> Option<usize> {
-        if self.csr.mstatus & mstatus::MIE_FILTER == 0 && self.mode == Mode::M && !self.is_wfi {
-            // Interrupts are disabled while in M-mode if mstatus.MIE is 0
+        if self.csr.mstatus & mstatus::MIE_FILTER == 0 && self.mode == Mode::M {
+            // Interrupts are disabled while in M-mode if mstatus.MIE is 0, the interrupt stays
+            // pending until the firmware sets mstatus.MIE. This is also true when waking up from
+            // a WFI: execution resumes after the WFI without taking the interrupt.
             return None;
         }
 
         get_next_interrupt(self.csr.mie, self.csr.mip, self.csr.mideleg)
     }
 
+    /// Whether the vCPU runs in VS or VU-mode.
+    ///
+    /// The virtualization mode is given by the virtual `mstatus.MPV`, which is installed in the
+    /// physical `mstatus` on world switch and is therefore used when returning to S or U-mode.
+    pub fn is_virtualized(&self) -> bool {
+        self.extensions.has_h_extension
+            && self.mode != Mode::M
+            && self.csr.mstatus & MPV_FILTER != 0
+    }
+
+    /// Returns the virtual mode whose access rights apply to a memory access of the vCPU.
+    ///
+    /// Data accesses of the virtual firmware use the virtual `mstatus.MPP` if the virtual
+    /// `mstatus.MPRV` is set, see [arch::effective_access_mode].
+    pub fn access_mode(&self, kind: AccessKind) -> Mode {
+        arch::effective_access_mode(self.mode, self.csr.mstatus, kind)
+    }
+
+    /// Returns the mode a synchronous exception taken in the current mode must be delivered to.
+    ///
+    /// `medeleg` is the authority for synchronous exceptions: they are delivered to S-mode only
+    /// if their bit is set, and never leave M-mode once taken there. All other exceptions must
+    /// reach the firmware.
+    pub fn get_exception_target_mode(&self, cause: MCause) -> Mode {
+        // Unknown exceptions do not have a well defined bit in medeleg, they are never delegated
+        let delegated = self.csr.misa & misa::S != 0
+            && cause != MCause::UnknownException
+            && self.csr.medeleg & (1 << MCause::cause_number(cause as usize)) != 0;
+
+        if delegated && self.mode != Mode::M {
+            Mode::S
+        } else {
+            Mode::M
+        }
+    }
+
+    /// Returns the behavior of `cbo.inval` when executed in the given mode.
+    ///
+    /// Instructions executed in S or U-mode are controlled by `menvcfg.CBIE`, and those executed
+    /// in U-mode are further restricted by `senvcfg.CBIE` (if S-mode is implemented).
+    pub fn get_cbo_inval(&self, mode: Mode) -> CboInval {
+        let m_cbie = menvcfg::cbie(self.csr.menvcfg);
+        let s_cbie = menvcfg::cbie(self.get_supervisor_envcfg());
+
+        match mode {
+            Mode::M => CboInval::Inval,
+            _ if m_cbie == menvcfg::CBIE_ILLEGAL => CboInval::Illegal,
+            Mode::U if s_cbie == menvcfg::CBIE_ILLEGAL => CboInval::Illegal,
+            _ if m_cbie == menvcfg::CBIE_FLUSH => CboInval::Flush,
+            Mode::U if s_cbie == menvcfg::CBIE_FLUSH => CboInval::Flush,
+            _ => CboInval::Inval,
+        }
+    }
+
+    /// Whether `cbo.clean` and `cbo.flush` are enabled in the given mode (`CBCFE` fields).
+    pub fn is_cbo_clean_flush_enabled(&self, mode: Mode) -> bool {
+        self.is_envcfg_feature_enabled(mode, menvcfg::CBCFE_FILTER)
+    }
+
+    /// Whether `cbo.zero` is enabled in the given mode (`CBZE` fields).
+    pub fn is_cbo_zero_enabled(&self, mode: Mode) -> bool {
+        self.is_envcfg_feature_enabled(mode, menvcfg::CBZE_FILTER)
+    }
+
+    /// Whether an `envcfg` enable bit allows the feature in the given mode.
+    ///
+    /// Features are always enabled in M-mode, require the `menvcfg` bit in S-mode, and both the
+    /// `menvcfg` and `senvcfg` bits in U-mode.
+    fn is_envcfg_feature_enabled(&self, mode: Mode, filter: usize) -> bool {
+        match mode {
+            Mode::M => true,
+            Mode::S => self.csr.menvcfg & filter != 0,
+            Mode::U => self.csr.menvcfg & filter != 0 && self.get_supervisor_envcfg() & filter != 0,
+        }
+    }
+
+    /// Returns the `envcfg` value controlling U-mode, `senvcfg` if S-mode is implemented and
+    /// `menvcfg` otherwise.
+    fn get_supervisor_envcfg(&self) -> usize {
+        if self.csr.misa & misa::S != 0 {
+            self.csr.senvcfg
+        } else {
+            self.csr.menvcfg
+        }
+    }
+
+    /// Return the next pending virtual supervisor interrupt, if any.
+    ///
+    /// Virtual supervisor interrupts are injected by the hypervisor through `hvip`, and are only
+    /// taken while running in VS or VU-mode. The returned ID is the one of the interrupt as seen
+    /// from VS-mode, that is the corresponding S-mode interrupt.
+    fn has_pending_vs_interrupt(&self) -> Option<usize> {
+        if !self.is_virtualized() {
+            return None;
+        }
+
+        if self.mode == Mode::S && self.csr.vsstatus & mstatus::SIE_FILTER == 0 {
+            // Interrupts are disabled while in VS-mode if vsstatus.SIE is 0
+            return None;
+        }
+
+        get_next_vs_interrupt(self.csr.vsie, self.csr.hvip, self.csr.hideleg)
+    }
+
+    /// Return the next pending interrupt delegated to S-mode, if any.
+    ///
+    /// Delegated interrupts are never taken in M-mode, and are only taken in S-mode if
+    /// `sstatus.SIE` is set. Otherwise the interrupt stays pending until the vCPU can take it.
+    fn has_pending_s_interrupt(&self) -> Option<usize> {
+        if self.mode == Mode::M {
+            return None;
+        }
+
+        if self.is_virtualized() {
+            // Interrupts delegated to HS-mode are always enabled in VS and VU-mode, we leave
+            // them to the hardware which also updates the hypervisor state when taking them.
+            return None;
+        }
+
+        if self.mode == Mode::S && self.csr.mstatus & mstatus::SIE_FILTER == 0 {
+            // Interrupts are disabled while in S-mode if sstatus.SIE is 0
+            return None;
+        }
+
+        let ip = self.csr.mie & self.csr.mip & self.csr.mideleg & mie::SIE_FILTER;
+        find_pending_interrupt_by_priority(ip)
+    }
+
+    /// Inject an interrupt delegated to S-mode.
+    ///
+    /// This function jumps to the S-mode trap handler and updates the S-mode CSRs, as the hardware
+    /// would do when taking the interrupt in S-mode.
+    fn inject_s_interrupt(&mut self, next_int: usize) {
+        // Update sstatus to match the semantic of a trap
+        let spie = (self.csr.mstatus & mstatus::SIE_FILTER) >> mstatus::SIE_OFFSET;
+        VirtCsr::set_csr_field(&mut self.csr.mstatus, SPIE_OFFSET, SPIE_FILTER, spie);
+        VirtCsr::set_csr_field(
+            &mut self.csr.mstatus,
+            mstatus::SIE_OFFSET,
+            mstatus::SIE_FILTER,
+            0,
+        );
+        let spp = if self.mode == Mode::S { 1 } else { 0 };
+        VirtCsr::set_csr_field(&mut self.csr.mstatus, SPP_OFFSET, SPP_FILTER, spp);
+
+        self.csr.scause = next_int | (1 << (usize::BITS - 1));
+        self.csr.sepc = self.pc;
+        self.csr.stval = 0;
+        self.mode = Mode::S;
+
+        // The S trap vector follows the same format as mtvec
+        self.pc = match mtvec::get_mode(self.csr.stvec) {
+            mtvec::Mode::Direct => self.csr.stvec & mtvec::BASE_FILTER,
+            mtvec::Mode::Vectored => {
+                (self.csr.stvec & mtvec::BASE_FILTER).wrapping_add(4_usize.wrapping_mul(next_int))
+            }
+        };
+    }
+
+    /// Inject a virtual supervisor interrupt.
+    ///
+    /// This function jumps to the VS-mode trap handler and updates the VS-mode CSRs, as the
+    /// hardware would do when taking the interrupt in VS-mode.
+    fn inject_vs_interrupt(&mut self, next_int: usize) {
+        // Update vsstatus to match the semantic of a trap
+        let spie = (self.csr.vsstatus & mstatus::SIE_FILTER) >> mstatus::SIE_OFFSET;
+        VirtCsr::set_csr_field(&mut self.csr.vsstatus, SPIE_OFFSET, SPIE_FILTER, spie);
+        VirtCsr::set_csr_field(
+            &mut self.csr.vsstatus,
+            mstatus::SIE_OFFSET,
+            mstatus::SIE_FILTER,
+            0,
+        );
+        let spp = if self.mode == Mode::S { 1 } else { 0 };
+        VirtCsr::set_csr_field(&mut self.csr.vsstatus, SPP_OFFSET, SPP_FILTER, spp);
+
+        self.csr.vscause = next_int | (1 << (usize::BITS - 1));
+        self.csr.vsepc = self.pc;
+        self.csr.vstval = 0;
+        self.mode = Mode::S;
+
+        // The VS trap vector follows the same format as mtvec
+        self.pc = match mtvec::get_mode(self.csr.vstvec) {
+            mtvec::Mode::Direct => self.csr.vstvec & mtvec::BASE_FILTER,
+            mtvec::Mode::Vectored => {
+                (self.csr.vstvec & mtvec::BASE_FILTER).wrapping_add(4_usize.wrapping_mul(next_int))
+            }
+        };
+    }
+
     /// Inject a virtual interrupt.
     ///
     /// This function jumps to the trap handler for the corresponding interrupts and updates the
     /// virtual CSRs accordingly.
     fn inject_interrupt(&mut self, next_int: usize) {
+        // With the H extension, MPV records the virtualization mode the interrupt is taken from
+        // and GVA is cleared as interrupts never write a guest virtual address to mtval.
+        if self.extensions.has_h_extension {
+            let mpv = if self.is_virtualized() { 1 } else { 0 };
+            VirtCsr::set_csr_field(&mut self.csr.mstatus, mstatus::MPV_OFFSET, MPV_FILTER, mpv);
+            VirtCsr::set_csr_field(
+                &mut self.csr.mstatus,
+                mstatus::GVA_OFFSET,
+                mstatus::GVA_FILTER,
+                0,
+            );
+        }
+
         // Update Mstatus to match the semantic of a trap
         VirtCsr::set_csr_field(
             &mut self.csr.mstatus,
@@ -275,6 +587,33 @@ impl VirtContext {
         self.set_pc_to_mtvec();
     }
 
+    /// Propagate the FP state transitions of the firmware to the virtual `mstatus`.
+    ///
+    /// The firmware writes to the FP registers and CSRs (`fcsr`, `frm`, `fflags`) natively, in
+    /// which case the hardware transitions the physical `mstatus.FS` from Initial or Clean to
+    /// Dirty. We reflect that transition in the virtual `mstatus`, and update SD following the
+    /// `dirty` computation of `legalize_mstatus` in the Sail model.
+    fn update_fs_dirty(&mut self) {
+        let fs_dirty = mstatus::FS_FILTER;
+        if self.trap_info.mstatus & fs_dirty == fs_dirty && self.csr.mstatus & fs_dirty != 0 {
+            self.csr.mstatus = mstatus::with_sd(self.csr.mstatus | mstatus::FS_FILTER);
+        }
+    }
+
+    /// Propagate the vector state transitions of the firmware to the virtual `mstatus`.
+    ///
+    /// Same as [Self::update_fs_dirty], but for `mstatus.VS` which the hardware transitions to
+    /// Dirty when the firmware executes vector instructions or writes the vector CSRs.
+    fn update_vs_dirty(&mut self) {
+        let vs_dirty = mstatus::VS_FILTER;
+        if self.extensions.has_v_extension
+            && self.trap_info.mstatus & vs_dirty == vs_dirty
+            && self.csr.mstatus & vs_dirty != 0
+        {
+            self.csr.mstatus = mstatus::with_sd(self.csr.mstatus | mstatus::VS_FILTER);
+        }
+    }
+
     /// Emulate a firmware trap, jumping to the firmware's mtvec.
     ///
     /// This function modifies the virtual context to emulate a hardware trap to M-mode. It injects
@@ -306,9 +645,11 @@ impl VirtContext {
         // the firmware want to read virtual mip.
         //
         // We also preserve the virtualized interrupt bits from the virtual mip, as those are pure
-        // software and might not match the physical mip.
-        let hw_mip_bits = self.trap_info.mip & !(mie::SEIE_FILTER | mie::MIDELEG_READ_ONLY_ZERO);
-        let sw_mip_bits = self.csr.mip & (mie::SEIE_FILTER | mie::MIDELEG_READ_ONLY_ZERO);
+        // software and might not match the physical mip. This includes LCOFIP, which is raised by
+        // the virtual HPM counters while the firmware is running.
+        let sw_bits = mie::SEIE_FILTER | mie::MIDELEG_READ_ONLY_ZERO | mie::LCOFIE_FILTER;
+        let hw_mip_bits = self.trap_info.mip & !sw_bits;
+        let sw_mip_bits = self.csr.mip & sw_bits;
         self.csr.mip = hw_mip_bits | sw_mip_bits;
 
         match self.mode {
@@ -520,186 +861,889 @@ impl VirtContext {
         }
     }
 
+    /// Increment the virtual HPM counters monitoring the given event.
+    ///
+    /// Counters are filtered by the privilege mode of the virtual context, if the Sscofpmf
+    /// extension is implemented. A counter wrapping around sets the overflow bit of the
+    /// corresponding `mhpmevent` and raises a local counter overflow interrupt (LCOFI), unless the
+    /// overflow bit was already set.
+    fn count_hpm_event(&mut self, event: usize) {
+        let inhibit_filter = match self.mode {
+            Mode::M => mhpmevent::MINH_FILTER,
+            Mode::S => mhpmevent::SINH_FILTER,
+            Mode::U => mhpmevent::UINH_FILTER,
+        };
+
+        for idx in 0..self.csr.mhpmcounter.len() {
+            let event_cfg = self.csr.mhpmevent[idx];
+            if event_cfg & mhpmevent::EVENT_FILTER != event || event_cfg & inhibit_filter != 0 {
+                continue;
+            }
+
+            let (value, overflow) = self.csr.mhpmcounter[idx].overflowing_add(1);
+            self.csr.mhpmcounter[idx] = value;
+            if overflow
+                && self.extensions.has_sscofpmf_extension
+                && event_cfg & mhpmevent::OF_FILTER == 0
+            {
+                self.csr.mhpmevent[idx] |= mhpmevent::OF_FILTER;
+                self.csr.mip |= mie::LCOFIE_FILTER;
+            }
+        }
+    }
+
     /// Handle the trap coming from the firmware
     pub fn handle_firmware_trap(
         &mut self,
         mctx: &mut MiralisContext,
         module: &mut MainModule,
     ) -> ExitResult {
+        self.count_hpm_event(mhpmevent::FIRMWARE_TRAP_EVENT);
+        self.update_fs_dirty();
+        self.update_vs_dirty();
+
         if module.trap_from_firmware(mctx, self).overwrites() {
             logger::trace!("Catching trap in the policy module");
             return ExitResult::Continue;
         }
 
-        let cause = self.trap_info.get_cause();
-        match cause {
-            MCause::EcallFromUMode if module.ecall_from_firmware(mctx, self).overwrites() => {
-                // Nothing to do, the policy module handles those ecalls
-                logger::trace!("Catching E-call from firmware in the policy module");
-            }
-            MCause::EcallFromUMode if self.get(Register::X17) == abi::MIRALIS_EID => {
-                return self.handle_ecall();
-            }
-            MCause::EcallFromUMode => {
-                todo!("ecall is not yet supported for EID other than Miralis ABI");
-            }
-            MCause::EcallFromSMode => {
-                panic!("Firmware should not be able to come from S-mode");
-            }
-            MCause::IllegalInstr => {
-                let instr = unsafe { get_raw_faulting_instr(self) };
+        let handler = Self::firmware_trap_handler(self.trap_info.get_cause());
+        handler(self, mctx, module)
+    }
 
-                // Illegal instruction can have two causes:
-                // - privileged (system) instructions excepts ebreak and ecall
-                // - Vector/floating points while they are disabled
-                // For now we only decode system instructions, but we should handle floating
-                // points/vector in the future.
-                self.emulate_illegal_instruction(mctx, instr)
-            }
-            MCause::Breakpoint => {
-                self.emulate_firmware_trap();
-            }
-            MCause::StoreAccessFault => {
-                let instr = unsafe { get_raw_faulting_instr(self) };
-                let instr = mctx.decode_store(instr);
-                self.handle_pmp_fault(mctx, LoadStoreInstr::Store(instr));
-            }
-            MCause::LoadAccessFault => {
-                let instr = unsafe { get_raw_faulting_instr(self) };
-                let instr = mctx.decode_load(instr);
-                self.handle_pmp_fault(mctx, LoadStoreInstr::Load(instr));
-            }
-            MCause::InstrAccessFault => {
-                logger::trace!("Instruction access fault: {:x?}", self.trap_info);
-                self.emulate_firmware_trap();
-            }
-            MCause::MachineTimerInt => {
-                self.handle_machine_timer_interrupt(mctx);
-            }
-            MCause::MachineSoftInt => {
-                self.handle_machine_software_interrupt(mctx, module);
-            }
-            MCause::MachineExternalInt => {
-                todo!("Virtualize machine external interrupt")
+    /// The dispatch table of firmware traps, returning the handler for each trap cause.
+    ///
+    /// All handlers share the same signature, so that new causes can be supported by adding a
+    /// single entry here.
+    fn firmware_trap_handler(cause: MCause) -> FirmwareTrapHandler {
+        match cause {
+            // The firmware runs in U-mode, its M-mode ecalls are reported as coming from U-mode
+            MCause::EcallFromUMode => Self::handle_firmware_ecall,
+            MCause::EcallFromSMode | MCause::EcallFromMMode => {
+                Self::handle_firmware_unexpected_ecall
             }
-            MCause::LoadAddrMisaligned
+            MCause::IllegalInstr => Self::handle_firmware_illegal_instr,
+            MCause::StoreAccessFault => Self::handle_firmware_store_access_fault,
+            MCause::LoadAccessFault => Self::handle_firmware_load_access_fault,
+            MCause::InstrAccessFault => Self::handle_firmware_instr_access_fault,
+            MCause::MachineTimerInt => Self::handle_firmware_timer_interrupt,
+            MCause::MachineSoftInt => Self::handle_firmware_software_interrupt,
+            MCause::MachineExternalInt => Self::handle_firmware_external_interrupt,
+            MCause::Breakpoint
+            | MCause::LoadAddrMisaligned
             | MCause::StoreAddrMisaligned
-            | MCause::InstrAddrMisaligned => self.emulate_firmware_trap(),
-            _ => {
-                if cause.is_interrupt() {
-                    // TODO : For now, only care for MTIP bit
-                    todo!(
-                        "Other interrupts are not yet implemented {:?} at {:x}",
-                        cause,
-                        self.trap_info.mepc
-                    );
-                } else {
-                    // TODO : Need to match other traps
-                    todo!(
-                        "Other traps are not yet implemented {:?} at {:x}",
-                        cause,
-                        self.trap_info.mepc
-                    );
-                }
-            }
+            | MCause::InstrAddrMisaligned => Self::forward_firmware_trap,
+            MCause::EcallFromVsMode
+            | MCause::InstrPageFault
+            | MCause::LoadPageFault
+            | MCause::StorePageFault
+            | MCause::UnknownException
+            | MCause::InstrGuestPageFault
+            | MCause::LoadGuestPageFault
+            | MCause::VirtualInstr
+            | MCause::StoreGuestPageFault
+            | MCause::UserSoftInt
+            | MCause::SupervisorSoftInt
+            | MCause::VirtualSupervisorSoftInt
+            | MCause::UserTimerInt
+            | MCause::SupervisorTimerInt
+            | MCause::VirtualSupervisorTimerInt
+            | MCause::UserExternalInt
+            | MCause::SupervisorExternalInt
+            | MCause::VirtualSupervisorExternalInt
+            | MCause::SupervisorGuestExternalInt
+            | MCause::LocalCounterOverflowInt
+            | MCause::UnknownInt => Self::handle_firmware_unimplemented_trap,
         }
-
-        ExitResult::Continue
     }
 
-    /// Handle the trap coming from the payload
-    pub fn handle_payload_trap(
+    /// Handles an ecall from the firmware, that is an ecall from (virtual) M-mode.
+    ///
+    /// Calls to the Miralis ABI and SBI base extension are served by Miralis, and return to the
+    /// instruction following the ecall. Other ecalls trap to the firmware's own trap handler as
+    /// an ecall from M-mode, with `mepc` pointing to the ecall as on hardware.
+    fn handle_firmware_ecall(
         &mut self,
         mctx: &mut MiralisContext,
         module: &mut MainModule,
     ) -> ExitResult {
-        // Update the current mode
-        self.mode = parse_mpp_return_mode(self.trap_info.mstatus);
-
-        if module.trap_from_payload(mctx, self).overwrites() {
-            logger::trace!("Catching trap in the policy module");
-            return ExitResult::Continue;
-        }
-
-        // Handle the exit.
-        // We only care about ecalls and virtualized interrupts.
-        match self.trap_info.get_cause() {
-            MCause::EcallFromSMode if module.ecall_from_payload(mctx, self).overwrites() => {
-                // Nothing to do, the Policy module handles those ecalls
-                logger::trace!("Catching E-call from payload in the policy module");
-            }
-            MCause::EcallFromSMode if self.get(Register::X17) == abi::MIRALIS_EID => {
-                return self.handle_ecall();
-            }
-            MCause::EcallFromSMode => {
-                logger::debug!(
-                    "Forwarding ecall from s-mode with values 0x{:x}, 0x{:x} to the firmware",
-                    self.get(Register::X16),
-                    self.get(Register::X17)
-                );
-                self.emulate_firmware_trap();
-            }
-            MCause::MachineTimerInt => {
-                self.handle_machine_timer_interrupt(mctx);
-            }
-            MCause::MachineSoftInt => {
-                self.handle_machine_software_interrupt(mctx, module);
-            }
-            _ => self.emulate_firmware_trap(),
+        if module.ecall_from_firmware(mctx, self).overwrites() {
+            // Nothing to do, the policy module handles those ecalls
+            logger::trace!("Catching E-call from firmware in the policy module");
+        } else if self.get(Register::X17) == abi::MIRALIS_EID {
+            return self.handle_ecall(mctx, module);
+        } else if self.get(Register::X17) == sbi_codes::SBI_BASE_EID {
+            return self.handle_sbi_base(module);
+        } else {
+            logger::debug!(
+                "Forwarding ecall from m-mode with EID 0x{:x} to the firmware",
+                self.get(Register::X17)
+            );
+            self.trap_info.mcause = MCause::EcallFromMMode as usize;
+            self.emulate_firmware_trap();
         }
 
         ExitResult::Continue
     }
 
-    /// Handles Miralis-specific ecalls from firmware or payload.
-    ///
-    /// Miralis-specific ecalls are ecalls from the firmware or payload with extension ID (`eid`)
-    /// equal to `miralis_core::abi::MIRALIS_EID`. The individual ecall functon IDs (`fid`s) are
-    /// defined in the `miralis_core::abi` crate.
-    fn handle_ecall(&mut self) -> ExitResult {
-        let fid = self.get(Register::X16);
-        match fid {
-            abi::MIRALIS_FAILURE_FID => {
-                log::error!("Firmware or payload panicked!");
-                log::error!("  pc:    0x{:x}", self.pc);
-                log::error!("  exits: {}", self.nb_exits);
-                panic!();
-            }
-            abi::MIRALIS_SUCCESS_FID => {
-                log::info!("Success!");
-                log::info!("Number of exits: {}", self.nb_exits);
-                // Terminate execution
-                return ExitResult::Done;
-            }
-            abi::MIRALIS_LOG_FID => {
-                let log_level = self.get(Register::X10);
-                let addr = self.get(Register::X11);
-                let size = self.get(Register::X12);
-
-                // TODO: add proper validation that this memory range belongs to the
-                // payload
-                let bytes = unsafe { core::slice::from_raw_parts(addr as *const u8, size) };
-                let message =
-                    core::str::from_utf8(bytes).unwrap_or("note: invalid message, not utf-8");
-                match log_level {
-                    abi::log::MIRALIS_ERROR => log::error!("> {}", message),
-                    abi::log::MIRALIS_WARN => log::warn!("> {}", message),
-                    abi::log::MIRALIS_INFO => log::info!("> {}", message),
-                    abi::log::MIRALIS_DEBUG => log::debug!("> {}", message),
-                    abi::log::MIRALIS_TRACE => log::trace!("> {}", message),
-                    _ => {
-                        log::info!("Miralis log SBI call with invalid level: {}", log_level)
-                    }
-                }
+    fn handle_firmware_unexpected_ecall(
+        &mut self,
+        _mctx: &mut MiralisContext,
+        _module: &mut MainModule,
+    ) -> ExitResult {
+        panic!(
+            "Firmware ecalls should come from U-mode, got {:?}",
+            self.trap_info.get_cause()
+        );
+    }
 
-                // For now we don't return error code or the length written
-                self.set(Register::X10, 0);
-                self.set(Register::X11, 0);
+    fn handle_firmware_illegal_instr(
+        &mut self,
+        mctx: &mut MiralisContext,
+        _module: &mut MainModule,
+    ) -> ExitResult {
+        let instr = unsafe { get_raw_faulting_instr(self) };
+
+        if let Some(rd) = decode_rdtime(instr) {
+            // Fast path for cores without the `time` CSR, M-mode can always read the time
+            self.set(rd, Plat::get_clint().read_mtime());
+            self.pc += 4;
+        } else if config::VCPU_EMULATE_ZACAS && mctx.decode_amocas(instr).is_some() {
+            if emulate_amocas(self, mctx).is_err() {
+                self.emulate_firmware_trap();
             }
-            _ => panic!("Invalid Miralis FID: 0x{:x}", fid),
+        } else if config::VCPU_EMULATE_ZICOND && self.emulate_zicond(mctx).is_ok() {
+            // The conditional-zero has been emulated
+        } else {
+            // Illegal instruction can have two causes:
+            // - privileged (system) instructions excepts ebreak and ecall
+            // - Vector/floating points while they are disabled
+            // For now we only decode system instructions, but we should handle floating
+            // points/vector in the future.
+            self.emulate_illegal_instruction(mctx, instr)
         }
 
-        self.pc += 4;
+        ExitResult::Continue
+    }
+
+    fn handle_firmware_store_access_fault(
+        &mut self,
+        mctx: &mut MiralisContext,
+        _module: &mut MainModule,
+    ) -> ExitResult {
+        let instr = unsafe { get_raw_faulting_instr(self) };
+        let instr = mctx.decode_store(instr);
+        self.handle_pmp_fault(mctx, LoadStoreInstr::Store(instr));
+        ExitResult::Continue
+    }
+
+    fn handle_firmware_load_access_fault(
+        &mut self,
+        mctx: &mut MiralisContext,
+        _module: &mut MainModule,
+    ) -> ExitResult {
+        let instr = unsafe { get_raw_faulting_instr(self) };
+        let instr = mctx.decode_load(instr);
+        self.handle_pmp_fault(mctx, LoadStoreInstr::Load(instr));
+        ExitResult::Continue
+    }
+
+    fn handle_firmware_instr_access_fault(
+        &mut self,
+        _mctx: &mut MiralisContext,
+        _module: &mut MainModule,
+    ) -> ExitResult {
+        logger::trace!("Instruction access fault: {:x?}", self.trap_info);
+        self.emulate_firmware_trap();
+        ExitResult::Continue
+    }
+
+    fn handle_firmware_timer_interrupt(
+        &mut self,
+        mctx: &mut MiralisContext,
+        _module: &mut MainModule,
+    ) -> ExitResult {
+        self.handle_machine_timer_interrupt(mctx);
+        ExitResult::Continue
+    }
+
+    fn handle_firmware_software_interrupt(
+        &mut self,
+        mctx: &mut MiralisContext,
+        module: &mut MainModule,
+    ) -> ExitResult {
+        self.handle_machine_software_interrupt(mctx, module);
+        ExitResult::Continue
+    }
+
+    fn handle_firmware_external_interrupt(
+        &mut self,
+        _mctx: &mut MiralisContext,
+        _module: &mut MainModule,
+    ) -> ExitResult {
+        todo!("Virtualize machine external interrupt")
+    }
+
+    /// Forward the trap to the firmware's own trap handler.
+    fn forward_firmware_trap(
+        &mut self,
+        _mctx: &mut MiralisContext,
+        _module: &mut MainModule,
+    ) -> ExitResult {
+        self.emulate_firmware_trap();
+        ExitResult::Continue
+    }
+
+    fn handle_firmware_unimplemented_trap(
+        &mut self,
+        _mctx: &mut MiralisContext,
+        _module: &mut MainModule,
+    ) -> ExitResult {
+        let cause = self.trap_info.get_cause();
+        if cause.is_interrupt() {
+            // TODO : For now, only care for MTIP bit
+            todo!(
+                "Other interrupts are not yet implemented {:?} at {:x}",
+                cause,
+                self.trap_info.mepc
+            );
+        } else {
+            // TODO : Need to match other traps
+            todo!(
+                "Other traps are not yet implemented {:?} at {:x}",
+                cause,
+                self.trap_info.mepc
+            );
+        }
+    }
+
+    /// Serve a payload read of `cycle`, `time` or `instret` from the virtual counters.
+    ///
+    /// Returns an error if the trapping instruction is not such a read, or if the counter is not
+    /// enabled for the current mode (see [Self::is_counter_enabled]). The illegal instruction must
+    /// then be delivered to the payload or firmware according to `medeleg`.
+    fn emulate_counter_read(&mut self, mctx: &mut MiralisContext) -> Result<(), ()> {
+        let instr = unsafe { get_raw_faulting_instr(self) };
+        let (csr, rd) = match mctx.decode_illegal_instruction(instr) {
+            IllegalInst::Csrrs { csr, rd, rs1 } | IllegalInst::Csrrc { csr, rd, rs1 }
+                if rs1 == Register::X0 =>
+            {
+                (csr, rd)
+            }
+            IllegalInst::Csrrsi { csr, rd, uimm } | IllegalInst::Csrrci { csr, rd, uimm }
+                if uimm == 0 =>
+            {
+                (csr, rd)
+            }
+            _ => return Err(()),
+        };
+
+        let counter_bit: usize = match csr {
+            Csr::Cycle => 1 << 0,
+            Csr::Time => 1 << 1,
+            Csr::Instret => 1 << 2,
+            _ => return Err(()),
+        };
+        if !self.is_counter_enabled(counter_bit) {
+            return Err(());
+        }
+
+        let value = self.get(csr);
+        self.set(rd, value);
+        self.pc += 4;
+        Ok(())
+    }
+
+    /// Returns true if the payload can read the counter of the given `counteren` bit in its current
+    /// mode.
+    ///
+    /// The counter must be enabled by `mcounteren`, then by `hcounteren` in VS and VU-mode, and
+    /// finally by `scounteren` in U and VU-mode. The payload S-mode and hypervisor registers are
+    /// installed on the hardware, so those are read from the physical registers.
+    fn is_counter_enabled(&self, counter_bit: usize) -> bool {
+        let mut enabled = self.csr.mcounteren as usize & counter_bit != 0;
+        if self.is_virtualized() {
+            enabled &= arch::read_csr(Csr::Hcounteren) & counter_bit != 0;
+        }
+        if self.mode == Mode::U {
+            enabled &= arch::read_csr(Csr::Scounteren) & counter_bit != 0;
+        }
+        enabled
+    }
+
+    /// Serve a payload `rdtime` from the platform `mtime`.
+    ///
+    /// This is a fast path for cores that do not implement the `time` CSR, on which `rdtime`
+    /// traps even when enabled. Returns an error if the trapping instruction is not a `rdtime`,
+    /// or if `time` is not enabled for the current mode (see [Self::is_counter_enabled]), in
+    /// which case the trap must be forwarded as usual.
+    fn emulate_payload_rdtime(&mut self) -> Result<(), ()> {
+        let instr = unsafe { get_raw_faulting_instr(self) };
+        let rd = decode_rdtime(instr).ok_or(())?;
+
+        const TIME_BIT: usize = 1 << 1;
+        if !self.is_counter_enabled(TIME_BIT) {
+            return Err(());
+        }
+
+        self.set(rd, Plat::get_clint().read_mtime());
+        self.pc += 4;
+        Ok(())
+    }
+
+    /// Emulates a Svinval instruction executed by the payload.
+    ///
+    /// Returns an error if the faulting instruction is not a Svinval instruction, or if the
+    /// instruction is not allowed in the current mode. In that case the illegal instruction trap
+    /// must be forwarded as usual.
+    fn emulate_payload_svinval(&mut self, mctx: &mut MiralisContext) -> Result<(), ()> {
+        let raw_instr = unsafe { get_raw_faulting_instr(self) };
+        if raw_instr & 0b1111111 != 0b1110011 {
+            // Not a system instruction
+            return Err(());
+        }
+
+        let instr = mctx.decode_illegal_instruction(raw_instr);
+        match instr {
+            IllegalInst::Sinvalvma { .. }
+            | IllegalInst::Sfencewinval
+            | IllegalInst::Sfenceinvalir => {}
+            _ => return Err(()),
+        }
+
+        // The Svinval instructions are illegal in U-mode, and we do not emulate the guest
+        // translations of VS-mode.
+        if self.mode != Mode::S || self.is_virtualized() {
+            return Err(());
+        }
+        // Like sfence.vma, sinval.vma is illegal in S-mode when mstatus.TVM is set
+        if matches!(instr, IllegalInst::Sinvalvma { .. })
+            && self.csr.mstatus & mstatus::TVM_FILTER != 0
+        {
+            return Err(());
+        }
+
+        self.emulate_svinval(mctx, &instr);
+        self.pc += 4;
+        Ok(())
+    }
+
+    /// Emulates a payload `satp` access, or an address translation fence, trapping because the
+    /// modules validate the `satp` writes (see [VirtContext::payload_mstatus]).
+    ///
+    /// Returns an error if the faulting instruction is none of those, if the instruction is
+    /// illegal for the payload (in U-mode, or when the firmware set mstatus.TVM), or if the
+    /// modules rejected the new `satp`. In that case the trap must be forwarded as usual, which
+    /// injects an illegal instruction exception.
+    fn emulate_payload_satp(
+        &mut self,
+        mctx: &mut MiralisContext,
+        module: &mut MainModule,
+    ) -> Result<(), ()> {
+        let raw_instr = unsafe { get_raw_faulting_instr(self) };
+        if raw_instr & 0b1111111 != 0b1110011 {
+            // Not a system instruction
+            return Err(());
+        }
+
+        if self.mode != Mode::S
+            || self.is_virtualized()
+            || self.csr.mstatus & mstatus::TVM_FILTER != 0
+        {
+            return Err(());
+        }
+
+        let old_satp = arch::read_csr(Csr::Satp);
+        let (rd, new_satp) = match mctx.decode_illegal_instruction(raw_instr) {
+            IllegalInst::Sfencevma { rs1, rs2 } => {
+                self.emulate_sfence_vma(mctx, &rs1, &rs2);
+                self.pc += 4;
+                return Ok(());
+            }
+            instr @ IllegalInst::Sinvalvma { .. } => {
+                self.emulate_svinval(mctx, &instr);
+                self.pc += 4;
+                return Ok(());
+            }
+            IllegalInst::Csrrw {
+                csr: Csr::Satp,
+                rd,
+                rs1,
+            } => (rd, Some(self.get(rs1))),
+            IllegalInst::Csrrs {
+                csr: Csr::Satp,
+                rd,
+                rs1,
+            } => (rd, (rs1 != Register::X0).then(|| old_satp | self.get(rs1))),
+            IllegalInst::Csrrc {
+                csr: Csr::Satp,
+                rd,
+                rs1,
+            } => (rd, (rs1 != Register::X0).then(|| old_satp & !self.get(rs1))),
+            IllegalInst::Csrrwi {
+                csr: Csr::Satp,
+                rd,
+                uimm,
+            } => (rd, Some(uimm)),
+            IllegalInst::Csrrsi {
+                csr: Csr::Satp,
+                rd,
+                uimm,
+            } => (rd, (uimm != 0).then_some(old_satp | uimm)),
+            IllegalInst::Csrrci {
+                csr: Csr::Satp,
+                rd,
+                uimm,
+            } => (rd, (uimm != 0).then_some(old_satp & !uimm)),
+            _ => return Err(()),
+        };
+
+        if let Some(new_satp) = new_satp {
+            if !module.on_satp_write(self, mctx, new_satp) {
+                logger::debug!("satp write 0x{:x} rejected by the modules", new_satp);
+                return Err(());
+            }
+            // The hardware legalizes the value, as if the payload wrote it
+            unsafe { arch::write_csr(Csr::Satp, new_satp) };
+        }
+
+        self.set(rd, old_satp);
+        self.pc += 4;
+        Ok(())
+    }
+
+    /// Emulates a Zawrs instruction executed by the payload.
+    ///
+    /// Returns an error if the faulting instruction is not a Zawrs instruction, or if it must raise
+    /// an illegal instruction because of mstatus.TW, in which case the illegal instruction trap
+    /// must be forwarded as usual.
+    fn emulate_payload_wrs(&mut self, mctx: &mut MiralisContext) -> Result<(), ()> {
+        let raw_instr = unsafe { get_raw_faulting_instr(self) };
+        if raw_instr & 0b1111111 != 0b1110011 {
+            // Not a system instruction
+            return Err(());
+        }
+
+        let instr = mctx.decode_illegal_instruction(raw_instr);
+        if !matches!(instr, IllegalInst::Wrsnto | IllegalInst::Wrssto) {
+            return Err(());
+        }
+
+        // With mstatus.TW, a `wrs.nto` that does not complete in bounded time raises an illegal
+        // instruction below M-mode. Our stall is not bounded, so it is never emulated.
+        if instr == IllegalInst::Wrsnto && self.csr.mstatus & mstatus::TW_FILTER != 0 {
+            return Err(());
+        }
+
+        self.emulate_wrs(&instr);
+        self.pc += 4;
+        Ok(())
+    }
+
+    /// Emulates a conditional-zero instruction (`czero.eqz` or `czero.nez`) for cores lacking
+    /// Zicond.
+    ///
+    /// Returns an error if the faulting instruction is not a conditional-zero, in which case the
+    /// illegal instruction trap must be forwarded as usual.
+    fn emulate_zicond(&mut self, mctx: &mut MiralisContext) -> Result<(), ()> {
+        let raw_instr = unsafe { get_raw_faulting_instr(self) };
+        let ZicondInstr { rd, rs1, rs2, op } = mctx.decode_zicond(raw_instr).ok_or(())?;
+
+        let condition = self.get(rs2);
+        let value = match op {
+            ZicondOp::CzeroEqz if condition == 0 => 0,
+            ZicondOp::CzeroNez if condition != 0 => 0,
+            _ => self.get(rs1),
+        };
+
+        self.set(rd, value);
+        self.pc += 4;
+        Ok(())
+    }
+
+    /// Handle the trap coming from the payload
+    pub fn handle_payload_trap(
+        &mut self,
+        mctx: &mut MiralisContext,
+        module: &mut MainModule,
+    ) -> ExitResult {
+        // Update the current mode
+        self.mode = parse_mpp_return_mode(self.trap_info.mstatus);
+
+        // While single-stepping, breakpoints are raised by the instruction count trigger. The
+        // `ebreak` instructions of the payload leave the trigger armed and are handled as usual.
+        if self.single_step
+            && self.trap_info.get_cause() == MCause::Breakpoint
+            && world_switch::single_step_fired()
+        {
+            module.on_step(self, mctx);
+            world_switch::arm_single_step();
+            return ExitResult::Continue;
+        }
+
+        if module.trap_from_payload(mctx, self).overwrites() {
+            logger::trace!("Catching trap in the policy module");
+            return ExitResult::Continue;
+        }
+
+        // Handle the exit.
+        // We only care about ecalls and virtualized interrupts.
+        match self.trap_info.get_cause() {
+            MCause::EcallFromSMode if module.ecall_from_payload(mctx, self).overwrites() => {
+                // Nothing to do, the Policy module handles those ecalls
+                logger::trace!("Catching E-call from payload in the policy module");
+            }
+            MCause::EcallFromSMode if self.get(Register::X17) == abi::MIRALIS_EID => {
+                return self.handle_ecall(mctx, module);
+            }
+            MCause::EcallFromSMode if self.is_miralis_sbi_base_call(module) => {
+                return self.handle_sbi_base(module);
+            }
+            MCause::EcallFromSMode => {
+                logger::debug!(
+                    "Forwarding ecall from s-mode with values 0x{:x}, 0x{:x} to the firmware",
+                    self.get(Register::X16),
+                    self.get(Register::X17)
+                );
+                self.emulate_firmware_trap();
+            }
+            // Ecalls from U-mode are system calls, never SBI or Miralis calls. They belong to the
+            // payload kernel when delegated, and to the firmware otherwise.
+            MCause::EcallFromUMode
+                if self.get_exception_target_mode(MCause::EcallFromUMode) == Mode::S =>
+            {
+                self.emulate_payload_trap();
+            }
+            MCause::EcallFromUMode => self.emulate_firmware_trap(),
+            MCause::MachineTimerInt => {
+                self.handle_machine_timer_interrupt(mctx);
+            }
+            MCause::MachineSoftInt => {
+                self.handle_machine_software_interrupt(mctx, module);
+            }
+            MCause::IllegalInstr if self.emulate_payload_rdtime().is_ok() => {
+                // The time read has been served, otherwise the trap is forwarded below
+            }
+            MCause::IllegalInstr
+                if config::VCPU_EMULATE_ZACAS
+                    && mctx
+                        .decode_amocas(unsafe { get_raw_faulting_instr(self) })
+                        .is_some() =>
+            {
+                if emulate_amocas(self, mctx).is_err() {
+                    // The trap might have been turned into a store/AMO fault
+                    self.forward_payload_exception();
+                }
+            }
+            MCause::IllegalInstr
+                if config::VCPU_VIRTUALIZE_ZICNTR && self.emulate_counter_read(mctx).is_ok() =>
+            {
+                // The counter read has been served, otherwise the trap is forwarded below
+            }
+            MCause::IllegalInstr
+                if config::VCPU_EMULATE_SVINVAL && self.emulate_payload_svinval(mctx).is_ok() =>
+            {
+                // The Svinval instruction has been emulated, otherwise the trap is forwarded below
+            }
+            MCause::IllegalInstr
+                if config::VCPU_EMULATE_ZAWRS && self.emulate_payload_wrs(mctx).is_ok() =>
+            {
+                // The Zawrs instruction has been emulated, otherwise the trap is forwarded below
+            }
+            MCause::IllegalInstr
+                if config::VCPU_EMULATE_ZICOND && self.emulate_zicond(mctx).is_ok() =>
+            {
+                // The conditional-zero has been emulated, otherwise the trap is forwarded below
+            }
+            MCause::IllegalInstr
+                if self.trap_satp && self.emulate_payload_satp(mctx, module).is_ok() =>
+            {
+                // The satp access has been emulated, otherwise the trap is forwarded below
+            }
+            cause if cause.is_trap() => self.forward_payload_exception(),
+            _ => self.emulate_firmware_trap(),
+        }
+
+        ExitResult::Continue
+    }
+
+    /// Forwards the exception in the trap info, taken while running the payload, to the mode it
+    /// is delegated to.
+    fn forward_payload_exception(&mut self) {
+        if self.get_exception_target_mode(self.trap_info.get_cause()) == Mode::S {
+            // The exception is delegated, but still trapped to Miralis (e.g. because a policy
+            // intercepts it). It belongs to the payload, not to the firmware.
+            self.emulate_payload_trap();
+        } else {
+            self.emulate_firmware_trap();
+        }
+    }
+
+    /// Handles Miralis-specific ecalls from firmware or payload.
+    ///
+    /// Miralis-specific ecalls are ecalls from the firmware or payload with extension ID (`eid`)
+    /// equal to `miralis_core::abi::MIRALIS_EID`. The individual ecall functon IDs (`fid`s) are
+    /// defined in the `miralis_core::abi` crate.
+    fn handle_ecall(&mut self, mctx: &mut MiralisContext, module: &MainModule) -> ExitResult {
+        let fid = self.get(Register::X16);
+        match fid {
+            abi::MIRALIS_FAILURE_FID => {
+                log::error!("Firmware or payload panicked!");
+                log::error!("  pc:    0x{:x}", self.pc);
+                log::error!("  exits: {}", self.nb_exits);
+                // Terminate execution
+                return ExitResult::Failure;
+            }
+            abi::MIRALIS_SUCCESS_FID => {
+                log::info!("Success!");
+                log::info!("Number of exits: {}", self.nb_exits);
+                // Terminate execution
+                return ExitResult::Done;
+            }
+            abi::MIRALIS_IDENTITY_MAP_FID => {
+                if self.mode != Mode::M {
+                    log::warn!("The payload requested an identity map, denying");
+                    self.set(Register::X10, sbi_codes::SBI_ERR_DENIED);
+                } else {
+                    self.identity_map = true;
+                    self.set(Register::X10, 0);
+                }
+                self.set(Register::X11, 0);
+            }
+            abi::MIRALIS_ENABLE_SINGLESTEP_FID => {
+                let enabled = self.get(Register::X10) != 0;
+                if self.mode == Mode::M {
+                    // Takes effect on the next switch to the payload
+                    self.single_step = enabled;
+                } else {
+                    // SAFETY: the payload configuration is installed on the hardware
+                    unsafe { self.set_payload_single_step(enabled) };
+                }
+                self.set(Register::X10, 0);
+                self.set(Register::X11, 0);
+            }
+            abi::MIRALIS_ZERO_REGION_FID => {
+                let result = self.zero_region(mctx, module);
+                self.set(Register::X10, result.err().unwrap_or(0));
+                self.set(Register::X11, 0);
+            }
+            abi::MIRALIS_RELEASE_PMP_FID => {
+                let result = self.release_pmp(mctx);
+                self.set(Register::X10, result.err().unwrap_or(0));
+                self.set(Register::X11, 0);
+            }
+            abi::MIRALIS_BENCHMARK_PAUSE_FID | abi::MIRALIS_BENCHMARK_RESUME_FID => {
+                benchmark::set_paused(self.hart_id, fid == abi::MIRALIS_BENCHMARK_PAUSE_FID);
+                self.set(Register::X10, 0);
+                self.set(Register::X11, 0);
+            }
+            abi::MIRALIS_PROBE_SBI_FID => {
+                let eid = self.get(Register::X10);
+                let virtualized = virtualizes_sbi(module, eid);
+                self.set(Register::X10, 0);
+                self.set(Register::X11, virtualized as usize);
+            }
+            abi::MIRALIS_LOG_FID => {
+                let log_level = self.get(Register::X10);
+                let addr = self.get(Register::X11);
+                let size = self.get(Register::X12);
+
+                // TODO: add proper validation that this memory range belongs to the
+                // payload
+                let bytes = unsafe { core::slice::from_raw_parts(addr as *const u8, size) };
+                let message =
+                    core::str::from_utf8(bytes).unwrap_or("note: invalid message, not utf-8");
+                match log_level {
+                    abi::log::MIRALIS_ERROR => log::error!("> {}", message),
+                    abi::log::MIRALIS_WARN => log::warn!("> {}", message),
+                    abi::log::MIRALIS_INFO => log::info!("> {}", message),
+                    abi::log::MIRALIS_DEBUG => log::debug!("> {}", message),
+                    abi::log::MIRALIS_TRACE => log::trace!("> {}", message),
+                    _ => {
+                        log::info!("Miralis log SBI call with invalid level: {}", log_level)
+                    }
+                }
+
+                // For now we don't return error code or the length written
+                self.set(Register::X10, 0);
+                self.set(Register::X11, 0);
+            }
+            _ => panic!("Invalid Miralis FID: 0x{:x}", fid),
+        }
+
+        self.pc += 4;
+        ExitResult::Continue
+    }
+
+    /// Releases the highest-numbered virtual PMP entries requested by the firmware, see
+    /// `MIRALIS_RELEASE_PMP_FID`.
+    ///
+    /// The released physical entries are disabled right away and handed over to the modules, see
+    /// [PmpGroup::release_virt_pmp]. Returns the SBI error code on failure.
+    fn release_pmp(&mut self, mctx: &mut MiralisContext) -> Result<(), usize> {
+        let count = self.get(Register::X10);
+
+        if self.mode != Mode::M {
+            log::warn!("The payload requested to release PMP entries, denying");
+            return Err(sbi_codes::SBI_ERR_DENIED);
+        }
+        if count > self.nb_pmp {
+            return Err(sbi_codes::SBI_ERR_INVALID_PARAM);
+        }
+
+        // Locked entries must keep enforcing their rule until reset
+        let nb_pmp = self.nb_pmp - count;
+        if (nb_pmp..self.nb_pmp).any(|idx| self.get_pmpcfg(idx) & pmpcfg::L != 0) {
+            log::warn!("The firmware requested to release a locked PMP entry, denying");
+            return Err(sbi_codes::SBI_ERR_DENIED);
+        }
+
+        // The released entries read as zero, like the entries not implemented by the hardware
+        for idx in nb_pmp..self.nb_pmp {
+            self.csr.pmpaddr[idx] = 0;
+            self.csr.pmpcfg[idx / 8] &= !(0xff << ((idx % 8) * 8));
+        }
+        self.nb_pmp = nb_pmp;
+        mctx.pmp.release_virt_pmp(count);
+        logger::debug!("Released {} PMP entries, {} left", count, nb_pmp);
+
+        self.update_firmware_pmp(mctx);
+        Ok(())
+    }
+
+    /// Zeroes the memory region requested by the firmware, see `MIRALIS_ZERO_REGION_FID`.
+    ///
+    /// Returns the SBI error code on failure.
+    fn zero_region(&mut self, mctx: &MiralisContext, module: &MainModule) -> Result<(), usize> {
+        let base = self.get(Register::X10);
+        let size = self.get(Register::X11);
+
+        if self.mode != Mode::M {
+            log::warn!("The payload requested to zero memory, denying");
+            return Err(sbi_codes::SBI_ERR_DENIED);
+        }
+        let Some(end) = base.checked_add(size) else {
+            return Err(sbi_codes::SBI_ERR_INVALID_PARAM);
+        };
+
+        let region = Segment::new(base, size);
+        if let Err(reason) = self.check_zeroable(mctx, region) {
+            log::warn!(
+                "The firmware requested to zero {} [0x{:x}, 0x{:x}), denying",
+                reason,
+                base,
+                end
+            );
+            return Err(sbi_codes::SBI_ERR_DENIED);
+        }
+        if !module.allows_zeroing(region) {
+            return Err(sbi_codes::SBI_ERR_DENIED);
+        }
+
+        // Volatile writes can not be elided, even though Miralis never reads the memory back.
+        // SAFETY: the region is guest memory the firmware can write to, and the policies accepted
+        // to zero it.
+        let mut addr = base;
+        while addr < end {
+            unsafe {
+                if addr.is_multiple_of(8) && end - addr >= 8 {
+                    ptr::write_volatile(addr as *mut u64, 0);
+                    addr += 8;
+                } else {
+                    ptr::write_volatile(addr as *mut u8, 0);
+                    addr += 1;
+                }
+            }
+        }
+        fence(Ordering::SeqCst);
+
+        Ok(())
+    }
+
+    /// Checks that the firmware could write the whole region itself.
+    ///
+    /// The region must be guest RAM (see [MiralisContext::check_guest_ram]), and writable from
+    /// M-mode under the virtual PMP configuration (including locked entries). Returns a
+    /// description of the offending memory otherwise.
+    fn check_zeroable(&self, mctx: &MiralisContext, region: Segment) -> Result<(), &'static str> {
+        mctx.check_guest_ram(region)?;
+        if logger::SHARED_PAGE
+            .is_some_and(|addr| region.overlap(Segment::new(addr, log_ring::PAGE_SIZE)))
+        {
+            return Err("the log page");
+        }
+
+        // The firmware itself must be allowed to write the region
+        let mut vpmp = PmpGroup::new(self.nb_pmp);
+        vpmp.load_with_offset(
+            &self.csr.pmpaddr,
+            &self.csr.pmpcfg,
+            self.csr.mseccfg,
+            Mode::M,
+            0,
+            self.nb_pmp,
+        );
+        let mut covered = false;
+        for (segment, permissions) in &vpmp {
+            if region.overlap(segment) {
+                if permissions & pmpcfg::W == 0 {
+                    return Err("memory protected by the virtual PMP");
+                }
+                covered |= segment.contain(region);
+            }
+        }
+        if !covered && pmp::machine_default_permissions(self.csr.mseccfg) & pmpcfg::W == 0 {
+            return Err("memory protected by the virtual PMP");
+        }
+
+        Ok(())
+    }
+
+    /// Whether an ecall from the payload is an SBI base extension call answered by Miralis.
+    ///
+    /// Probes of extensions that are not virtualized by Miralis are left to the firmware, which
+    /// knows which of the remaining extensions it implements.
+    fn is_miralis_sbi_base_call(&self, module: &MainModule) -> bool {
+        if self.get(Register::X17) != sbi_codes::SBI_BASE_EID {
+            return false;
+        }
+
+        self.get(Register::X16) != sbi_codes::PROBE_EXTENSION_FID
+            || virtualizes_sbi(module, self.get(Register::X10))
+    }
+
+    /// Handles the SBI base extension, returning the identity of Miralis.
+    ///
+    /// The machine identity (`mvendorid`, `marchid` and `mimpid`) is the one of the virtual
+    /// hart, as exposed to the firmware.
+    fn handle_sbi_base(&mut self, module: &MainModule) -> ExitResult {
+        let fid = self.get(Register::X16);
+        let result = match fid {
+            sbi_codes::GET_SPEC_VERSION_FID => Ok(sbi_codes::SBI_SPEC_VERSION),
+            sbi_codes::GET_IMPL_ID_FID => Ok(sbi_codes::MIRALIS_IMPL_ID),
+            sbi_codes::GET_IMPL_VERSION_FID => Ok(MIRALIS_IMPL_VERSION),
+            sbi_codes::PROBE_EXTENSION_FID => {
+                Ok(virtualizes_sbi(module, self.get(Register::X10)) as usize)
+            }
+            sbi_codes::GET_MVENDORID_FID => Ok(self.csr.mvendorid as usize),
+            sbi_codes::GET_MARCHID_FID => Ok(self.csr.marchid),
+            sbi_codes::GET_MIMPID_FID => Ok(self.csr.mimpid),
+            _ => Err(sbi_codes::SBI_ERR_NOT_SUPPORTED),
+        };
+
+        match result {
+            Ok(value) => {
+                self.set(Register::X10, sbi_codes::SBI_SUCCESS);
+                self.set(Register::X11, value);
+            }
+            Err(error) => {
+                logger::debug!("Unsupported SBI base extension FID: 0x{:x}", fid);
+                self.set(Register::X10, error);
+                self.set(Register::X11, 0);
+            }
+        }
+
+        self.pc += 4;
         ExitResult::Continue
     }
 
@@ -720,8 +1764,9 @@ impl VirtContext {
     /// an interrupt, so the firmware might be able to put the core in perpetual sleep
     /// state.
     pub fn emulate_wfi(&mut self, _mctx: &mut MiralisContext) {
-        // The WFI instruction put the processor in a special state that enables taking interrupts
-        // even if mstatus.MIE = 0. We keep a bit in the virtual context to model that state.
+        // The WFI instruction put the processor in a special state that wakes up on pending
+        // interrupts even if mstatus.MIE = 0. We keep a bit in the virtual context to model that
+        // state, the interrupt itself is only taken if enabled.
         self.is_wfi = true;
 
         // If there is an interrupt that can be taken, then exit without doing a real WFI.
@@ -865,11 +1910,24 @@ impl VirtContext {
     }
 
     /// Emulates the MRET (Machine Return) instruction.
+    ///
+    /// With the H extension, the virtualization mode is restored from `mstatus.MPV` when
+    /// returning to S or U-mode. The virtual MPV is kept as is in that case, as it holds the
+    /// virtualization mode of the payload until the next trap (see [VirtContext::is_virtualized]),
+    /// and the hardware does the same when the payload is resumed with a physical `mret`.
     pub fn emulate_mret(&mut self, mctx: &mut MiralisContext) {
         match parse_mpp_return_mode(self.csr.mstatus) {
             Mode::M => {
                 logger::trace!("mret to m-mode to {:x}", self.trap_info.mepc);
-                // Mret is jumping back to machine mode, do nothing
+                // Mret is jumping back to machine mode, which is never virtualized
+                if self.extensions.has_h_extension {
+                    VirtCsr::set_csr_field(
+                        &mut self.csr.mstatus,
+                        mstatus::MPV_OFFSET,
+                        MPV_FILTER,
+                        0,
+                    );
+                }
             }
             Mode::S if mctx.hw.extensions.has_s_extension => {
                 logger::trace!("mret to s-mode to {:x}", self.trap_info.mepc);
@@ -904,7 +1962,7 @@ impl VirtContext {
             }
         }
 
-        // MIE = MPIE, MPIE = 1, MPRV = 0
+        // MIE = MPIE, MPIE = 1 (MPRV is cleared above when returning to S or U-mode)
         let mpie = (self.csr.mstatus & mstatus::MPIE_FILTER) >> mstatus::MPIE_OFFSET;
 
         VirtCsr::set_csr_field(
@@ -920,6 +1978,7 @@ impl VirtContext {
             mpie,
         );
 
+        // MPP is set to the least-privileged supported mode
         let ret_mpp_val: usize = if has_user_mode(self) { 0b00 } else { 0b11 };
 
         VirtCsr::set_csr_field(
@@ -929,11 +1988,14 @@ impl VirtContext {
             ret_mpp_val,
         );
 
-        // Jump back to firmware
-        self.pc = self.csr.mepc;
+        // Jump back to firmware, without C the return address is 4 bytes aligned
+        self.pc = self.csr.mepc & self.pc_alignment_mask();
     }
 
     /// Emulates the SRET (Supervisor Return) instruction.
+    ///
+    /// Interrupts that become deliverable in the new mode are injected by the main loop right
+    /// after the emulation, see [Self::check_and_inject_interrupts].
     pub fn emulate_sret(&mut self, mctx: &mut MiralisContext) {
         match parse_spp_return_mode(self.csr.mstatus) {
             Mode::S if mctx.hw.extensions.has_s_extension => {
@@ -942,7 +2004,7 @@ impl VirtContext {
                 self.mode = Mode::S;
             }
             Mode::U => {
-                log::trace!("mret to u-mode with SPP");
+                log::trace!("sret to u-mode with SPP");
                 // Sret is jumping to user mode, the runner is the guest OS
                 self.mode = Mode::U;
             }
@@ -984,8 +2046,13 @@ impl VirtContext {
             0,
         );
 
-        // Jump back to firmware
-        self.pc = self.csr.sepc;
+        // Jump back to firmware, without C the return address is 4 bytes aligned
+        self.pc = self.csr.sepc & self.pc_alignment_mask();
+    }
+
+    /// Emulate fence by emitting the narrowest physical fence enforcing the same ordering.
+    pub fn emulate_fence(&mut self, _mctx: &mut MiralisContext, kind: BarrierKind) {
+        arch::fence(kind);
     }
 
     /// Emulate sfencevma by emitting a physical sfencevma.
@@ -1006,6 +2073,76 @@ impl VirtContext {
         arch::sfencevma(vaddr, asid);
     }
 
+    /// Emulate the Svinval instructions by emitting physical sfencevma.
+    ///
+    /// The emulation is conservative: sinval.vma is promoted to a sfencevma with the same
+    /// operands, which already orders the surrounding accesses, and the sfence.w.inval and
+    /// sfence.inval.ir ordering fences are promoted to a full sfencevma.
+    pub fn emulate_svinval(&mut self, mctx: &mut MiralisContext, instr: &IllegalInst) {
+        match instr {
+            IllegalInst::Sinvalvma { rs1, rs2 } => self.emulate_sfence_vma(mctx, rs1, rs2),
+            IllegalInst::Sfencewinval | IllegalInst::Sfenceinvalir => arch::sfencevma(None, None),
+            _ => unreachable!("Not a Svinval instruction: {:?}", instr),
+        }
+    }
+
+    /// Emulate the Zawrs instructions, by stalling as long as the reservation set is valid.
+    ///
+    /// The stall terminates once the reservation is invalidated or when an interrupt becomes
+    /// pending (even if interrupts are globally disabled, like WFI), and after a short timeout for
+    /// `wrs.sto`. Without a valid reservation the instruction terminates immediately, as required
+    /// by Zawrs. See [memory::find_reservation].
+    pub fn emulate_wrs(&mut self, instr: &IllegalInst) {
+        let timeout = match instr {
+            IllegalInst::Wrsnto => None,
+            IllegalInst::Wrssto => Some(WRS_STO_ITERATIONS),
+            _ => unreachable!("Not a Zawrs instruction: {:?}", instr),
+        };
+
+        if get_next_interrupt(self.csr.mie, self.csr.mip, self.csr.mideleg).is_some() {
+            return;
+        }
+        let Some(reservation) = memory::find_reservation(self) else {
+            return;
+        };
+
+        // Interrupts of either the vCPU or Miralis terminate the stall
+        let mode = parse_mpp_return_mode(self.trap_info.mstatus);
+        let enabled = self.csr.mie | arch::read_csr(Csr::Mie);
+        wrs_stall(timeout, || {
+            arch::read_csr(Csr::Mip) & enabled != 0 || !reservation.is_valid(mode)
+        });
+    }
+
+    /// Emulate a hypervisor virtual-machine load (hlv) from the firmware.
+    ///
+    /// The load is performed with the two-stage translation configured by the firmware, using the
+    /// privilege selected by hstatus.SPVP. Faults are forwarded to the firmware.
+    pub fn emulate_hlv(&mut self, _mctx: &mut MiralisContext, instr: &LoadInstr) {
+        if !self.extensions.has_h_extension {
+            self.emulate_firmware_trap();
+            return;
+        }
+
+        // SAFETY: the PMP is configured for the virtual firmware, and the guest translation is
+        // the one configured by the firmware.
+        unsafe { arch::handle_hypervisor_load(instr.clone(), self) };
+    }
+
+    /// Emulate a hypervisor virtual-machine store (hsv) from the firmware.
+    ///
+    /// See [VirtContext::emulate_hlv].
+    pub fn emulate_hsv(&mut self, _mctx: &mut MiralisContext, instr: &StoreInstr) {
+        if !self.extensions.has_h_extension {
+            self.emulate_firmware_trap();
+            return;
+        }
+
+        // SAFETY: the PMP is configured for the virtual firmware, and the guest translation is
+        // the one configured by the firmware.
+        unsafe { arch::handle_hypervisor_store(instr.clone(), self) };
+    }
+
     /// Emulate hfencegvma by emitting a physical hfencegvma.
     pub fn emulate_hfence_gvma(
         &mut self,
@@ -1045,6 +2182,30 @@ impl VirtContext {
 
 // ————————————————————————————————— Utils —————————————————————————————————— //
 
+/// The SBI implementation version of Miralis, with the major version in the upper 16 bits and
+/// the minor version in the lower 16 bits.
+const MIRALIS_IMPL_VERSION: usize = {
+    let major = parse_usize_or(Some(env!("CARGO_PKG_VERSION_MAJOR")), 0);
+    let minor = parse_usize_or(Some(env!("CARGO_PKG_VERSION_MINOR")), 0);
+    (major << 16) | minor
+};
+
+/// Returns the destination register if `instr` is a `rdtime` (i.e. `csrrs rd, time, x0`).
+fn decode_rdtime(instr: usize) -> Option<Register> {
+    const RDTIME: usize = 0xc0102073;
+    const RD_FILTER: usize = 0b11111 << 7;
+
+    if instr & !RD_FILTER != RDTIME {
+        return None;
+    }
+    Register::try_from((instr & RD_FILTER) >> 7).ok()
+}
+
+/// Returns true if the SBI extension `eid` is handled by Miralis instead of the firmware.
+fn virtualizes_sbi(module: &MainModule, eid: usize) -> bool {
+    eid == abi::MIRALIS_EID || eid == sbi_codes::SBI_BASE_EID || module.virtualizes_sbi(eid)
+}
+
 /// Returns true if the virtual machine has support for U-mode.
 fn has_user_mode(ctx: &VirtContext) -> bool {
     (ctx.csr.misa & misa::U) != 0
@@ -1064,6 +2225,8 @@ fn find_pending_interrupt_by_priority(ip: usize) -> Option<usize> {
         Some(SSIE_OFFSET)
     } else if ip & mie::STIE_FILTER != 0 {
         Some(STIE_OFFSET)
+    } else if ip & mie::LCOFIE_FILTER != 0 {
+        Some(LCOFIE_OFFSET)
     } else {
         None
     }
@@ -1076,15 +2239,95 @@ fn get_next_interrupt(mie: usize, mip: usize, mideleg: usize) -> Option<usize> {
     find_pending_interrupt_by_priority(ip)
 }
 
+/// Return the ID of the next virtual supervisor interrupt to be delivered, if any.
+///
+/// Only the interrupts delegated to VS-mode through `hideleg` are considered. VS-level interrupts
+/// are seen as S-level interrupts from VS-mode, hence the shift to match `vsie`.
+fn get_next_vs_interrupt(vsie: usize, hvip: usize, hideleg: usize) -> Option<usize> {
+    let vs_ip = (hvip & hideleg & mie::VS_INT) >> 1;
+
+    find_pending_interrupt_by_priority(vs_ip & vsie)
+}
+
+/// Returns true if the instruction accesses an unknown CSR or writes to a read-only CSR.
+///
+/// The CSR address space is partitioned by the decoder: unallocated addresses, as well as CSRs
+/// belonging to extensions that are not available, are decoded as [Csr::Unknown] and any access
+/// raises an illegal instruction exception. All other CSRs are emulated, including the ones that
+/// are hardwired to zero on this hart (such as PMP or HPM counters that are not implemented).
+///
+/// CSRRS and CSRRC with x0 as source register (and their immediate variants with a zero
+/// immediate) do not write the CSR, they are therefore legal pure reads of read-only CSRs.
+/// Stalls until `terminate` returns true, or until `timeout` iterations elapsed if any.
+///
+/// Returns the number of iterations.
+fn wrs_stall(timeout: Option<usize>, mut terminate: impl FnMut() -> bool) -> usize {
+    let mut iterations = 0;
+    while timeout.is_none_or(|timeout| iterations < timeout) {
+        iterations += 1;
+        if terminate() {
+            break;
+        }
+        core::hint::spin_loop();
+    }
+    iterations
+}
+
+pub fn is_illegal_csr_access(instr: &IllegalInst) -> bool {
+    match *instr {
+        IllegalInst::Csrrw { csr, .. }
+        | IllegalInst::Csrrs { csr, .. }
+        | IllegalInst::Csrrc { csr, .. }
+        | IllegalInst::Csrrwi { csr, .. }
+        | IllegalInst::Csrrsi { csr, .. }
+        | IllegalInst::Csrrci { csr, .. }
+            if csr.is_unknown() =>
+        {
+            true
+        }
+        IllegalInst::Csrrw { csr, .. } | IllegalInst::Csrrwi { csr, .. } => csr.is_read_only(),
+        IllegalInst::Csrrs { csr, rs1, .. } | IllegalInst::Csrrc { csr, rs1, .. } => {
+            csr.is_read_only() && rs1 != Register::X0
+        }
+        IllegalInst::Csrrsi { csr, uimm, .. } | IllegalInst::Csrrci { csr, uimm, .. } => {
+            csr.is_read_only() && uimm != 0
+        }
+        _ => false,
+    }
+}
+
+/// Returns true if the instruction accesses one of the floating-point CSRs.
+fn is_fp_csr_access(instr: &IllegalInst) -> bool {
+    match *instr {
+        IllegalInst::Csrrw { csr, .. }
+        | IllegalInst::Csrrs { csr, .. }
+        | IllegalInst::Csrrc { csr, .. }
+        | IllegalInst::Csrrwi { csr, .. }
+        | IllegalInst::Csrrsi { csr, .. }
+        | IllegalInst::Csrrci { csr, .. } => matches!(csr, Csr::Fflags | Csr::Frm | Csr::Fcsr),
+        _ => false,
+    }
+}
+
 // ————————————————————————————————— Tests —————————————————————————————————— //
 
 #[cfg(test)]
 mod tests {
-    use super::get_next_interrupt;
-    use crate::arch::{Csr, mie};
+    use miralis_core::{abi, sbi_codes};
+
+    use super::{
+        ExitResult, decode_rdtime, get_next_interrupt, is_fp_csr_access, is_illegal_csr_access,
+    };
+    use crate::arch::pmp::{Segment, pmpcfg};
+    use crate::arch::{
+        Csr, MCause, Mode, Register, csr, mhpmevent, mie, mseccfg, mstatus, parse_mpp_return_mode,
+    };
+    use crate::decoder::IllegalInst;
     use crate::host::MiralisContext;
-    use crate::virt::VirtContext;
-    use crate::{HwRegisterContextSetter, arch};
+    use crate::modules::{MainModule, Module};
+    use crate::virt::traits::RegisterContextSetter;
+    use crate::virt::{VirtContext, inject_trap};
+    use crate::{HwRegisterContextSetter, RegisterContextGetter, arch};
 
     /// If the firmware wants to read the `mip` register after cleaning `vmip.SEIP`,
     /// and we don't sync `vmip.SEIP` with `mip.SEIP`, it can't know if there is an interrupt
@@ -1130,5 +2373,538 @@ mod tests {
         assert_eq!(get_next_interrupt(0b010, 0b010, 0b000), Some(1));
         assert_eq!(get_next_interrupt(0b010, 0b011, 0b000), Some(1));
         assert_eq!(get_next_interrupt(0b011, 0b011, 0b001), Some(1));
+
+        // LCOFI has the lowest priority
+        let lcofi_and_sti = mie::LCOFIE_FILTER | mie::STIE_FILTER;
+        assert_eq!(get_next_interrupt(lcofi_and_sti, lcofi_and_sti, 0), Some(5));
+        assert_eq!(
+            get_next_interrupt(lcofi_and_sti, mie::LCOFIE_FILTER, 0),
+            Some(mie::LCOFIE_OFFSET)
+        );
+        assert_eq!(
+            get_next_interrupt(lcofi_and_sti, lcofi_and_sti, mie::LCOFIE_FILTER),
+            Some(5)
+        );
+    }
+
+    /// A virtual HPM counter wrapping around must set the overflow bit and raise a local counter
+    /// overflow interrupt, but only once until the overflow bit is cleared.
+    #[test]
+    fn hpm_counter_overflow() {
+        let hw = unsafe { arch::detect_hardware() };
+        let mut extensions = hw.extensions.clone();
+        extensions.has_sscofpmf_extension = true;
+        let mut ctx = VirtContext::new(0, hw.available_reg.nb_pmp, extensions);
+        ctx.mode = Mode::M;
+
+        ctx.csr.mhpmcounter[0] = usize::MAX;
+        ctx.csr.mhpmevent[0] = mhpmevent::FIRMWARE_TRAP_EVENT;
+        ctx.csr.mhpmcounter[1] = usize::MAX;
+        ctx.csr.mhpmevent[1] = mhpmevent::FIRMWARE_TRAP_EVENT | mhpmevent::MINH_FILTER;
+
+        ctx.count_hpm_event(mhpmevent::FIRMWARE_TRAP_EVENT);
+        assert_eq!(ctx.csr.mhpmcounter[0], 0);
+        assert_ne!(ctx.csr.mhpmevent[0] & mhpmevent::OF_FILTER, 0);
+        assert_ne!(ctx.csr.mip & mie::LCOFIE_FILTER, 0);
+
+        // Inhibited counters must not count
+        assert_eq!(ctx.csr.mhpmcounter[1], usize::MAX);
+        assert_eq!(ctx.csr.mhpmevent[1] & mhpmevent::OF_FILTER, 0);
+
+        // No new interrupt while the overflow bit is set
+        ctx.csr.mip = 0;
+        ctx.csr.mhpmcounter[0] = usize::MAX;
+        ctx.count_hpm_event(mhpmevent::FIRMWARE_TRAP_EVENT);
+        assert_eq!(ctx.csr.mip & mie::LCOFIE_FILTER, 0);
+    }
+
+    /// CSRRS and CSRRC with x0 as source are pure reads, and must not trap on read-only CSRs.
+    #[test]
+    fn read_only_csr_access() {
+        let csrrs = |csr, rs1| IllegalInst::Csrrs {
+            csr,
+            rd: Register::X5,
+            rs1,
+        };
+        let csrrci = |csr, uimm| IllegalInst::Csrrci {
+            csr,
+            rd: Register::X5,
+            uimm,
+        };
+
+        assert!(!is_illegal_csr_access(&csrrs(Csr::Mvendorid, Register::X0)));
+        assert!(is_illegal_csr_access(&csrrs(Csr::Mvendorid, Register::X6)));
+        assert!(!is_illegal_csr_access(&csrrci(Csr::Mhartid, 0)));
+        assert!(is_illegal_csr_access(&csrrci(Csr::Mhartid, 1)));
+        assert!(is_illegal_csr_access(&IllegalInst::Csrrw {
+            csr: Csr::Mimpid,
+            rd: Register::X0,
+            rs1: Register::X0,
+        }));
+
+        // Writes to read-write CSRs and accesses to unknown CSRs
+        assert!(!is_illegal_csr_access(&csrrs(Csr::Mscratch, Register::X6)));
+        assert!(is_illegal_csr_access(&csrrs(Csr::Unknown, Register::X0)));
+    }
+
+    /// The upper halves of the counters are RV32 only, on RV64 they read as zero without trapping.
+    #[test]
+    fn read_cycleh() {
+        let hw = unsafe { arch::detect_hardware() };
+        let mut mctx = MiralisContext::new(hw, 0x10000, 0x2000);
+        let mut ctx = VirtContext::new(0, mctx.hw.available_reg.nb_pmp, mctx.hw.extensions.clone());
+        mctx.hw.extensions.has_zicntr = true;
+        ctx.mode = Mode::M;
+        ctx.csr.mcycle = 0x1234;
+        ctx.set(Register::X10, 0xdead);
+
+        let instr = mctx.decode_illegal_instruction(0xc8002573); // csrr a0, cycleh
+        assert!(!is_illegal_csr_access(&instr));
+        ctx.emulate_privileged_instr(&instr, &mut mctx);
+        assert_eq!(ctx.get(Register::X10), 0);
+    }
+
+    /// Accesses to the FP CSRs are forwarded to the firmware as illegal instructions while
+    /// mstatus.FS is Off.
+    #[test]
+    fn fp_csr_access_fs_off() {
+        let hw = unsafe { arch::detect_hardware() };
+        let mut mctx = MiralisContext::new(hw, 0x10000, 0x2000);
+        let mut ctx = VirtContext::new(0, mctx.hw.available_reg.nb_pmp, mctx.hw.extensions.clone());
+        mctx.hw.extensions.has_f_extension = true;
+        ctx.mode = Mode::M;
+        ctx.pc = 0x8000_0100;
+        ctx.csr.mtvec = 0x8000_0400;
+        ctx.csr.mstatus &= !mstatus::FS_FILTER;
+        assert!(!ctx.is_fp_enabled());
+
+        let raw = 0x003022f3; // csrr t0, fcsr
+        let instr = mctx.decode_illegal_instruction(raw);
+        assert!(is_fp_csr_access(&instr));
+        assert!(!is_illegal_csr_access(&instr));
+        inject_trap(&mut ctx, MCause::IllegalInstr, raw, 0x8000_0100);
+        ctx.emulate_privileged_instr(&instr, &mut mctx);
+        assert_eq!(ctx.csr.mcause, MCause::IllegalInstr as usize);
+        assert_eq!(ctx.csr.mtval, raw);
+        assert_eq!(ctx.csr.mepc, 0x8000_0100);
+        assert_eq!(ctx.pc, 0x8000_0400);
+
+        // Without the F extension (or with Zfinx) the FP CSRs do not exist
+        mctx.hw.extensions.has_zfinx = true;
+        assert_eq!(mctx.decode_csr(csr::FCSR), Csr::Unknown);
+    }
+
+    /// The firmware traps that Miralis does not handle itself are forwarded to the firmware trap
+    /// handler, with the trap information of the physical trap.
+    #[test]
+    fn firmware_trap_dispatch() {
+        let hw = unsafe { arch::detect_hardware() };
+        let mut mctx = MiralisContext::new(hw, 0x10000, 0x2000);
+        let mut ctx = VirtContext::new(0, mctx.hw.available_reg.nb_pmp, mctx.hw.extensions.clone());
+        let mut module = MainModule::init();
+        ctx.csr.mtvec = 0x8000_0400;
+
+        for cause in [
+            MCause::Breakpoint,
+            MCause::InstrAccessFault,
+            MCause::LoadAddrMisaligned,
+            MCause::StoreAddrMisaligned,
+            MCause::InstrAddrMisaligned,
+        ] {
+            ctx.mode = Mode::M;
+            ctx.pc = 0x8000_0100;
+            ctx.csr.mcause = 0;
+            inject_trap(&mut ctx, cause, 0x8000_0042, 0x8000_0100);
+            ctx.trap_info.mstatus = Mode::M.to_bits() << mstatus::MPP_OFFSET;
+
+            let result = ctx.handle_firmware_trap(&mut mctx, &mut module);
+            assert!(result == ExitResult::Continue);
+            assert_eq!(ctx.csr.mcause, cause as usize, "{:?} not forwarded", cause);
+            assert_eq!(ctx.csr.mtval, 0x8000_0042);
+            assert_eq!(ctx.csr.mepc, 0x8000_0100);
+            assert_eq!(ctx.pc, 0x8000_0400);
+        }
+    }
+
+    /// The firmware traps that Miralis does not support are not silently forwarded.
+    #[test]
+    #[should_panic]
+    fn firmware_trap_dispatch_unimplemented() {
+        let hw = unsafe { arch::detect_hardware() };
+        let mut mctx = MiralisContext::new(hw, 0x10000, 0x2000);
+        let mut ctx = VirtContext::new(0, mctx.hw.available_reg.nb_pmp, mctx.hw.extensions.clone());
+        let mut module = MainModule::init();
+        ctx.mode = Mode::M;
+        inject_trap(&mut ctx, MCause::InstrPageFault, 0, 0x8000_0100);
+        ctx.trap_info.mstatus = Mode::M.to_bits() << mstatus::MPP_OFFSET;
+
+        ctx.handle_firmware_trap(&mut mctx, &mut module);
+    }
+
+    /// Ecalls are routed according to the privilege mode they come from: Miralis serves its own
+    /// ABI, and the other ecalls trap to the firmware (or payload kernel) with `mepc` pointing to
+    /// the ecall.
+    #[test]
+    fn ecall_dispatch() {
+        let hw = unsafe { arch::detect_hardware() };
+        let mut mctx = MiralisContext::new(hw, 0x10000, 0x2000);
+        let mut ctx = VirtContext::new(0, mctx.hw.available_reg.nb_pmp, mctx.hw.extensions.clone());
+        let mut module = MainModule::init();
+        ctx.csr.mtvec = 0x8000_0400;
+        ctx.csr.misa |= arch::misa::S;
+
+        let mut ecall = |ctx: &mut VirtContext, mode: Mode, cause: MCause, eid: usize| {
+            ctx.mode = Mode::M;
+            ctx.pc = 0x8000_0100;
+            ctx.csr.mcause = 0;
+            ctx.csr.mepc = 0;
+            ctx.set(Register::X17, eid);
+            ctx.set(Register::X16, sbi_codes::GET_SPEC_VERSION_FID);
+            ctx.trap_info.mcause = cause as usize;
+            ctx.trap_info.mepc = 0x8000_0100;
+            ctx.trap_info.mstatus = mode.to_bits() << mstatus::MPP_OFFSET;
+            match mode {
+                Mode::M => ctx.handle_firmware_trap(&mut mctx, &mut module),
+                _ => ctx.handle_payload_trap(&mut mctx, &mut module),
+            };
+        };
+
+        // Firmware calls to the SBI base extension are served by Miralis
+        ecall(
+            &mut ctx,
+            Mode::M,
+            MCause::EcallFromUMode,
+            sbi_codes::SBI_BASE_EID,
+        );
+        assert_eq!(ctx.get(Register::X10), sbi_codes::SBI_SUCCESS);
+        assert_eq!(ctx.pc, 0x8000_0104);
+        assert_eq!(ctx.mode, Mode::M);
+
+        // Other firmware ecalls trap to the firmware as ecalls from M-mode
+        ecall(&mut ctx, Mode::M, MCause::EcallFromUMode, 0x1234);
+        assert_eq!(ctx.csr.mcause, MCause::EcallFromMMode as usize);
+        assert_eq!(ctx.csr.mepc, 0x8000_0100);
+        assert_eq!(ctx.pc, 0x8000_0400);
+        assert_eq!(parse_mpp_return_mode(ctx.csr.mstatus), Mode::M);
+
+        // Payload calls to the SBI base extension are served by Miralis
+        ecall(
+            &mut ctx,
+            Mode::S,
+            MCause::EcallFromSMode,
+            sbi_codes::SBI_BASE_EID,
+        );
+        assert_eq!(ctx.get(Register::X10), sbi_codes::SBI_SUCCESS);
+        assert_eq!(ctx.pc, 0x8000_0104);
+        assert_eq!(ctx.mode, Mode::S);
+
+        // Other payload ecalls are forwarded to the firmware
+        ecall(&mut ctx, Mode::S, MCause::EcallFromSMode, 0x1234);
+        assert_eq!(ctx.csr.mcause, MCause::EcallFromSMode as usize);
+        assert_eq!(ctx.csr.mepc, 0x8000_0100);
+        assert_eq!(ctx.pc, 0x8000_0400);
+        assert_eq!(ctx.mode, Mode::M);
+
+        // Ecalls from U-mode are never served by Miralis, even with the SBI base EID
+        ecall(
+            &mut ctx,
+            Mode::U,
+            MCause::EcallFromUMode,
+            sbi_codes::SBI_BASE_EID,
+        );
+        assert_eq!(ctx.csr.mcause, MCause::EcallFromUMode as usize);
+        assert_eq!(ctx.csr.mepc, 0x8000_0100);
+        assert_eq!(ctx.pc, 0x8000_0400);
+        assert_eq!(ctx.mode, Mode::M);
+
+        // Unless delegated to the payload kernel
+        ctx.csr.medeleg = 1 << MCause::EcallFromUMode as usize;
+        unsafe { arch::write_csr(Csr::Stvec, 0x8020_0000) };
+        ecall(
+            &mut ctx,
+            Mode::U,
+            MCause::EcallFromUMode,
+            sbi_codes::SBI_BASE_EID,
+        );
+        assert_eq!(ctx.csr.mcause, 0);
+        assert_eq!(arch::read_csr(Csr::Scause), MCause::EcallFromUMode as usize);
+        assert_eq!(arch::read_csr(Csr::Sepc), 0x8000_0100);
+        assert_eq!(ctx.pc, 0x8020_0000);
+        assert_eq!(parse_mpp_return_mode(arch::read_csr(Csr::Mstatus)), Mode::S);
+    }
+
+    /// S-mode only observes the supervisor interrupts delegated with `mideleg`.
+    #[test]
+    fn sie_follows_mideleg() {
+        let hw = unsafe { arch::detect_hardware() };
+        let mut mctx = MiralisContext::new(hw, 0x10000, 0x2000);
+        let mut ctx = VirtContext::new(0, mctx.hw.available_reg.nb_pmp, mctx.hw.extensions.clone());
+        ctx.extensions.has_sscofpmf_extension = true;
+
+        let supervisor = mie::SIE_FILTER | mie::LCOFIE_FILTER;
+        ctx.csr.mie = supervisor | mie::MTIE_FILTER;
+        ctx.csr.mip = supervisor;
+        ctx.csr.mideleg = supervisor;
+        assert_eq!(ctx.get(Csr::Sie), supervisor);
+        assert_eq!(ctx.get(Csr::Sip), supervisor);
+
+        // Narrowing mideleg hides the local counter overflow interrupt
+        ctx.csr.mideleg = mie::SIE_FILTER;
+        assert_eq!(ctx.get(Csr::Sie), mie::SIE_FILTER);
+        assert_eq!(ctx.get(Csr::Sip), mie::SIE_FILTER);
+
+        // And it can no longer be disabled through sie
+        ctx.set_csr(Csr::Sie, 0, &mut mctx);
+        assert_eq!(ctx.csr.mie, mie::LCOFIE_FILTER | mie::MTIE_FILTER);
+    }
+
+    /// The local counter overflow interrupt can only be delegated with Sscofpmf.
+    #[test]
+    fn lcofi_delegation_requires_sscofpmf() {
+        let mut hw = unsafe { arch::detect_hardware() };
+        hw.interrupts |= mie::LCOFIE_FILTER;
+        hw.extensions.has_sscofpmf_extension = false;
+        let mut mctx = MiralisContext::new(hw, 0x10000, 0x2000);
+        let mut ctx = VirtContext::new(0, mctx.hw.available_reg.nb_pmp, mctx.hw.extensions.clone());
+
+        ctx.set_csr(Csr::Mideleg, mie::LCOFIE_FILTER, &mut mctx);
+        ctx.set_csr(Csr::Mie, mie::LCOFIE_FILTER, &mut mctx);
+        assert_eq!(ctx.csr.mideleg & mie::LCOFIE_FILTER, 0);
+        assert_eq!(ctx.csr.mie & mie::LCOFIE_FILTER, 0);
+
+        mctx.hw.extensions.has_sscofpmf_extension = true;
+        ctx.set_csr(Csr::Mideleg, mie::LCOFIE_FILTER, &mut mctx);
+        ctx.set_csr(Csr::Mie, mie::LCOFIE_FILTER, &mut mctx);
+        assert_ne!(ctx.csr.mideleg & mie::LCOFIE_FILTER, 0);
+        assert_ne!(ctx.csr.mie & mie::LCOFIE_FILTER, 0);
+    }
+
+    /// Locked PMP entries can only be modified while `mseccfg.RLB` is set.
+    #[test]
+    fn pmp_rule_locking_bypass() {
+        let hw = unsafe { arch::detect_hardware() };
+        let mut mctx = MiralisContext::new(hw, 0x10000, 0x2000);
+        let mut ctx = VirtContext::new(0, mctx.hw.available_reg.nb_pmp, mctx.hw.extensions.clone());
+        let locked_tor = (pmpcfg::L | pmpcfg::TOR | pmpcfg::R) as usize;
+
+        // Locked entries can be modified while RLB is set
+        ctx.set_csr(Csr::Mseccfg, mseccfg::RLB_FILTER, &mut mctx);
+        ctx.set_csr(Csr::Pmpcfg(0), locked_tor, &mut mctx);
+        ctx.set_csr(Csr::Pmpcfg(0), locked_tor | pmpcfg::W as usize, &mut mctx);
+        ctx.set_csr(Csr::Pmpaddr(0), 0x1000, &mut mctx);
+        assert_eq!(
+            ctx.get_pmpcfg(0),
+            pmpcfg::L | pmpcfg::TOR | pmpcfg::R | pmpcfg::W
+        );
+        assert_eq!(ctx.csr.pmpaddr[0], 0x1000);
+
+        // Once RLB is cleared, the entry and its address are locked
+        ctx.set_csr(Csr::Mseccfg, 0, &mut mctx);
+        ctx.set_csr(Csr::Pmpcfg(0), 0, &mut mctx);
+        ctx.set_csr(Csr::Pmpaddr(0), 0x2000, &mut mctx);
+        assert_eq!(
+            ctx.get_pmpcfg(0),
+            pmpcfg::L | pmpcfg::TOR | pmpcfg::R | pmpcfg::W
+        );
+        assert_eq!(ctx.csr.pmpaddr[0], 0x1000);
+
+        // And RLB can not be set again
+        ctx.set_csr(Csr::Mseccfg, mseccfg::RLB_FILTER, &mut mctx);
+        assert_eq!(ctx.csr.mseccfg, 0);
+
+        // MML and MMWP are sticky
+        ctx.set_csr(
+            Csr::Mseccfg,
+            mseccfg::MML_FILTER | mseccfg::MMWP_FILTER,
+            &mut mctx,
+        );
+        ctx.set_csr(Csr::Mseccfg, 0, &mut mctx);
+        assert_eq!(ctx.csr.mseccfg, mseccfg::MML_FILTER | mseccfg::MMWP_FILTER);
+    }
+
+    /// R = 0 and W = 1 is reserved, except for the shared regions of `mseccfg.MML`.
+    #[test]
+    fn pmp_reserved_permissions() {
+        let hw = unsafe { arch::detect_hardware() };
+        let mut mctx = MiralisContext::new(hw, 0x10000, 0x2000);
+        let mut ctx = VirtContext::new(0, mctx.hw.available_reg.nb_pmp, mctx.hw.extensions.clone());
+        let shared = (pmpcfg::TOR | pmpcfg::W | pmpcfg::X) as usize;
+
+        // Without MML the permissions are cleared, but not the address matching mode
+        ctx.set_csr(Csr::Pmpcfg(0), shared | (shared << 8), &mut mctx);
+        assert_eq!(ctx.get_pmpcfg(0), pmpcfg::TOR);
+        assert_eq!(ctx.get_pmpcfg(1), pmpcfg::TOR);
+
+        // With MML the combination encodes a shared region
+        ctx.set_csr(Csr::Mseccfg, mseccfg::MML_FILTER, &mut mctx);
+        ctx.set_csr(Csr::Pmpcfg(0), shared, &mut mctx);
+        assert_eq!(ctx.get_pmpcfg(0), pmpcfg::TOR | pmpcfg::W | pmpcfg::X);
+    }
+
+    /// When the modules validate the `satp` writes, the payload accesses to `satp` are emulated
+    /// unless the firmware set mstatus.TVM.
+    #[test]
+    fn payload_satp_access() {
+        let hw = unsafe { arch::detect_hardware() };
+        let mut mctx = MiralisContext::new(hw, 0x10000, 0x2000);
+        let mut ctx = VirtContext::new(0, mctx.hw.available_reg.nb_pmp, mctx.hw.extensions.clone());
+        let mut module = MainModule::init();
+        ctx.trap_satp = true;
+        ctx.mode = Mode::S;
+        ctx.pc = 0x8020_0000;
+        ctx.trap_info.mcause = MCause::IllegalInstr as usize;
+
+        // csrw satp, a0
+        ctx.trap_info.mtval = 0x18051073;
+        ctx.set(Register::X10, 0);
+        assert!(ctx.emulate_payload_satp(&mut mctx, &mut module).is_ok());
+        assert_eq!(arch::read_csr(Csr::Satp), 0);
+        assert_eq!(ctx.pc, 0x8020_0004);
+
+        // csrr a1, satp
+        ctx.trap_info.mtval = 0x180025f3;
+        ctx.set(Register::X11, 0xdead);
+        assert!(ctx.emulate_payload_satp(&mut mctx, &mut module).is_ok());
+        assert_eq!(ctx.get(Register::X11), 0);
+
+        // With mstatus.TVM set by the firmware the access must trap to the firmware
+        ctx.csr.mstatus |= mstatus::TVM_FILTER;
+        assert!(ctx.emulate_payload_satp(&mut mctx, &mut module).is_err());
+        assert_eq!(ctx.pc, 0x8020_0008);
+    }
+
+    #[test]
+    fn release_pmp() {
+        let hw = unsafe { arch::detect_hardware() };
+        let mut mctx = MiralisContext::new(hw, 0x10000, 0x2000);
+        let mut ctx = VirtContext::new(0, mctx.pmp.nb_virt_pmp, mctx.hw.extensions.clone());
+        let module = MainModule::init();
+        let nb_pmp = ctx.nb_pmp;
+        assert!(nb_pmp >= 8, "Not enough virtual PMP entries for the test");
+
+        // The last entry is locked
+        let last = nb_pmp - 1;
+        let locked_tor = (pmpcfg::L | pmpcfg::TOR | pmpcfg::R) as usize;
+        ctx.mode = Mode::M;
+        ctx.csr.pmpaddr[last] = 0x1000;
+        ctx.csr.pmpcfg[last / 8] |= locked_tor << ((last % 8) * 8);
+
+        let mut release = |ctx: &mut VirtContext, count: usize| {
+            ctx.set(Register::X16, abi::MIRALIS_RELEASE_PMP_FID);
+            ctx.set(Register::X10, count);
+            ctx.handle_ecall(&mut mctx, &module);
+            ctx.get(Register::X10)
+        };
+
+        // Locked entries and more entries than available can not be released
+        assert_eq!(release(&mut ctx, 1), sbi_codes::SBI_ERR_DENIED);
+        assert_eq!(
+            release(&mut ctx, nb_pmp + 1),
+            sbi_codes::SBI_ERR_INVALID_PARAM
+        );
+        assert_eq!(ctx.nb_pmp, nb_pmp);
+
+        // Once released, the entries read as zero
+        ctx.csr.pmpcfg[last / 8] = 0;
+        assert_eq!(release(&mut ctx, 4), 0);
+        assert_eq!(ctx.nb_pmp, nb_pmp - 4);
+        assert_eq!(ctx.get(Csr::Pmpaddr(last)), 0);
+        assert!(ctx.validate().is_ok());
+
+        // The payload can not release entries
+        ctx.mode = Mode::S;
+        assert_eq!(release(&mut ctx, 1), sbi_codes::SBI_ERR_DENIED);
+
+        // The released entries can no longer be written
+        ctx.mode = Mode::M;
+        ctx.set_csr(Csr::Pmpaddr(last), 0x1234, &mut mctx);
+        assert_eq!(ctx.get(Csr::Pmpaddr(last)), 0);
+
+        // The released entries are placed before the virtual PMP, for the modules to use
+        let free_offset = mctx.pmp.free_pmp_offset;
+        assert_eq!(mctx.pmp.nb_free_pmp, 4);
+        assert_eq!(mctx.pmp.nb_virt_pmp, nb_pmp - 4);
+        assert_eq!(mctx.pmp.virt_pmp_offset, free_offset + 4 + 1);
+        mctx.pmp.set_free(3, 0x8000_0000 >> 2, pmpcfg::NA4);
+        assert_eq!(mctx.pmp.pmpaddr()[free_offset + 3], 0x8000_0000 >> 2);
+        assert_eq!(mctx.pmp.get_pmpcfg(free_offset + 4), pmpcfg::INACTIVE);
+    }
+
+    #[test]
+    fn zero_region() {
+        let hw = unsafe { arch::detect_hardware() };
+        let mut mctx = MiralisContext::new(hw, 0x10000, 0x2000);
+        let mut ctx = VirtContext::new(0, mctx.hw.available_reg.nb_pmp, mctx.hw.extensions.clone());
+        let module = MainModule::init();
+        let mut buffer = [0xff_u8; 21];
+        static DMA_REGIONS: [Segment; 1] = [Segment::new(0x8800_0000, 0x1000)];
+        mctx.dma_regions = &DMA_REGIONS;
+
+        let mut zero = |ctx: &mut VirtContext, base: usize, size: usize| {
+            ctx.set(Register::X16, abi::MIRALIS_ZERO_REGION_FID);
+            ctx.set(Register::X10, base);
+            ctx.set(Register::X11, size);
+            ctx.handle_ecall(&mut mctx, &module);
+            ctx.get(Register::X10)
+        };
+
+        // Unaligned region, the surrounding bytes must be preserved
+        ctx.mode = Mode::M;
+        let base = buffer.as_mut_ptr() as usize;
+        assert_eq!(zero(&mut ctx, base + 1, 19), 0);
+        assert_eq!(buffer[0], 0xff);
+        assert_eq!(&buffer[1..20], &[0; 19]);
+        assert_eq!(buffer[20], 0xff);
+
+        // Miralis memory and overflowing regions are rejected
+        assert_eq!(zero(&mut ctx, 0x11000, 0x10), sbi_codes::SBI_ERR_DENIED);
+        assert_eq!(
+            zero(&mut ctx, usize::MAX, 2),
+            sbi_codes::SBI_ERR_INVALID_PARAM
+        );
+
+        // DMA buffers belong to the devices
+        assert_eq!(zero(&mut ctx, 0x8800_0ff8, 0x10), sbi_codes::SBI_ERR_DENIED);
+
+        // The firmware can not bypass its own locked PMP entries
+        ctx.csr.pmpaddr[0] = (base + buffer.len()) >> 2;
+        ctx.csr.pmpcfg[0] = (pmpcfg::L | pmpcfg::TOR | pmpcfg::R) as usize;
+        assert_eq!(zero(&mut ctx, base, 1), sbi_codes::SBI_ERR_DENIED);
+        assert_eq!(buffer[0], 0xff);
+        ctx.csr.pmpcfg[0] = 0;
+
+        // Only the firmware can zero memory
+        ctx.mode = Mode::S;
+        assert_eq!(zero(&mut ctx, base, 1), sbi_codes::SBI_ERR_DENIED);
+        assert_eq!(buffer[0], 0xff);
+    }
+
+    /// The Zawrs stall lasts as long as the reservation is valid, bounded by the timeout of
+    /// `wrs.sto` only.
+    #[test]
+    fn wrs_stall() {
+        // An invalid reservation terminates the stall right away
+        assert_eq!(super::wrs_stall(None, || true), 1);
+        assert_eq!(super::wrs_stall(Some(10), || true), 1);
+
+        // A valid reservation stalls until invalidated, or until the timeout
+        let invalidated_after = |n| {
+            let mut polls = 0;
+            move || {
+                polls += 1;
+                polls >= n
+            }
+        };
+        assert_eq!(super::wrs_stall(None, invalidated_after(100)), 100);
+        assert_eq!(super::wrs_stall(Some(10), invalidated_after(100)), 10);
+    }
+
+    #[test]
+    fn rdtime() {
+        assert_eq!(decode_rdtime(0xc0102073), Some(Register::X0)); // rdtime x0
+        assert_eq!(decode_rdtime(0xc01022f3), Some(Register::X5)); // rdtime t0
+        assert_eq!(decode_rdtime(0xc0002573), None); // rdcycle a0
+        assert_eq!(decode_rdtime(0xc0103573), None); // csrrc a0, time, x0
+        assert_eq!(decode_rdtime(0xc0112573), None); // csrrs a0, time, sp
     }
 }
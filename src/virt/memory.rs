@@ -1,10 +1,22 @@
-//! Emulation logic for misaligned loads and stores
+//! Emulation logic for misaligned loads and stores, atomic compare-and-swap, and the reservation
+//! sets waited on by the Zawrs instructions
+
+use core::sync::atomic::{Ordering, fence};
+
+use spin::Mutex;
 
 use crate::arch;
-use crate::arch::{get_raw_faulting_instr, parse_mpp_return_mode};
-use crate::decoder::{LoadInstr, StoreInstr};
+use crate::arch::{MCause, Mode, Register, get_raw_faulting_instr, parse_mpp_return_mode};
+use crate::decoder::{AmoCasInstr, LoadInstr, StoreInstr};
 use crate::host::MiralisContext;
 use crate::virt::VirtContext;
+use crate::virt::traits::*;
+
+/// Serializes the compare-and-swap operations emulated by Miralis across harts.
+static AMOCAS_LOCK: Mutex<()> = Mutex::new(());
+
+/// Maximum number of instructions between an `lr` and the `wrs` waiting on its reservation.
+const RESERVATION_WINDOW: usize = 4;
 
 pub fn emulate_misaligned_read(ctx: &mut VirtContext, mctx: &mut MiralisContext) -> Result<(), ()> {
     let raw_instruction = unsafe { get_raw_faulting_instr(ctx) };
@@ -111,3 +123,246 @@ pub fn emulate_misaligned_write(
         Err(_) => Err(()),
     }
 }
+
+/// Emulates an atomic compare-and-swap instruction (`amocas.w/d/q`) for cores lacking Zacas.
+///
+/// The operation is atomic with respect to all the other compare-and-swap emulated by Miralis,
+/// but not with respect to regular stores from other harts. Returns an error if the instruction
+/// can not be emulated, in which case the trap must be forwarded. Misaligned or inaccessible
+/// addresses turn the trap into a store/AMO fault with the address in `mtval`.
+pub fn emulate_amocas(ctx: &mut VirtContext, mctx: &mut MiralisContext) -> Result<(), ()> {
+    let raw_instruction = unsafe { get_raw_faulting_instr(ctx) };
+    let mode = parse_mpp_return_mode(ctx.trap_info.mstatus);
+
+    let AmoCasInstr {
+        rd, rs1, rs2, len, ..
+    } = mctx.decode_amocas(raw_instruction).ok_or(())?;
+
+    let addr = ctx.get(rs1);
+    if !addr.is_multiple_of(len) {
+        return store_fault(ctx, MCause::StoreAddrMisaligned, addr);
+    }
+
+    let (expected, new_value) = if len == 16 {
+        (read_register_pair(ctx, rd)?, read_register_pair(ctx, rs2)?)
+    } else {
+        let mask = u128::MAX >> (128 - 8 * len);
+        (ctx.get(rd) as u128 & mask, ctx.get(rs2) as u128 & mask)
+    };
+
+    let mut value = [0u8; 16];
+    {
+        let _guard = AMOCAS_LOCK.lock();
+        fence(Ordering::SeqCst);
+        if unsafe { arch::read_bytes_from_mode(addr as *const u8, &mut value[..len], mode) }
+            .is_err()
+        {
+            return store_fault(ctx, MCause::StoreAccessFault, addr);
+        }
+        if u128::from_le_bytes(value) == expected {
+            let new_value = new_value.to_le_bytes();
+            if unsafe { arch::store_bytes_from_mode(&new_value[..len], addr as *mut u8, mode) }
+                .is_err()
+            {
+                return store_fault(ctx, MCause::StoreAccessFault, addr);
+            }
+        }
+        fence(Ordering::SeqCst);
+    }
+
+    let loaded = u128::from_le_bytes(value);
+    match len {
+        4 => ctx.set(rd, loaded as u32 as i32 as isize as usize),
+        8 => ctx.set(rd, loaded as usize),
+        _ => {
+            // The x0 register pair is never written
+            if rd != Register::X0 {
+                ctx.set(rd, loaded as u64 as usize);
+                ctx.set(Register::from(rd as usize + 1), (loaded >> 64) as usize);
+            }
+        }
+    }
+
+    ctx.pc += 4;
+    Ok(())
+}
+
+/// Replaces the trap being handled by a store/AMO fault on `addr`, to be forwarded by the caller.
+///
+/// AMOs only ever report store/AMO exceptions, even when the load half of the operation faults.
+fn store_fault(ctx: &mut VirtContext, cause: MCause, addr: usize) -> Result<(), ()> {
+    ctx.trap_info.mcause = cause as usize;
+    ctx.trap_info.mtval = addr;
+    Err(())
+}
+
+/// Reads an even-odd register pair as used by `amocas.q`, the x0 pair reads as zero.
+///
+/// Odd registers are reserved encodings and return an error.
+fn read_register_pair(ctx: &VirtContext, reg: Register) -> Result<u128, ()> {
+    if !(reg as usize).is_multiple_of(2) {
+        return Err(());
+    }
+    if reg == Register::X0 {
+        return Ok(0);
+    }
+
+    let low = ctx.get(reg) as u128;
+    let high = ctx.get(Register::from(reg as usize + 1)) as u128;
+    Ok(low | (high << 64))
+}
+
+// ——————————————————————————— Reservation Sets ———————————————————————————— //
+
+/// The reservation registered by an `lr` instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Reservation {
+    /// The reserved address.
+    pub addr: usize,
+    /// The size of the reservation in bytes, 4 or 8.
+    pub len: usize,
+    /// The value loaded by the `lr`.
+    pub value: usize,
+}
+
+impl Reservation {
+    /// Returns true if the reserved memory still holds the value loaded by the `lr`.
+    ///
+    /// A store from another hart invalidates the reservation, which Miralis can only observe as
+    /// a change of value. Stores of the same value are missed, but the caller re-checks its
+    /// condition and waits again anyway.
+    pub fn is_valid(&self, mode: Mode) -> bool {
+        let mut value = [0u8; 8];
+        let read = unsafe {
+            arch::read_bytes_from_mode(self.addr as *const u8, &mut value[..self.len], mode)
+        };
+        let mask = usize::MAX >> (64 - 8 * self.len);
+        read.is_ok() && usize::from_le_bytes(value) == self.value & mask
+    }
+}
+
+/// Returns the reservation the `wrs` instruction trapping in `ctx` waits on.
+///
+/// The reservation set of the hart is not visible from M-mode, instead we look for the `lr`
+/// right before the `wrs`. Returns None if there is no such `lr`, or if the following
+/// instructions overwrote its address or loaded value, in which case the stall must terminate.
+pub fn find_reservation(ctx: &VirtContext) -> Option<Reservation> {
+    let mode = parse_mpp_return_mode(ctx.trap_info.mstatus);
+    let mut instructions = [0u32; RESERVATION_WINDOW];
+    for (idx, instr) in instructions.iter_mut().enumerate() {
+        let addr = ctx.trap_info.mepc.checked_sub(4 * (idx + 1))?;
+        let mut bytes = [0u8; 4];
+        unsafe { arch::read_bytes_from_mode(addr as *const u8, &mut bytes, mode).ok()? };
+        *instr = u32::from_le_bytes(bytes);
+    }
+
+    let (rd, rs1, len) = decode_reservation(&instructions)?;
+    Some(Reservation {
+        addr: ctx.get(rs1),
+        len,
+        value: ctx.get(rd),
+    })
+}
+
+/// Finds the `lr` in the instructions preceding a `wrs`, the closest first.
+///
+/// Returns the destination and address registers of the `lr` and its size in bytes, if neither
+/// register is written by the instructions in between.
+fn decode_reservation(instructions: &[u32]) -> Option<(Register, Register, usize)> {
+    const OPCODE_AMO: u32 = 0b0101111;
+    const OPCODE_BRANCH: u32 = 0b1100011;
+    const OPCODE_STORE: u32 = 0b0100011;
+    const OPCODE_STORE_FP: u32 = 0b0100111;
+    const FUNCT5_LR: u32 = 0b00010;
+
+    let mut written: u32 = 0;
+    for &instr in instructions {
+        if instr & 0b11 != 0b11 {
+            // Compressed instructions are not supported
+            return None;
+        }
+
+        let opcode = instr & 0x7f;
+        let rd = (instr >> 7) & 0x1f;
+        let rs1 = (instr >> 15) & 0x1f;
+        let len = match (instr >> 12) & 0b111 {
+            0b010 => 4,
+            0b011 => 8,
+            _ => 0,
+        };
+        if opcode == OPCODE_AMO && instr >> 27 == FUNCT5_LR && len != 0 {
+            if written & (1 << rd | 1 << rs1) != 0 {
+                return None;
+            }
+            return Some((
+                Register::from(rd as usize),
+                Register::from(rs1 as usize),
+                len,
+            ));
+        }
+
+        if !matches!(opcode, OPCODE_BRANCH | OPCODE_STORE | OPCODE_STORE_FP) {
+            written |= 1 << rd;
+        }
+    }
+    None
+}
+
+// ————————————————————————————————— Tests —————————————————————————————————— //
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_reservation, emulate_amocas};
+    use crate::arch::{self, MCause, Mode, Register, mstatus};
+    use crate::host::MiralisContext;
+    use crate::virt::VirtContext;
+    use crate::virt::traits::*;
+
+    #[test]
+    fn reservation() {
+        const LR_W: u32 = 0x100525af; // lr.w a1, (a0)
+        const LR_D: u32 = 0x100535af; // lr.d a1, (a0)
+        const SW: u32 = 0x00c52023; // sw a2, 0(a0)
+        const BNE: u32 = 0x00c59463; // bne a1, a2, 8
+        const XOR: u32 = 0x00c5c5b3; // xor a1, a1, a2
+        const ADDI: u32 = 0x00450513; // addi a0, a0, 4
+        const WRS_NTO: u32 = 0x00d00073; // wrs.nto
+
+        let expected = Some((Register::X11, Register::X10, 4));
+        assert_eq!(decode_reservation(&[LR_W, 0, 0, 0]), expected);
+        assert_eq!(decode_reservation(&[SW, LR_W, 0, 0]), expected);
+        assert_eq!(decode_reservation(&[WRS_NTO, BNE, LR_W, 0]), expected);
+        assert_eq!(
+            decode_reservation(&[BNE, LR_D, 0, 0]),
+            Some((Register::X11, Register::X10, 8))
+        );
+
+        // The loaded value or the address are overwritten
+        assert_eq!(decode_reservation(&[BNE, XOR, LR_W, 0]), None);
+        assert_eq!(decode_reservation(&[ADDI, LR_W, 0, 0]), None);
+
+        // No lr within the window, or compressed instructions
+        assert_eq!(decode_reservation(&[SW, SW, SW, SW]), None);
+        assert_eq!(decode_reservation(&[0x0001, LR_W, 0, 0]), None);
+    }
+
+    /// A misaligned compare-and-swap raises a store/AMO fault carrying the address, not an
+    /// illegal instruction.
+    #[test]
+    fn amocas_misaligned() {
+        let hw = unsafe { arch::detect_hardware() };
+        let mut mctx = MiralisContext::new(hw, 0x10000, 0x2000);
+        let mut ctx = VirtContext::new(0, mctx.hw.available_reg.nb_pmp, mctx.hw.extensions.clone());
+        ctx.pc = 0x8020_0000;
+        ctx.trap_info.mcause = MCause::IllegalInstr as usize;
+        ctx.trap_info.mstatus = Mode::S.to_bits() << mstatus::MPP_OFFSET;
+
+        // amocas.d a0, a2, (a1)
+        ctx.trap_info.mtval = 0x2ec5b52f;
+        ctx.set(Register::X11, 0x8030_0004);
+        assert!(emulate_amocas(&mut ctx, &mut mctx).is_err());
+        assert_eq!(ctx.trap_info.get_cause(), MCause::StoreAddrMisaligned);
+        assert_eq!(ctx.trap_info.mtval, 0x8030_0004);
+        assert_eq!(ctx.pc, 0x8020_0000);
+    }
+}
@@ -1,14 +1,22 @@
 //! Firmware Virtualisation
 
+mod checkpoint;
 mod csr;
 mod emulator;
 pub mod memory;
 mod world_switch;
 
+use core::fmt;
+
 pub use csr::traits;
-pub use emulator::ExitResult;
+pub use emulator::{ExitResult, is_illegal_csr_access};
 
-use crate::arch::{ExtensionsCapability, Mode, TrapInfo, mie, misa};
+#[cfg(test)]
+use crate::arch::MCause;
+use crate::arch::satp::{self, SatpMode};
+use crate::arch::{ExtensionsCapability, Mode, TrapInfo, mie, misa, mseccfg, mstatus};
+use crate::benchmark::folded::FoldedStacks;
+use crate::debug::TrapHistory;
 
 /// The execution mode, either virtualized firmware or native payload.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -50,6 +58,25 @@ pub struct VirtContext {
     pub nb_exits: usize,
     /// Whether the vCPU is currently in Wait For Interrupt mode (WFI)
     pub is_wfi: bool,
+    /// The most recent traps, displayed in crash reports
+    pub trap_history: TrapHistory,
+    /// Whether the payload is granted access to all memory not protected by Miralis until the
+    /// next world switch to the firmware (see `MIRALIS_IDENTITY_MAP_FID`).
+    pub identity_map: bool,
+    /// Whether the payload is single-stepped (see `MIRALIS_ENABLE_SINGLESTEP_FID`).
+    pub single_step: bool,
+    /// Number of times the vCPU was resumed on a spurious interrupt, without handling the trap
+    pub nb_transient_retries: usize,
+    /// The open benchmark scopes, only used when `MIRALIS_BENCHMARK_FOLDED_STACKS` is enabled
+    pub folded_stacks: FoldedStacks,
+    /// Whether the payload accesses to `satp` trap into Miralis, for the modules to validate the
+    /// `satp` writes (see `Module::on_satp_write`).
+    pub trap_satp: bool,
+    /// Number of instructions retired by the guest during the last run of the vCPU, as measured
+    /// by the hardware `minstret`. This includes the context switch code of Miralis, which
+    /// retires a constant number of instructions on each run. Only measured when the exit counter
+    /// benchmark is enabled.
+    pub last_run_instret: usize,
 }
 
 impl VirtContext {
@@ -95,6 +122,7 @@ impl VirtContext {
                 stval: 0,
                 satp: 0,
                 scontext: 0,
+                mcontext: 0,
                 stimecmp: 0,
                 medeleg: 0,
                 mideleg: mie::MIDELEG_READ_ONLY_ONE,
@@ -153,6 +181,13 @@ impl VirtContext {
             hart_id,
             extensions: available_extension,
             is_wfi: false,
+            trap_history: TrapHistory::new(),
+            identity_map: false,
+            single_step: false,
+            nb_transient_retries: 0,
+            folded_stacks: FoldedStacks::new(),
+            trap_satp: false,
+            last_run_instret: 0,
         }
     }
 
@@ -164,6 +199,130 @@ impl VirtContext {
             !0b10
         }
     }
+
+    /// Whether the floating-point unit is enabled, i.e. `mstatus.FS` is not Off.
+    ///
+    /// When FS is Off, any FP instruction or FP CSR access raises an illegal instruction
+    /// exception. FS is always Off with Zfinx, following `legalize_mstatus` of the Sail model.
+    pub fn is_fp_enabled(&self) -> bool {
+        self.csr.mstatus & mstatus::FS_FILTER != 0
+    }
+
+    /// Returns the address translation mode of the payload, as configured in `satp`.
+    ///
+    /// Returns `None` if the `MODE` field does not hold a mode known to Miralis.
+    pub fn current_satp_mode(&self) -> Option<SatpMode> {
+        satp::mode_of_bits(self.csr.satp)
+    }
+
+    /// Checks the invariants that the emulation must maintain across virtual CSRs.
+    ///
+    /// The following invariants are checked:
+    /// - `mstatus.SD` summarizes whether any of `mstatus.FS`, `mstatus.VS` or `mstatus.XS` is
+    ///   dirty.
+    /// - `mstatus.XS` is zero, as no custom extension is exposed to the firmware.
+    /// - `mstatus.MPP` holds a supported privilege mode (never the reserved value 2, and S-mode
+    ///   only if the S extension is available).
+    /// - The read-only zero bits of `mideleg` (M-mode interrupts) are cleared, and the read-only
+    ///   one bits (S-mode interrupts) are set when the S extension is exposed through `misa`.
+    /// - Bit 0 of `mepc` and `sepc` is cleared.
+    /// - Each emulated `pmpcfg` entry is legal: reserved bits are cleared, the reserved R=0 and
+    ///   W=1 combination is never stored, and NA4 is not selected if the PMP grain is at least 1.
+    /// - The `pmpcfg` entries of the PMPs that are not emulated are zero, and in particular are
+    ///   not locked.
+    ///
+    /// Locked PMP entries are a property of successive writes rather than of a single context,
+    /// they are checked by the `locked_pmp` model checking proof instead.
+    pub fn validate(&self) -> Result<(), InvariantError> {
+        let status = self.csr.mstatus;
+        if status != mstatus::with_sd(status) {
+            return Err(InvariantError::MstatusSd);
+        }
+        if status & mstatus::XS_FILTER != 0 {
+            return Err(InvariantError::MstatusXs);
+        }
+        let mpp = (status & mstatus::MPP_FILTER) >> mstatus::MPP_OFFSET;
+        if mpp == 2 || (mpp == 1 && !self.extensions.has_s_extension) {
+            return Err(InvariantError::MstatusMpp(mpp));
+        }
+
+        // LCOFIE can only be delegated with the Sscofpmf extension
+        let mut read_only_zero = mie::MIDELEG_READ_ONLY_ZERO;
+        if !self.extensions.has_sscofpmf_extension {
+            read_only_zero |= mie::LCOFIE_FILTER;
+        }
+        if self.csr.mideleg & read_only_zero != 0 {
+            return Err(InvariantError::MidelegReadOnlyZero);
+        }
+        if self.csr.misa & misa::S != 0
+            && self.csr.mideleg & mie::MIDELEG_READ_ONLY_ONE != mie::MIDELEG_READ_ONLY_ONE
+        {
+            return Err(InvariantError::MidelegReadOnlyOne);
+        }
+
+        if self.csr.mepc & 0b1 != 0 {
+            return Err(InvariantError::MisalignedEpc(self.csr.mepc));
+        }
+        if self.csr.sepc & 0b1 != 0 {
+            return Err(InvariantError::MisalignedEpc(self.csr.sepc));
+        }
+
+        for idx in 0..64 {
+            let cfg = self.get_pmpcfg(idx);
+            let legal = if idx >= self.nb_pmp {
+                cfg == 0
+            } else {
+                let reserved_bits = cfg & 0b1100000 != 0;
+                // W = 1 & R = 0 encodes a shared region when mseccfg.MML is set
+                let mml = self.csr.mseccfg & mseccfg::MML_FILTER != 0;
+                let reserved_rw = !mml && cfg & 0b11 == 0b10;
+                let illegal_na4 = self.pmp_grain >= 1 && cfg & 0b11000 == 0b10000;
+                !(reserved_bits || reserved_rw || illegal_na4)
+            };
+            if !legal {
+                return Err(InvariantError::IllegalPmpcfg(idx));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A violated invariant of the virtual context, see [VirtContext::validate].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvariantError {
+    /// `mstatus.SD` does not summarize the dirty state of FS, VS and XS.
+    MstatusSd,
+    /// `mstatus.XS` is not zero.
+    MstatusXs,
+    /// `mstatus.MPP` holds an unsupported privilege mode.
+    MstatusMpp(usize),
+    /// A read-only zero bit of `mideleg` is set.
+    MidelegReadOnlyZero,
+    /// A read-only one bit of `mideleg` is cleared.
+    MidelegReadOnlyOne,
+    /// An exception program counter (`mepc` or `sepc`) is misaligned.
+    MisalignedEpc(usize),
+    /// The configuration of the PMP entry with the given index is illegal.
+    IllegalPmpcfg(usize),
+}
+
+impl fmt::Display for InvariantError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvariantError::MstatusSd => write!(f, "mstatus.SD does not match FS/VS/XS"),
+            InvariantError::MstatusXs => write!(f, "mstatus.XS is not zero"),
+            InvariantError::MstatusMpp(mpp) => write!(f, "mstatus.MPP is illegal: {}", mpp),
+            InvariantError::MidelegReadOnlyZero => {
+                write!(f, "mideleg read-only zero bits are set")
+            }
+            InvariantError::MidelegReadOnlyOne => {
+                write!(f, "mideleg read-only one bits are cleared")
+            }
+            InvariantError::MisalignedEpc(epc) => write!(f, "misaligned epc: 0x{:x}", epc),
+            InvariantError::IllegalPmpcfg(idx) => write!(f, "illegal pmpcfg entry {}", idx),
+        }
+    }
 }
 
 /// Control and Status Registers (CSR) for a virtual firmware.
@@ -201,6 +360,7 @@ pub struct VirtCsr {
     pub stval: usize,
     pub satp: usize,
     pub scontext: usize,
+    pub mcontext: usize,
     pub stimecmp: usize,
     pub medeleg: usize,
     pub mideleg: usize,
@@ -276,6 +436,7 @@ impl VirtCsr {
             stval: 0,
             satp: 0,
             scontext: 0,
+            mcontext: 0,
             stimecmp: 0,
             medeleg: 0,
             mideleg: 0,
@@ -337,3 +498,23 @@ impl VirtCsr {
         !0b0
     }
 }
+
+// —————————————————————————————— Test Helpers —————————————————————————————— //
+
+/// Populates the trap information of `ctx` as if the hardware had just trapped with `cause`.
+///
+/// This lets unit tests drive any cause through `handle_trap` without running on real hardware.
+/// For interrupts, the matching `mip` bit is also set so that the trap is not spurious. The saved
+/// `mstatus` is left untouched and can be adjusted by the caller.
+#[cfg(test)]
+pub(crate) fn inject_trap(ctx: &mut VirtContext, cause: MCause, mtval: usize, mepc: usize) {
+    ctx.trap_info.mcause = cause as usize;
+    ctx.trap_info.mtval = mtval;
+    ctx.trap_info.mepc = mepc;
+    ctx.trap_info.mtval2 = 0;
+    ctx.trap_info.mtinst = 0;
+    ctx.trap_info.gva = false;
+    if cause.is_interrupt() {
+        ctx.trap_info.mip |= 1 << MCause::cause_number(cause as usize);
+    }
+}
@@ -4,10 +4,15 @@
 
 use super::{VirtContext, VirtCsr};
 use crate::arch;
+use crate::arch::perf_counters::DELGATE_PERF_COUNTERS_MASK;
 use crate::arch::pmp::pmpcfg;
 use crate::arch::pmp::pmpcfg::NO_PERMISSIONS;
-use crate::arch::{Csr, Mode, mie, mstatus};
-use crate::config::DELEGATE_PERF_COUNTER;
+use crate::arch::pmp::pmplayout::MPRV_EMULATION_OFFSET;
+use crate::arch::{Csr, MCause, Mode, icount, mie, mstatus, pmp};
+use crate::config::{
+    DELEGATE_PERF_COUNTER, VCPU_EMULATE_SVINVAL, VCPU_EMULATE_ZAWRS, VCPU_EMULATE_ZICOND,
+    VCPU_VIRTUALIZE_ZICNTR,
+};
 use crate::host::MiralisContext;
 
 impl VirtContext {
@@ -39,10 +44,10 @@ impl VirtContext {
                 arch::write_csr(Csr::Menvcfg, self.csr.menvcfg);
             }
 
-            arch::write_csr(Csr::Mstatus, mstatus & !mstatus::MIE_FILTER);
+            arch::write_csr(Csr::Mstatus, self.payload_mstatus(mstatus));
             arch::write_csr(Csr::Mideleg, self.csr.mideleg);
-            arch::write_csr(Csr::Medeleg, self.csr.medeleg);
-            arch::write_csr(Csr::Mcounteren, self.csr.mcounteren as usize);
+            arch::write_csr(Csr::Medeleg, self.payload_medeleg());
+            arch::write_csr(Csr::Mcounteren, self.payload_mcounteren());
 
             // NOTE: `mip` mut be set _after_ `menvcfg`, because `menvcfg` might change which bits
             // in `mip` are writeable. For more information see the Sstc extension specification.
@@ -67,6 +72,9 @@ impl VirtContext {
                 if mctx.hw.extensions.is_sstc_enabled {
                     arch::write_csr(Csr::Stimecmp, self.csr.stimecmp);
                 }
+                if mctx.hw.available_reg.scontext {
+                    arch::write_csr(Csr::Scontext, self.csr.scontext);
+                }
             }
 
             // If H extension is present - save the registers
@@ -96,18 +104,32 @@ impl VirtContext {
             }
         }
 
+        // MPRV is cleared when leaving M-mode, which disables its emulation
+        mctx.pmp.set_inactive(MPRV_EMULATION_OFFSET, 0);
+
         // Load virtual PMP registers into Miralis's own registers
         mctx.pmp.load_with_offset(
             &self.csr.pmpaddr,
             &self.csr.pmpcfg,
+            self.csr.mseccfg,
+            self.mode,
             mctx.pmp.virt_pmp_offset,
             self.nb_pmp,
         );
-        // Deny all addresses by default if at least one PMP is implemented
+        // Deny all addresses by default if at least one PMP is implemented, unless the firmware
+        // requested an identity map.
         if self.nb_pmp > 0 {
             let last_pmp_idx = mctx.pmp.nb_pmp as usize - 1;
-            mctx.pmp
-                .set_napot(last_pmp_idx, 0, usize::MAX, NO_PERMISSIONS);
+            let permissions = if self.identity_map {
+                pmpcfg::RWX
+            } else {
+                NO_PERMISSIONS
+            };
+            mctx.pmp.set_napot(last_pmp_idx, 0, usize::MAX, permissions);
+        }
+
+        if self.single_step {
+            arm_single_step();
         }
     }
 
@@ -119,16 +141,26 @@ impl VirtContext {
     /// This function changes the configuration of the hardware CSR registers. It assumes the
     /// hardware is under the full control of Miralis.
     pub unsafe fn switch_from_payload_to_firmware(&mut self, mctx: &mut MiralisContext) {
+        // The identity map only lasts until the payload traps back into the firmware
+        self.identity_map = false;
+
         // Now save M-mode registers which are (partially) exposed as S-mode registers.
         // For mstatus we read the current value and clear the two MPP bits to jump into U-mode
         // (virtual firmware) during the next mret.
 
         unsafe {
-            self.csr.mstatus = self.csr.mstatus & !mstatus::SSTATUS_FILTER
-                | arch::read_csr(Csr::Mstatus) & mstatus::SSTATUS_FILTER;
+            // The payload may have dirtied its extension states, we recompute SD accordingly
+            self.csr.mstatus = mstatus::with_sd(
+                self.csr.mstatus & !mstatus::SSTATUS_FILTER
+                    | arch::read_csr(Csr::Mstatus) & mstatus::SSTATUS_FILTER,
+            );
             arch::set_mpp(Mode::U);
             arch::write_csr(Csr::Mideleg, 0); // Do not delegate any interrupts
             arch::write_csr(Csr::Medeleg, 0); // Do not delegate any exceptions
+            if self.single_step {
+                // The firmware is never single-stepped
+                disarm_single_step();
+            }
 
             let mie_hw_bits = arch::read_csr(Csr::Mie) & !(mie::MIDELEG_READ_ONLY_ZERO);
             let mie_sw_bits = self.csr.mie & mie::MIDELEG_READ_ONLY_ZERO;
@@ -149,8 +181,10 @@ impl VirtContext {
 
             let delegate_perf_counter_mask: usize = if DELEGATE_PERF_COUNTER { 1 } else { 0 };
 
-            self.csr.mcounteren =
-                arch::write_csr(Csr::Mcounteren, delegate_perf_counter_mask) as u32;
+            // The basic counters hidden from the payload keep their virtual value
+            let hidden = self.csr.mcounteren as usize & !self.payload_mcounteren();
+            let mcounteren = arch::write_csr(Csr::Mcounteren, delegate_perf_counter_mask);
+            self.csr.mcounteren = (mcounteren | hidden) as u32;
 
             if mctx.hw.available_reg.senvcfg {
                 self.csr.senvcfg = arch::write_csr(Csr::Senvcfg, 0);
@@ -175,6 +209,9 @@ impl VirtContext {
                 if mctx.hw.extensions.is_sstc_enabled {
                     self.csr.stimecmp = arch::write_csr(Csr::Stimecmp, 0);
                 }
+                if mctx.hw.available_reg.scontext {
+                    self.csr.scontext = arch::write_csr(Csr::Scontext, 0);
+                }
             }
 
             // If H extension is present - save the registers
@@ -204,12 +241,133 @@ impl VirtContext {
             }
         }
 
-        // Set firmware PMP as RWX to emulate access to all memory in vM-mode
-        mctx.pmp
-            .set_range_rwx(mctx.pmp.virt_pmp_offset, self.nb_pmp);
-        // Allow all addresses by default
+        self.load_firmware_pmp(mctx);
+    }
+
+    /// Loads the virtual PMP registers with the access rights of vM-mode.
+    ///
+    /// Unlocked entries grant access to all memory in vM-mode, unless the Smepmp bits of
+    /// `mseccfg` say otherwise. The last entry emulates the access rights of addresses matching
+    /// no entry.
+    pub(crate) fn load_firmware_pmp(&self, mctx: &mut MiralisContext) {
+        mctx.pmp.load_with_offset(
+            &self.csr.pmpaddr,
+            &self.csr.pmpcfg,
+            self.csr.mseccfg,
+            Mode::M,
+            mctx.pmp.virt_pmp_offset,
+            self.nb_pmp,
+        );
         let last_pmp_idx = mctx.pmp.nb_pmp as usize - 1;
-        mctx.pmp.set_napot(last_pmp_idx, 0, usize::MAX, pmpcfg::RWX);
+        let permissions = pmp::machine_default_permissions(self.csr.mseccfg);
+        mctx.pmp.set_napot(last_pmp_idx, 0, usize::MAX, permissions);
+    }
+}
+
+// ————————————————————————————— Basic Counters ————————————————————————————— //
+
+impl VirtContext {
+    /// Returns the counters accessible to the payload without trapping.
+    ///
+    /// When the basic counters are virtualized, reads of `cycle`, `time` and `instret` must trap
+    /// into Miralis to be served from the virtual counters.
+    pub(crate) fn payload_mcounteren(&self) -> usize {
+        if VCPU_VIRTUALIZE_ZICNTR {
+            self.csr.mcounteren as usize & !DELGATE_PERF_COUNTERS_MASK
+        } else {
+            self.csr.mcounteren as usize
+        }
+    }
+
+    /// Returns true if some basic counters are enabled by the firmware but hidden from the
+    /// payload, in which case their reads must be served by Miralis.
+    pub(crate) fn emulates_counter_reads(&self) -> bool {
+        self.payload_mcounteren() != self.csr.mcounteren as usize
+    }
+}
+
+// —————————————————————————————— Satp Trapping ————————————————————————————— //
+
+impl VirtContext {
+    /// Returns the physical `mstatus` while the payload runs, from the virtual `mstatus` with MPP
+    /// already set.
+    ///
+    /// When the modules validate the `satp` writes, mstatus.TVM is set so that `satp` accesses
+    /// trap into Miralis. As a side effect `sfence.vma` and `sinval.vma` trap too, and must be
+    /// emulated unless the firmware itself set the virtual mstatus.TVM.
+    pub(crate) fn payload_mstatus(&self, mstatus: usize) -> usize {
+        let mstatus = mstatus & !mstatus::MIE_FILTER;
+        if self.trap_satp {
+            mstatus | mstatus::TVM_FILTER
+        } else {
+            mstatus
+        }
+    }
+}
+
+// ——————————————————————————————— Single Step —————————————————————————————— //
+
+impl VirtContext {
+    /// Returns the exceptions to delegate while the payload runs.
+    ///
+    /// When single-stepping, breakpoints must trap into Miralis to be reported as steps.
+    /// Similarly, illegal instructions must trap into Miralis to serve the reads of the basic
+    /// counters hidden from the payload, to emulate the Svinval, Zawrs or conditional-zero
+    /// instructions, or to validate the `satp` writes.
+    pub(crate) fn payload_medeleg(&self) -> usize {
+        let mut medeleg = self.csr.medeleg;
+        if self.single_step {
+            medeleg &= !(1 << MCause::Breakpoint as usize);
+        }
+        if self.emulates_counter_reads()
+            || VCPU_EMULATE_SVINVAL
+            || VCPU_EMULATE_ZAWRS
+            || VCPU_EMULATE_ZICOND
+            || self.trap_satp
+        {
+            medeleg &= !(1 << MCause::IllegalInstr as usize);
+        }
+        medeleg
+    }
+
+    /// Starts or stops single-stepping the payload.
+    ///
+    /// # Safety
+    ///
+    /// This function changes the configuration of the hardware, it must be called while the
+    /// payload configuration is installed.
+    pub(crate) unsafe fn set_payload_single_step(&mut self, enabled: bool) {
+        self.single_step = enabled;
+        unsafe { arch::write_csr(Csr::Medeleg, self.payload_medeleg()) };
+        if enabled {
+            arm_single_step();
+        } else {
+            disarm_single_step();
+        }
+    }
+}
+
+/// Arms the instruction count trigger, to trap back into Miralis once the next payload
+/// instruction retires.
+pub(crate) fn arm_single_step() {
+    unsafe {
+        arch::write_csr(Csr::Tselect, 0);
+        arch::write_csr(Csr::Tdata1, icount::SINGLE_STEP);
+    }
+}
+
+/// Returns true if the instruction count trigger fired, as opposed to a breakpoint raised by the
+/// payload itself (e.g. an `ebreak` instruction).
+pub(crate) fn single_step_fired() -> bool {
+    unsafe { arch::write_csr(Csr::Tselect, 0) };
+    icount::has_fired(arch::read_csr(Csr::Tdata1))
+}
+
+/// Disarms the instruction count trigger.
+fn disarm_single_step() {
+    unsafe {
+        arch::write_csr(Csr::Tselect, 0);
+        arch::write_csr(Csr::Tdata1, 0);
     }
 }
 
@@ -218,7 +376,8 @@ impl VirtContext {
 #[cfg(test)]
 mod tests {
     use crate::arch;
-    use crate::arch::{Csr, Mode, mstatus};
+    use crate::arch::pmp::pmpcfg;
+    use crate::arch::{Csr, MCause, Mode, icount, mstatus};
     use crate::host::MiralisContext;
     use crate::virt::VirtContext;
 
@@ -272,6 +431,39 @@ mod tests {
         );
     }
 
+    /// The virtual `scontext` is installed while the payload runs, and the payload's updates are
+    /// saved back on the next switch to the firmware. Without the physical register the virtual
+    /// value is left untouched.
+    #[test]
+    fn switch_context_scontext() {
+        let hw = unsafe { arch::detect_hardware() };
+        let mut mctx = MiralisContext::new(hw, 0x10000, 0x2000);
+        let mut ctx = VirtContext::new(0, mctx.hw.available_reg.nb_pmp, mctx.hw.extensions.clone());
+        let has_scontext = mctx.hw.available_reg.scontext;
+
+        ctx.csr.mstatus |= Mode::S.to_bits() << mstatus::MPP_OFFSET;
+        ctx.csr.scontext = 0x2a;
+        unsafe { ctx.switch_from_firmware_to_payload(&mut mctx) }
+        if has_scontext {
+            assert_eq!(arch::read_csr(Csr::Scontext), 0x2a);
+        }
+
+        // The payload updates scontext, then traps
+        unsafe {
+            if has_scontext {
+                arch::write_csr(Csr::Scontext, 0x15);
+            }
+            arch::write_csr(Csr::Mstatus, Mode::S.to_bits() << mstatus::MPP_OFFSET);
+            ctx.switch_from_payload_to_firmware(&mut mctx);
+        }
+        if has_scontext {
+            assert_eq!(ctx.csr.scontext, 0x15);
+            assert_eq!(arch::read_csr(Csr::Scontext), 0);
+        } else {
+            assert_eq!(ctx.csr.scontext, 0x2a);
+        }
+    }
+
     /// We test value of mideleg when switching from payload to firmware.
     /// Mideleg must always be 0 when executing the firware.
     #[test]
@@ -286,4 +478,57 @@ mod tests {
 
         assert_eq!(arch::read_csr(Csr::Mideleg), 0, "Mideleg must be 0");
     }
+
+    /// The identity map requested by the firmware must only last until the next switch back to
+    /// the firmware.
+    #[test]
+    fn identity_map_revoked() {
+        let hw = unsafe { arch::detect_hardware() };
+        let mut mctx = MiralisContext::new(hw, 0x10000, 0x2000);
+        let mut ctx = VirtContext::new(0, mctx.hw.available_reg.nb_pmp, mctx.hw.extensions.clone());
+        let last_pmp_idx = mctx.pmp.nb_pmp as usize - 1;
+
+        ctx.identity_map = true;
+        unsafe { ctx.switch_from_firmware_to_payload(&mut mctx) }
+        assert_eq!(
+            mctx.pmp.get_pmpcfg(last_pmp_idx) & pmpcfg::RWX,
+            pmpcfg::RWX,
+            "The payload must be granted access to all memory"
+        );
+
+        unsafe { ctx.switch_from_payload_to_firmware(&mut mctx) }
+        assert!(!ctx.identity_map, "The identity map must be revoked");
+
+        unsafe { ctx.switch_from_firmware_to_payload(&mut mctx) }
+        assert_eq!(
+            mctx.pmp.get_pmpcfg(last_pmp_idx) & pmpcfg::RWX,
+            pmpcfg::NO_PERMISSIONS,
+            "The payload must not have access to all memory anymore"
+        );
+    }
+
+    /// Breakpoints are not delegated to the payload while it is single-stepped, so that the
+    /// instruction count trigger traps into Miralis.
+    #[test]
+    fn single_step_delegation() {
+        let hw = unsafe { arch::detect_hardware() };
+        let mut ctx = VirtContext::new(0, 0, hw.extensions);
+        let breakpoint = 1 << MCause::Breakpoint as usize;
+        ctx.csr.medeleg = breakpoint | 0b1;
+
+        assert_eq!(ctx.payload_medeleg(), breakpoint | 0b1);
+        ctx.single_step = true;
+        assert_eq!(ctx.payload_medeleg(), 0b1);
+    }
+
+    /// Only breakpoints raised by the instruction count trigger are steps, an `ebreak` traps
+    /// before retiring and leaves the trigger armed.
+    #[test]
+    fn single_step_fired() {
+        assert!(!icount::has_fired(icount::SINGLE_STEP));
+        assert!(icount::has_fired(
+            icount::SINGLE_STEP & !icount::COUNT_FILTER
+        ));
+        assert!(icount::has_fired(icount::SINGLE_STEP | icount::HIT_FILTER));
+    }
 }